@@ -0,0 +1,177 @@
+// Compiles every GLSL shader in `src/shader` to SPIR-V with `glslc` and embeds
+// the results. Without `glslc` the precompiled files in `shaders/` are used.
+//
+// Set `INSTALL_SHADERS=1` to also copy the compiled SPIR-V and its manifest
+// into `shaders/`, which is how the precompiled fallback is refreshed.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SHADER_DIR: &str = "src/shader";
+const PRECOMPILED_DIR: &str = "shaders";
+const MANIFEST: &str = "shaders/manifest.txt";
+const STAGES: &[&str] = &["vert", "frag", "comp", "geom", "tesc", "tese"];
+
+struct ManifestEntry {
+    checksum: u64,
+    source_checksum: u64,
+}
+
+// FNV-1a, must match `core::shaders::checksum`.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn find_glslc() -> Option<PathBuf> {
+    if let Some(glslc) = env::var_os("GLSLC") {
+        return Some(PathBuf::from(glslc));
+    }
+    if let Some(sdk) = env::var_os("VULKAN_SDK") {
+        let glslc = Path::new(&sdk).join("bin").join(if cfg!(windows) { "glslc.exe" } else { "glslc" });
+        if glslc.exists() {
+            return Some(glslc);
+        }
+    }
+    Command::new("glslc")
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|_| PathBuf::from("glslc"))
+}
+
+fn read_manifest() -> HashMap<String, ManifestEntry> {
+    let Ok(manifest) = fs::read_to_string(MANIFEST) else {
+        return HashMap::new();
+    };
+    manifest
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let name = parts.next()?.to_string();
+            let checksum = u64::from_str_radix(parts.next()?, 16).ok()?;
+            let source_checksum = u64::from_str_radix(parts.next()?, 16).ok()?;
+            Some((name, ManifestEntry { checksum, source_checksum }))
+        })
+        .collect()
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", SHADER_DIR);
+    println!("cargo:rerun-if-changed={}", PRECOMPILED_DIR);
+    println!("cargo:rerun-if-env-changed=GLSLC");
+    println!("cargo:rerun-if-env-changed=VULKAN_SDK");
+    println!("cargo:rerun-if-env-changed=INSTALL_SHADERS");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let glslc = find_glslc();
+    let manifest = read_manifest();
+    let install = env::var_os("INSTALL_SHADERS").is_some();
+
+    if install && glslc.is_some() {
+        fs::create_dir_all(PRECOMPILED_DIR).unwrap();
+    }
+    if glslc.is_none() {
+        println!("cargo:warning=glslc not found (set GLSLC or VULKAN_SDK), using precompiled shaders from `{}`", PRECOMPILED_DIR);
+    }
+
+    let mut sources = fs::read_dir(SHADER_DIR)
+        .expect("Failed to read shader directory")
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| STAGES.contains(&e))
+        })
+        .collect::<Vec<_>>();
+    sources.sort();
+
+    let mut table = String::from("pub static EMBEDDED_SHADERS: &[EmbeddedShader] = &[\n");
+    let mut new_manifest = String::new();
+
+    for source in &sources {
+        let name = source.file_name().unwrap().to_str().unwrap().to_string();
+        let source_checksum = checksum(&fs::read(source).unwrap());
+        let output = out_dir.join(format!("{}.spv", name));
+
+        // What gets embedded next to the SPIR-V for the loader to check: its
+        // manifest checksum and the checksum of the source it was built from.
+        let embedded_entry = match &glslc {
+            Some(glslc) => {
+                let result = Command::new(glslc)
+                    .arg("-I")
+                    .arg(SHADER_DIR)
+                    .arg(source)
+                    .arg("-o")
+                    .arg(&output)
+                    .output()
+                    .unwrap_or_else(|e| panic!("Failed to run {}: {}", glslc.display(), e));
+                if !result.status.success() {
+                    panic!("Shader compile error ({}):\n{}", source.display(), String::from_utf8_lossy(&result.stderr));
+                }
+                Some((checksum(&fs::read(&output).unwrap()), source_checksum))
+            }
+            None => {
+                let precompiled = Path::new(PRECOMPILED_DIR).join(format!("{}.spv", name));
+                if !precompiled.exists() {
+                    panic!("No glslc and no precompiled `{}`; install the Vulkan SDK or set GLSLC", precompiled.display());
+                }
+                let spirv = fs::read(&precompiled).unwrap();
+                match manifest.get(&name) {
+                    None => println!("cargo:warning=`{}` is missing from `{}`", precompiled.display(), MANIFEST),
+                    Some(entry) if entry.checksum != checksum(&spirv) => {
+                        println!("cargo:warning=Checksum mismatch for `{}`, the file is corrupt", precompiled.display());
+                    }
+                    Some(entry) if entry.source_checksum != source_checksum => {
+                        println!("cargo:warning=`{}` is older than `{}`", precompiled.display(), source.display());
+                    }
+                    Some(_) => {}
+                }
+                fs::write(&output, spirv).unwrap();
+                manifest.get(&name).map(|entry| (entry.checksum, entry.source_checksum))
+            }
+        };
+        let embedded_entry = match embedded_entry {
+            Some((checksum, source_checksum)) => {
+                format!("Some(ManifestEntry {{ checksum: 0x{:016x}, source_checksum: 0x{:016x} }})", checksum, source_checksum)
+            }
+            None => "None".to_string(),
+        };
+
+        let spirv = fs::read(&output).unwrap();
+        let spirv_checksum = checksum(&spirv);
+        if install && glslc.is_some() {
+            write_if_changed(&Path::new(PRECOMPILED_DIR).join(format!("{}.spv", name)), &spirv);
+        }
+
+        writeln!(new_manifest, "{} {:016x} {:016x}", name, spirv_checksum, source_checksum).unwrap();
+        writeln!(
+            table,
+            "    EmbeddedShader {{ name: {:?}, spirv: include_bytes!({:?}), manifest: {} }},",
+            name,
+            output.display().to_string(),
+            embedded_entry,
+        )
+        .unwrap();
+    }
+
+    table.push_str("];\n");
+    fs::write(out_dir.join("shaders.rs"), table).unwrap();
+
+    if install && glslc.is_some() {
+        write_if_changed(Path::new(MANIFEST), new_manifest.as_bytes());
+    }
+}
+
+// Rewriting identical files would retrigger `rerun-if-changed` on every build.
+fn write_if_changed(path: &Path, contents: &[u8]) {
+    if fs::read(path).ok().as_deref() != Some(contents) {
+        fs::write(path, contents).unwrap();
+    }
+}
@@ -0,0 +1,662 @@
+use anyhow::{Result, anyhow};
+use vulkanalia::prelude::v1_0::*;
+use vulkanalia::Version;
+use vulkanalia::vk::InstanceV1_1;
+use log::*;
+
+use std::collections::HashSet;
+
+use crate::AppData;
+use crate::core;
+use crate::core::reflect::ShaderReflection;
+use crate::core::specialization::Specialization;
+
+pub const CORE_VERSION: Version = Version::new(1, 2, 0);
+
+// Requested table sizes, clamped to the device limits.
+pub const MAX_SAMPLED_IMAGES: u32 = 4096;
+pub const MAX_STORAGE_BUFFERS: u32 = 1024;
+pub const MAX_SAMPLERS: u32 = 32;
+
+// Per-stage resources left to the other sets and the attachments of the
+// pipelines binding the table.
+const RESERVED_STAGE_RESOURCES: u32 = 32;
+
+// The set pipelines built with `PipelineState::bindless` bind the table at.
+pub const BINDLESS_SET: u32 = 1;
+
+// Bindings of the table's set, which shaders declare as arrays sized by these
+// specialization constants:
+//
+//     layout(constant_id = 100) const uint SAMPLED_IMAGE_COUNT = 1;
+//     layout(set = 1, binding = 0) uniform texture2D textures[SAMPLED_IMAGE_COUNT];
+//     layout(set = 1, binding = 1) buffer Buffers { ... } buffers[STORAGE_BUFFER_COUNT];
+//     layout(set = 1, binding = 2) uniform sampler samplers[SAMPLER_COUNT];
+//
+// Sized arrays need no runtime descriptor arrays, so the same shaders run with
+// and without descriptor indexing.
+pub const SAMPLED_IMAGE_BINDING: u32 = 0;
+pub const STORAGE_BUFFER_BINDING: u32 = 1;
+pub const SAMPLER_BINDING: u32 = 2;
+
+pub const SAMPLED_IMAGE_COUNT_CONSTANT: u32 = 100;
+pub const STORAGE_BUFFER_COUNT_CONSTANT: u32 = 101;
+pub const SAMPLER_COUNT_CONSTANT: u32 = 102;
+
+const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_bits_truncate(
+    vk::ShaderStageFlags::VERTEX.bits() | vk::ShaderStageFlags::FRAGMENT.bits() | vk::ShaderStageFlags::COMPUTE.bits(),
+);
+
+// Where descriptor indexing comes from on the selected device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DescriptorIndexing {
+    Core,
+    Ext,
+}
+
+fn device_version(instance: &Instance, properties: &vk::PhysicalDeviceProperties) -> Version {
+    Version::from(properties.api_version).min(instance.version())
+}
+
+pub unsafe fn get_support(instance: &Instance, physical_device: vk::PhysicalDevice) -> Result<Option<DescriptorIndexing>> {
+    // The feature query needs `vkGetPhysicalDeviceFeatures2`.
+    if !core::config::BINDLESS || instance.version() < Version::new(1, 1, 0) {
+        return Ok(None);
+    }
+
+    let properties = instance.get_physical_device_properties(physical_device);
+    let version = device_version(instance, &properties);
+    if version < Version::new(1, 1, 0) {
+        return Ok(None);
+    }
+
+    let mode = if version >= CORE_VERSION {
+        DescriptorIndexing::Core
+    } else {
+        let extensions = instance
+            .enumerate_device_extension_properties(physical_device, None)?
+            .iter()
+            .map(|e| e.extension_name)
+            .collect::<HashSet<_>>();
+        if !extensions.contains(&vk::EXT_DESCRIPTOR_INDEXING_EXTENSION.name) {
+            return Ok(None);
+        }
+        DescriptorIndexing::Ext
+    };
+
+    let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::builder();
+    let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut indexing_features);
+    instance.get_physical_device_features2(physical_device, &mut features);
+
+    let supported = [
+        indexing_features.shader_sampled_image_array_non_uniform_indexing,
+        indexing_features.shader_storage_buffer_array_non_uniform_indexing,
+        indexing_features.descriptor_binding_sampled_image_update_after_bind,
+        indexing_features.descriptor_binding_storage_buffer_update_after_bind,
+        indexing_features.descriptor_binding_update_unused_while_pending,
+        indexing_features.descriptor_binding_partially_bound,
+        indexing_features.runtime_descriptor_array,
+    ];
+    Ok(supported.iter().all(|f| *f == vk::TRUE).then_some(mode))
+}
+
+pub fn device_extensions(mode: Option<DescriptorIndexing>) -> Vec<vk::ExtensionName> {
+    match mode {
+        Some(DescriptorIndexing::Ext) => vec![vk::EXT_DESCRIPTOR_INDEXING_EXTENSION.name],
+        _ => Vec::new(),
+    }
+}
+
+// The features `get_support` checked, to chain into the device create info.
+pub fn enabled_features() -> vk::PhysicalDeviceDescriptorIndexingFeaturesBuilder {
+    vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+        .shader_sampled_image_array_non_uniform_indexing(true)
+        .shader_storage_buffer_array_non_uniform_indexing(true)
+        .descriptor_binding_sampled_image_update_after_bind(true)
+        .descriptor_binding_storage_buffer_update_after_bind(true)
+        .descriptor_binding_update_unused_while_pending(true)
+        .descriptor_binding_partially_bound(true)
+        .runtime_descriptor_array(true)
+}
+
+// A stable index into `textures[]`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ImageIndex(pub u32);
+
+// A stable index into `buffers[]`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BufferIndex(pub u32);
+
+// A stable index into `samplers[]`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SamplerIndex(pub u32);
+
+// Hands out the indices of one binding, reusing released ones first.
+#[derive(Clone, Debug, Default)]
+struct Slots {
+    capacity: u32,
+    next: u32,
+    free: Vec<u32>,
+}
+
+impl Slots {
+    fn new(capacity: u32) -> Self {
+        Self { capacity, ..Default::default() }
+    }
+
+    fn allocate(&mut self, what: &str) -> Result<u32> {
+        if let Some(index) = self.free.pop() {
+            return Ok(index);
+        }
+        if self.next == self.capacity {
+            return Err(anyhow!("The bindless table is full ({} {}).", self.capacity, what));
+        }
+        self.next += 1;
+        Ok(self.next - 1)
+    }
+
+    fn release(&mut self, index: u32) {
+        debug_assert!(index < self.next && !self.free.contains(&index));
+        self.free.push(index);
+    }
+}
+
+// Written into every empty slot when the table is not partially bound.
+#[derive(Copy, Clone, Debug, Default)]
+struct Placeholders {
+    image: vk::Image,
+    image_memory: vk::DeviceMemory,
+    image_view: vk::ImageView,
+    buffer: vk::Buffer,
+    buffer_memory: vk::DeviceMemory,
+    sampler: vk::Sampler,
+}
+
+// One descriptor set holding every sampled image, storage buffer and sampler
+// the shaders index, bound once per pass instead of per draw. Indices stay
+// valid until the resource is removed.
+//
+// With descriptor indexing the bindings are update-after-bind and partially
+// bound, so resources are added and removed while frames are in flight and
+// empty slots stay unwritten. Without it the table falls back to a plain set
+// sized by the regular per-stage limits: empty slots point at placeholders,
+// updates wait for the device to idle, and `take_stale` tells the caller to
+// re-record the command buffers that bound it. Shaders must then index with
+// dynamically uniform values, e.g. from push constants, as the scene does.
+#[derive(Clone, Debug, Default)]
+pub struct BindlessTable {
+    pub indexing: Option<DescriptorIndexing>,
+    pub layout: vk::DescriptorSetLayout,
+    pub pool: vk::DescriptorPool,
+    pub set: vk::DescriptorSet,
+    images: Slots,
+    buffers: Slots,
+    samplers: Slots,
+    placeholders: Placeholders,
+    stale: bool,
+}
+
+impl BindlessTable {
+    pub fn capacities(&self) -> (u32, u32, u32) {
+        (self.images.capacity, self.buffers.capacity, self.samplers.capacity)
+    }
+
+    pub fn bindings(&self) -> [vk::DescriptorSetLayoutBinding; 3] {
+        layout_bindings(self.capacities())
+    }
+
+    // Sizes the arrays a shader declares to the whole table.
+    pub fn specialize(&self, specialization: &Specialization, reflection: &ShaderReflection) -> Specialization {
+        let (image_count, buffer_count, sampler_count) = self.capacities();
+        [
+            (SAMPLED_IMAGE_COUNT_CONSTANT, image_count),
+            (STORAGE_BUFFER_COUNT_CONSTANT, buffer_count),
+            (SAMPLER_COUNT_CONSTANT, sampler_count),
+        ]
+        .into_iter()
+        .filter(|(id, _)| reflection.specialization_constants.iter().any(|c| c.id == *id))
+        .fold(specialization.clone(), |specialization, (id, count)| specialization.set(id, count))
+    }
+
+    pub unsafe fn add_sampled_image(&mut self, device: &Device, view: vk::ImageView) -> Result<ImageIndex> {
+        let index = self.images.allocate("sampled images")?;
+        self.write_image(device, index, view)?;
+        Ok(ImageIndex(index))
+    }
+
+    pub unsafe fn add_storage_buffer(&mut self, device: &Device, buffer: vk::Buffer) -> Result<BufferIndex> {
+        let index = self.buffers.allocate("storage buffers")?;
+        self.write_buffer(device, index, buffer)?;
+        Ok(BufferIndex(index))
+    }
+
+    pub unsafe fn add_sampler(&mut self, device: &Device, sampler: vk::Sampler) -> Result<SamplerIndex> {
+        let index = self.samplers.allocate("samplers")?;
+        self.write_sampler(device, index, sampler)?;
+        Ok(SamplerIndex(index))
+    }
+
+    // The slot may be handed out again, so the GPU must be done with the
+    // resource; a partially bound table leaves the old descriptor in place.
+    pub unsafe fn remove_sampled_image(&mut self, device: &Device, index: ImageIndex) -> Result<()> {
+        if self.indexing.is_none() {
+            self.write_image(device, index.0, self.placeholders.image_view)?;
+        }
+        self.images.release(index.0);
+        Ok(())
+    }
+
+    pub unsafe fn remove_storage_buffer(&mut self, device: &Device, index: BufferIndex) -> Result<()> {
+        if self.indexing.is_none() {
+            self.write_buffer(device, index.0, self.placeholders.buffer)?;
+        }
+        self.buffers.release(index.0);
+        Ok(())
+    }
+
+    pub unsafe fn remove_sampler(&mut self, device: &Device, index: SamplerIndex) -> Result<()> {
+        if self.indexing.is_none() {
+            self.write_sampler(device, index.0, self.placeholders.sampler)?;
+        }
+        self.samplers.release(index.0);
+        Ok(())
+    }
+
+    // Whether an update since the last call invalidated recorded command
+    // buffers. Always false with descriptor indexing.
+    pub fn take_stale(&mut self) -> bool {
+        std::mem::take(&mut self.stale)
+    }
+
+    pub unsafe fn bind(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        set_index: u32,
+    ) {
+        device.cmd_bind_descriptor_sets(command_buffer, bind_point, layout, set_index, &[self.set], &[]);
+    }
+
+    unsafe fn write_image(&mut self, device: &Device, index: u32, view: vk::ImageView) -> Result<()> {
+        let info = vk::DescriptorImageInfo::builder()
+            .image_view(view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let image_info = &[info];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(SAMPLED_IMAGE_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(image_info);
+        self.update(device, write)
+    }
+
+    unsafe fn write_buffer(&mut self, device: &Device, index: u32, buffer: vk::Buffer) -> Result<()> {
+        let info = vk::DescriptorBufferInfo::builder()
+            .buffer(buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE);
+        let buffer_info = &[info];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(STORAGE_BUFFER_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(buffer_info);
+        self.update(device, write)
+    }
+
+    unsafe fn write_sampler(&mut self, device: &Device, index: u32, sampler: vk::Sampler) -> Result<()> {
+        let info = vk::DescriptorImageInfo::builder()
+            .sampler(sampler);
+        let image_info = &[info];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(self.set)
+            .dst_binding(SAMPLER_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(image_info);
+        self.update(device, write)
+    }
+
+    unsafe fn update(&mut self, device: &Device, write: impl vk::Cast<Target = vk::WriteDescriptorSet>) -> Result<()> {
+        if self.indexing.is_none() {
+            device.device_wait_idle()?;
+            self.stale = true;
+        }
+        device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+        Ok(())
+    }
+}
+
+// Table sizes within the limits that apply to the set's bindings.
+unsafe fn capacities(instance: &Instance, data: &AppData) -> (u32, u32, u32) {
+    let limits = instance.get_physical_device_properties(data.physical_device).limits;
+    match data.descriptor_indexing {
+        Some(_) => {
+            let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::builder();
+            let mut properties = vk::PhysicalDeviceProperties2::builder().push_next(&mut indexing_properties);
+            instance.get_physical_device_properties2(data.physical_device, &mut properties);
+            fit_stage_resources(
+                (
+                    MAX_SAMPLED_IMAGES
+                        .min(indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images)
+                        .min(indexing_properties.max_descriptor_set_update_after_bind_sampled_images),
+                    MAX_STORAGE_BUFFERS
+                        .min(indexing_properties.max_per_stage_descriptor_update_after_bind_storage_buffers)
+                        .min(indexing_properties.max_descriptor_set_update_after_bind_storage_buffers),
+                    MAX_SAMPLERS
+                        .min(indexing_properties.max_per_stage_descriptor_update_after_bind_samplers)
+                        .min(indexing_properties.max_descriptor_set_update_after_bind_samplers),
+                ),
+                indexing_properties.max_per_stage_update_after_bind_resources,
+            )
+        }
+        None => fit_stage_resources(
+            (
+                MAX_SAMPLED_IMAGES
+                    .min(limits.max_per_stage_descriptor_sampled_images)
+                    .min(limits.max_descriptor_set_sampled_images),
+                MAX_STORAGE_BUFFERS
+                    .min(limits.max_per_stage_descriptor_storage_buffers)
+                    .min(limits.max_descriptor_set_storage_buffers),
+                MAX_SAMPLERS
+                    .min(limits.max_per_stage_descriptor_samplers)
+                    .min(limits.max_descriptor_set_samplers),
+            ),
+            limits.max_per_stage_resources,
+        ),
+    }
+}
+
+// Scales the three arrays down so together they stay within `max_resources`
+// per stage, less the reserved resources. Each array keeps at least one slot.
+fn fit_stage_resources(capacities: (u32, u32, u32), max_resources: u32) -> (u32, u32, u32) {
+    let available = max_resources.saturating_sub(RESERVED_STAGE_RESOURCES).max(3) as u64;
+    let total = capacities.0 as u64 + capacities.1 as u64 + capacities.2 as u64;
+    if total <= available {
+        return capacities;
+    }
+    let scale = |count: u32| ((count as u64 * available / total) as u32).max(1);
+    (scale(capacities.0), scale(capacities.1), scale(capacities.2))
+}
+
+fn layout_bindings((image_count, buffer_count, sampler_count): (u32, u32, u32)) -> [vk::DescriptorSetLayoutBinding; 3] {
+    let binding = |binding, type_, count| {
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(binding)
+            .descriptor_type(type_)
+            .descriptor_count(count)
+            .stage_flags(STAGES)
+            .build()
+    };
+    [
+        binding(SAMPLED_IMAGE_BINDING, vk::DescriptorType::SAMPLED_IMAGE, image_count),
+        binding(STORAGE_BUFFER_BINDING, vk::DescriptorType::STORAGE_BUFFER, buffer_count),
+        binding(SAMPLER_BINDING, vk::DescriptorType::SAMPLER, sampler_count),
+    ]
+}
+
+pub unsafe fn create_table(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let (image_count, buffer_count, sampler_count) = capacities(instance, data);
+    let bindings = layout_bindings((image_count, buffer_count, sampler_count));
+
+    let binding_flags = [vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+        | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING
+        | vk::DescriptorBindingFlags::PARTIALLY_BOUND; 3];
+    let mut flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+        .binding_flags(&binding_flags);
+    let mut info = vk::DescriptorSetLayoutCreateInfo::builder()
+        .bindings(&bindings);
+    if data.descriptor_indexing.is_some() {
+        info = info
+            .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .push_next(&mut flags_info);
+    }
+    data.bindless.layout = device.create_descriptor_set_layout(&info, None)?;
+
+    let pool_sizes = bindings
+        .iter()
+        .map(|b| {
+            vk::DescriptorPoolSize::builder()
+                .type_(b.descriptor_type)
+                .descriptor_count(b.descriptor_count)
+                .build()
+        })
+        .collect::<Vec<_>>();
+    let flags = match data.descriptor_indexing {
+        Some(_) => vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+        None => vk::DescriptorPoolCreateFlags::empty(),
+    };
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .flags(flags)
+        .pool_sizes(&pool_sizes)
+        .max_sets(1);
+    data.bindless.pool = device.create_descriptor_pool(&info, None)?;
+
+    let layouts = &[data.bindless.layout];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.bindless.pool)
+        .set_layouts(layouts);
+    data.bindless.set = device.allocate_descriptor_sets(&info)?[0];
+
+    data.bindless.indexing = data.descriptor_indexing;
+    data.bindless.images = Slots::new(image_count);
+    data.bindless.buffers = Slots::new(buffer_count);
+    data.bindless.samplers = Slots::new(sampler_count);
+    match data.descriptor_indexing {
+        Some(mode) => info!(
+            "Bindless table ({:?} descriptor indexing): {} images, {} buffers, {} samplers.",
+            mode, image_count, buffer_count, sampler_count,
+        ),
+        None => {
+            info!(
+                "Bindless table without descriptor indexing: {} images, {} buffers, {} samplers.",
+                image_count, buffer_count, sampler_count,
+            );
+            create_placeholders(instance, device, data)?;
+        }
+    }
+
+    Ok(())
+}
+
+// A black 1x1 image, a small buffer and a nearest sampler, written into every
+// slot so the whole set is valid.
+unsafe fn create_placeholders(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let format = vk::Format::R8G8B8A8_UNORM;
+    let (image, image_memory) = core::image::create_image(
+        instance,
+        device,
+        data,
+        1,
+        1,
+        1,
+        vk::SampleCountFlags::_1,
+        format,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+    let image_view = core::image::create_image_view(device, image, format, vk::ImageAspectFlags::COLOR, 1)?;
+    let (buffer, buffer_memory) = core::vertex::create_buffer(
+        instance,
+        device,
+        data,
+        16,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+    let info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST)
+        .min_filter(vk::Filter::NEAREST);
+    let sampler = device.create_sampler(&info, None)?;
+    data.bindless.placeholders = Placeholders { image, image_memory, image_view, buffer, buffer_memory, sampler };
+
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1)
+        .build();
+    core::compute::run_once(device, data, |command_buffer| {
+        let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+            vk::ImageMemoryBarrier::builder()
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(subresource_range)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+        };
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[barrier(
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+            )],
+        );
+        let black = vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] };
+        device.cmd_clear_color_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &black,
+            &[subresource_range],
+        );
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &[] as &[vk::BufferMemoryBarrier],
+            &[barrier(
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            )],
+        );
+        Ok(())
+    })?;
+
+    let table = &mut data.bindless;
+    let (image_count, buffer_count, sampler_count) = table.capacities();
+    let image_infos = vec![
+        vk::DescriptorImageInfo::builder()
+            .image_view(image_view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .build();
+        image_count as usize
+    ];
+    let buffer_infos = vec![
+        vk::DescriptorBufferInfo::builder()
+            .buffer(buffer)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+            .build();
+        buffer_count as usize
+    ];
+    let sampler_infos = vec![vk::DescriptorImageInfo::builder().sampler(sampler).build(); sampler_count as usize];
+    let writes = [
+        vk::WriteDescriptorSet::builder()
+            .dst_set(table.set)
+            .dst_binding(SAMPLED_IMAGE_BINDING)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(&image_infos),
+        vk::WriteDescriptorSet::builder()
+            .dst_set(table.set)
+            .dst_binding(STORAGE_BUFFER_BINDING)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_infos),
+        vk::WriteDescriptorSet::builder()
+            .dst_set(table.set)
+            .dst_binding(SAMPLER_BINDING)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(&sampler_infos),
+    ];
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+
+    Ok(())
+}
+
+// Frees the set with the pool.
+pub unsafe fn destroy_table(device: &Device, data: &mut AppData) {
+    let table = std::mem::take(&mut data.bindless);
+    let placeholders = table.placeholders;
+    device.destroy_sampler(placeholders.sampler, None);
+    device.destroy_buffer(placeholders.buffer, None);
+    device.free_memory(placeholders.buffer_memory, None);
+    device.destroy_image_view(placeholders.image_view, None);
+    device.destroy_image(placeholders.image, None);
+    device.free_memory(placeholders.image_memory, None);
+    device.destroy_descriptor_pool(table.pool, None);
+    device.destroy_descriptor_set_layout(table.layout, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::reflect::SpecializationValue;
+    use crate::core::shaders::spirv_words;
+
+    fn table(capacities: (u32, u32, u32)) -> BindlessTable {
+        BindlessTable {
+            images: Slots::new(capacities.0),
+            buffers: Slots::new(capacities.1),
+            samplers: Slots::new(capacities.2),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn slots_reuse_released_indices() {
+        let mut slots = Slots::new(2);
+        assert_eq!(slots.allocate("images").unwrap(), 0);
+        assert_eq!(slots.allocate("images").unwrap(), 1);
+        assert!(slots.allocate("images").is_err());
+        slots.release(0);
+        assert_eq!(slots.allocate("images").unwrap(), 0);
+    }
+
+    #[test]
+    fn fits_the_table_to_the_stage_resource_limit() {
+        assert_eq!(fit_stage_resources((4096, 1024, 32), u32::MAX), (4096, 1024, 32));
+        let (images, buffers, samplers) = fit_stage_resources((4096, 1024, 32), 1024);
+        assert!(images + buffers + samplers <= 1024 - RESERVED_STAGE_RESOURCES);
+        assert!(images > buffers && buffers > samplers && samplers >= 1);
+        // The minimum `maxPerStageResources` still leaves a slot in each array.
+        assert_eq!(fit_stage_resources((4096, 1024, 32), 128), (76, 19, 1));
+    }
+
+    #[test]
+    fn scene_shaders_index_the_table() {
+        let table = table((64, 8, 4));
+        let shaders: [&[u8]; 2] =
+            [include_bytes!("../../shaders/shader.frag.spv"), include_bytes!("../../shaders/gbuffer.frag.spv")];
+        for shader in shaders {
+            let reflection = core::reflect::reflect_shader(&spirv_words(shader).unwrap()).unwrap();
+            core::reflect::validate_descriptor_set_layout(&[&reflection], BINDLESS_SET, &table.bindings()).unwrap();
+
+            let specialization = table.specialize(&Specialization::new(), &reflection);
+            specialization.validate(&reflection).unwrap();
+            assert_eq!(specialization.get(SAMPLED_IMAGE_COUNT_CONSTANT), Some(SpecializationValue::UInt(64)));
+            assert_eq!(specialization.get(SAMPLER_COUNT_CONSTANT), Some(SpecializationValue::UInt(4)));
+            // Not declared by the scene shaders.
+            assert_eq!(specialization.get(STORAGE_BUFFER_COUNT_CONSTANT), None);
+        }
+    }
+}
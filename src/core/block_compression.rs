@@ -0,0 +1,606 @@
+use anyhow::{Result, anyhow};
+use vulkanalia::prelude::v1_0::*;
+
+// The size of a format's texel blocks; uncompressed formats have 1x1 blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub width: u32,
+    pub height: u32,
+    pub bytes: u32,
+}
+
+impl BlockInfo {
+    const fn new(width: u32, height: u32, bytes: u32) -> Self {
+        Self { width, height, bytes }
+    }
+
+    // Bytes of one image of `extent`, in whole blocks.
+    pub fn image_size(self, extent: vk::Extent2D) -> usize {
+        let blocks_x = extent.width.div_ceil(self.width) as usize;
+        let blocks_y = extent.height.div_ceil(self.height) as usize;
+        blocks_x * blocks_y * self.bytes as usize
+    }
+
+    // Bytes of `layers` images of `extent`, or `None` if that overflows, for
+    // sizes read from a file.
+    pub fn checked_size(self, extent: vk::Extent2D, layers: u32) -> Option<usize> {
+        (extent.width.div_ceil(self.width) as usize)
+            .checked_mul(extent.height.div_ceil(self.height) as usize)?
+            .checked_mul(self.bytes as usize)?
+            .checked_mul(layers as usize)
+    }
+}
+
+// The formats textures can be loaded in.
+pub fn block_info(format: vk::Format) -> Option<BlockInfo> {
+    use vk::Format as F;
+    let info = match format {
+        F::R8_UNORM | F::R8_SRGB => BlockInfo::new(1, 1, 1),
+        F::R8G8_UNORM | F::R8G8_SRGB => BlockInfo::new(1, 1, 2),
+        F::R8G8B8A8_UNORM | F::R8G8B8A8_SRGB | F::B8G8R8A8_UNORM | F::B8G8R8A8_SRGB => BlockInfo::new(1, 1, 4),
+        F::R16G16B16A16_UNORM | F::R16G16B16A16_SFLOAT => BlockInfo::new(1, 1, 8),
+        F::R32G32B32A32_SFLOAT => BlockInfo::new(1, 1, 16),
+
+        F::BC1_RGB_UNORM_BLOCK
+        | F::BC1_RGB_SRGB_BLOCK
+        | F::BC1_RGBA_UNORM_BLOCK
+        | F::BC1_RGBA_SRGB_BLOCK
+        | F::BC4_UNORM_BLOCK
+        | F::BC4_SNORM_BLOCK => BlockInfo::new(4, 4, 8),
+        F::BC2_UNORM_BLOCK
+        | F::BC2_SRGB_BLOCK
+        | F::BC3_UNORM_BLOCK
+        | F::BC3_SRGB_BLOCK
+        | F::BC5_UNORM_BLOCK
+        | F::BC5_SNORM_BLOCK
+        | F::BC6H_UFLOAT_BLOCK
+        | F::BC6H_SFLOAT_BLOCK
+        | F::BC7_UNORM_BLOCK
+        | F::BC7_SRGB_BLOCK => BlockInfo::new(4, 4, 16),
+
+        F::ETC2_R8G8B8_UNORM_BLOCK
+        | F::ETC2_R8G8B8_SRGB_BLOCK
+        | F::ETC2_R8G8B8A1_UNORM_BLOCK
+        | F::ETC2_R8G8B8A1_SRGB_BLOCK
+        | F::EAC_R11_UNORM_BLOCK
+        | F::EAC_R11_SNORM_BLOCK => BlockInfo::new(4, 4, 8),
+        F::ETC2_R8G8B8A8_UNORM_BLOCK
+        | F::ETC2_R8G8B8A8_SRGB_BLOCK
+        | F::EAC_R11G11_UNORM_BLOCK
+        | F::EAC_R11G11_SNORM_BLOCK => BlockInfo::new(4, 4, 16),
+
+        F::ASTC_4X4_UNORM_BLOCK | F::ASTC_4X4_SRGB_BLOCK => BlockInfo::new(4, 4, 16),
+        F::ASTC_5X4_UNORM_BLOCK | F::ASTC_5X4_SRGB_BLOCK => BlockInfo::new(5, 4, 16),
+        F::ASTC_5X5_UNORM_BLOCK | F::ASTC_5X5_SRGB_BLOCK => BlockInfo::new(5, 5, 16),
+        F::ASTC_6X5_UNORM_BLOCK | F::ASTC_6X5_SRGB_BLOCK => BlockInfo::new(6, 5, 16),
+        F::ASTC_6X6_UNORM_BLOCK | F::ASTC_6X6_SRGB_BLOCK => BlockInfo::new(6, 6, 16),
+        F::ASTC_8X5_UNORM_BLOCK | F::ASTC_8X5_SRGB_BLOCK => BlockInfo::new(8, 5, 16),
+        F::ASTC_8X6_UNORM_BLOCK | F::ASTC_8X6_SRGB_BLOCK => BlockInfo::new(8, 6, 16),
+        F::ASTC_8X8_UNORM_BLOCK | F::ASTC_8X8_SRGB_BLOCK => BlockInfo::new(8, 8, 16),
+        F::ASTC_10X5_UNORM_BLOCK | F::ASTC_10X5_SRGB_BLOCK => BlockInfo::new(10, 5, 16),
+        F::ASTC_10X6_UNORM_BLOCK | F::ASTC_10X6_SRGB_BLOCK => BlockInfo::new(10, 6, 16),
+        F::ASTC_10X8_UNORM_BLOCK | F::ASTC_10X8_SRGB_BLOCK => BlockInfo::new(10, 8, 16),
+        F::ASTC_10X10_UNORM_BLOCK | F::ASTC_10X10_SRGB_BLOCK => BlockInfo::new(10, 10, 16),
+        F::ASTC_12X10_UNORM_BLOCK | F::ASTC_12X10_SRGB_BLOCK => BlockInfo::new(12, 10, 16),
+        F::ASTC_12X12_UNORM_BLOCK | F::ASTC_12X12_SRGB_BLOCK => BlockInfo::new(12, 12, 16),
+        _ => return None,
+    };
+    Some(info)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Decoder {
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Etc2Rgb,
+    Etc2RgbA1,
+    Etc2Rgba,
+}
+
+fn decoder(format: vk::Format) -> Option<(Decoder, vk::Format)> {
+    use vk::Format as F;
+    let decoder = match format {
+        F::BC1_RGB_UNORM_BLOCK | F::BC1_RGBA_UNORM_BLOCK => (Decoder::Bc1, F::R8G8B8A8_UNORM),
+        F::BC1_RGB_SRGB_BLOCK | F::BC1_RGBA_SRGB_BLOCK => (Decoder::Bc1, F::R8G8B8A8_SRGB),
+        F::BC2_UNORM_BLOCK => (Decoder::Bc2, F::R8G8B8A8_UNORM),
+        F::BC2_SRGB_BLOCK => (Decoder::Bc2, F::R8G8B8A8_SRGB),
+        F::BC3_UNORM_BLOCK => (Decoder::Bc3, F::R8G8B8A8_UNORM),
+        F::BC3_SRGB_BLOCK => (Decoder::Bc3, F::R8G8B8A8_SRGB),
+        F::BC4_UNORM_BLOCK => (Decoder::Bc4, F::R8G8B8A8_UNORM),
+        F::BC5_UNORM_BLOCK => (Decoder::Bc5, F::R8G8B8A8_UNORM),
+        F::ETC2_R8G8B8_UNORM_BLOCK => (Decoder::Etc2Rgb, F::R8G8B8A8_UNORM),
+        F::ETC2_R8G8B8_SRGB_BLOCK => (Decoder::Etc2Rgb, F::R8G8B8A8_SRGB),
+        F::ETC2_R8G8B8A1_UNORM_BLOCK => (Decoder::Etc2RgbA1, F::R8G8B8A8_UNORM),
+        F::ETC2_R8G8B8A1_SRGB_BLOCK => (Decoder::Etc2RgbA1, F::R8G8B8A8_SRGB),
+        F::ETC2_R8G8B8A8_UNORM_BLOCK => (Decoder::Etc2Rgba, F::R8G8B8A8_UNORM),
+        F::ETC2_R8G8B8A8_SRGB_BLOCK => (Decoder::Etc2Rgba, F::R8G8B8A8_SRGB),
+        _ => return None,
+    };
+    Some(decoder)
+}
+
+// The RGBA8 format `decompress` turns `format` into, if it has a CPU decoder.
+// BC4 and BC5 decode into red and red-green with opaque alpha.
+pub fn decompressed_format(format: vk::Format) -> Option<vk::Format> {
+    decoder(format).map(|(_, format)| format)
+}
+
+// Decodes one image of `extent` to tightly packed RGBA8 texels.
+pub fn decompress(format: vk::Format, extent: vk::Extent2D, data: &[u8]) -> Result<Vec<u8>> {
+    let (decoder, _) = decoder(format).ok_or_else(|| anyhow!("No CPU decoder for {:?}", format))?;
+    let info = block_info(format).ok_or_else(|| anyhow!("Unknown block size for {:?}", format))?;
+    if data.len() < info.image_size(extent) {
+        return Err(anyhow!("{:?} image of {}x{} is truncated", format, extent.width, extent.height));
+    }
+
+    let (width, height) = (extent.width as usize, extent.height as usize);
+    let blocks_x = width.div_ceil(4);
+    let mut texels = vec![0; width * height * 4];
+    for (i, block) in data.chunks_exact(info.bytes as usize).take(info.image_size(extent) / info.bytes as usize).enumerate() {
+        let decoded = match decoder {
+            Decoder::Bc1 => bc1(block, true),
+            Decoder::Bc2 => bc2(block),
+            Decoder::Bc3 => bc3(block),
+            Decoder::Bc4 => bc4(block),
+            Decoder::Bc5 => bc5(block),
+            Decoder::Etc2Rgb => etc2(block, false),
+            Decoder::Etc2RgbA1 => etc2(block, true),
+            Decoder::Etc2Rgba => etc2_eac(block),
+        };
+
+        // Blocks past an edge that isn't a multiple of 4 are cut off.
+        let (bx, by) = ((i % blocks_x) * 4, (i / blocks_x) * 4);
+        for y in 0..4.min(height - by) {
+            for x in 0..4.min(width - bx) {
+                let offset = ((by + y) * width + bx + x) * 4;
+                texels[offset..offset + 4].copy_from_slice(&decoded[y * 4 + x]);
+            }
+        }
+    }
+
+    Ok(texels)
+}
+
+// A decoded 4x4 block in row-major order.
+type Block = [[u8; 4]; 16];
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let r = (color >> 11) & 0x1f;
+    let g = (color >> 5) & 0x3f;
+    let b = color & 0x1f;
+    [((r << 3) | (r >> 2)) as u8, ((g << 2) | (g >> 4)) as u8, ((b << 3) | (b >> 2)) as u8]
+}
+
+fn mix(a: [u8; 3], b: [u8; 3], wa: u32, wb: u32) -> [u8; 3] {
+    [0, 1, 2].map(|i| ((a[i] as u32 * wa + b[i] as u32 * wb) / (wa + wb)) as u8)
+}
+
+// BC2 and BC3 color blocks always use four colors.
+fn bc1(block: &[u8], punch_through: bool) -> Block {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let palette = if c0 > c1 || !punch_through {
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], rgba(mix(a, b, 2, 1)), rgba(mix(a, b, 1, 2))]
+    } else {
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], rgba(mix(a, b, 1, 1)), [0, 0, 0, 0]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| palette[((indices >> (2 * i)) & 3) as usize])
+}
+
+fn rgba([r, g, b]: [u8; 3]) -> [u8; 4] {
+    [r, g, b, 255]
+}
+
+fn bc2(block: &[u8]) -> Block {
+    let mut texels = bc1(&block[8..], false);
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = ((alpha >> (4 * i)) & 0xf) as u8 * 17;
+    }
+    texels
+}
+
+// The 8-byte interpolated channel of BC3, BC4 and BC5.
+fn bc_channel(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let palette: [u8; 8] = std::array::from_fn(|i| match i {
+        0 => a0 as u8,
+        1 => a1 as u8,
+        _ if a0 > a1 => (((8 - i as u32) * a0 + (i as u32 - 1) * a1) / 7) as u8,
+        6 => 0,
+        7 => 255,
+        _ => (((6 - i as u32) * a0 + (i as u32 - 1) * a1) / 5) as u8,
+    });
+
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    std::array::from_fn(|i| palette[((indices >> (3 * i)) & 7) as usize])
+}
+
+fn bc3(block: &[u8]) -> Block {
+    let mut texels = bc1(&block[8..], false);
+    let alpha = bc_channel(&block[..8]);
+    texels.iter_mut().zip(alpha).for_each(|(texel, a)| texel[3] = a);
+    texels
+}
+
+fn bc4(block: &[u8]) -> Block {
+    let red = bc_channel(block);
+    std::array::from_fn(|i| [red[i], 0, 0, 255])
+}
+
+fn bc5(block: &[u8]) -> Block {
+    let (red, green) = (bc_channel(&block[..8]), bc_channel(&block[8..]));
+    std::array::from_fn(|i| [red[i], green[i], 0, 255])
+}
+
+const ETC_MODIFIERS: [[i32; 4]; 8] = [
+    [2, 8, -2, -8],
+    [5, 17, -5, -17],
+    [9, 29, -9, -29],
+    [13, 42, -13, -42],
+    [18, 60, -18, -60],
+    [24, 80, -24, -80],
+    [33, 106, -33, -106],
+    [47, 183, -47, -183],
+];
+
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+// `count` bits of `value` ending at bit `high`, counting from the LSB.
+fn bits(value: u64, high: u32, count: u32) -> i32 {
+    ((value >> (high + 1 - count)) & ((1 << count) - 1)) as i32
+}
+
+fn extend(value: i32, from: u32) -> i32 {
+    (value << (8 - from)) | (value >> (2 * from - 8))
+}
+
+fn clamp(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+fn offset(color: [i32; 3], by: i32) -> [u8; 4] {
+    [clamp(color[0] + by), clamp(color[1] + by), clamp(color[2] + by), 255]
+}
+
+// ETC2 RGB, or RGB with punch-through alpha when `punch_through` is set, where
+// the differential bit instead says whether the block is opaque.
+fn etc2(block: &[u8], punch_through: bool) -> Block {
+    let value = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let flag = bits(value, 33, 1) == 1;
+    let (differential, opaque) = if punch_through { (true, flag) } else { (flag, true) };
+    // Pixels are numbered down the columns; the index MSBs come first.
+    let index = |x: usize, y: usize| {
+        let i = (x * 4 + y) as u32;
+        (bits(value, i + 16, 1) << 1 | bits(value, i, 1)) as usize
+    };
+    let transparent = |i: usize| !opaque && i == 2;
+
+    let base = |high| [bits(value, high, 5), bits(value, high - 8, 5), bits(value, high - 16, 5)];
+    let delta = |high| {
+        let d = bits(value, high, 3);
+        if d >= 4 { d - 8 } else { d }
+    };
+    let (r, g, b) = (base(63)[0], base(63)[1], base(63)[2]);
+    let (dr, dg, db) = (delta(58), delta(50), delta(42));
+
+    if differential && !(0..32).contains(&(r + dr)) {
+        // T mode.
+        let c0 = [(bits(value, 60, 2) << 2) | bits(value, 57, 2), bits(value, 55, 4), bits(value, 51, 4)].map(|c| extend(c, 4));
+        let c1 = [bits(value, 47, 4), bits(value, 43, 4), bits(value, 39, 4)].map(|c| extend(c, 4));
+        let d = ETC_DISTANCES[((bits(value, 35, 2) << 1) | bits(value, 32, 1)) as usize];
+        let paint = [offset(c0, 0), offset(c1, d), offset(c1, 0), offset(c1, -d)];
+        return paint_block(paint, index, transparent);
+    }
+    if differential && !(0..32).contains(&(g + dg)) {
+        // H mode.
+        let c0 = [
+            bits(value, 62, 4),
+            (bits(value, 58, 3) << 1) | bits(value, 52, 1),
+            (bits(value, 51, 1) << 3) | bits(value, 49, 3),
+        ];
+        let c1 = [bits(value, 46, 4), bits(value, 42, 4), bits(value, 38, 4)];
+        let ordered = (c0[0] << 8 | c0[1] << 4 | c0[2]) >= (c1[0] << 8 | c1[1] << 4 | c1[2]);
+        let d = ETC_DISTANCES[((bits(value, 34, 1) << 2) | (bits(value, 32, 1) << 1) | ordered as i32) as usize];
+        let (c0, c1) = (c0.map(|c| extend(c, 4)), c1.map(|c| extend(c, 4)));
+        let paint = [offset(c0, d), offset(c0, -d), offset(c1, d), offset(c1, -d)];
+        return paint_block(paint, index, transparent);
+    }
+    if differential && !(0..32).contains(&(b + db)) {
+        // Planar mode, always opaque.
+        let o = [
+            extend(bits(value, 62, 6), 6),
+            extend((bits(value, 56, 1) << 6) | bits(value, 54, 6), 7),
+            extend((bits(value, 48, 1) << 5) | (bits(value, 44, 2) << 3) | bits(value, 41, 3), 6),
+        ];
+        let h = [
+            extend((bits(value, 38, 5) << 1) | bits(value, 32, 1), 6),
+            extend(bits(value, 31, 7), 7),
+            extend(bits(value, 24, 6), 6),
+        ];
+        let v = [extend(bits(value, 18, 6), 6), extend(bits(value, 12, 7), 7), extend(bits(value, 5, 6), 6)];
+        return std::array::from_fn(|i| {
+            let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+            let channel = |c: usize| clamp((x * (h[c] - o[c]) + y * (v[c] - o[c]) + 4 * o[c] + 2) >> 2);
+            [channel(0), channel(1), channel(2), 255]
+        });
+    }
+
+    // Two subblocks of 2x4 or, flipped, 4x2 texels.
+    let (c0, c1) = if differential {
+        ([r, g, b].map(|c| extend(c, 5)), [r + dr, g + dg, b + db].map(|c| extend(c, 5)))
+    } else {
+        (
+            [bits(value, 63, 4), bits(value, 55, 4), bits(value, 47, 4)].map(|c| extend(c, 4)),
+            [bits(value, 59, 4), bits(value, 51, 4), bits(value, 43, 4)].map(|c| extend(c, 4)),
+        )
+    };
+    let tables = [bits(value, 39, 3) as usize, bits(value, 36, 3) as usize];
+    let flip = bits(value, 32, 1) == 1;
+    std::array::from_fn(|i| {
+        let (x, y) = (i % 4, i / 4);
+        let subblock = if flip { y >= 2 } else { x >= 2 } as usize;
+        let pixel = index(x, y);
+        if transparent(pixel) {
+            return [0, 0, 0, 0];
+        }
+        // Non-opaque punch-through blocks drop the small positive modifier.
+        let modifier = if !opaque && pixel == 0 { 0 } else { ETC_MODIFIERS[tables[subblock]][pixel] };
+        offset([c0, c1][subblock], modifier)
+    })
+}
+
+fn paint_block(paint: [[u8; 4]; 4], index: impl Fn(usize, usize) -> usize, transparent: impl Fn(usize) -> bool) -> Block {
+    std::array::from_fn(|i| {
+        let pixel = index(i % 4, i / 4);
+        if transparent(pixel) { [0, 0, 0, 0] } else { paint[pixel] }
+    })
+}
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+// ETC2 RGBA: an EAC alpha block followed by an ETC2 color block.
+fn etc2_eac(block: &[u8]) -> Block {
+    let mut texels = etc2(&block[8..], false);
+    let value = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let base = bits(value, 63, 8);
+    let multiplier = bits(value, 55, 4);
+    let table = EAC_MODIFIERS[bits(value, 51, 4) as usize];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        let index = bits(value, 47 - 3 * (x * 4 + y) as u32, 3) as usize;
+        texel[3] = clamp(base + table[index] * multiplier);
+    }
+    texels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPAQUE_BLACK: [u8; 4] = [0, 0, 0, 255];
+    const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+
+    fn texel(block: &Block, x: usize, y: usize) -> [u8; 4] {
+        block[y * 4 + x]
+    }
+
+    // ETC2 index bits for the texel at (x, y): the MSB at bit 16 + i, the LSB
+    // at bit i, with texels numbered down the columns.
+    fn etc_index(x: usize, y: usize, index: u64) -> u64 {
+        let i = x * 4 + y;
+        ((index >> 1) << (16 + i)) | ((index & 1) << i)
+    }
+
+    fn rgb(block: &Block, x: usize, y: usize) -> [u8; 3] {
+        let [r, g, b, _] = texel(block, x, y);
+        [r, g, b]
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        // Red and blue, with texels 0-3 using palette entries 0-3.
+        let block = bc1(&[0x00, 0xf8, 0x1f, 0x00, 0xe4, 0, 0, 0], true);
+        assert_eq!(block[0], [255, 0, 0, 255]);
+        assert_eq!(block[1], [0, 0, 255, 255]);
+        assert_eq!(block[2], [170, 0, 85, 255]);
+        assert_eq!(block[3], [85, 0, 170, 255]);
+        assert_eq!(block[4], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bc1_punch_through() {
+        // Black then white: three colors and transparent black.
+        let block = bc1(&[0x00, 0x00, 0xff, 0xff, 0xe4, 0, 0, 0], true);
+        assert_eq!(block[0], OPAQUE_BLACK);
+        assert_eq!(block[1], [255, 255, 255, 255]);
+        assert_eq!(block[2], [127, 127, 127, 255]);
+        assert_eq!(block[3], TRANSPARENT);
+
+        // BC2 and BC3 color blocks ignore the endpoint order.
+        let block = bc1(&[0x00, 0x00, 0xff, 0xff, 0xe4, 0, 0, 0], false);
+        assert_eq!(block[3], [170, 170, 170, 255]);
+    }
+
+    #[test]
+    fn bc4_interpolates_eight_values() {
+        // 255 and 0 with texels 0-7 using palette entries 0-7.
+        let indices: u64 = (0..8).fold(0, |bits, i| bits | (i << (3 * i)));
+        let mut data = [255, 0, 0, 0, 0, 0, 0, 0];
+        data[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+        let red = bc4(&data).map(|t| t[0]);
+        assert_eq!(red[..8], [255, 0, 218, 182, 145, 109, 72, 36]);
+    }
+
+    #[test]
+    fn etc2_individual() {
+        let value = (0x8 << 60) | (0xf << 56) | (0x4 << 52) | (0x2 << 44) | (0x8 << 40) | (7 << 34)
+            | etc_index(0, 0, 3)
+            | etc_index(3, 3, 1);
+        let block = etc2(&value.to_be_bytes(), false);
+        assert_eq!(rgb(&block, 0, 0), [128, 60, 26]);
+        assert_eq!(rgb(&block, 1, 2), [138, 70, 36]);
+        assert_eq!(rgb(&block, 2, 0), [255, 47, 183]);
+        assert_eq!(rgb(&block, 3, 3), [255, 183, 255]);
+    }
+
+    #[test]
+    fn etc2_differential() {
+        // 16 + 3, 8 - 1 and 31 + 0 in 5 bits, flipped into top and bottom halves.
+        let value: u64 = (16 << 59) | (3 << 56) | (8 << 51) | (0b111 << 48) | (31 << 43) | (1 << 37) | (1 << 34)
+            | (1 << 33)
+            | (1 << 32);
+        let block = etc2(&value.to_be_bytes(), false);
+        assert_eq!(texel(&block, 3, 1), [137, 71, 255, 255]);
+        assert_eq!(texel(&block, 0, 2), [161, 62, 255, 255]);
+    }
+
+    #[test]
+    fn etc2_t_mode() {
+        // Red overflows: 0b11111 + 1.
+        let value: u64 = (0b111 << 61) | (0b11 << 59) | (0b01 << 56) | (0x4 << 52) | (0x8 << 44) | (0x8 << 40)
+            | (0x8 << 36)
+            | (0b01 << 34)
+            | (1 << 33)
+            | etc_index(1, 0, 1)
+            | etc_index(2, 0, 2)
+            | etc_index(3, 0, 3);
+        let block = etc2(&value.to_be_bytes(), false);
+        assert_eq!(rgb(&block, 0, 0), [221, 68, 0]);
+        assert_eq!(rgb(&block, 1, 0), [147, 147, 147]);
+        assert_eq!(rgb(&block, 2, 0), [136, 136, 136]);
+        assert_eq!(rgb(&block, 3, 0), [125, 125, 125]);
+    }
+
+    #[test]
+    fn etc2_h_mode() {
+        // Green overflows: 0b11111 + 1. The first color is the larger, so the
+        // distance index's LSB is set.
+        let value: u64 = (0x8 << 59) | (0b010 << 56) | (0b111 << 53) | (1 << 52) | (1 << 51) | (0b010 << 47)
+            | (0x2 << 43)
+            | (0x3 << 39)
+            | (0x4 << 35)
+            | (1 << 34)
+            | (1 << 33)
+            | etc_index(1, 0, 1)
+            | etc_index(2, 0, 2)
+            | etc_index(3, 0, 3);
+        let block = etc2(&value.to_be_bytes(), false);
+        assert_eq!(rgb(&block, 0, 0), [168, 117, 202]);
+        assert_eq!(rgb(&block, 1, 0), [104, 53, 138]);
+        assert_eq!(rgb(&block, 2, 0), [66, 83, 100]);
+        assert_eq!(rgb(&block, 3, 0), [2, 19, 36]);
+    }
+
+    #[test]
+    fn etc2_planar() {
+        // Blue underflows: 2 - 4. Red ramps from 32 to 63 across, green and
+        // blue are flat.
+        let (ro, go, bo) = (32u64, 64u64, 16u64);
+        let (rh, gh, bh) = (63u64, 64u64, 16u64);
+        let (rv, gv, bv) = (32u64, 64u64, 16u64);
+        let value = (ro << 57) | ((go >> 6) << 56) | ((go & 0x3f) << 49) | ((bo >> 5) << 48) | (((bo >> 3) & 3) << 43)
+            | (1 << 42)
+            | ((bo & 7) << 39)
+            | ((rh >> 1) << 34)
+            | (1 << 33)
+            | ((rh & 1) << 32)
+            | (gh << 25)
+            | (bh << 19)
+            | (rv << 13)
+            | (gv << 6)
+            | bv;
+        let block = etc2(&value.to_be_bytes(), false);
+        for y in 0..4 {
+            let row = (0..4).map(|x| texel(&block, x, y)).collect::<Vec<_>>();
+            assert_eq!(row, [[130, 129, 65, 255], [161, 129, 65, 255], [193, 129, 65, 255], [224, 129, 65, 255]]);
+        }
+    }
+
+    #[test]
+    fn etc2_punch_through() {
+        // A differential block of gray 132 without the opaque bit.
+        let value: u64 = (16 << 59) | (16 << 51) | (16 << 43) | etc_index(0, 0, 2) | etc_index(2, 0, 1) | etc_index(3, 0, 3);
+        let block = etc2(&value.to_be_bytes(), true);
+        assert_eq!(texel(&block, 0, 0), TRANSPARENT);
+        assert_eq!(texel(&block, 1, 0), [132, 132, 132, 255]);
+        assert_eq!(texel(&block, 2, 0), [140, 140, 140, 255]);
+        assert_eq!(texel(&block, 3, 0), [124, 124, 124, 255]);
+
+        // With it set, index 0 keeps its modifier and index 2 is opaque.
+        let block = etc2(&(value | (1 << 33)).to_be_bytes(), true);
+        assert_eq!(texel(&block, 0, 0), [130, 130, 130, 255]);
+        assert_eq!(texel(&block, 1, 0), [134, 134, 134, 255]);
+    }
+
+    #[test]
+    fn eac_alpha() {
+        // Base 128, multiplier 2, table 13; texels numbered down the columns.
+        let alpha: u64 = (128 << 56) | (2 << 52) | (13 << 48) | (7 << 45) | (3 << 42) | (4 << 33);
+        let mut data = [0; 16];
+        data[..8].copy_from_slice(&alpha.to_be_bytes());
+        let block = etc2_eac(&data);
+        assert_eq!(texel(&block, 0, 0)[3], 146);
+        assert_eq!(texel(&block, 0, 1)[3], 108);
+        assert_eq!(texel(&block, 1, 0)[3], 128);
+        assert_eq!(texel(&block, 3, 3)[3], 126);
+        // An all-zero color block is gray 2 from the small positive modifier.
+        assert_eq!(rgb(&block, 0, 0), [2, 2, 2]);
+
+        // Clamped to 255.
+        let alpha: u64 = (250 << 56) | (15 << 52) | (7 << 45);
+        data[..8].copy_from_slice(&alpha.to_be_bytes());
+        assert_eq!(texel(&etc2_eac(&data), 0, 0)[3], 255);
+    }
+
+    #[test]
+    fn decompress_crops_partial_blocks() {
+        let extent = vk::Extent2D { width: 5, height: 3 };
+        let red = [0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0];
+        let data = [red, [0; 8]].concat();
+        let texels = decompress(vk::Format::BC1_RGBA_UNORM_BLOCK, extent, &data).unwrap();
+        assert_eq!(texels.len(), 5 * 3 * 4);
+        assert_eq!(texels[..4], [255, 0, 0, 255]);
+        assert_eq!(texels[4 * 4..5 * 4], OPAQUE_BLACK);
+        assert_eq!(texels[(2 * 5 + 3) * 4..(2 * 5 + 4) * 4], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn decompress_rejects_truncated_data() {
+        let extent = vk::Extent2D { width: 8, height: 4 };
+        assert!(decompress(vk::Format::BC1_RGBA_UNORM_BLOCK, extent, &[0; 15]).is_err());
+        assert!(decompress(vk::Format::BC7_UNORM_BLOCK, extent, &[0; 32]).is_err());
+    }
+
+    #[test]
+    fn checked_size_overflows() {
+        let info = block_info(vk::Format::BC1_RGBA_UNORM_BLOCK).unwrap();
+        assert_eq!(info.checked_size(vk::Extent2D { width: 5, height: 4 }, 6), Some(2 * 8 * 6));
+        assert_eq!(info.checked_size(vk::Extent2D { width: u32::MAX, height: u32::MAX }, u32::MAX), None);
+    }
+}
@@ -1,16 +1,23 @@
 use anyhow::Result;
 use vulkanalia::prelude::v1_0::*;
+use log::*;
 
 use crate::AppData;
 use crate::core;
+use crate::core::config::RenderPath;
+use crate::core::render_graph::{BufferAccess, CompiledGraph, ImageDesc, LoadOp, RenderGraph, ResourceState};
 use crate::QueueFamilyIndices;
 use crate::MAX_FRAMES_IN_FLIGHT;
 
+// Path to write the frame's render graph to, as Graphviz DOT.
+const RENDER_GRAPH_DOT: &str = "RENDER_GRAPH_DOT";
+
 pub unsafe fn create_command_pool(instance: &Instance,  device: &Device, data: &mut AppData,
 ) -> Result<()> {
     let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
     let info = vk::CommandPoolCreateInfo::builder()
-        .flags(vk::CommandPoolCreateFlags::empty())
+        // Stale command buffers are reset and re-recorded one at a time.
+        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
         .queue_family_index(indices.graphics);
 
     data.command_pool = device.create_command_pool(&info, None)?;
@@ -18,50 +25,211 @@ pub unsafe fn create_command_pool(instance: &Instance,  device: &Device, data: &
     Ok(())
 }
 
-pub unsafe fn create_command_buffers(device: &Device, data: &mut AppData,
+// One command buffer per frame in flight and swapchain image, each binding
+// that frame's uniforms, see `command_buffer`.
+pub unsafe fn create_command_buffers(instance: &Instance, device: &Device, data: &mut AppData,
 ) -> Result<()> {
+    let image_count = data.swapchain_images.len();
     let allocate_info = vk::CommandBufferAllocateInfo::builder()
         .command_pool(data.command_pool)
         .level(vk::CommandBufferLevel::PRIMARY)
-        .command_buffer_count(data.framebuffers.len() as u32);
+        .command_buffer_count((MAX_FRAMES_IN_FLIGHT * image_count) as u32);
 
     data.command_buffers = device.allocate_command_buffers(&allocate_info)?;
+    data.stale_command_buffers = vec![false; data.command_buffers.len()];
+
+    for index in 0..data.command_buffers.len() {
+        let compiled = record_command_buffer(instance, device, data, index)?;
+        data.render_graphs.push(compiled);
+    }
+    Ok(())
+}
 
-    for (i, command_buffer) in data.command_buffers.iter().enumerate() {
-        let inheritance = vk::CommandBufferInheritanceInfo::builder();
-        let info = vk::CommandBufferBeginInfo::builder()
-            .flags(vk::CommandBufferUsageFlags::empty())
-            .inheritance_info(&inheritance);
-        device.begin_command_buffer(*command_buffer, &info)?;
-
-        let render_area = vk::Rect2D::builder()
-            .offset(vk::Offset2D::default())
-            .extent(data.swapchain_extent);
-
-        let color_clear_value = vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [0.0, 0.0, 0.0, 1.0],
-            },
-        };
-
-        let color_clear_values = &[color_clear_value];
-        let info = vk::RenderPassBeginInfo::builder()
-            .render_pass(data.render_pass)
-            .framebuffer(data.framebuffers[i])
-            .render_area(render_area)
-            .clear_values(color_clear_values);
+unsafe fn record_command_buffer(instance: &Instance, device: &Device, data: &AppData, index: usize,
+) -> Result<CompiledGraph> {
+    let image_count = data.swapchain_images.len();
+    let command_buffer = data.command_buffers[index];
+    let graph = frame_graph(data, index / image_count, index % image_count);
+    let compiled = graph.compile(instance, device, data)?;
 
+    if index == 0
+        && let Some(path) = std::env::var_os(RENDER_GRAPH_DOT)
+    {
+        match std::fs::write(&path, graph.to_dot(&compiled)) {
+            Ok(()) => info!("Wrote render graph to {}.", path.to_string_lossy()),
+            Err(e) => warn!("Failed to write render graph to {}: {}", path.to_string_lossy(), e),
+        }
+    }
+
+    let inheritance = vk::CommandBufferInheritanceInfo::builder();
+    let info = vk::CommandBufferBeginInfo::builder()
+        .flags(vk::CommandBufferUsageFlags::empty())
+        .inheritance_info(&inheritance);
+    device.begin_command_buffer(command_buffer, &info)?;
+    graph.record(device, command_buffer, &compiled);
+    device.end_command_buffer(command_buffer)?;
+
+    Ok(compiled)
+}
+
+// Marks every command buffer for re-recording, for changes that only affect
+// what is recorded (e.g. push constant values) and need no new pipelines.
+pub fn invalidate_command_buffers(data: &mut AppData) {
+    data.stale_command_buffers.iter_mut().for_each(|stale| *stale = true);
+}
+
+// Re-records the command buffer `command_buffer(frame, image_index)` if it is
+// stale, once its last submission has completed.
+pub unsafe fn refresh_command_buffer(instance: &Instance, device: &Device, data: &mut AppData, frame: usize, image_index: usize,
+) -> Result<()> {
+    let index = frame * data.swapchain_images.len() + image_index;
+    if !data.stale_command_buffers[index] {
+        return Ok(());
+    }
+
+    // Last submitted by the same frame in flight.
+    device.wait_for_fences(&[data.in_flight_fences[frame]], true, u64::MAX)?;
+    device.reset_command_buffer(data.command_buffers[index], vk::CommandBufferResetFlags::empty())?;
+    let compiled = record_command_buffer(instance, device, data, index)?;
+    std::mem::replace(&mut data.render_graphs[index], compiled).destroy(device);
+    data.stale_command_buffers[index] = false;
+
+    Ok(())
+}
+
+// The command buffer frame in flight `frame` submits to render into swapchain
+// image `image_index`.
+pub fn command_buffer(data: &AppData, frame: usize, image_index: usize) -> vk::CommandBuffer {
+    data.command_buffers[frame * data.swapchain_images.len() + image_index]
+}
+
+pub unsafe fn destroy_command_buffers(device: &Device, data: &mut AppData) {
+    device.free_command_buffers(data.command_pool, &data.command_buffers);
+    data.render_graphs.drain(..).for_each(|g| g.destroy(device));
+}
+
+// Frame in flight `frame` rendering into swapchain image `image_index`.
+fn frame_graph(data: &AppData, frame: usize, image_index: usize) -> RenderGraph<'static> {
+    let mut graph = RenderGraph::new();
+    let extent = data.swapchain_extent;
+
+    let swapchain = graph.import_image(
+        "swapchain",
+        data.swapchain_images[image_index],
+        data.swapchain_image_views[image_index],
+        ImageDesc { format: data.swapchain_format, extent, samples: vk::SampleCountFlags::_1 },
+        // Chained to the acquire semaphore wait at this stage.
+        ResourceState::discard(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags::empty()),
+        Some(ResourceState::PRESENT),
+    );
+    let depth = graph.import_image(
+        "depth",
+        data.depth_image,
+        data.depth_image_view,
+        ImageDesc { format: data.depth_format, extent, samples: data.msaa_samples },
+        ResourceState::discard(
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ),
+        None,
+    );
+    if data.render_path == RenderPath::Deferred {
+        core::deferred::add_passes(&mut graph, data, frame, image_index, swapchain, depth);
+        return graph;
+    }
+
+    let vertices = graph.import_buffer("vertices", data.vertex_buffer, vk::WHOLE_SIZE, ResourceState::NONE, None);
+    let indices = graph.import_buffer("indices", data.index_buffer, vk::WHOLE_SIZE, ResourceState::NONE, None);
+    let uniforms = graph.import_buffer("uniforms", data.uniform_buffers[frame], vk::WHOLE_SIZE, ResourceState::NONE, None);
+
+    // With post-processing the scene renders into an HDR target the effects
+    // read, otherwise straight into the swapchain image.
+    let target = if core::post::is_enabled(data) {
+        graph.create_image(
+            "hdr",
+            ImageDesc { format: core::post::HDR_FORMAT, extent, samples: vk::SampleCountFlags::_1 },
+        )
+    } else {
+        swapchain
+    };
+
+    // With MSAA the scene renders into the multisampled target and resolves
+    // into the target.
+    let msaa_color = core::msaa::is_enabled(data).then(|| {
+        graph.import_image(
+            "msaa color",
+            data.color_image,
+            data.color_image_view,
+            ImageDesc { format: core::post::scene_format(data), extent, samples: data.msaa_samples },
+            ResourceState::discard(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ),
+            None,
+        )
+    });
+
+    let clear = LoadOp::ClearColor([0.0, 0.0, 0.0, 1.0]);
+    let scene = graph
+        .add_pass("scene")
+        .depth_attachment(depth, LoadOp::ClearDepth(1.0, 0), true)
+        .read_buffer(vertices, BufferAccess::Vertex)
+        .read_buffer(indices, BufferAccess::Index)
+        .read_buffer(uniforms, BufferAccess::Uniform(vk::PipelineStageFlags::VERTEX_SHADER));
+    let scene = match msaa_color {
+        Some(color) => scene.color_attachment(color, clear).resolve_attachment(target),
+        None => scene.color_attachment(target, clear),
+    };
+
+    let (pipeline, layout) = (data.pipeline, data.pipeline_layout);
+    let descriptor_set = data.descriptor_sets[frame];
+    let (bindless_set, bindless_textures) = (data.bindless.set, data.bindless_textures);
+    let (vertex_buffer, index_buffer) = (data.vertex_buffer, data.index_buffer);
+    let (objects, draws) = (data.object_transforms.clone(), data.draws.clone());
+    let debug = core::debug_view::binding(data);
+    // The debug views show the geometry alone.
+    let skybox = core::skybox::binding(data).filter(|_| debug.is_none());
+    scene.execute(move |device, command_buffer, context| unsafe {
         // 渲染过程
         // TODO: 拆分提交、分配、绘制逻辑
-        device.cmd_begin_render_pass(*command_buffer, &info, vk::SubpassContents::INLINE);
-        device.cmd_bind_pipeline(*command_buffer, vk::PipelineBindPoint::GRAPHICS, data.pipeline);
-        device.cmd_bind_vertex_buffers(*command_buffer, 0, &[data.vertex_buffer], &[0]);
-        device.cmd_bind_index_buffer(*command_buffer, data.index_buffer, 0, vk::IndexType::UINT16);
-        device.cmd_draw_indexed(*command_buffer, core::vertex::INDICES.len() as u32, 1, 0, 0, 0);
-        device.cmd_end_render_pass(*command_buffer);
-        device.end_command_buffer(*command_buffer)?;
+        core::pipeline::set_viewport(device, command_buffer, context.extent());
+        match debug {
+            Some(debug) => debug.bind(device, command_buffer, descriptor_set),
+            None => {
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    layout,
+                    0,
+                    core::model::scene_sets(bindless_textures, &[descriptor_set, bindless_set]),
+                    &[],
+                );
+            }
+        }
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer], &[0]);
+        device.cmd_bind_index_buffer(command_buffer, index_buffer, 0, vk::IndexType::UINT32);
+        for model in &objects {
+            if let Some(debug) = debug {
+                debug.push(device, command_buffer, *model);
+            }
+            for draw in &draws {
+                if debug.is_none() {
+                    draw.bind(device, command_buffer, layout, *model);
+                }
+                device.cmd_draw_indexed(command_buffer, draw.index_count, 1, draw.first_index, 0, 0);
+            }
+        }
+        if let Some(skybox) = skybox {
+            skybox.draw(device, command_buffer, descriptor_set);
+        }
+    });
+
+    if target != swapchain {
+        core::post::add_passes(&mut graph, data, frame, image_index, target, swapchain);
     }
-    Ok(())
+
+    graph
 }
 
 pub unsafe fn create_sync_objects(device: &Device, data: &mut AppData,) -> Result<()> {
@@ -1,31 +1,281 @@
-use anyhow::{Context, Result};
-use shaderc::{Compiler, ShaderKind, CompileOptions};
+use anyhow::{Context, Result, anyhow};
+use shaderc::{Compiler, ShaderKind, CompileOptions, IncludeType, ResolvedInclude};
+use shaderc::{EnvVersion, OptimizationLevel};
 use log::*;
 
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
 use crate::core;
 
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+#[derive(Clone, Debug)]
+pub struct ShaderCompileOptions {
+    pub include_dirs: Vec<PathBuf>,
+    pub defines: BTreeMap<String, Option<String>>,
+    pub optimization: OptimizationLevel,
+    pub target_env: EnvVersion,
+    pub debug_info: bool,
+}
+
+impl Default for ShaderCompileOptions {
+    fn default() -> Self {
+        Self {
+            include_dirs: vec![Path::new(core::config::PROJECT_ROOT).join(core::shaders::SHADER_SOURCE_DIR)],
+            defines: BTreeMap::new(),
+            optimization: OptimizationLevel::Zero,
+            target_env: EnvVersion::Vulkan1_0,
+            debug_info: cfg!(debug_assertions),
+        }
+    }
+}
+
+impl ShaderCompileOptions {
+    pub fn include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.defines.insert(name.to_string(), value.map(|v| v.to_string()));
+        self
+    }
+
+    pub fn optimization(mut self, optimization: OptimizationLevel) -> Self {
+        self.optimization = optimization;
+        self
+    }
+
+    pub fn target_env(mut self, target_env: EnvVersion) -> Self {
+        self.target_env = target_env;
+        self
+    }
+
+    pub fn debug_info(mut self, debug_info: bool) -> Self {
+        self.debug_info = debug_info;
+        self
+    }
+}
+
+// A set of defines selecting one variant of a shader.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Permutation(pub BTreeMap<String, Option<String>>);
+
+impl Permutation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.0.insert(name.to_string(), value.map(|v| v.to_string()));
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CompiledShader {
+    pub spirv: Vec<u32>,
+    // Every file pulled in through `#include`, in resolution order.
+    pub includes: Vec<PathBuf>,
+}
+
+pub fn shader_kind(name: &str) -> Result<ShaderKind> {
+    let extension = std::path::Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    Ok(match extension {
+        "vert" => ShaderKind::Vertex,
+        "frag" => ShaderKind::Fragment,
+        "comp" => ShaderKind::Compute,
+        "geom" => ShaderKind::Geometry,
+        "tesc" => ShaderKind::TessControl,
+        "tese" => ShaderKind::TessEvaluation,
+        _ => return Err(anyhow!("Unknown shader stage for `{}`", name)),
+    })
+}
+
 pub fn compile_shader(relative_path: &str, kind: ShaderKind) -> Result<Vec<u32>> {
-    let path = std::path::Path::new(core::config::PROJECT_ROOT).join(relative_path);
+    Ok(compile_shader_with_options(relative_path, kind, &ShaderCompileOptions::default())?.spirv)
+}
+
+pub fn compile_shader_with_options(
+    relative_path: &str,
+    kind: ShaderKind,
+    options: &ShaderCompileOptions,
+) -> Result<CompiledShader> {
+    let path = Path::new(core::config::PROJECT_ROOT).join(relative_path);
     let source = std::fs::read_to_string(&path)
         .with_context(|| format!("Failed to read file: {}", path.display()))?;
 
+    compile_cached(&source, &path, kind, options)
+}
+
+// Compiles one source once per permutation, with the permutation's defines
+// layered over `options.defines`.
+pub fn compile_permutations(
+    relative_path: &str,
+    kind: ShaderKind,
+    options: &ShaderCompileOptions,
+    permutations: &[Permutation],
+) -> Result<HashMap<Permutation, CompiledShader>> {
+    let path = Path::new(core::config::PROJECT_ROOT).join(relative_path);
+    let source = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+
+    permutations
+        .iter()
+        .map(|permutation| {
+            let mut options = options.clone();
+            options.defines.extend(permutation.0.clone());
+            let compiled = compile_cached(&source, &path, kind, &options)
+                .with_context(|| format!("Failed to compile permutation {:?}", permutation.0))?;
+            Ok((permutation.clone(), compiled))
+        })
+        .collect()
+}
+
+fn compile_cached(
+    source: &str,
+    path: &Path,
+    kind: ShaderKind,
+    options: &ShaderCompileOptions,
+) -> Result<CompiledShader> {
+    core::shader_cache::global()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .compile(source, path, kind, options)
+}
+
+// Compiles without going through the shader cache.
+pub fn compile_source(
+    source: &str,
+    path: &Path,
+    kind: ShaderKind,
+    options: &ShaderCompileOptions,
+) -> Result<CompiledShader> {
     let compiler = Compiler::new()
         .context("Failed to init Shader Compiler")?;
 
-    let mut options = CompileOptions::new().unwrap();
-    options.set_target_env(shaderc::TargetEnv::Vulkan, shaderc::EnvVersion::Vulkan1_0 as u32);
+    let includes = RefCell::new(Vec::new());
+    let mut compile_options = CompileOptions::new()
+        .context("Failed to init shader compile options")?;
+    compile_options.set_target_env(shaderc::TargetEnv::Vulkan, options.target_env as u32);
+    compile_options.set_optimization_level(options.optimization);
+    if options.debug_info {
+        compile_options.set_generate_debug_info();
+    }
+    for (name, value) in &options.defines {
+        compile_options.add_macro_definition(name, value.as_deref());
+    }
+    compile_options.set_include_callback(|requested, include_type, requester, depth| {
+        let resolved = resolve_include(requested, include_type, requester, depth, &options.include_dirs)?;
+        includes.borrow_mut().push(PathBuf::from(&resolved.resolved_name));
+        Ok(resolved)
+    });
 
     let binary_result = compiler
-        .compile_into_spirv(&source, 
-            kind, 
-            path.to_str().unwrap_or("shader_src"), 
-            "main", 
-            Some(&options)
+        .compile_into_spirv(source,
+            kind,
+            path.to_str().unwrap_or("shader_src"),
+            "main",
+            Some(&compile_options)
             )
         .with_context(|| {
             error!("Syntax error, shader file: {}", path.display());
             format!("Shader compile error. ({})", path.display())
         })?;
 
-    Ok(binary_result.as_binary().to_vec())
+    if binary_result.get_num_warnings() > 0 {
+        warn!("{}", binary_result.get_warning_messages());
+    }
+
+    let spirv = binary_result.as_binary().to_vec();
+    drop(compile_options);
+    Ok(CompiledShader { spirv, includes: includes.into_inner() })
+}
+
+// `#include "..."` is looked up next to the including file first, both forms
+// then fall back to the include directories in order.
+fn resolve_include(
+    requested: &str,
+    include_type: IncludeType,
+    requester: &str,
+    depth: usize,
+    include_dirs: &[PathBuf],
+) -> Result<ResolvedInclude, String> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(format!("Include depth limit ({}) exceeded including `{}`", MAX_INCLUDE_DEPTH, requested));
+    }
+
+    let relative = match include_type {
+        IncludeType::Relative => Path::new(requester).parent().map(|dir| dir.join(requested)),
+        IncludeType::Standard => None,
+    };
+    let path = relative
+        .into_iter()
+        .chain(include_dirs.iter().map(|dir| dir.join(requested)))
+        .find(|p| p.is_file())
+        .ok_or_else(|| format!("Cannot find include `{}` (from {})", requested, requester))?;
+
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read include {}: {}", path.display(), e))?;
+    let resolved_name = path.canonicalize().unwrap_or(path).display().to_string();
+
+    Ok(ResolvedInclude { resolved_name, content })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_util::TempDir;
+
+    fn options(dir: &Path) -> ShaderCompileOptions {
+        ShaderCompileOptions { include_dirs: vec![dir.join("include")], ..Default::default() }
+    }
+
+    const FRAGMENT: &str = "#version 450\n#include \"color.glsl\"\nlayout(location = 0) out vec4 outColor;\nvoid main() { outColor = COLOR; }\n";
+
+    #[test]
+    fn resolves_relative_includes_before_include_dirs() {
+        let dir = TempDir::new("compiler-resolve", &[("shader.frag", ""), ("color.glsl", "near"), ("include/color.glsl", "far")]);
+        let requester = dir.join("shader.frag").display().to_string();
+        let include_dirs = [dir.join("include")];
+
+        let relative = resolve_include("color.glsl", IncludeType::Relative, &requester, 1, &include_dirs).unwrap();
+        assert_eq!(relative.content, "near");
+        let standard = resolve_include("color.glsl", IncludeType::Standard, &requester, 1, &include_dirs).unwrap();
+        assert_eq!(standard.content, "far");
+
+        assert!(resolve_include("missing.glsl", IncludeType::Relative, &requester, 1, &include_dirs).is_err());
+        assert!(resolve_include("color.glsl", IncludeType::Relative, &requester, MAX_INCLUDE_DEPTH + 1, &include_dirs).is_err());
+    }
+
+    #[test]
+    fn compiles_includes_and_records_them() {
+        let dir = TempDir::new("compiler-includes", &[("include/color.glsl", "#define COLOR vec4(1.0)\n")]);
+        let compiled = compile_source(FRAGMENT, &dir.join("shader.frag"), ShaderKind::Fragment, &options(&dir)).unwrap();
+        assert_eq!(compiled.spirv[0], 0x0723_0203);
+        assert_eq!(compiled.includes, [dir.join("include/color.glsl").canonicalize().unwrap()]);
+    }
+
+    #[test]
+    fn applies_defines() {
+        let dir = TempDir::new("compiler-defines", &[("include/color.glsl", "#ifndef COLOR\n#error COLOR is not defined\n#endif\n")]);
+        let path = dir.join("shader.frag");
+        assert!(compile_source(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir)).is_err());
+        let defined = options(&dir).define("COLOR", Some("vec4(0.5)"));
+        assert!(compile_source(FRAGMENT, &path, ShaderKind::Fragment, &defined).is_ok());
+    }
+
+    #[test]
+    fn reports_errors_at_the_included_file_and_line() {
+        let dir = TempDir::new("compiler-errors", &[("include/color.glsl", "#define COLOR vec4(1.0)\n\nfloat broken = ;\n")]);
+        let error = compile_source(FRAGMENT, &dir.join("shader.frag"), ShaderKind::Fragment, &options(&dir)).unwrap_err();
+        let message = format!("{:#}", error);
+        let include = dir.join("include/color.glsl").canonicalize().unwrap();
+        assert!(message.contains(&format!("{}:3:", include.display())), "{}", message);
+    }
 }
@@ -0,0 +1,315 @@
+use anyhow::{Result, Context, anyhow};
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+use crate::AppData;
+use crate::core;
+use crate::core::reflect::{ShaderReflection, SpecializationValue};
+use crate::core::specialization::Specialization;
+
+// A compute pipeline with the layouts reflected from its shader.
+#[derive(Clone, Debug, Default)]
+pub struct ComputePipeline {
+    pub set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub workgroup_size: [u32; 3],
+}
+
+impl ComputePipeline {
+    // Loads the shader like every other in `src/shader`, so with
+    // `runtime-shaders` edited sources go through `core::compiler`.
+    pub unsafe fn from_shader(device: &Device, data: &AppData, name: &str) -> Result<Self> {
+        let binary = core::shaders::load_shader(name).with_context(|| format!("Failed to load {}", name))?;
+        Self::build(device, data, &binary).with_context(|| format!("Failed to build compute pipeline for {}", name))
+    }
+
+    pub unsafe fn build(device: &Device, data: &AppData, binary: &[u32]) -> Result<Self> {
+        Self::build_specialized(device, data, binary, &Specialization::default())
+    }
+
+    pub unsafe fn build_specialized(
+        device: &Device,
+        data: &AppData,
+        binary: &[u32],
+        specialization: &Specialization,
+    ) -> Result<Self> {
+        let reflection = core::reflect::reflect_shader(binary).context("Failed to reflect compute shader")?;
+        if reflection.stage != vk::ShaderStageFlags::COMPUTE {
+            return Err(anyhow!("Expected a compute shader, got a {:?} shader", reflection.stage));
+        }
+        specialization.validate(&reflection)?;
+        let workgroup_size = workgroup_size(&reflection, specialization);
+
+        let reflections = &[&reflection];
+        let set_layouts =
+            core::descriptor::create_descriptor_set_layouts_from_reflection(device, &data.descriptors.layout_cache, reflections, &[])?;
+        let push_constant_ranges = core::reflect::push_constant_ranges(reflections);
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let layout = device.create_pipeline_layout(&layout_info, None)?;
+
+        let bytes = std::slice::from_raw_parts(binary.as_ptr() as *const u8, binary.len() * 4);
+        let shader_module = match core::pipeline::create_shader_module(device, bytes) {
+            Ok(module) => module,
+            Err(e) => {
+                device.destroy_pipeline_layout(layout, None);
+                return Err(e);
+            }
+        };
+
+        let (entries, specialization_data) = specialization.entries();
+        let specialization_info = vk::SpecializationInfo::builder()
+            .map_entries(&entries)
+            .data(&specialization_data);
+        let stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader_module)
+            .name(b"main\0")
+            .specialization_info(&specialization_info);
+        let info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage)
+            .layout(layout);
+        let result = device.create_compute_pipelines(vk::PipelineCache::null(), &[info], None);
+
+        device.destroy_shader_module(shader_module, None);
+
+        match result {
+            Ok((pipelines, _)) => Ok(Self { set_layouts, layout, pipeline: pipelines[0], workgroup_size }),
+            Err(e) => {
+                device.destroy_pipeline_layout(layout, None);
+                Err(e.into())
+            }
+        }
+    }
+
+    // Workgroups needed for at least `invocations` invocations per dimension.
+    pub fn group_count(&self, invocations: [u32; 3]) -> [u32; 3] {
+        [0, 1, 2].map(|i| invocations[i].div_ceil(self.workgroup_size[i]))
+    }
+
+    pub unsafe fn bind(&self, device: &Device, command_buffer: vk::CommandBuffer, descriptor_sets: &[vk::DescriptorSet]) {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+        if !descriptor_sets.is_empty() {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                descriptor_sets,
+                &[],
+            );
+        }
+    }
+
+    pub unsafe fn push_constants(&self, device: &Device, command_buffer: vk::CommandBuffer, bytes: &[u8]) {
+        device.cmd_push_constants(command_buffer, self.layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    }
+
+    pub unsafe fn dispatch(&self, device: &Device, command_buffer: vk::CommandBuffer, invocations: [u32; 3]) {
+        let [x, y, z] = self.group_count(invocations);
+        device.cmd_dispatch(command_buffer, x, y, z);
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.layout, None);
+    }
+}
+
+// The workgroup size `reflection` declares, with `local_size_*_id` dimensions
+// following their specialization constants.
+fn workgroup_size(reflection: &ShaderReflection, specialization: &Specialization) -> [u32; 3] {
+    let mut workgroup_size = reflection.workgroup_size.unwrap_or_else(|| {
+        warn!("Compute shader has no workgroup size, dispatching one invocation per group.");
+        [1, 1, 1]
+    });
+    for (size, id) in workgroup_size.iter_mut().zip(reflection.workgroup_size_ids) {
+        if let Some(SpecializationValue::UInt(value)) = id.and_then(|id| specialization.get(id)) {
+            *size = value;
+        }
+    }
+    workgroup_size
+}
+
+pub unsafe fn create_storage_buffer(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    size: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+) -> Result<(vk::Buffer, vk::DeviceMemory)> {
+    core::vertex::create_buffer(
+        instance,
+        device,
+        data,
+        size,
+        vk::BufferUsageFlags::STORAGE_BUFFER | usage,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )
+}
+
+pub unsafe fn create_storage_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+) -> Result<(vk::Image, vk::DeviceMemory, vk::ImageView)> {
+    let properties = instance.get_physical_device_format_properties(data.physical_device, format);
+    if !properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE) {
+        return Err(anyhow!("{:?} does not support storage images on this device", format));
+    }
+
+    let (image, memory) = core::image::create_image(
+        instance,
+        device,
+        data,
+        extent.width,
+        extent.height,
+        1,
+        vk::SampleCountFlags::_1,
+        format,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::STORAGE | usage,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+    let view = core::image::create_image_view(device, image, format, vk::ImageAspectFlags::COLOR, 1)?;
+
+    Ok((image, memory, view))
+}
+
+pub unsafe fn write_storage_buffer(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    binding: u32,
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    range: vk::DeviceSize,
+) {
+    let info = vk::DescriptorBufferInfo::builder()
+        .buffer(buffer)
+        .offset(offset)
+        .range(range);
+    let buffer_info = &[info];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .buffer_info(buffer_info);
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+}
+
+// Storage images are accessed in the GENERAL layout.
+pub unsafe fn write_storage_image(device: &Device, descriptor_set: vk::DescriptorSet, binding: u32, view: vk::ImageView) {
+    let info = vk::DescriptorImageInfo::builder()
+        .image_layout(vk::ImageLayout::GENERAL)
+        .image_view(view);
+    let image_info = &[info];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+        .image_info(image_info);
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+}
+
+pub unsafe fn write_combined_image_sampler(
+    device: &Device,
+    descriptor_set: vk::DescriptorSet,
+    binding: u32,
+    info: vk::DescriptorImageInfo,
+) {
+    let image_info = &[info];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .image_info(image_info);
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+}
+
+// Records with `record` and runs it on the compute queue, waiting for it to
+// finish. For one-off work outside the frame, such as initializing a
+// simulation; per-frame work goes through the render graph instead. Nothing
+// is submitted when `record` fails.
+pub unsafe fn run_once(
+    device: &Device,
+    data: &AppData,
+    record: impl FnOnce(vk::CommandBuffer) -> Result<()>,
+) -> Result<()> {
+    let info = vk::CommandBufferAllocateInfo::builder()
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_pool(data.command_pool)
+        .command_buffer_count(1);
+    let command_buffer = device.allocate_command_buffers(&info)?[0];
+
+    let result = (|| -> Result<()> {
+        let info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        device.begin_command_buffer(command_buffer, &info)?;
+        record(command_buffer)?;
+        device.end_command_buffer(command_buffer)?;
+
+        let command_buffers = &[command_buffer];
+        let info = vk::SubmitInfo::builder()
+            .command_buffers(command_buffers);
+        device.queue_submit(data.compute_queue, &[info], vk::Fence::null())?;
+        device.queue_wait_idle(data.compute_queue)?;
+        Ok(())
+    })();
+
+    device.free_command_buffers(data.command_pool, &[command_buffer]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reflection(workgroup_size: Option<[u32; 3]>, workgroup_size_ids: [Option<u32>; 3]) -> ShaderReflection {
+        ShaderReflection {
+            stage: vk::ShaderStageFlags::COMPUTE,
+            entry_point: "main".into(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            descriptor_bindings: Vec::new(),
+            push_constants: Vec::new(),
+            specialization_constants: Vec::new(),
+            workgroup_size,
+            workgroup_size_ids,
+        }
+    }
+
+    #[test]
+    fn rounds_group_counts_up() {
+        let pipeline = ComputePipeline { workgroup_size: [8, 8, 1], ..Default::default() };
+        assert_eq!(pipeline.group_count([64, 64, 1]), [8, 8, 1]);
+        assert_eq!(pipeline.group_count([65, 1, 3]), [9, 1, 3]);
+        assert_eq!(pipeline.group_count([0, 0, 0]), [0, 0, 0]);
+    }
+
+    #[test]
+    fn specialization_overrides_workgroup_dimensions() {
+        let reflection = reflection(Some([16, 16, 1]), [Some(0), None, Some(2)]);
+        assert_eq!(workgroup_size(&reflection, &Specialization::new()), [16, 16, 1]);
+
+        let specialization = Specialization::new().set(0, 32u32).set(1, 4u32).set(2, 2u32);
+        // Constant 1 doesn't size the workgroup.
+        assert_eq!(workgroup_size(&reflection, &specialization), [32, 16, 2]);
+
+        // Only unsigned values are sizes.
+        assert_eq!(workgroup_size(&reflection, &Specialization::new().set(0, 1.5f32)), [16, 16, 1]);
+    }
+
+    #[test]
+    fn missing_workgroup_size_dispatches_single_invocations() {
+        let reflection = reflection(None, [None; 3]);
+        assert_eq!(workgroup_size(&reflection, &Specialization::new()), [1, 1, 1]);
+    }
+}
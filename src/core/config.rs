@@ -1 +1,67 @@
 pub const PROJECT_ROOT: &str = env!("CARGO_MANIFEST_DIR");
+
+// Pick a depth format with a stencil aspect for the depth attachment.
+pub const DEPTH_STENCIL: bool = false;
+
+// Requested MSAA sample count, clamped to what the device supports.
+pub const MSAA_SAMPLES: u32 = 4;
+
+// Shade every sample instead of once per pixel when MSAA is on.
+pub const SAMPLE_SHADING: bool = false;
+
+// Render the forward scene into an HDR target and run `core::post`'s effect
+// chain before blitting to the swapchain.
+pub const POST_PROCESSING: bool = true;
+
+// Render without render pass and framebuffer objects when the device supports
+// dynamic rendering (Vulkan 1.3 or VK_KHR_dynamic_rendering).
+pub const DYNAMIC_RENDERING: bool = true;
+
+// Make `core::bindless`'s table update-after-bind and partially bound when the
+// device supports descriptor indexing (Vulkan 1.2 or VK_EXT_descriptor_indexing).
+pub const BINDLESS: bool = true;
+
+// Sample the scene's textures from the bindless table by push constant index
+// when the device can index sampled image arrays dynamically. Otherwise each
+// draw binds its texture's combined image sampler at set 1
+// (`core::texture::TEXTURE_SET`).
+pub const BINDLESS_TEXTURES: bool = true;
+
+// The Wavefront OBJ or glTF (`.gltf`, `.glb`) model the scene draws, relative
+// to `PROJECT_ROOT`. Without one it draws a textured quad.
+pub const MODEL: Option<&str> = Some("resources/models/cube.obj");
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SkyboxSource {
+    // +X, -X, +Y, -Y, +Z, -Z, with +Y up.
+    Faces([&'static str; 6]),
+    // Converted to a cube map with `SKYBOX_FACE_SIZE` texel faces.
+    Equirect(&'static str),
+}
+
+// Drawn behind the forward scene, relative to `PROJECT_ROOT`.
+pub const SKYBOX: Option<SkyboxSource> = Some(SkyboxSource::Equirect("resources/sky.png"));
+
+pub const SKYBOX_FACE_SIZE: u32 = 512;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RenderPath {
+    #[default]
+    Forward,
+    Deferred,
+}
+
+pub const RENDER_PATH: RenderPath = RenderPath::Forward;
+
+// `--forward` or `--deferred` on the command line overrides `RENDER_PATH`.
+pub fn render_path() -> RenderPath {
+    std::env::args()
+        .skip(1)
+        .rev()
+        .find_map(|arg| match arg.as_str() {
+            "--forward" => Some(RenderPath::Forward),
+            "--deferred" => Some(RenderPath::Deferred),
+            _ => None,
+        })
+        .unwrap_or(RENDER_PATH)
+}
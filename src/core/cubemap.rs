@@ -0,0 +1,271 @@
+use anyhow::{Context, Result, anyhow};
+use vulkanalia::prelude::v1_0::*;
+
+use std::path::Path;
+
+use crate::AppData;
+use crate::core;
+use crate::core::compute::ComputePipeline;
+use crate::core::descriptor::DescriptorAllocator;
+use crate::core::texture::{ColorSpace, Pixels, Prebaked, Texture};
+
+pub const EQUIRECT_COMP: &str = "equirect.comp";
+
+// What equirectangular images are converted into. Every device can store to it
+// and sample it with linear filtering, and it keeps HDR sources intact.
+const CUBE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+// A cube map from six square PNGs of the same size, +X, -X, +Y, -Y, +Z, -Z.
+pub unsafe fn from_faces(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    paths: &[impl AsRef<Path>; 6],
+    color_space: ColorSpace,
+) -> Result<Texture> {
+    let mut faces = Vec::with_capacity(paths.len());
+    for path in paths {
+        let path = path.as_ref();
+        let pixels = core::texture::decode_png(path, color_space, false)
+            .with_context(|| format!("Failed to decode cube map face {}", path.display()))?;
+        faces.push(pixels);
+    }
+
+    let paths = paths.iter().map(|p| p.as_ref()).collect::<Vec<_>>();
+    let prebaked = stack_faces(&paths, faces)?;
+    Texture::from_prebaked(instance, device, data, &prebaked).context("Failed to upload cube map")
+}
+
+// Stacks the decoded faces into the six layers of one cube map level. Every
+// face must be square, the same size and format as the first.
+fn stack_faces(paths: &[&Path], faces: Vec<Pixels>) -> Result<Prebaked> {
+    if faces.len() != 6 {
+        return Err(anyhow!("A cube map needs 6 faces, got {}", faces.len()));
+    }
+
+    let (width, height, format) = (faces[0].width, faces[0].height, faces[0].format);
+    if width != height {
+        return Err(anyhow!("Cube map face {} is {}x{}, not square", paths[0].display(), width, height));
+    }
+    if let Some((face, path)) = faces.iter().zip(paths).find(|(f, _)| (f.width, f.height) != (width, height)) {
+        return Err(anyhow!(
+            "Cube map face {} is {}x{}; the first face is {}x{}",
+            path.display(),
+            face.width,
+            face.height,
+            width,
+            height,
+        ));
+    }
+    if let Some((face, path)) = faces.iter().zip(paths).find(|(f, _)| f.format != format) {
+        return Err(anyhow!("Cube map face {} is {:?}; the first face is {:?}", path.display(), face.format, format));
+    }
+
+    Ok(Prebaked {
+        format,
+        extent: vk::Extent2D { width, height },
+        layers: 6,
+        cube: true,
+        levels: vec![faces.into_iter().flat_map(|f| f.data).collect()],
+    })
+}
+
+// A cube map with `face_size` texel faces, converted from an equirectangular
+// PNG by `equirect.comp`.
+pub unsafe fn from_equirect(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    path: impl AsRef<Path>,
+    color_space: ColorSpace,
+    face_size: u32,
+) -> Result<Texture> {
+    let path = path.as_ref();
+    let equirect = Texture::from_png(instance, device, data, path, color_space)?;
+    let result = convert(instance, device, data, &equirect, face_size)
+        .with_context(|| format!("Failed to convert {} to a cube map", path.display()));
+    equirect.destroy(device);
+    result
+}
+
+unsafe fn convert(instance: &Instance, device: &Device, data: &AppData, equirect: &Texture, face_size: u32) -> Result<Texture> {
+    let properties = instance.get_physical_device_format_properties(data.physical_device, CUBE_FORMAT);
+    let features = vk::FormatFeatureFlags::STORAGE_IMAGE | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+    if !properties.optimal_tiling_features.contains(features) {
+        return Err(anyhow!("{:?} can't be stored to and filtered on this device", CUBE_FORMAT));
+    }
+
+    let extent = vk::Extent2D { width: face_size, height: face_size };
+    let (image, image_memory) = core::texture::create_image(
+        instance,
+        device,
+        data,
+        extent,
+        CUBE_FORMAT,
+        (1, 6),
+        vk::ImageCreateFlags::CUBE_COMPATIBLE,
+        vk::ImageUsageFlags::STORAGE,
+    )?;
+    let mut cube = Texture {
+        image,
+        image_memory,
+        extent,
+        format: CUBE_FORMAT,
+        mip_levels: 1,
+        layers: 6,
+        view_type: vk::ImageViewType::CUBE,
+        ..Default::default()
+    };
+
+    let result = (|| -> Result<()> {
+        // Storage images can't be cubes, so the shader writes the faces as layers.
+        let faces_view = core::texture::create_image_view(device, &Texture { view_type: vk::ImageViewType::_2D_ARRAY, ..cube })?;
+        let result = dispatch(device, data, equirect, &cube, faces_view);
+        device.destroy_image_view(faces_view, None);
+        result?;
+
+        cube.image_view = core::texture::create_image_view(device, &cube)?;
+        cube.sampler =
+            core::texture::create_sampler(device, data, vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE, 1)?;
+        Ok(())
+    })();
+    if let Err(e) = result {
+        cube.destroy(device);
+        return Err(e);
+    }
+
+    Ok(cube)
+}
+
+// Leaves every face in SHADER_READ_ONLY_OPTIMAL.
+unsafe fn dispatch(
+    device: &Device,
+    data: &AppData,
+    equirect: &Texture,
+    cube: &Texture,
+    faces_view: vk::ImageView,
+) -> Result<()> {
+    let pipeline = ComputePipeline::from_shader(device, data, EQUIRECT_COMP)?;
+    let mut allocator = DescriptorAllocator::default();
+
+    let result = (|| -> Result<()> {
+        let set = allocator.allocate(device, pipeline.set_layouts[0])?;
+        core::compute::write_combined_image_sampler(device, set, 0, equirect.descriptor_info());
+        core::compute::write_storage_image(device, set, 1, faces_view);
+
+        core::compute::run_once(device, data, |command_buffer| {
+            face_barrier(
+                device,
+                command_buffer,
+                cube.image,
+                (vk::ImageLayout::UNDEFINED, vk::ImageLayout::GENERAL),
+                (vk::AccessFlags::empty(), vk::AccessFlags::SHADER_WRITE),
+                (vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::COMPUTE_SHADER),
+            );
+            pipeline.bind(device, command_buffer, &[set]);
+            pipeline.dispatch(device, command_buffer, [cube.extent.width, cube.extent.height, cube.layers]);
+            face_barrier(
+                device,
+                command_buffer,
+                cube.image,
+                (vk::ImageLayout::GENERAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+                (vk::AccessFlags::SHADER_WRITE, vk::AccessFlags::SHADER_READ),
+                (vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::FRAGMENT_SHADER),
+            );
+            Ok(())
+        })
+    })();
+
+    allocator.destroy(device);
+    pipeline.destroy(device);
+    result
+}
+
+unsafe fn face_barrier(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    layouts: (vk::ImageLayout, vk::ImageLayout),
+    access: (vk::AccessFlags, vk::AccessFlags),
+    stages: (vk::PipelineStageFlags, vk::PipelineStageFlags),
+) {
+    let subresource = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(6);
+    let barrier = vk::ImageMemoryBarrier::builder()
+        .old_layout(layouts.0)
+        .new_layout(layouts.1)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource)
+        .src_access_mask(access.0)
+        .dst_access_mask(access.1);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        stages.0,
+        stages.1,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATHS: [&str; 6] = ["px.png", "nx.png", "py.png", "ny.png", "pz.png", "nz.png"];
+
+    fn face(width: u32, height: u32, format: vk::Format) -> Pixels {
+        let texel_size = if format == vk::Format::R16G16B16A16_UNORM { 8 } else { 4 };
+        Pixels { width, height, format, data: vec![0; (width * height * texel_size) as usize] }
+    }
+
+    fn error(faces: Vec<Pixels>) -> String {
+        let paths = PATHS.map(Path::new);
+        match stack_faces(&paths[..faces.len().min(6)], faces) {
+            Ok(_) => panic!("stacked invalid cube map faces"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn stacks_faces_into_layers() {
+        let faces = (0..6).map(|_| face(4, 4, vk::Format::R8G8B8A8_SRGB)).collect();
+        let prebaked = stack_faces(&PATHS.map(Path::new), faces).unwrap();
+        assert_eq!((prebaked.extent.width, prebaked.extent.height), (4, 4));
+        assert_eq!((prebaked.layers, prebaked.cube), (6, true));
+        assert_eq!(prebaked.levels.len(), 1);
+        assert_eq!(prebaked.levels[0].len(), 6 * 4 * 4 * 4);
+    }
+
+    #[test]
+    fn rejects_non_square_faces() {
+        let faces = (0..6).map(|_| face(8, 4, vk::Format::R8G8B8A8_SRGB)).collect();
+        assert_eq!(error(faces), "Cube map face px.png is 8x4, not square");
+    }
+
+    #[test]
+    fn rejects_mismatched_faces() {
+        let mut faces = (0..6).map(|_| face(4, 4, vk::Format::R8G8B8A8_SRGB)).collect::<Vec<_>>();
+        faces[3] = face(2, 2, vk::Format::R8G8B8A8_SRGB);
+        assert_eq!(error(faces), "Cube map face ny.png is 2x2; the first face is 4x4");
+
+        let mut faces = (0..6).map(|_| face(4, 4, vk::Format::R8G8B8A8_SRGB)).collect::<Vec<_>>();
+        faces[5] = face(4, 4, vk::Format::R16G16B16A16_UNORM);
+        assert!(error(faces).starts_with("Cube map face nz.png is R16G16B16A16_UNORM"));
+    }
+
+    #[test]
+    fn rejects_wrong_face_counts() {
+        for count in [0, 5] {
+            let faces = (0..count).map(|_| face(4, 4, vk::Format::R8G8B8A8_SRGB)).collect();
+            assert_eq!(error(faces), format!("A cube map needs 6 faces, got {}", count));
+        }
+    }
+}
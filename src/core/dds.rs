@@ -0,0 +1,280 @@
+use anyhow::{Result, anyhow};
+use vulkanalia::prelude::v1_0::*;
+
+use std::path::Path;
+
+use crate::core;
+use crate::core::texture::{ColorSpace, Prebaked};
+
+const MAGIC: &[u8; 4] = b"DDS ";
+
+// The magic followed by `DDS_HEADER`, and the optional `DDS_HEADER_DXT10`.
+const HEADER_SIZE: usize = 128;
+const DX10_HEADER_SIZE: usize = 20;
+
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xfc00;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+const D3D10_RESOURCE_DIMENSION_TEXTURE3D: u32 = 4;
+const D3D11_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+pub fn load(path: &Path, color_space: ColorSpace) -> Result<Prebaked> {
+    parse(&std::fs::read(path)?, color_space)
+}
+
+// Reads a DDS file, with or without the DX10 header. Legacy formats don't say
+// whether they're sRGB, so `color_space` picks; DX10 formats do.
+pub fn parse(bytes: &[u8], color_space: ColorSpace) -> Result<Prebaked> {
+    if bytes.get(..MAGIC.len()) != Some(&MAGIC[..]) {
+        return Err(anyhow!("Not a DDS file"));
+    }
+
+    let flags = read_u32(bytes, 8)?;
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let mip_map_count = read_u32(bytes, 28)?;
+    let caps2 = read_u32(bytes, 112)?;
+    if caps2 & DDSCAPS2_VOLUME != 0 {
+        return Err(anyhow!("Volume DDS textures aren't supported"));
+    }
+
+    let pixel_format_flags = read_u32(bytes, 80)?;
+    let four_cc = bytes.get(84..88).ok_or_else(|| anyhow!("DDS header is truncated"))?;
+    let (format, cube, array_size, data_offset) = if pixel_format_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+        let dxgi_format = read_u32(bytes, HEADER_SIZE)?;
+        if read_u32(bytes, HEADER_SIZE + 4)? == D3D10_RESOURCE_DIMENSION_TEXTURE3D {
+            return Err(anyhow!("Volume DDS textures aren't supported"));
+        }
+        let cube = read_u32(bytes, HEADER_SIZE + 8)? & D3D11_RESOURCE_MISC_TEXTURECUBE != 0;
+        let array_size = read_u32(bytes, HEADER_SIZE + 12)?.max(1);
+        if cube && array_size > u32::MAX / 6 {
+            return Err(anyhow!("DDS file has too many cube maps ({})", array_size));
+        }
+        (dxgi_to_vk(dxgi_format)?, cube, array_size, HEADER_SIZE + DX10_HEADER_SIZE)
+    } else {
+        if caps2 & DDSCAPS2_CUBEMAP != 0 && caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+            return Err(anyhow!("DDS cube maps without all six faces aren't supported"));
+        }
+        let format = legacy_to_vk(bytes, pixel_format_flags, four_cc, color_space)?;
+        (format, caps2 & DDSCAPS2_CUBEMAP != 0, 1, HEADER_SIZE)
+    };
+
+    let info = core::block_compression::block_info(format)
+        .ok_or_else(|| anyhow!("DDS format {:?} isn't supported", format))?;
+    if width == 0 || height == 0 {
+        return Err(anyhow!("DDS file is {}x{}", width, height));
+    }
+    let extent = vk::Extent2D { width, height };
+    let mip_levels = if flags & DDSD_MIPMAPCOUNT != 0 { mip_map_count.max(1) } else { 1 };
+    if mip_levels > core::mipmap::mip_levels(extent) {
+        return Err(anyhow!(
+            "DDS file has {} mip levels, but a {}x{} mip chain has only {}",
+            mip_levels,
+            width,
+            height,
+            core::mipmap::mip_levels(extent),
+        ));
+    }
+    let layers = array_size * if cube { 6 } else { 1 };
+
+    // Every level's layers, checked against the file before anything is
+    // allocated for them.
+    let available = bytes.len().saturating_sub(data_offset);
+    let level_sizes = (0..mip_levels)
+        .map(|level| info.checked_size(core::mipmap::level_extent(extent, level), layers))
+        .collect::<Option<Vec<_>>>()
+        .filter(|sizes| {
+            let total = sizes.iter().try_fold(0usize, |total, size| total.checked_add(*size));
+            total.is_some_and(|total| total <= available)
+        })
+        .ok_or_else(|| anyhow!("DDS file is too short for {} layers of {} mip levels", layers, mip_levels))?;
+
+    // Each layer stores its whole mip chain in turn; the upload wants each
+    // level's layers together instead.
+    let mut levels = level_sizes.into_iter().map(Vec::with_capacity).collect::<Vec<_>>();
+    let mut offset = data_offset;
+    for _ in 0..layers {
+        for (level, texels) in levels.iter_mut().enumerate() {
+            let size = info.image_size(core::mipmap::level_extent(extent, level as u32));
+            texels.extend_from_slice(&bytes[offset..offset + size]);
+            offset += size;
+        }
+    }
+
+    Ok(Prebaked { format, extent, layers, cube, levels })
+}
+
+fn legacy_to_vk(bytes: &[u8], pixel_format_flags: u32, four_cc: &[u8], color_space: ColorSpace) -> Result<vk::Format> {
+    let srgb = color_space == ColorSpace::Srgb;
+    let pick = |unorm, srgb_format| if srgb { srgb_format } else { unorm };
+    if pixel_format_flags & DDPF_FOURCC != 0 {
+        let format = match four_cc {
+            b"DXT1" => pick(vk::Format::BC1_RGBA_UNORM_BLOCK, vk::Format::BC1_RGBA_SRGB_BLOCK),
+            b"DXT2" | b"DXT3" => pick(vk::Format::BC2_UNORM_BLOCK, vk::Format::BC2_SRGB_BLOCK),
+            b"DXT4" | b"DXT5" => pick(vk::Format::BC3_UNORM_BLOCK, vk::Format::BC3_SRGB_BLOCK),
+            b"ATI1" | b"BC4U" => vk::Format::BC4_UNORM_BLOCK,
+            b"BC4S" => vk::Format::BC4_SNORM_BLOCK,
+            b"ATI2" | b"BC5U" => vk::Format::BC5_UNORM_BLOCK,
+            b"BC5S" => vk::Format::BC5_SNORM_BLOCK,
+            _ => return Err(anyhow!("DDS FourCC {:?} isn't supported", String::from_utf8_lossy(four_cc))),
+        };
+        return Ok(format);
+    }
+
+    if pixel_format_flags & DDPF_RGB != 0 && read_u32(bytes, 88)? == 32 {
+        let masks = (read_u32(bytes, 92)?, read_u32(bytes, 96)?, read_u32(bytes, 100)?);
+        match masks {
+            (0xff, 0xff00, 0xff0000) => return Ok(pick(vk::Format::R8G8B8A8_UNORM, vk::Format::R8G8B8A8_SRGB)),
+            (0xff0000, 0xff00, 0xff) => return Ok(pick(vk::Format::B8G8R8A8_UNORM, vk::Format::B8G8R8A8_SRGB)),
+            _ => {}
+        }
+    }
+
+    Err(anyhow!("DDS pixel format isn't supported; only BC1-5 and 32-bit RGBA/BGRA without a DX10 header are"))
+}
+
+fn dxgi_to_vk(dxgi_format: u32) -> Result<vk::Format> {
+    let format = match dxgi_format {
+        2 => vk::Format::R32G32B32A32_SFLOAT,
+        10 => vk::Format::R16G16B16A16_SFLOAT,
+        11 => vk::Format::R16G16B16A16_UNORM,
+        28 => vk::Format::R8G8B8A8_UNORM,
+        29 => vk::Format::R8G8B8A8_SRGB,
+        49 => vk::Format::R8G8_UNORM,
+        61 => vk::Format::R8_UNORM,
+        71 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        72 => vk::Format::BC1_RGBA_SRGB_BLOCK,
+        74 => vk::Format::BC2_UNORM_BLOCK,
+        75 => vk::Format::BC2_SRGB_BLOCK,
+        77 => vk::Format::BC3_UNORM_BLOCK,
+        78 => vk::Format::BC3_SRGB_BLOCK,
+        80 => vk::Format::BC4_UNORM_BLOCK,
+        81 => vk::Format::BC4_SNORM_BLOCK,
+        83 => vk::Format::BC5_UNORM_BLOCK,
+        84 => vk::Format::BC5_SNORM_BLOCK,
+        87 => vk::Format::B8G8R8A8_UNORM,
+        91 => vk::Format::B8G8R8A8_SRGB,
+        95 => vk::Format::BC6H_UFLOAT_BLOCK,
+        96 => vk::Format::BC6H_SFLOAT_BLOCK,
+        98 => vk::Format::BC7_UNORM_BLOCK,
+        99 => vk::Format::BC7_SRGB_BLOCK,
+        _ => return Err(anyhow!("DXGI format {} isn't supported", dxgi_format)),
+    };
+    Ok(format)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    let field = bytes.get(offset..offset + 4).ok_or_else(|| anyhow!("DDS header is truncated"))?;
+    Ok(u32::from_le_bytes(field.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 4x4 DXT1 header with the given mip count.
+    fn header(mip_map_count: u32) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[..4].copy_from_slice(MAGIC);
+        set_u32(&mut bytes, 4, 124);
+        set_u32(&mut bytes, 8, DDSD_MIPMAPCOUNT);
+        set_u32(&mut bytes, 12, 4);
+        set_u32(&mut bytes, 16, 4);
+        set_u32(&mut bytes, 28, mip_map_count);
+        set_u32(&mut bytes, 76, 32);
+        set_u32(&mut bytes, 80, DDPF_FOURCC);
+        bytes[84..88].copy_from_slice(b"DXT1");
+        bytes
+    }
+
+    // A BC1 cube map array with the DX10 header.
+    fn dx10_header(array_size: u32) -> Vec<u8> {
+        let mut bytes = header(1);
+        bytes[84..88].copy_from_slice(b"DX10");
+        for field in [71, 3, D3D11_RESOURCE_MISC_TEXTURECUBE, array_size, 0] {
+            bytes.extend_from_slice(&u32::to_le_bytes(field));
+        }
+        bytes
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        match parse(bytes, ColorSpace::Linear) {
+            Ok(_) => panic!("parsed a malformed DDS file"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_mip_chain() {
+        // Three levels of one 8-byte block each.
+        let mut bytes = header(3);
+        bytes.extend((0..24).map(|i| i as u8));
+
+        let prebaked = parse(&bytes, ColorSpace::Srgb).unwrap();
+        assert_eq!(prebaked.format, vk::Format::BC1_RGBA_SRGB_BLOCK);
+        assert_eq!((prebaked.layers, prebaked.cube), (1, false));
+        assert_eq!(prebaked.levels, [(0..8).collect::<Vec<_>>(), (8..16).collect(), (16..24).collect()]);
+    }
+
+    #[test]
+    fn regroups_cube_faces_by_level() {
+        let mut bytes = dx10_header(1);
+        // Each face stores its two levels in turn.
+        for face in 0..6u8 {
+            bytes.extend([face; 8]);
+            bytes.extend([face + 10; 8]);
+        }
+        set_u32(&mut bytes, 28, 2);
+
+        let prebaked = parse(&bytes, ColorSpace::Linear).unwrap();
+        assert_eq!(prebaked.format, vk::Format::BC1_RGBA_UNORM_BLOCK);
+        assert_eq!((prebaked.layers, prebaked.cube), (6, true));
+        assert_eq!(prebaked.levels[0].chunks(8).map(|face| face[0]).collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(prebaked.levels[1].chunks(8).map(|face| face[0]).collect::<Vec<_>>(), [10, 11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let mut bytes = header(1);
+        bytes.extend([0; 8]);
+        assert!(parse(&bytes, ColorSpace::Linear).is_ok());
+
+        assert_eq!(error(&bytes[..3]), "Not a DDS file");
+        assert_eq!(error(&bytes[..60]), "DDS header is truncated");
+        assert_eq!(error(&bytes[..bytes.len() - 1]), "DDS file is too short for 1 layers of 1 mip levels");
+        assert_eq!(error(&dx10_header(1)[..HEADER_SIZE + 8]), "DDS header is truncated");
+    }
+
+    #[test]
+    fn rejects_bad_counts() {
+        let mut valid = header(1);
+        valid.extend([0; 8]);
+
+        let mut bytes = valid.clone();
+        set_u32(&mut bytes, 16, 0);
+        assert_eq!(error(&bytes), "DDS file is 0x4");
+
+        let mut bytes = valid.clone();
+        set_u32(&mut bytes, 28, 4);
+        assert_eq!(error(&bytes), "DDS file has 4 mip levels, but a 4x4 mip chain has only 3");
+
+        let mut bytes = valid;
+        set_u32(&mut bytes, 12, u32::MAX);
+        set_u32(&mut bytes, 16, u32::MAX);
+        assert_eq!(error(&bytes), "DDS file is too short for 1 layers of 1 mip levels");
+
+        let bytes = dx10_header(u32::MAX / 6 + 1);
+        assert_eq!(error(&bytes), format!("DDS file has too many cube maps ({})", u32::MAX / 6 + 1));
+
+        // Sizes that fit in a u32 layer count but not in memory.
+        let bytes = dx10_header(u32::MAX / 6);
+        assert!(error(&bytes).starts_with("DDS file is too short"));
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::{Result, Context};
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+use crate::AppData;
+use crate::core;
+use crate::core::config::RenderPath;
+use crate::core::pipeline::{BlendMode, DepthState, PipelineState};
+use crate::core::push_constants;
+use crate::core::push_constants::PushConstants as _;
+use crate::core::specialization::{Specialization, SpecializedPipelines, StageSpecializations};
+use crate::core::vertex::Vertex;
+
+type Mat4 = cgmath::Matrix4<f32>;
+
+pub const DEBUG_VERT: &str = "debug.vert";
+pub const DEBUG_FRAG: &str = "debug.frag";
+
+// The `constant_id` of `MODE` in `debug.frag`, and the modes it switches on.
+const MODE_CONSTANT: u32 = 0;
+const MODE_VERTEX_COLOR: u32 = 0;
+const MODE_FLAT_COLOR: u32 = 1;
+const MODE_NORMALS: u32 = 2;
+const MODE_UV_CHECKER: u32 = 3;
+const MODE_DEPTH: u32 = 4;
+const MODE_OVERDRAW: u32 = 5;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    None,
+    Wireframe,
+    FlatColor,
+    Normals,
+    UvChecker,
+    Depth,
+    Overdraw,
+}
+
+impl DebugView {
+    pub const ALL: [Self; 7] = [
+        Self::None,
+        Self::Wireframe,
+        Self::FlatColor,
+        Self::Normals,
+        Self::UvChecker,
+        Self::Depth,
+        Self::Overdraw,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|v| *v == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // The branch `debug.frag` is specialized to take. The wireframe shows the
+    // interpolated vertex color, the flat view each triangle's provoking one.
+    fn shader_mode(self) -> u32 {
+        match self {
+            Self::None | Self::Wireframe => MODE_VERTEX_COLOR,
+            Self::FlatColor => MODE_FLAT_COLOR,
+            Self::Normals => MODE_NORMALS,
+            Self::UvChecker => MODE_UV_CHECKER,
+            Self::Depth => MODE_DEPTH,
+            Self::Overdraw => MODE_OVERDRAW,
+        }
+    }
+
+    // Each view has its own mode, so the cached pipelines can be keyed by it.
+    fn specializations(self) -> StageSpecializations {
+        StageSpecializations {
+            fragment: Specialization::new().set(MODE_CONSTANT, self.shader_mode()),
+            ..Default::default()
+        }
+    }
+
+    fn state(self, data: &AppData) -> PipelineState {
+        let state = PipelineState { push_constants: Some(PushConstants::range()), ..PipelineState::forward(data) };
+        match self {
+            Self::Wireframe => PipelineState { polygon_mode: vk::PolygonMode::LINE, ..state },
+            Self::FlatColor | Self::Normals | Self::UvChecker | Self::Depth => {
+                PipelineState { blend: BlendMode::Opaque, ..state }
+            }
+            // Every fragment counts, including hidden ones.
+            Self::Overdraw => PipelineState { depth: DepthState::DISABLED, blend: BlendMode::Additive, ..state },
+            Self::None => state,
+        }
+    }
+}
+
+// The object transform of `debug.vert`, then the block in `debug.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct PushConstants {
+    pub model: Mat4,
+    pub near: f32,
+    pub far: f32,
+}
+
+// SAFETY: `repr(C)` with a 64-byte matrix and two `f32`s, so no padding.
+unsafe impl push_constants::PushConstants for PushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX.union(vk::ShaderStageFlags::FRAGMENT);
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DebugViewData {
+    pub vert_shader: Vec<u32>,
+    pub frag_shader: Vec<u32>,
+    // Built for a view the first time it's selected and kept until the
+    // render targets are recreated.
+    pub pipelines: SpecializedPipelines,
+}
+
+// What the scene pass binds instead of the main pipeline.
+#[derive(Copy, Clone, Debug)]
+pub struct DebugBinding {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    // The camera's, so the depth view spans what is visible.
+    near: f32,
+    far: f32,
+}
+
+impl DebugBinding {
+    pub unsafe fn bind(&self, device: &Device, command_buffer: vk::CommandBuffer, uniform_set: vk::DescriptorSet) {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.layout,
+            0,
+            &[uniform_set],
+            &[],
+        );
+    }
+
+    // Pushed per draw, in place of the scene's `ObjectConstants`.
+    pub unsafe fn push(&self, device: &Device, command_buffer: vk::CommandBuffer, model: Mat4) {
+        let constants = PushConstants { model, near: self.near, far: self.far };
+        push_constants::push(device, command_buffer, self.layout, &constants);
+    }
+}
+
+pub fn load_shaders(data: &mut AppData) -> Result<()> {
+    data.debug.vert_shader = core::shaders::load_shader(DEBUG_VERT).context("Failed to load debug vertex shader")?;
+    data.debug.frag_shader = core::shaders::load_shader(DEBUG_FRAG).context("Failed to load debug fragment shader")?;
+
+    Ok(())
+}
+
+pub unsafe fn build_pipeline(device: &Device, data: &mut AppData, view: DebugView) -> Result<()> {
+    let mut pipelines = std::mem::take(&mut data.debug.pipelines);
+    let (vert_shader, frag_shader) = (&data.debug.vert_shader, &data.debug.frag_shader);
+    let result = pipelines
+        .get_or_build::<Vertex>(device, data, vert_shader, frag_shader, view.state(data), &view.specializations())
+        .map(|_| ())
+        .with_context(|| format!("Failed to build {:?} debug pipeline", view));
+    data.debug.pipelines = pipelines;
+    result
+}
+
+pub unsafe fn create_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    if data.debug_view != DebugView::None {
+        build_pipeline(device, data, data.debug_view)?;
+    }
+
+    Ok(())
+}
+
+pub unsafe fn destroy_pipeline(device: &Device, data: &mut AppData) {
+    data.debug.pipelines.destroy(device);
+}
+
+pub fn binding(data: &AppData) -> Option<DebugBinding> {
+    if data.debug_view == DebugView::None {
+        return None;
+    }
+
+    let pipeline = data.debug.pipelines.get(&data.debug_view.specializations())?;
+    Some(DebugBinding {
+        pipeline: pipeline.pipeline,
+        layout: pipeline.layout,
+        near: data.camera.near,
+        far: data.camera.far,
+    })
+}
+
+pub unsafe fn set_debug_view(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    view: DebugView,
+) -> Result<()> {
+    if data.render_path == RenderPath::Deferred {
+        warn!("Debug views are not supported on the deferred path.");
+        return Ok(());
+    }
+    if view == DebugView::Wireframe && !data.wireframe_supported {
+        warn!("Wireframe requested but `fillModeNonSolid` is not supported by the device.");
+        return Ok(());
+    }
+    if view == data.debug_view {
+        return Ok(());
+    }
+
+    info!("Switching debug view from {:?} to {:?}.", data.debug_view, view);
+    if view != DebugView::None {
+        build_pipeline(device, data, view)?;
+    }
+
+    device.device_wait_idle()?;
+    data.debug_view = view;
+
+    core::commands::destroy_command_buffers(device, data);
+    core::commands::create_command_buffers(instance, device, data)
+}
+
+// The view after `data.debug_view`, skipping wireframe without device support.
+pub fn next_debug_view(data: &AppData) -> DebugView {
+    let next = data.debug_view.next();
+    if next == DebugView::Wireframe && !data.wireframe_supported {
+        next.next()
+    } else {
+        next
+    }
+}
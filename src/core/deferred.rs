@@ -0,0 +1,616 @@
+use anyhow::{Result, Context, anyhow};
+use vulkanalia::prelude::v1_0::*;
+
+use std::mem::{size_of, size_of_val};
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use crate::AppData;
+use crate::MAX_FRAMES_IN_FLIGHT;
+use crate::core;
+use crate::core::pipeline::{BlendMode, DepthState, ObjectConstants, PipelineState};
+use crate::core::push_constants::PushConstants;
+use crate::core::render_graph::{BufferAccess, ImageAccess, ImageHandle, RenderGraph, ResourceState};
+use crate::core::vertex::{UniformBufferObject, Vertex};
+
+pub const GBUFFER_VERT: &str = "gbuffer.vert";
+pub const GBUFFER_FRAG: &str = "gbuffer.frag";
+pub const GBUFFER_COMBINED_FRAG: &str = "gbuffer_combined.frag";
+pub const LIGHTING_VERT: &str = "fullscreen.vert";
+pub const LIGHTING_FRAG: &str = "lighting.frag";
+
+const ALBEDO_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+const NORMAL_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const MATERIAL_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+pub const MAX_LIGHTS: usize = 256;
+const AMBIENT: f32 = 0.05;
+
+// The light buffer starts with the count and ambient term, padded to the
+// alignment of `Light` as in std430.
+const LIGHTS_OFFSET: usize = 16;
+
+// Lights are placed like the vertices, before the uniform model transform;
+// the lighting pass moves them into view space every frame.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Light {
+    // xyz: position, w: radius
+    pub position: [f32; 4],
+    // rgb: color, a: intensity
+    pub color: [f32; 4],
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GBufferImage {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DeferredPipelines {
+    pub geometry_set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub geometry_layout: vk::PipelineLayout,
+    pub geometry: vk::Pipeline,
+    pub lighting_set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub lighting_layout: vk::PipelineLayout,
+    pub lighting: vk::Pipeline,
+}
+
+impl DeferredPipelines {
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_pipeline(self.geometry, None);
+        device.destroy_pipeline_layout(self.geometry_layout, None);
+        device.destroy_pipeline(self.lighting, None);
+        device.destroy_pipeline_layout(self.lighting_layout, None);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DeferredData {
+    pub gbuffer_vert: Vec<u32>,
+    pub gbuffer_frag: Vec<u32>,
+    pub lighting_vert: Vec<u32>,
+    pub lighting_frag: Vec<u32>,
+
+    pub render_pass: vk::RenderPass,
+    pub framebuffers: Vec<vk::Framebuffer>,
+    pub albedo: GBufferImage,
+    pub normal: GBufferImage,
+    pub material: GBufferImage,
+    // Depth aspect only, as input attachments can't read depth and stencil together.
+    pub depth_input_view: vk::ImageView,
+    pub pipelines: DeferredPipelines,
+    pub descriptor_pool: vk::DescriptorPool,
+    // One per frame in flight, for its uniform and light buffers.
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+
+    // What every frame's light buffer is rewritten from.
+    pub lights: Vec<Light>,
+    // One per frame in flight, written once its fence has signaled.
+    pub light_buffers: Vec<vk::Buffer>,
+    pub light_buffers_memory: Vec<vk::DeviceMemory>,
+}
+
+// The G-buffer fragment shader for the way textures are bound.
+pub fn gbuffer_frag(data: &AppData) -> &'static str {
+    if data.bindless_textures { GBUFFER_FRAG } else { GBUFFER_COMBINED_FRAG }
+}
+
+pub fn load_shaders(data: &mut AppData) -> Result<()> {
+    let gbuffer_frag = gbuffer_frag(data);
+    let deferred = &mut data.deferred;
+    deferred.gbuffer_vert = core::shaders::load_shader(GBUFFER_VERT).context("Failed to load G-buffer vertex shader")?;
+    deferred.gbuffer_frag = core::shaders::load_shader(gbuffer_frag).context("Failed to load G-buffer fragment shader")?;
+    deferred.lighting_vert = core::shaders::load_shader(LIGHTING_VERT).context("Failed to load lighting vertex shader")?;
+    deferred.lighting_frag = core::shaders::load_shader(LIGHTING_FRAG).context("Failed to load lighting fragment shader")?;
+
+    Ok(())
+}
+
+// A grid of colored point lights above the scene.
+pub fn default_lights() -> Vec<Light> {
+    const SIZE: usize = 8;
+    (0..SIZE * SIZE)
+        .map(|i| {
+            let (x, y) = ((i % SIZE) as f32, (i / SIZE) as f32);
+            let step = 2.4 / (SIZE - 1) as f32;
+            let [r, g, b] = hue_to_rgb(i as f32 / (SIZE * SIZE) as f32);
+            Light {
+                position: [-1.2 + x * step, -1.2 + y * step, 0.6, 0.6],
+                color: [r, g, b, 1.0],
+            }
+        })
+        .collect()
+}
+
+fn hue_to_rgb(hue: f32) -> [f32; 3] {
+    let h = hue.fract() * 6.0;
+    [
+        ((h - 3.0).abs() - 1.0).clamp(0.0, 1.0),
+        (2.0 - (h - 2.0).abs()).clamp(0.0, 1.0),
+        (2.0 - (h - 4.0).abs()).clamp(0.0, 1.0),
+    ]
+}
+
+pub unsafe fn create_light_buffers(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let size = (LIGHTS_OFFSET + size_of::<Light>() * MAX_LIGHTS) as u64;
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let (light_buffer, light_buffer_memory) = core::vertex::create_buffer(
+            instance,
+            device,
+            data,
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+
+        data.deferred.light_buffers.push(light_buffer);
+        data.deferred.light_buffers_memory.push(light_buffer_memory);
+    }
+
+    Ok(())
+}
+
+// Takes effect on the next frame: each frame in flight writes the lights into
+// its own buffer before rendering, so nothing in flight has to finish first.
+pub fn set_lights(data: &mut AppData, lights: &[Light]) -> Result<()> {
+    if lights.len() > MAX_LIGHTS {
+        return Err(anyhow!("{} lights exceed the limit of {}.", lights.len(), MAX_LIGHTS));
+    }
+    data.deferred.lights = lights.to_vec();
+    Ok(())
+}
+
+// Writes the lights frame in flight `frame` reads.
+pub unsafe fn update_light_buffer(device: &Device, data: &AppData, frame: usize) -> Result<()> {
+    let lights = &data.deferred.lights;
+    let memory_handle = data.deferred.light_buffers_memory[frame];
+    let size = (LIGHTS_OFFSET + size_of_val(lights.as_slice())) as u64;
+    let memory = device.map_memory(memory_handle, 0, size, vk::MemoryMapFlags::empty())?;
+    let header = [(lights.len() as u32).to_ne_bytes(), AMBIENT.to_ne_bytes()].concat();
+    memcpy(header.as_ptr(), memory.cast(), header.len());
+    memcpy(lights.as_ptr(), memory.cast::<u8>().add(LIGHTS_OFFSET).cast(), lights.len());
+    device.unmap_memory(memory_handle);
+
+    Ok(())
+}
+
+unsafe fn destroy_light_buffers(device: &Device, data: &mut AppData) {
+    data.deferred.light_buffers.drain(..).for_each(|b| device.destroy_buffer(b, None));
+    data.deferred.light_buffers_memory.drain(..).for_each(|m| device.free_memory(m, None));
+}
+
+// Subpass 0 fills the G-buffer, subpass 1 reads it back as input attachments
+// and shades into the swapchain image. The render graph moves the swapchain
+// and depth images in and out of attachment layouts around the render pass.
+pub unsafe fn create_render_pass(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    data.depth_format = core::depth::get_depth_format(instance, data)?;
+
+    let gbuffer_attachment = |format: vk::Format| {
+        vk::AttachmentDescription::builder()
+            .format(format)
+            .samples(vk::SampleCountFlags::_1)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .build()
+    };
+
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(data.swapchain_format)
+        .samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .build();
+
+    let depth_stencil_attachment = vk::AttachmentDescription::builder()
+        .format(data.depth_format)
+        .samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::CLEAR)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        .build();
+
+    let attachments = &[
+        color_attachment,
+        gbuffer_attachment(ALBEDO_FORMAT),
+        gbuffer_attachment(NORMAL_FORMAT),
+        gbuffer_attachment(MATERIAL_FORMAT),
+        depth_stencil_attachment,
+    ];
+
+    let reference = |attachment: u32, layout: vk::ImageLayout| {
+        vk::AttachmentReference::builder()
+            .attachment(attachment)
+            .layout(layout)
+            .build()
+    };
+
+    let gbuffer_refs = (1..4)
+        .map(|i| reference(i, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+        .collect::<Vec<_>>();
+    let depth_stencil_ref = reference(4, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let geometry_subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(&gbuffer_refs)
+        .depth_stencil_attachment(&depth_stencil_ref);
+
+    let color_refs = &[reference(0, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    let input_refs = &[
+        reference(1, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        reference(2, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        reference(3, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        reference(4, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL),
+    ];
+    let lighting_subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_refs)
+        .input_attachments(input_refs);
+
+    // The G-buffer isn't tracked by the render graph, so this covers the
+    // previous frame still reading it.
+    let external_dependency = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER
+            | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+
+    let gbuffer_dependency = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(1)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::INPUT_ATTACHMENT_READ)
+        .dependency_flags(vk::DependencyFlags::BY_REGION);
+
+    let subpasses = &[geometry_subpass, lighting_subpass];
+    let dependencies = &[external_dependency, gbuffer_dependency];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+
+    data.deferred.render_pass = device.create_render_pass(&info, None)?;
+
+    Ok(())
+}
+
+pub unsafe fn create_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    let deferred = &data.deferred;
+    data.deferred.pipelines = build_pipelines(
+        device,
+        data,
+        [&deferred.gbuffer_vert, &deferred.gbuffer_frag, &deferred.lighting_vert, &deferred.lighting_frag],
+    )?;
+
+    Ok(())
+}
+
+// Shaders in the order G-buffer vertex, G-buffer fragment, lighting vertex,
+// lighting fragment.
+pub unsafe fn build_pipelines(device: &Device, data: &AppData, shaders: [&[u32]; 4]) -> Result<DeferredPipelines> {
+    let geometry_state = PipelineState {
+        render_pass: data.deferred.render_pass,
+        subpass: 0,
+        color_attachments: 3,
+        // Subpasses need a render pass even with dynamic rendering.
+        color_format: vk::Format::UNDEFINED,
+        depth_format: vk::Format::UNDEFINED,
+        samples: vk::SampleCountFlags::_1,
+        depth: DepthState::default(),
+        blend: BlendMode::Opaque,
+        polygon_mode: vk::PolygonMode::FILL,
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        uniforms: true,
+        bindless: data.bindless_textures,
+        textures: !data.bindless_textures,
+        push_constants: Some(ObjectConstants::range()),
+    };
+    let (geometry_set_layouts, geometry_layout, geometry) =
+        core::pipeline::build_pipeline::<Vertex>(device, data, shaders[0], shaders[1], geometry_state)
+            .context("Failed to build G-buffer pipeline")?;
+
+    let lighting_state = PipelineState {
+        subpass: 1,
+        color_attachments: 1,
+        depth: DepthState::DISABLED,
+        front_face: vk::FrontFace::CLOCKWISE,
+        uniforms: false,
+        bindless: false,
+        textures: false,
+        push_constants: None,
+        ..geometry_state
+    };
+    let lighting = core::pipeline::build_pipeline::<()>(device, data, shaders[2], shaders[3], lighting_state)
+        .context("Failed to build lighting pipeline");
+
+    match lighting {
+        Ok((lighting_set_layouts, lighting_layout, lighting)) => Ok(DeferredPipelines {
+            geometry_set_layouts,
+            geometry_layout,
+            geometry,
+            lighting_set_layouts,
+            lighting_layout,
+            lighting,
+        }),
+        Err(e) => {
+            DeferredPipelines { geometry_set_layouts, geometry_layout, geometry, ..Default::default() }.destroy(device);
+            Err(e)
+        }
+    }
+}
+
+// G-buffer images, framebuffers and the lighting descriptor sets. Needs the
+// depth objects and uniform buffers.
+pub unsafe fn create_gbuffer(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    data.deferred.albedo = create_gbuffer_image(instance, device, data, ALBEDO_FORMAT)?;
+    data.deferred.normal = create_gbuffer_image(instance, device, data, NORMAL_FORMAT)?;
+    data.deferred.material = create_gbuffer_image(instance, device, data, MATERIAL_FORMAT)?;
+    data.deferred.depth_input_view = core::image::create_image_view(
+        device,
+        data.depth_image,
+        data.depth_format,
+        vk::ImageAspectFlags::DEPTH,
+        1,
+    )?;
+
+    let deferred = &data.deferred;
+    data.deferred.framebuffers = data
+        .swapchain_image_views
+        .iter()
+        .map(|i| {
+            let attachments = &[
+                *i,
+                deferred.albedo.view,
+                deferred.normal.view,
+                deferred.material.view,
+                data.depth_image_view,
+            ];
+            let create_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(deferred.render_pass)
+                .attachments(attachments)
+                .width(data.swapchain_extent.width)
+                .height(data.swapchain_extent.height)
+                .layers(1);
+
+            device.create_framebuffer(&create_info, None)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    create_descriptor_sets(device, data)
+}
+
+unsafe fn create_gbuffer_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    format: vk::Format,
+) -> Result<GBufferImage> {
+    let (image, memory) = core::image::create_image(
+        instance,
+        device,
+        data,
+        data.swapchain_extent.width,
+        data.swapchain_extent.height,
+        1,
+        vk::SampleCountFlags::_1,
+        format,
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | vk::ImageUsageFlags::INPUT_ATTACHMENT
+            | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+    let view = core::image::create_image_view(device, image, format, vk::ImageAspectFlags::COLOR, 1)?;
+
+    Ok(GBufferImage { image, memory, view })
+}
+
+unsafe fn create_descriptor_sets(device: &Device, data: &mut AppData) -> Result<()> {
+    let count = MAX_FRAMES_IN_FLIGHT as u32;
+    let pool_sizes = &[
+        vk::DescriptorPoolSize::builder()
+            .type_(vk::DescriptorType::INPUT_ATTACHMENT)
+            .descriptor_count(4 * count),
+        vk::DescriptorPoolSize::builder()
+            .type_(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(count),
+        vk::DescriptorPoolSize::builder()
+            .type_(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(count),
+    ];
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(pool_sizes)
+        .max_sets(count);
+    data.deferred.descriptor_pool = device.create_descriptor_pool(&info, None)?;
+
+    let layouts = vec![data.deferred.pipelines.lighting_set_layouts[0]; count as usize];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.deferred.descriptor_pool)
+        .set_layouts(&layouts);
+    data.deferred.descriptor_sets = device.allocate_descriptor_sets(&info)?;
+
+    for (i, descriptor_set) in data.deferred.descriptor_sets.iter().enumerate() {
+        write_descriptor_set(device, &data.deferred, *descriptor_set, data.deferred.light_buffers[i], data.uniform_buffers[i]);
+    }
+
+    Ok(())
+}
+
+unsafe fn write_descriptor_set(
+    device: &Device,
+    deferred: &DeferredData,
+    descriptor_set: vk::DescriptorSet,
+    light_buffer: vk::Buffer,
+    uniform_buffer: vk::Buffer,
+) {
+    let input = |view: vk::ImageView, layout: vk::ImageLayout| {
+        [vk::DescriptorImageInfo::builder()
+            .image_view(view)
+            .image_layout(layout)
+            .build()]
+    };
+    let inputs = [
+        input(deferred.albedo.view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        input(deferred.normal.view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        input(deferred.material.view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        input(deferred.depth_input_view, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL),
+    ];
+    let lights = &[vk::DescriptorBufferInfo::builder()
+        .buffer(light_buffer)
+        .offset(0)
+        .range(vk::WHOLE_SIZE)];
+    let uniforms = &[vk::DescriptorBufferInfo::builder()
+        .buffer(uniform_buffer)
+        .offset(0)
+        .range(size_of::<UniformBufferObject>() as u64)];
+
+    let mut writes = inputs
+        .iter()
+        .enumerate()
+        .map(|(binding, info)| {
+            vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(binding as u32)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                .image_info(info)
+        })
+        .collect::<Vec<_>>();
+    writes.push(
+        vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(4)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(lights),
+    );
+    writes.push(
+        vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(5)
+            .dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .buffer_info(uniforms),
+    );
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+}
+
+pub unsafe fn destroy_render_targets(device: &Device, data: &mut AppData) {
+    let deferred = &mut data.deferred;
+    device.destroy_descriptor_pool(deferred.descriptor_pool, None);
+    deferred.framebuffers.drain(..).for_each(|f| device.destroy_framebuffer(f, None));
+    device.destroy_image_view(deferred.depth_input_view, None);
+    for image in [deferred.albedo, deferred.normal, deferred.material] {
+        device.destroy_image_view(image.view, None);
+        device.free_memory(image.memory, None);
+        device.destroy_image(image.image, None);
+    }
+    deferred.pipelines.destroy(device);
+    device.destroy_render_pass(deferred.render_pass, None);
+    destroy_light_buffers(device, data);
+}
+
+// The whole deferred render pass is one graph pass, since the graph only
+// builds single-subpass render passes itself.
+pub fn add_passes(
+    graph: &mut RenderGraph,
+    data: &AppData,
+    frame: usize,
+    image_index: usize,
+    color: ImageHandle,
+    depth: ImageHandle,
+) {
+    let vertices = graph.import_buffer("vertices", data.vertex_buffer, vk::WHOLE_SIZE, ResourceState::NONE, None);
+    let indices = graph.import_buffer("indices", data.index_buffer, vk::WHOLE_SIZE, ResourceState::NONE, None);
+    let uniforms = graph.import_buffer("uniforms", data.uniform_buffers[frame], vk::WHOLE_SIZE, ResourceState::NONE, None);
+    let lights = graph.import_buffer("lights", data.deferred.light_buffers[frame], vk::WHOLE_SIZE, ResourceState::NONE, None);
+
+    let deferred = &data.deferred;
+    let (render_pass, framebuffer, extent) = (deferred.render_pass, deferred.framebuffers[image_index], data.swapchain_extent);
+    let (geometry, lighting) = (deferred.pipelines.geometry, deferred.pipelines.lighting);
+    let (geometry_layout, uniform_set) = (deferred.pipelines.geometry_layout, data.descriptor_sets[frame]);
+    let (bindless_set, bindless_textures) = (data.bindless.set, data.bindless_textures);
+    let (objects, draws) = (data.object_transforms.clone(), data.draws.clone());
+    let (lighting_layout, descriptor_set) = (deferred.pipelines.lighting_layout, deferred.descriptor_sets[frame]);
+    let (vertex_buffer, index_buffer) = (data.vertex_buffer, data.index_buffer);
+
+    graph
+        .add_pass("deferred")
+        .write_image(color, ImageAccess::ColorAttachment)
+        .write_image(depth, ImageAccess::DepthAttachment)
+        .read_buffer(vertices, BufferAccess::Vertex)
+        .read_buffer(indices, BufferAccess::Index)
+        .read_buffer(uniforms, BufferAccess::Uniform(vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER))
+        .read_buffer(lights, BufferAccess::StorageRead(vk::PipelineStageFlags::FRAGMENT_SHADER))
+        .execute(move |device, command_buffer, _| unsafe {
+            let color_clear = vk::ClearValue { color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] } };
+            let depth_clear = vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
+            };
+            let clear_values = &[color_clear, color_clear, color_clear, color_clear, depth_clear];
+            let render_area = vk::Rect2D::builder()
+                .offset(vk::Offset2D::default())
+                .extent(extent);
+            let info = vk::RenderPassBeginInfo::builder()
+                .render_pass(render_pass)
+                .framebuffer(framebuffer)
+                .render_area(render_area)
+                .clear_values(clear_values);
+
+            device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+            core::pipeline::set_viewport(device, command_buffer, extent);
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, geometry);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                geometry_layout,
+                0,
+                core::model::scene_sets(bindless_textures, &[uniform_set, bindless_set]),
+                &[],
+            );
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, index_buffer, 0, vk::IndexType::UINT32);
+            for model in &objects {
+                for draw in &draws {
+                    draw.bind(device, command_buffer, geometry_layout, *model);
+                    device.cmd_draw_indexed(command_buffer, draw.index_count, 1, draw.first_index, 0, 0);
+                }
+            }
+
+            device.cmd_next_subpass(command_buffer, vk::SubpassContents::INLINE);
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, lighting);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                lighting_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            device.cmd_end_render_pass(command_buffer);
+        });
+}
@@ -1,18 +1,460 @@
 use anyhow::Result;
 use vulkanalia::prelude::v1_0::*;
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem::size_of;
+
 use crate::AppData;
+use crate::MAX_FRAMES_IN_FLIGHT;
 use crate::core;
+use crate::core::reflect::ShaderReflection;
+use crate::core::vertex::UniformBufferObject;
+
+// Set 0 of the scene shaders. Pipelines built with `PipelineState::uniforms`
+// are checked against it, and their reflected layouts are then identical and
+// so compatible with the sets allocated from `data.descriptor_set_layout`.
+pub fn uniform_bindings() -> [vk::DescriptorSetLayoutBinding; 1] {
+    let ubo_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .build();
+
+    [ubo_binding]
+}
+
+// Descriptors per set, relative to the number of sets a pool is sized for.
+pub const DEFAULT_POOL_RATIOS: &[(vk::DescriptorType, f32)] = &[
+    (vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 2.0),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (vk::DescriptorType::SAMPLED_IMAGE, 1.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 1.0),
+    (vk::DescriptorType::SAMPLER, 1.0),
+    (vk::DescriptorType::INPUT_ATTACHMENT, 1.0),
+];
+
+const INITIAL_POOL_SETS: u32 = 16;
+const MAX_POOL_SETS: u32 = 4096;
+
+// Allocates sets of any layout, creating another pool whenever the current one
+// runs out or is too fragmented. Each new pool is twice the size of the last.
+// Sets are never freed one by one; `reset` returns all of them at once and
+// keeps the pools for reuse.
+#[derive(Clone, Debug)]
+pub struct DescriptorAllocator {
+    ratios: Vec<(vk::DescriptorType, f32)>,
+    sets_per_pool: u32,
+    current: Option<vk::DescriptorPool>,
+    // Full pools, reset with the current one.
+    used: Vec<vk::DescriptorPool>,
+    // Reset pools, used before creating new ones.
+    free: Vec<vk::DescriptorPool>,
+}
+
+impl Default for DescriptorAllocator {
+    fn default() -> Self {
+        Self::new(DEFAULT_POOL_RATIOS)
+    }
+}
+
+impl DescriptorAllocator {
+    pub fn new(ratios: &[(vk::DescriptorType, f32)]) -> Self {
+        Self {
+            ratios: ratios.to_vec(),
+            sets_per_pool: INITIAL_POOL_SETS,
+            current: None,
+            used: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub unsafe fn allocate(&mut self, device: &Device, layout: vk::DescriptorSetLayout) -> Result<vk::DescriptorSet> {
+        let pool = match self.current {
+            Some(pool) => pool,
+            None => self.next_pool(device)?,
+        };
+
+        match allocate_from(device, pool, layout) {
+            Err(vk::ErrorCode::OUT_OF_POOL_MEMORY | vk::ErrorCode::FRAGMENTED_POOL) => {
+                self.used.push(pool);
+                self.current = None;
+                let pool = self.next_pool(device)?;
+                Ok(allocate_from(device, pool, layout)?)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    // `vkResetDescriptorPool` always succeeds.
+    pub unsafe fn reset(&mut self, device: &Device) {
+        for pool in self.used.drain(..).chain(self.current.take()) {
+            let _ = device.reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty());
+            self.free.push(pool);
+        }
+    }
+
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        self.used
+            .drain(..)
+            .chain(self.free.drain(..))
+            .chain(self.current.take())
+            .for_each(|p| device.destroy_descriptor_pool(p, None));
+        self.sets_per_pool = INITIAL_POOL_SETS;
+    }
+
+    unsafe fn next_pool(&mut self, device: &Device) -> Result<vk::DescriptorPool> {
+        let pool = match self.free.pop() {
+            Some(pool) => pool,
+            None => {
+                let pool = create_pool(device, self.sets_per_pool, &self.ratios)?;
+                self.sets_per_pool = next_pool_sets(self.sets_per_pool);
+                pool
+            }
+        };
+        self.current = Some(pool);
+        Ok(pool)
+    }
+}
+
+// Each new pool doubles the sets, up to `MAX_POOL_SETS`.
+fn next_pool_sets(sets_per_pool: u32) -> u32 {
+    (sets_per_pool * 2).min(MAX_POOL_SETS)
+}
+
+// At least one descriptor of each type, so every pool can hold any layout.
+fn pool_sizes(max_sets: u32, ratios: &[(vk::DescriptorType, f32)]) -> Vec<vk::DescriptorPoolSize> {
+    ratios
+        .iter()
+        .map(|(type_, ratio)| {
+            vk::DescriptorPoolSize::builder()
+                .type_(*type_)
+                .descriptor_count(((max_sets as f32 * ratio) as u32).max(1))
+                .build()
+        })
+        .collect()
+}
+
+unsafe fn create_pool(
+    device: &Device,
+    max_sets: u32,
+    ratios: &[(vk::DescriptorType, f32)],
+) -> Result<vk::DescriptorPool> {
+    let pool_sizes = pool_sizes(max_sets, ratios);
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(&pool_sizes)
+        .max_sets(max_sets);
+    Ok(device.create_descriptor_pool(&info, None)?)
+}
+
+unsafe fn allocate_from(
+    device: &Device,
+    pool: vk::DescriptorPool,
+    layout: vk::DescriptorSetLayout,
+) -> Result<vk::DescriptorSet, vk::ErrorCode> {
+    let layouts = &[layout];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(pool)
+        .set_layouts(layouts);
+    Ok(device.allocate_descriptor_sets(&info)?[0])
+}
+
+// Identifies a layout by its bindings, in binding order.
+type LayoutKey = Vec<(u32, vk::DescriptorType, u32, vk::ShaderStageFlags)>;
+
+fn layout_key(bindings: &[vk::DescriptorSetLayoutBinding]) -> LayoutKey {
+    let mut key = bindings
+        .iter()
+        .map(|b| (b.binding, b.descriptor_type, b.descriptor_count, b.stage_flags))
+        .collect::<LayoutKey>();
+    key.sort_by_key(|b| b.0);
+    key
+}
+
+// Creates each distinct layout once. Layouts from the cache are owned by it
+// and destroyed with it, so callers must not destroy them. It only needs a
+// shared borrow, so pipeline builders reading `AppData` can use it too.
+#[derive(Clone, Debug, Default)]
+pub struct DescriptorLayoutCache {
+    layouts: RefCell<HashMap<LayoutKey, vk::DescriptorSetLayout>>,
+}
+
+impl DescriptorLayoutCache {
+    // Immutable samplers are not part of the key and not supported.
+    pub unsafe fn get(
+        &self,
+        device: &Device,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> Result<vk::DescriptorSetLayout> {
+        let key = layout_key(bindings);
+        if let Some(layout) = self.layouts.borrow().get(&key) {
+            return Ok(*layout);
+        }
+
+        let info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .bindings(bindings);
+        let layout = device.create_descriptor_set_layout(&info, None)?;
+        self.layouts.borrow_mut().insert(key, layout);
+        Ok(layout)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layouts.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layouts.borrow().is_empty()
+    }
+
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        self.layouts.get_mut().drain().for_each(|(_, l)| device.destroy_descriptor_set_layout(l, None));
+    }
+}
 
+enum DescriptorInfo {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
+// Collects bindings and what they point at, then gets the layout from the
+// cache, allocates a set and writes it in one go:
+//
+//     let (set, layout) = DescriptorBuilder::new(&mut cache, &mut allocator)
+//         .bind_buffer(0, vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX, info)
+//         .build(device)?;
+pub struct DescriptorBuilder<'a> {
+    cache: &'a mut DescriptorLayoutCache,
+    allocator: &'a mut DescriptorAllocator,
+    bindings: Vec<vk::DescriptorSetLayoutBinding>,
+    infos: Vec<DescriptorInfo>,
+}
+
+impl<'a> DescriptorBuilder<'a> {
+    pub fn new(cache: &'a mut DescriptorLayoutCache, allocator: &'a mut DescriptorAllocator) -> Self {
+        Self { cache, allocator, bindings: Vec::new(), infos: Vec::new() }
+    }
+
+    pub fn bind_buffer(
+        self,
+        binding: u32,
+        type_: vk::DescriptorType,
+        stages: vk::ShaderStageFlags,
+        info: vk::DescriptorBufferInfo,
+    ) -> Self {
+        self.bind(binding, type_, stages, DescriptorInfo::Buffer(info))
+    }
+
+    pub fn bind_image(
+        self,
+        binding: u32,
+        type_: vk::DescriptorType,
+        stages: vk::ShaderStageFlags,
+        info: vk::DescriptorImageInfo,
+    ) -> Self {
+        self.bind(binding, type_, stages, DescriptorInfo::Image(info))
+    }
+
+    fn bind(mut self, binding: u32, type_: vk::DescriptorType, stages: vk::ShaderStageFlags, info: DescriptorInfo) -> Self {
+        self.bindings.push(
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(type_)
+                .descriptor_count(1)
+                .stage_flags(stages)
+                .build(),
+        );
+        self.infos.push(info);
+        self
+    }
+
+    pub unsafe fn build(self, device: &Device) -> Result<(vk::DescriptorSet, vk::DescriptorSetLayout)> {
+        let layout = self.cache.get(device, &self.bindings)?;
+        let set = self.allocator.allocate(device, layout)?;
+
+        let buffer_infos = self
+            .infos
+            .iter()
+            .map(|i| match i {
+                DescriptorInfo::Buffer(info) => [*info],
+                DescriptorInfo::Image(_) => [vk::DescriptorBufferInfo::default()],
+            })
+            .collect::<Vec<_>>();
+        let image_infos = self
+            .infos
+            .iter()
+            .map(|i| match i {
+                DescriptorInfo::Image(info) => [*info],
+                DescriptorInfo::Buffer(_) => [vk::DescriptorImageInfo::default()],
+            })
+            .collect::<Vec<_>>();
+
+        let writes = self
+            .bindings
+            .iter()
+            .zip(&self.infos)
+            .enumerate()
+            .map(|(i, (binding, info))| {
+                let write = vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(binding.binding)
+                    .dst_array_element(0)
+                    .descriptor_type(binding.descriptor_type);
+                match info {
+                    DescriptorInfo::Buffer(_) => write.buffer_info(&buffer_infos[i]).build(),
+                    DescriptorInfo::Image(_) => write.image_info(&image_infos[i]).build(),
+                }
+            })
+            .collect::<Vec<_>>();
+        device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+
+        Ok((set, layout))
+    }
+}
+
+// The layout cache, an allocator for sets that live until shutdown, and one
+// per frame in flight for the sets that are rebuilt with the swapchain.
+#[derive(Clone, Debug, Default)]
+pub struct Descriptors {
+    pub layout_cache: DescriptorLayoutCache,
+    pub allocator: DescriptorAllocator,
+    pub frame_allocators: Vec<DescriptorAllocator>,
+}
+
+impl Descriptors {
+    // Grows to one allocator per frame in flight.
+    pub fn reserve_frames(&mut self, frame_count: usize) {
+        if self.frame_allocators.len() < frame_count {
+            self.frame_allocators.resize_with(frame_count, DescriptorAllocator::default);
+        }
+    }
+
+    pub unsafe fn reset_frames(&mut self, device: &Device) {
+        self.frame_allocators.iter_mut().for_each(|a| a.reset(device));
+    }
+
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        self.allocator.destroy(device);
+        self.frame_allocators.drain(..).for_each(|mut a| a.destroy(device));
+        self.layout_cache.destroy(device);
+    }
+}
+
+// The layout is owned by `data.descriptors.layout_cache`.
 pub unsafe fn create_descriptor_set_layout(
     device: &Device,
     data: &mut AppData,
 ) -> Result<()> {
-    let ubo_binding = vk::DescriptorSetLayoutBinding::builder()
-    .binding(0)
-    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-    .descriptor_count(1)
-    .stage_flags(vk::ShaderStageFlags::VERTEX);
+    data.descriptor_set_layout = data.descriptors.layout_cache.get(device, &uniform_bindings())?;
 
     Ok(())
 }
+
+// Destroys every cached layout and every pool.
+pub unsafe fn destroy_descriptors(device: &Device, data: &mut AppData) {
+    data.descriptors.destroy(device);
+    data.descriptor_set_layout = vk::DescriptorSetLayout::null();
+}
+
+// One set per swapchain image, pointing at that image's uniform buffer and
+// allocated from that image's allocator.
+pub unsafe fn create_descriptor_sets(device: &Device, data: &mut AppData) -> Result<()> {
+    data.descriptors.reserve_frames(MAX_FRAMES_IN_FLIGHT);
+    let Descriptors { layout_cache, frame_allocators, .. } = &mut data.descriptors;
+    for (allocator, uniform_buffer) in frame_allocators.iter_mut().zip(&data.uniform_buffers) {
+        let info = vk::DescriptorBufferInfo::builder()
+            .buffer(*uniform_buffer)
+            .offset(0)
+            .range(size_of::<UniformBufferObject>() as u64)
+            .build();
+        let (set, _) = DescriptorBuilder::new(layout_cache, allocator)
+            .bind_buffer(0, vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX, info)
+            .build(device)?;
+        data.descriptor_sets.push(set);
+    }
+
+    Ok(())
+}
+
+// Returns the sets to their pools, which are kept for the next swapchain.
+pub unsafe fn destroy_descriptor_sets(device: &Device, data: &mut AppData) {
+    data.descriptors.reset_frames(device);
+    data.descriptor_sets.clear();
+}
+
+// Gets one layout per set index used by the shaders from the cache, except for
+// the sets in `external`, which take the given layout. Gaps between used sets
+// get empty layouts so the indices line up with the pipeline layout.
+pub unsafe fn create_descriptor_set_layouts_from_reflection(
+    device: &Device,
+    cache: &DescriptorLayoutCache,
+    reflections: &[&ShaderReflection],
+    external: &[(u32, vk::DescriptorSetLayout)],
+) -> Result<Vec<vk::DescriptorSetLayout>> {
+    let external_sets = external.iter().map(|(set, _)| *set).collect::<Vec<_>>();
+    let sets = core::reflect::descriptor_set_layout_bindings(reflections, &external_sets)?;
+    let set_count = reflections
+        .iter()
+        .flat_map(|r| r.descriptor_bindings.iter().map(|b| b.set + 1))
+        .max()
+        .unwrap_or(0);
+
+    (0..set_count)
+        .map(|set| {
+            if let Some((_, layout)) = external.iter().find(|(s, _)| *s == set) {
+                return Ok(*layout);
+            }
+            let bindings = sets.get(&set).map(|b| b.as_slice()).unwrap_or(&[]);
+            cache.get(device, bindings)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(binding: u32, descriptor_type: vk::DescriptorType) -> vk::DescriptorSetLayoutBinding {
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(binding)
+            .descriptor_type(descriptor_type)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()
+    }
+
+    #[test]
+    fn pools_double_up_to_the_limit() {
+        let mut sets = INITIAL_POOL_SETS;
+        let mut sizes = vec![sets];
+        while sets < MAX_POOL_SETS {
+            sets = next_pool_sets(sets);
+            sizes.push(sets);
+        }
+        assert_eq!(sizes, [16, 32, 64, 128, 256, 512, 1024, 2048, 4096]);
+        assert_eq!(next_pool_sets(MAX_POOL_SETS), MAX_POOL_SETS);
+    }
+
+    #[test]
+    fn pool_sizes_follow_the_ratios() {
+        let ratios = [(vk::DescriptorType::UNIFORM_BUFFER, 2.0), (vk::DescriptorType::STORAGE_IMAGE, 0.01)];
+        let sizes = pool_sizes(32, &ratios).iter().map(|s| (s.type_, s.descriptor_count)).collect::<Vec<_>>();
+        // Small ratios still leave room for one descriptor.
+        assert_eq!(sizes, [(vk::DescriptorType::UNIFORM_BUFFER, 64), (vk::DescriptorType::STORAGE_IMAGE, 1)]);
+    }
+
+    #[test]
+    fn layout_keys_ignore_binding_order() {
+        let uniforms = binding(0, vk::DescriptorType::UNIFORM_BUFFER);
+        let texture = binding(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+        assert_eq!(layout_key(&[uniforms, texture]), layout_key(&[texture, uniforms]));
+        assert_eq!(layout_key(&[texture, uniforms])[0].0, 0);
+
+        let storage = binding(1, vk::DescriptorType::STORAGE_IMAGE);
+        assert_ne!(layout_key(&[uniforms, texture]), layout_key(&[uniforms, storage]));
+        let vertex = vk::DescriptorSetLayoutBinding { stage_flags: vk::ShaderStageFlags::VERTEX, ..uniforms };
+        assert_ne!(layout_key(&[uniforms]), layout_key(&[vertex]));
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+use vulkanalia::Version;
+use vulkanalia::vk::DeviceV1_3;
+use vulkanalia::vk::InstanceV1_1;
+use vulkanalia::vk::KhrDynamicRenderingExtensionDeviceCommands;
+
+use std::collections::HashSet;
+
+use crate::core;
+
+pub const CORE_VERSION: Version = Version::new(1, 3, 0);
+
+// Where `vkCmdBeginRendering` comes from on the selected device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DynamicRendering {
+    Core,
+    Khr,
+}
+
+// The extension also needs these on devices older than Vulkan 1.2, where they
+// are not core yet.
+const KHR_DEPENDENCIES: &[vk::ExtensionName] = &[
+    vk::KHR_DEPTH_STENCIL_RESOLVE_EXTENSION.name,
+    vk::KHR_CREATE_RENDERPASS2_EXTENSION.name,
+];
+
+// The API version the instance asks for: the newest the loader offers, up to
+// the one dynamic rendering became core in.
+pub unsafe fn get_instance_version(entry: &Entry) -> Result<Version> {
+    Ok(entry.version()?.min(CORE_VERSION))
+}
+
+fn device_version(instance: &Instance, properties: &vk::PhysicalDeviceProperties) -> Version {
+    Version::from(properties.api_version).min(instance.version())
+}
+
+pub unsafe fn get_support(instance: &Instance, physical_device: vk::PhysicalDevice) -> Result<Option<DynamicRendering>> {
+    // The feature query needs `vkGetPhysicalDeviceFeatures2`.
+    if !core::config::DYNAMIC_RENDERING || instance.version() < Version::new(1, 1, 0) {
+        return Ok(None);
+    }
+
+    let properties = instance.get_physical_device_properties(physical_device);
+    let version = device_version(instance, &properties);
+    if version < Version::new(1, 1, 0) {
+        return Ok(None);
+    }
+
+    let mode = if version >= CORE_VERSION {
+        DynamicRendering::Core
+    } else {
+        let extensions = instance
+            .enumerate_device_extension_properties(physical_device, None)?
+            .iter()
+            .map(|e| e.extension_name)
+            .collect::<HashSet<_>>();
+        let required = required_extensions(DynamicRendering::Khr, version);
+        if !required.iter().all(|e| extensions.contains(e)) {
+            return Ok(None);
+        }
+        DynamicRendering::Khr
+    };
+
+    let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::builder();
+    let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut dynamic_rendering_features);
+    instance.get_physical_device_features2(physical_device, &mut features);
+
+    Ok((dynamic_rendering_features.dynamic_rendering == vk::TRUE).then_some(mode))
+}
+
+// Device extensions to enable for `mode`.
+pub unsafe fn device_extensions(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    mode: Option<DynamicRendering>,
+) -> Vec<vk::ExtensionName> {
+    match mode {
+        Some(mode) => {
+            let properties = instance.get_physical_device_properties(physical_device);
+            required_extensions(mode, device_version(instance, &properties))
+        }
+        None => Vec::new(),
+    }
+}
+
+fn required_extensions(mode: DynamicRendering, version: Version) -> Vec<vk::ExtensionName> {
+    match mode {
+        DynamicRendering::Core => Vec::new(),
+        DynamicRendering::Khr if version < Version::new(1, 2, 0) => {
+            let mut extensions = vec![vk::KHR_DYNAMIC_RENDERING_EXTENSION.name];
+            extensions.extend_from_slice(KHR_DEPENDENCIES);
+            extensions
+        }
+        DynamicRendering::Khr => vec![vk::KHR_DYNAMIC_RENDERING_EXTENSION.name],
+    }
+}
+
+pub unsafe fn cmd_begin_rendering(
+    device: &Device,
+    mode: DynamicRendering,
+    command_buffer: vk::CommandBuffer,
+    info: &vk::RenderingInfo,
+) {
+    match mode {
+        DynamicRendering::Core => device.cmd_begin_rendering(command_buffer, info),
+        DynamicRendering::Khr => device.cmd_begin_rendering_khr(command_buffer, info),
+    }
+}
+
+pub unsafe fn cmd_end_rendering(device: &Device, mode: DynamicRendering, command_buffer: vk::CommandBuffer) {
+    match mode {
+        DynamicRendering::Core => device.cmd_end_rendering(command_buffer),
+        DynamicRendering::Khr => device.cmd_end_rendering_khr(command_buffer),
+    }
+}
@@ -0,0 +1,1658 @@
+use anyhow::{Context, Result, anyhow};
+use cgmath::{InnerSpace, Matrix, Matrix3, Quaternion, Rad, SquareMatrix, point3, vec2, vec3, vec4};
+use log::*;
+use serde_json::Value;
+use vulkanalia::prelude::v1_0::*;
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::AppData;
+use crate::core;
+use crate::core::texture::ColorSpace;
+use crate::core::vertex::Vertex;
+
+type Vec3 = cgmath::Vector3<f32>;
+type Mat4 = cgmath::Matrix4<f32>;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_HEADER_SIZE: usize = 12;
+const CHUNK_HEADER_SIZE: usize = 8;
+const CHUNK_JSON: u32 = 0x4e4f534a;
+const CHUNK_BIN: u32 = 0x004e4942;
+
+// Extensions the importer reads. A file requiring any other one fails to load;
+// one only using others loads as if they weren't there, with a warning.
+const SUPPORTED_EXTENSIONS: &[&str] =
+    &["KHR_lights_punctual", "KHR_materials_emissive_strength", "KHR_mesh_quantization"];
+
+// The far plane of cameras with an infinite projection.
+const INFINITE_FAR_PLANE: f32 = 1000.0;
+
+// Accessors without a buffer view are zeros but for their sparse elements, so
+// nothing in the file bounds their size.
+const MAX_ELEMENTS_WITHOUT_VIEW: usize = 1 << 24;
+
+// Primitive modes from the spec.
+const MODE_TRIANGLES: usize = 4;
+const MODE_TRIANGLE_STRIP: usize = 5;
+const MODE_TRIANGLE_FAN: usize = 6;
+
+// A glTF 2.0 scene, read from a `.gltf` file with its buffers and images or a
+// binary `.glb`. Indices between its parts are checked on load.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    pub nodes: Vec<Node>,
+    // The top-level nodes of the default scene.
+    pub roots: Vec<usize>,
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<Texture>,
+    pub images: Vec<Image>,
+    pub cameras: Vec<Camera>,
+    pub lights: Vec<Light>,
+    // Used by the file but not supported here, so left out of the scene.
+    pub unsupported_extensions: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: Option<String>,
+    pub children: Vec<usize>,
+    // Relative to the parent, and from the node to the scene's root.
+    pub local: Mat4,
+    pub world: Mat4,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub light: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    pub name: Option<String>,
+    pub primitives: Vec<Primitive>,
+}
+
+// One material's triangles. Vertex colors already include the material's base
+// color factor.
+#[derive(Clone, Debug)]
+pub struct Primitive {
+    pub mesh: core::model::Mesh,
+    pub material: Option<usize>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    Mask,
+    Blend,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextureSlot {
+    pub texture: usize,
+    // The TEXCOORD_n set; only set 0 is imported into vertices.
+    pub tex_coord: u32,
+}
+
+// A PBR metallic-roughness material.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<TextureSlot>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    // Roughness in green, metalness in blue.
+    pub metallic_roughness_texture: Option<TextureSlot>,
+    pub normal_texture: Option<TextureSlot>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureSlot>,
+    pub occlusion_strength: f32,
+    pub emissive_texture: Option<TextureSlot>,
+    // Includes `KHR_materials_emissive_strength`.
+    pub emissive_factor: [f32; 3],
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_texture: None,
+            emissive_factor: [0.0; 3],
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sampler {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    // Whether minification reads past the base level; `NEAREST` and `LINEAR`
+    // min filters don't.
+    pub mipmaps: bool,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            mipmaps: true,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+        }
+    }
+}
+
+impl Sampler {
+    pub unsafe fn create(&self, device: &Device, data: &AppData, mip_levels: u32) -> Result<vk::Sampler> {
+        let info = vk::SamplerCreateInfo::builder()
+            .mag_filter(self.mag_filter)
+            .min_filter(self.min_filter)
+            .mipmap_mode(self.mipmap_mode)
+            .address_mode_u(self.address_mode_u)
+            .address_mode_v(self.address_mode_v)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .anisotropy_enable(data.sampler_anisotropy_supported)
+            .max_anisotropy(if data.sampler_anisotropy_supported { data.max_sampler_anisotropy } else { 1.0 })
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+            .unnormalized_coordinates(false)
+            .compare_enable(false)
+            .compare_op(vk::CompareOp::ALWAYS)
+            .min_lod(0.0)
+            .max_lod(if self.mipmaps { mip_levels as f32 } else { 0.0 });
+
+        Ok(device.create_sampler(&info, None)?)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Texture {
+    pub source: usize,
+    pub sampler: Sampler,
+}
+
+#[derive(Clone, Debug)]
+pub enum Image {
+    File(PathBuf),
+    // From a buffer view or a data URI.
+    Embedded { mime_type: String, bytes: Vec<u8> },
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub name: Option<String>,
+    pub projection: Projection,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    // Without a far plane the projection is infinite; without an aspect ratio
+    // it follows the viewport's.
+    Perspective { yfov: Rad<f32>, aspect_ratio: Option<f32>, znear: f32, zfar: Option<f32> },
+    Orthographic { xmag: f32, ymag: f32, znear: f32, zfar: f32 },
+}
+
+// A `KHR_lights_punctual` light, shining down its node's -Z.
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub name: Option<String>,
+    pub kind: LightKind,
+    pub color: [f32; 3],
+    // Lux for directional lights, candela otherwise.
+    pub intensity: f32,
+    pub range: Option<f32>,
+}
+
+// A run of a flattened mesh's indices that share a material.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub indices: Range<u32>,
+    pub material: Option<usize>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot { inner_cone_angle: f32, outer_cone_angle: f32 },
+}
+
+// Reads a `.gltf` or `.glb` file, with its external buffers, and keeps the
+// paths of its external images.
+pub fn load(path: &Path) -> Result<Scene> {
+    let scene = (|| -> Result<Scene> {
+        let bytes = std::fs::read(path)?;
+        let (json, bin) = if bytes.starts_with(GLB_MAGIC) { parse_glb(&bytes)? } else { (&bytes[..], None) };
+        let text = std::str::from_utf8(json).context("The JSON isn't UTF-8")?;
+        let document = serde_json::from_str::<Value>(text.trim_start_matches('\u{feff}'))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Importer::new(&document, base, bin)?.import()
+    })()
+    .with_context(|| format!("Failed to load glTF {}", path.display()))?;
+
+    if !scene.unsupported_extensions.is_empty() {
+        warn!(
+            "{} uses unsupported extensions, loading it without them: {}",
+            path.display(),
+            scene.unsupported_extensions.join(", ")
+        );
+    }
+    Ok(scene)
+}
+
+// The JSON and BIN chunks of a binary glTF.
+fn parse_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>)> {
+    let version = read_u32(bytes, 4)?;
+    if version != 2 {
+        return Err(anyhow!("GLB version {} isn't supported", version));
+    }
+    let length = read_u32(bytes, 8)? as usize;
+    let bytes = bytes.get(..length).ok_or_else(|| anyhow!("GLB is shorter than its header says"))?;
+
+    let mut chunks = Vec::new();
+    let mut offset = GLB_HEADER_SIZE;
+    while offset < bytes.len() {
+        let chunk_length = read_u32(bytes, offset)? as usize;
+        let chunk_type = read_u32(bytes, offset + 4)?;
+        let start = offset + CHUNK_HEADER_SIZE;
+        let chunk = bytes.get(start..start + chunk_length).ok_or_else(|| anyhow!("GLB chunk is truncated"))?;
+        chunks.push((chunk_type, chunk));
+        offset = start + chunk_length;
+    }
+
+    match chunks.as_slice() {
+        [(CHUNK_JSON, json), rest @ ..] => {
+            let bin = rest.iter().find(|(t, _)| *t == CHUNK_BIN).map(|(_, chunk)| *chunk);
+            Ok((json, bin))
+        }
+        _ => Err(anyhow!("GLB doesn't start with a JSON chunk")),
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    let field = bytes.get(offset..offset + 4).ok_or_else(|| anyhow!("GLB header is truncated"))?;
+    Ok(u32::from_le_bytes(field.try_into().unwrap()))
+}
+
+impl Scene {
+    // Every node of the default scene, parents before their children.
+    pub fn scene_nodes(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        let mut stack = self.roots.iter().rev().copied().collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            order.push(node);
+            stack.extend(self.nodes[node].children.iter().rev());
+        }
+        order
+    }
+
+    // Every primitive of the default scene, placed by its node and then
+    // `transform`, as one mesh for the renderer, with the sections of its
+    // indices each material covers.
+    pub fn flatten(&self, transform: Mat4) -> (core::model::Mesh, Vec<Section>) {
+        let mut flat = core::model::Mesh::default();
+        let mut sections = Vec::<Section>::new();
+        for node in self.scene_nodes().into_iter().map(|n| &self.nodes[n]) {
+            let Some(mesh) = node.mesh else { continue };
+            let world = transform * node.world;
+            let linear = Matrix3::from_cols(world.x.truncate(), world.y.truncate(), world.z.truncate());
+            let normal_matrix = linear.invert().map_or(linear, |m| m.transpose());
+            // Mirroring transforms turn the triangles' winding around.
+            let mirrored = linear.determinant() < 0.0;
+
+            for primitive in &self.meshes[mesh].primitives {
+                let base = flat.vertices.len() as u32;
+                flat.vertices.extend(primitive.mesh.vertices.iter().map(|v| {
+                    let normal = normal_matrix * v.normal;
+                    let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { normal };
+                    Vertex { pos: (world * v.pos.extend(1.0)).truncate(), normal, ..*v }
+                }));
+                let first = flat.indices.len() as u32;
+                for triangle in primitive.mesh.indices.chunks_exact(3) {
+                    let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| base + i);
+                    flat.indices.extend(if mirrored { [a, c, b] } else { [a, b, c] });
+                }
+
+                let end = flat.indices.len() as u32;
+                match sections.last_mut() {
+                    Some(last) if last.material == primitive.material => last.indices.end = end,
+                    _ => sections.push(Section { indices: first..end, material: primitive.material }),
+                }
+            }
+        }
+        sections.retain(|s| !s.indices.is_empty());
+        (flat, sections)
+    }
+
+    // The first perspective camera in the default scene, placed by its node and
+    // then `transform`. An infinite projection gets `far_fallback` as its far
+    // plane.
+    pub fn camera(&self, transform: Mat4, far_fallback: f32) -> Option<core::uniform::Camera> {
+        self.scene_nodes().into_iter().find_map(|n| {
+            let node = &self.nodes[n];
+            let Projection::Perspective { yfov, znear, zfar, .. } = self.cameras[node.camera?].projection else {
+                return None;
+            };
+            let world = transform * node.world;
+            let eye = (world * vec4(0.0, 0.0, 0.0, 1.0)).truncate();
+            let forward = (world * vec4(0.0, 0.0, -1.0, 0.0)).truncate();
+            let up = (world * vec4(0.0, 1.0, 0.0, 0.0)).truncate();
+            Some(core::uniform::Camera {
+                eye: point3(eye.x, eye.y, eye.z),
+                target: point3(eye.x + forward.x, eye.y + forward.y, eye.z + forward.z),
+                up: up.normalize(),
+                fov: yfov.into(),
+                near: znear,
+                far: zfar.unwrap_or(far_fallback),
+            })
+        })
+    }
+
+    // The point and spot lights of the default scene for the deferred path,
+    // placed by their nodes and then `transform` like the flattened mesh. A
+    // light reaches its range, or without one as far as its intensity takes to
+    // fall to 1/256; its intensity sets nothing else. Spot lights shine all
+    // around, and directional lights are left out.
+    pub fn deferred_lights(&self, transform: Mat4) -> Vec<core::deferred::Light> {
+        self.scene_nodes()
+            .into_iter()
+            .map(|n| &self.nodes[n])
+            .filter_map(|node| {
+                let light = &self.lights[node.light?];
+                if light.kind == LightKind::Directional {
+                    return None;
+                }
+                let position = (transform * node.world * vec4(0.0, 0.0, 0.0, 1.0)).truncate();
+                let range = light.range.unwrap_or_else(|| (light.intensity.max(0.0) * 256.0).sqrt());
+                let [r, g, b] = light.color;
+                Some(core::deferred::Light {
+                    position: [position.x, position.y, position.z, range],
+                    color: [r, g, b, 1.0],
+                })
+            })
+            .collect()
+    }
+
+    // Uploads the scene for the renderer: the flattened mesh with a draw per
+    // material, sampling its base color texture (from the bindless table or
+    // its own set, see `AppData::bindless_textures`) or white without
+    // one, the first camera as `AppData::camera` and, on the deferred path,
+    // the lights. Other texture maps aren't shaded.
+    pub unsafe fn create(
+        &self,
+        instance: &Instance,
+        device: &Device,
+        data: &mut AppData,
+        transform: Mat4,
+    ) -> Result<core::model::Mesh> {
+        let (mesh, sections) = self.flatten(transform);
+        if let Some(camera) = self.camera(transform, INFINITE_FAR_PLANE) {
+            data.camera = camera;
+        }
+
+        let unshaded = self.materials.iter().any(|m| {
+            [m.metallic_roughness_texture, m.normal_texture, m.occlusion_texture, m.emissive_texture]
+                .iter()
+                .any(Option::is_some)
+        });
+        if unshaded {
+            warn!("Only glTF base color textures are shaded; the other texture maps are ignored.");
+        }
+
+        data.scene_textures = self.create_textures(instance, device, data)?;
+        let base_color = |section: &Section| section.material.and_then(|m| self.materials[m].base_color_texture);
+        if sections.iter().any(|s| base_color(s).is_none()) {
+            let white = core::texture::Pixels { width: 1, height: 1, format: vk::Format::R8G8B8A8_SRGB, data: vec![255; 4] };
+            data.scene_textures.push(core::texture::Texture::from_pixels(instance, device, data, &white)?);
+        }
+        let mip_levels = data.scene_textures.iter().map(|t| t.mip_levels).collect::<Vec<_>>();
+        let sampler_owners = self.sampler_owners(&mip_levels);
+        let mut sampler_slots = HashMap::new();
+        let mut slots = Vec::with_capacity(data.scene_textures.len());
+        for (texture, owner) in data.scene_textures.iter().zip(&sampler_owners) {
+            if data.bindless_textures {
+                let image = data.bindless.add_sampled_image(device, texture.image_view)?;
+                let sampler = match sampler_slots.get(owner) {
+                    Some(sampler) => *sampler,
+                    None => {
+                        let sampler = data.bindless.add_sampler(device, data.scene_textures[*owner].sampler)?;
+                        sampler_slots.insert(*owner, sampler);
+                        sampler
+                    }
+                };
+                slots.push((image, sampler, vk::DescriptorSet::null()));
+            } else {
+                let set = core::texture::create_texture_set(device, &mut data.descriptors, texture)?;
+                slots.push((Default::default(), Default::default(), set));
+            }
+        }
+        data.draws = sections
+            .iter()
+            .map(|section| {
+                let (texture, sampler, texture_set) =
+                    base_color(section).map_or(slots[slots.len() - 1], |slot| slots[slot.texture]);
+                core::model::Draw {
+                    first_index: section.indices.start,
+                    index_count: section.indices.len() as u32,
+                    texture,
+                    sampler,
+                    texture_set,
+                }
+            })
+            .collect();
+
+        let lit = self.scene_nodes().into_iter().filter_map(|n| self.nodes[n].light).collect::<Vec<_>>();
+        match data.render_path {
+            _ if lit.is_empty() => {}
+            core::config::RenderPath::Forward => warn!("glTF lights are only shaded on the deferred path."),
+            core::config::RenderPath::Deferred => {
+                if lit.iter().any(|l| self.lights[*l].kind == LightKind::Directional) {
+                    warn!("Directional glTF lights aren't shaded on the deferred path.");
+                }
+                let mut lights = self.deferred_lights(transform);
+                if lights.len() > core::deferred::MAX_LIGHTS {
+                    warn!("Shading {} of the glTF scene's {} lights.", core::deferred::MAX_LIGHTS, lights.len());
+                    lights.truncate(core::deferred::MAX_LIGHTS);
+                }
+                if !lights.is_empty() {
+                    core::deferred::set_lights(data, &lights)?;
+                }
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    // For each of the created textures, indexed like `textures` and followed by
+    // any extra ones with the default sampler such as white, the texture whose
+    // sampler the bindless table holds for it. Textures with the same sampler
+    // parameters share one, so the table's few sampler slots aren't used up per
+    // texture: the sampler of the one with the most mip levels, whose LOD
+    // range covers the others'.
+    fn sampler_owners(&self, mip_levels: &[u32]) -> Vec<usize> {
+        let sampler = |index: usize| self.textures.get(index).map_or(Sampler::default(), |t| t.sampler);
+        let mut owners: Vec<(Sampler, usize)> = Vec::new();
+        for (index, levels) in mip_levels.iter().enumerate() {
+            match owners.iter_mut().find(|(s, _)| *s == sampler(index)) {
+                Some((_, owner)) if *levels > mip_levels[*owner] => *owner = index,
+                Some(_) => {}
+                None => owners.push((sampler(index), index)),
+            }
+        }
+        (0..mip_levels.len())
+            .map(|index| owners.iter().find(|(s, _)| *s == sampler(index)).unwrap().1)
+            .collect()
+    }
+
+    // Uploads every texture with its sampler, indexed like `textures`. Base
+    // color and emissive textures are sRGB, the others linear.
+    pub unsafe fn create_textures(
+        &self,
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+    ) -> Result<Vec<core::texture::Texture>> {
+        let mut color_spaces = vec![ColorSpace::Linear; self.textures.len()];
+        for material in &self.materials {
+            for slot in [material.base_color_texture, material.emissive_texture].into_iter().flatten() {
+                color_spaces[slot.texture] = ColorSpace::Srgb;
+            }
+        }
+
+        let mut textures: Vec<core::texture::Texture> = Vec::with_capacity(self.textures.len());
+        for (index, (texture, color_space)) in self.textures.iter().zip(color_spaces).enumerate() {
+            let result = (|| -> Result<core::texture::Texture> {
+                let mut created = match &self.images[texture.source] {
+                    Image::File(path) => core::texture::Texture::from_file(instance, device, data, path, color_space)?,
+                    Image::Embedded { mime_type, bytes } if mime_type == "image/png" => {
+                        core::texture::Texture::from_png_bytes(instance, device, data, bytes, color_space)?
+                    }
+                    Image::Embedded { mime_type, .. } => return Err(anyhow!("{} images aren't supported", mime_type)),
+                };
+                if texture.sampler != Sampler::default() {
+                    device.destroy_sampler(created.sampler, None);
+                    created.sampler = vk::Sampler::null();
+                    match texture.sampler.create(device, data, created.mip_levels) {
+                        Ok(sampler) => created.sampler = sampler,
+                        Err(e) => {
+                            created.destroy(device);
+                            return Err(e);
+                        }
+                    }
+                }
+                Ok(created)
+            })();
+
+            match result {
+                Ok(created) => textures.push(created),
+                Err(e) => {
+                    textures.iter().for_each(|t| t.destroy(device));
+                    return Err(e.context(format!("Failed to create glTF texture {}", index)));
+                }
+            }
+        }
+
+        Ok(textures)
+    }
+}
+
+// A JSON object and where it is in the document, for error messages.
+#[derive(Clone)]
+struct Object<'a> {
+    value: &'a Value,
+    path: String,
+}
+
+impl<'a> Object<'a> {
+    fn new(value: &'a Value, path: String) -> Result<Self> {
+        match value {
+            Value::Object(_) => Ok(Self { value, path }),
+            _ => Err(anyhow!("`{}` must be an object", path)),
+        }
+    }
+
+    // The path of the member `key`.
+    fn member(&self, key: &str) -> String {
+        if self.path.is_empty() { key.to_string() } else { format!("{}.{}", self.path, key) }
+    }
+
+    fn invalid(&self, key: &str, expected: &str) -> anyhow::Error {
+        anyhow!("`{}` must be {}", self.member(key), expected)
+    }
+
+    fn index(&self, key: &str) -> Result<Option<usize>> {
+        self.value
+            .get(key)
+            .map(|v| {
+                v.as_f64()
+                    .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+                    .map(|n| n as usize)
+                    .ok_or_else(|| self.invalid(key, "a non-negative integer"))
+            })
+            .transpose()
+    }
+
+    fn missing(&self, key: &str) -> anyhow::Error {
+        anyhow!("`{}` is missing", self.member(key))
+    }
+
+    fn required_index(&self, key: &str) -> Result<usize> {
+        self.index(key)?.ok_or_else(|| self.missing(key))
+    }
+
+    // An index into another array of `len` elements.
+    fn reference(&self, key: &str, len: usize) -> Result<Option<usize>> {
+        match self.index(key)? {
+            Some(index) if index >= len => {
+                Err(anyhow!("`{}` is {}, but there are only {}", self.member(key), index, len))
+            }
+            index => Ok(index),
+        }
+    }
+
+    fn required_reference(&self, key: &str, len: usize) -> Result<usize> {
+        self.reference(key, len)?.ok_or_else(|| self.missing(key))
+    }
+
+    fn optional_number(&self, key: &str) -> Result<Option<f32>> {
+        self.value
+            .get(key)
+            .map(|v| v.as_f64().map(|n| n as f32).ok_or_else(|| self.invalid(key, "a number")))
+            .transpose()
+    }
+
+    fn number(&self, key: &str, default: f32) -> Result<f32> {
+        Ok(self.optional_number(key)?.unwrap_or(default))
+    }
+
+    fn required_number(&self, key: &str) -> Result<f32> {
+        self.optional_number(key)?.ok_or_else(|| self.missing(key))
+    }
+
+    fn numbers<const N: usize>(&self, key: &str, default: [f32; N]) -> Result<[f32; N]> {
+        let Some(value) = self.value.get(key) else { return Ok(default) };
+        let numbers = value
+            .as_array()
+            .filter(|a| a.len() == N)
+            .and_then(|a| a.iter().map(|v| v.as_f64().map(|n| n as f32)).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| self.invalid(key, &format!("an array of {} numbers", N)))?;
+        Ok(numbers.try_into().unwrap())
+    }
+
+    fn string(&self, key: &str) -> Result<Option<&'a str>> {
+        self.value.get(key).map(|v| v.as_str().ok_or_else(|| self.invalid(key, "a string"))).transpose()
+    }
+
+    fn name(&self) -> Result<Option<String>> {
+        Ok(self.string("name")?.map(str::to_string))
+    }
+
+    fn bool(&self, key: &str, default: bool) -> Result<bool> {
+        match self.value.get(key) {
+            Some(v) => v.as_bool().ok_or_else(|| self.invalid(key, "a boolean")),
+            None => Ok(default),
+        }
+    }
+
+    fn object(&self, key: &str) -> Result<Option<Object<'a>>> {
+        self.value.get(key).map(|v| Object::new(v, self.member(key))).transpose()
+    }
+
+    fn required_object(&self, key: &str) -> Result<Object<'a>> {
+        self.object(key)?.ok_or_else(|| self.missing(key))
+    }
+
+    fn array(&self, key: &str) -> Result<&'a [Value]> {
+        match self.value.get(key) {
+            Some(v) => v.as_array().map(Vec::as_slice).ok_or_else(|| self.invalid(key, "an array")),
+            None => Ok(&[]),
+        }
+    }
+
+    fn objects(&self, key: &str) -> Result<Vec<Object<'a>>> {
+        let prefix = self.member(key);
+        self.array(key)?
+            .iter()
+            .enumerate()
+            .map(|(i, v)| Object::new(v, format!("{}[{}]", prefix, i)))
+            .collect()
+    }
+
+    fn references(&self, key: &str, len: usize) -> Result<Vec<usize>> {
+        self.array(key)?
+            .iter()
+            .map(|v| v.as_f64().filter(|n| *n >= 0.0 && n.fract() == 0.0 && (*n as usize) < len).map(|n| n as usize))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| self.invalid(key, &format!("an array of indices below {}", len)))
+    }
+
+    fn strings(&self, key: &str) -> Result<Vec<&'a str>> {
+        self.array(key)?
+            .iter()
+            .map(Value::as_str)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| self.invalid(key, "an array of strings"))
+    }
+
+    fn extension(&self, name: &str) -> Result<Option<Object<'a>>> {
+        match self.object("extensions")? {
+            Some(extensions) => extensions.object(name),
+            None => Ok(None),
+        }
+    }
+}
+
+struct Importer<'a> {
+    root: Object<'a>,
+    buffers: Vec<Vec<u8>>,
+    base: &'a Path,
+}
+
+impl<'a> Importer<'a> {
+    fn new(document: &'a Value, base: &'a Path, bin: Option<&[u8]>) -> Result<Self> {
+        let root = Object::new(document, String::new())?;
+        let asset = root.required_object("asset")?;
+        let version = asset.string("version")?.ok_or_else(|| asset.missing("version"))?;
+        let min_version = asset.string("minVersion")?;
+        if !version.starts_with("2.") || min_version.is_some_and(|v| v != "2.0") {
+            return Err(anyhow!("glTF version {} isn't supported", min_version.unwrap_or(version)));
+        }
+
+        let unsupported = root
+            .strings("extensionsRequired")?
+            .into_iter()
+            .filter(|e| !SUPPORTED_EXTENSIONS.contains(e))
+            .collect::<Vec<_>>();
+        if !unsupported.is_empty() {
+            return Err(anyhow!("Requires unsupported extensions: {}", unsupported.join(", ")));
+        }
+
+        let mut buffers = Vec::new();
+        for (index, buffer) in root.objects("buffers")?.iter().enumerate() {
+            let length = buffer.required_index("byteLength")?;
+            let bytes = match (buffer.string("uri")?, bin) {
+                (Some(uri), _) => match decode_data_uri(uri)? {
+                    Some((_, bytes)) => bytes,
+                    None => {
+                        let path = base.join(decode_uri(uri));
+                        std::fs::read(&path).with_context(|| format!("Failed to read buffer {}", path.display()))?
+                    }
+                },
+                (None, Some(bin)) if index == 0 => bin.to_vec(),
+                (None, _) => return Err(anyhow!("`{}` has no data", buffer.path)),
+            };
+            if bytes.len() < length {
+                return Err(anyhow!("`{}` has {} bytes; expected {}", buffer.path, bytes.len(), length));
+            }
+            buffers.push(bytes);
+        }
+
+        Ok(Self { root, buffers, base })
+    }
+
+    fn import(&self) -> Result<Scene> {
+        let used = self.root.strings("extensionsUsed")?;
+        let unsupported_extensions =
+            used.iter().filter(|e| !SUPPORTED_EXTENSIONS.contains(e)).map(|e| e.to_string()).collect();
+        if !self.root.array("skins")?.is_empty() || !self.root.array("animations")?.is_empty() {
+            warn!("glTF skins and animations aren't imported; the scene stays in its rest pose.");
+        }
+
+        let images = self.root.objects("images")?.iter().map(|i| self.image(i)).collect::<Result<Vec<_>>>()?;
+        let textures =
+            self.root.objects("textures")?.iter().map(|t| self.texture(t, images.len())).collect::<Result<Vec<_>>>()?;
+        let materials = self
+            .root
+            .objects("materials")?
+            .iter()
+            .map(|m| self.material(m, textures.len()))
+            .collect::<Result<Vec<_>>>()?;
+        let meshes = self.root.objects("meshes")?.iter().map(|m| self.mesh(m, &materials)).collect::<Result<Vec<_>>>()?;
+        let cameras = self.root.objects("cameras")?.iter().map(|c| self.camera(c)).collect::<Result<Vec<_>>>()?;
+        let lights = match self.root.extension("KHR_lights_punctual")? {
+            Some(extension) => extension.objects("lights")?.iter().map(|l| self.light(l)).collect::<Result<_>>()?,
+            None => Vec::new(),
+        };
+
+        let mut nodes = Vec::new();
+        let node_objects = self.root.objects("nodes")?;
+        for node in &node_objects {
+            let light = match node.extension("KHR_lights_punctual")? {
+                Some(extension) => Some(extension.required_reference("light", lights.len())?),
+                None => None,
+            };
+            let local = node_transform(node)?;
+            nodes.push(Node {
+                name: node.name()?,
+                children: node.references("children", node_objects.len())?,
+                local,
+                world: local,
+                mesh: node.reference("mesh", meshes.len())?,
+                camera: node.reference("camera", cameras.len())?,
+                light,
+            });
+        }
+        let parentless = resolve_world_transforms(&mut nodes)?;
+
+        let scenes = self.root.objects("scenes")?;
+        let scene = self.root.reference("scene", scenes.len())?.or((!scenes.is_empty()).then_some(0));
+        let roots = match scene {
+            Some(scene) => scenes[scene].references("nodes", nodes.len())?,
+            None => parentless.clone(),
+        };
+        if let Some(child) = roots.iter().find(|r| !parentless.contains(r)) {
+            return Err(anyhow!("Scene root node {} is another node's child", child));
+        }
+
+        Ok(Scene { nodes, roots, meshes, materials, textures, images, cameras, lights, unsupported_extensions })
+    }
+
+    fn image(&self, image: &Object) -> Result<Image> {
+        let mime_type = image.string("mimeType")?;
+        match (image.string("uri")?, image.index("bufferView")?) {
+            (Some(uri), _) => match decode_data_uri(uri)? {
+                Some((data_type, bytes)) => {
+                    Ok(Image::Embedded { mime_type: mime_type.unwrap_or(&data_type).to_string(), bytes })
+                }
+                None => Ok(Image::File(self.base.join(decode_uri(uri)))),
+            },
+            (None, Some(view)) => {
+                let mime_type = mime_type.ok_or_else(|| image.missing("mimeType"))?;
+                let (bytes, _) = self.buffer_view(view)?;
+                Ok(Image::Embedded { mime_type: mime_type.to_string(), bytes: bytes.to_vec() })
+            }
+            (None, None) => Err(anyhow!("`{}` has neither a URI nor a buffer view", image.path)),
+        }
+    }
+
+    fn texture(&self, texture: &Object, images: usize) -> Result<Texture> {
+        // Extensions such as KHR_texture_basisu may replace the source.
+        let source = texture
+            .reference("source", images)?
+            .ok_or_else(|| anyhow!("`{}` has no source image the importer can read", texture.path))?;
+        let sampler = match texture.index("sampler")? {
+            Some(index) => {
+                let samplers = self.root.objects("samplers")?;
+                let sampler = samplers.get(index).ok_or_else(|| anyhow!("`{}.sampler` is out of range", texture.path))?;
+                parse_sampler(sampler)?
+            }
+            None => Sampler::default(),
+        };
+        Ok(Texture { source, sampler })
+    }
+
+    fn material(&self, material: &Object, textures: usize) -> Result<Material> {
+        let slot = |parent: &Object, key: &str| -> Result<Option<TextureSlot>> {
+            let Some(info) = parent.object(key)? else { return Ok(None) };
+            let texture = info.required_reference("index", textures)?;
+            let tex_coord = info.index("texCoord")?.unwrap_or(0) as u32;
+            if tex_coord != 0 {
+                warn!("`{}` samples TEXCOORD_{}, but only TEXCOORD_0 is imported.", info.path, tex_coord);
+            }
+            Ok(Some(TextureSlot { texture, tex_coord }))
+        };
+
+        let defaults = Material::default();
+        let mut imported = Material {
+            name: material.name()?,
+            normal_texture: slot(material, "normalTexture")?,
+            occlusion_texture: slot(material, "occlusionTexture")?,
+            emissive_texture: slot(material, "emissiveTexture")?,
+            emissive_factor: material.numbers("emissiveFactor", defaults.emissive_factor)?,
+            alpha_mode: match material.string("alphaMode")? {
+                None | Some("OPAQUE") => AlphaMode::Opaque,
+                Some("MASK") => AlphaMode::Mask,
+                Some("BLEND") => AlphaMode::Blend,
+                Some(_) => return Err(material.invalid("alphaMode", "OPAQUE, MASK or BLEND")),
+            },
+            alpha_cutoff: material.number("alphaCutoff", defaults.alpha_cutoff)?,
+            double_sided: material.bool("doubleSided", defaults.double_sided)?,
+            ..defaults
+        };
+        if let Some(normal) = material.object("normalTexture")? {
+            imported.normal_scale = normal.number("scale", 1.0)?;
+        }
+        if let Some(occlusion) = material.object("occlusionTexture")? {
+            imported.occlusion_strength = occlusion.number("strength", 1.0)?;
+        }
+        if let Some(pbr) = material.object("pbrMetallicRoughness")? {
+            imported.base_color_factor = pbr.numbers("baseColorFactor", imported.base_color_factor)?;
+            imported.base_color_texture = slot(&pbr, "baseColorTexture")?;
+            imported.metallic_factor = pbr.number("metallicFactor", imported.metallic_factor)?;
+            imported.roughness_factor = pbr.number("roughnessFactor", imported.roughness_factor)?;
+            imported.metallic_roughness_texture = slot(&pbr, "metallicRoughnessTexture")?;
+        }
+        if let Some(emissive) = material.extension("KHR_materials_emissive_strength")? {
+            let strength = emissive.number("emissiveStrength", 1.0)?;
+            imported.emissive_factor = imported.emissive_factor.map(|c| c * strength);
+        }
+        Ok(imported)
+    }
+
+    fn mesh(&self, mesh: &Object, materials: &[Material]) -> Result<Mesh> {
+        let mut primitives = Vec::new();
+        for primitive in mesh.objects("primitives")? {
+            if let Some(imported) = self.primitive(&primitive, materials)? {
+                primitives.push(imported);
+            }
+        }
+        Ok(Mesh { name: mesh.name()?, primitives })
+    }
+
+    // Triangles, strips and fans become triangle lists; points and lines are
+    // left out with a warning.
+    fn primitive(&self, primitive: &Object, materials: &[Material]) -> Result<Option<Primitive>> {
+        let mode = primitive.index("mode")?.unwrap_or(MODE_TRIANGLES);
+        if !matches!(mode, MODE_TRIANGLES | MODE_TRIANGLE_STRIP | MODE_TRIANGLE_FAN) {
+            warn!("`{}` draws points or lines (mode {}), which aren't imported.", primitive.path, mode);
+            return Ok(None);
+        }
+        if !primitive.array("targets")?.is_empty() {
+            warn!("`{}` has morph targets, which aren't applied.", primitive.path);
+        }
+
+        let material = primitive.reference("material", materials.len())?;
+        let attributes = primitive.required_object("attributes")?;
+        let attribute = |name: &str, components: &[usize]| -> Result<Option<Attribute>> {
+            let Some(index) = attributes.index(name)? else { return Ok(None) };
+            let (values, count) = self.accessor(index)?;
+            if !components.contains(&count) {
+                return Err(anyhow!("`{}.{}` has {} components per element", attributes.path, name, count));
+            }
+            Ok(Some(Attribute { values, components: count }))
+        };
+
+        let positions = attribute("POSITION", &[3])?.ok_or_else(|| attributes.missing("POSITION"))?;
+        let vertex_count = positions.len();
+        let normals = attribute("NORMAL", &[3])?;
+        let tex_coords = attribute("TEXCOORD_0", &[2])?;
+        // Alpha, if any, is dropped.
+        let colors = attribute("COLOR_0", &[3, 4])?;
+        for (name, attribute) in [("NORMAL", &normals), ("TEXCOORD_0", &tex_coords), ("COLOR_0", &colors)] {
+            if attribute.as_ref().is_some_and(|a| a.len() != vertex_count) {
+                return Err(anyhow!("`{}.{}` doesn't have one element per position", attributes.path, name));
+            }
+        }
+
+        let [r, g, b, _] = material.map_or([1.0; 4], |m| materials[m].base_color_factor);
+        let vertices = (0..vertex_count)
+            .map(|i| {
+                let [x, y, z] = positions.get(i);
+                let [nx, ny, nz] = normals.as_ref().map_or([0.0; 3], |a| a.get(i));
+                let [u, v] = tex_coords.as_ref().map_or([0.0; 2], |a| a.get(i));
+                let [cr, cg, cb] = colors.as_ref().map_or([1.0; 3], |a| a.get(i));
+                Vertex::new(vec3(x, y, z), vec3(nx, ny, nz), vec2(u, v), vec3(cr * r, cg * g, cb * b))
+            })
+            .collect::<Vec<_>>();
+
+        let order = match primitive.index("indices")? {
+            Some(index) => {
+                // Anything else isn't a whole vertex index once converted.
+                let accessors = self.root.objects("accessors")?;
+                if let Some(accessor) = accessors.get(index)
+                    && (!matches!(accessor.index("componentType")?, Some(5121 | 5123 | 5125))
+                        || accessor.bool("normalized", false)?)
+                {
+                    return Err(anyhow!(
+                        "`{}.indices` must be an unsigned byte, short or int accessor that isn't normalized",
+                        primitive.path,
+                    ));
+                }
+                let (values, components) = self.accessor(index)?;
+                if components != 1 {
+                    return Err(anyhow!("`{}.indices` must be a scalar accessor", primitive.path));
+                }
+                let indices = values.iter().map(|i| *i as u32).collect::<Vec<_>>();
+                if let Some(index) = indices.iter().find(|i| **i as usize >= vertex_count) {
+                    return Err(anyhow!("`{}` indexes vertex {} of {}", primitive.path, index, vertex_count));
+                }
+                indices
+            }
+            None => (0..vertex_count as u32).collect(),
+        };
+
+        let indices = match mode {
+            MODE_TRIANGLES if order.len() % 3 == 0 => order,
+            MODE_TRIANGLES => {
+                return Err(anyhow!("`{}` has {} indices, not whole triangles", primitive.path, order.len()));
+            }
+            MODE_TRIANGLE_STRIP => (0..order.len().saturating_sub(2))
+                .flat_map(|i| match i % 2 {
+                    0 => [order[i], order[i + 1], order[i + 2]],
+                    _ => [order[i + 1], order[i], order[i + 2]],
+                })
+                .collect(),
+            _ => (1..order.len().saturating_sub(1)).flat_map(|i| [order[i], order[i + 1], order[0]]).collect(),
+        };
+
+        let mut mesh = core::model::Mesh { vertices, indices };
+        if normals.is_none() {
+            core::model::smooth_normals(&mut mesh, &vec![true; vertex_count]);
+        }
+        Ok(Some(Primitive { mesh, material }))
+    }
+
+    fn camera(&self, camera: &Object) -> Result<Camera> {
+        let projection = match camera.string("type")? {
+            Some("perspective") => {
+                let perspective = camera.required_object("perspective")?;
+                Projection::Perspective {
+                    yfov: Rad(perspective.required_number("yfov")?),
+                    aspect_ratio: perspective.optional_number("aspectRatio")?,
+                    znear: perspective.required_number("znear")?,
+                    zfar: perspective.optional_number("zfar")?,
+                }
+            }
+            Some("orthographic") => {
+                let orthographic = camera.required_object("orthographic")?;
+                Projection::Orthographic {
+                    xmag: orthographic.required_number("xmag")?,
+                    ymag: orthographic.required_number("ymag")?,
+                    znear: orthographic.required_number("znear")?,
+                    zfar: orthographic.required_number("zfar")?,
+                }
+            }
+            _ => return Err(camera.invalid("type", "perspective or orthographic")),
+        };
+        Ok(Camera { name: camera.name()?, projection })
+    }
+
+    fn light(&self, light: &Object) -> Result<Light> {
+        let kind = match light.string("type")? {
+            Some("directional") => LightKind::Directional,
+            Some("point") => LightKind::Point,
+            Some("spot") => {
+                let spot = light.required_object("spot")?;
+                LightKind::Spot {
+                    inner_cone_angle: spot.number("innerConeAngle", 0.0)?,
+                    outer_cone_angle: spot.number("outerConeAngle", std::f32::consts::FRAC_PI_4)?,
+                }
+            }
+            _ => return Err(light.invalid("type", "directional, point or spot")),
+        };
+        Ok(Light {
+            name: light.name()?,
+            kind,
+            color: light.numbers("color", [1.0; 3])?,
+            intensity: light.number("intensity", 1.0)?,
+            range: light.optional_number("range")?,
+        })
+    }
+
+    // A view's bytes and its stride, if it has one.
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], Option<usize>)> {
+        let views = self.root.objects("bufferViews")?;
+        let view = views.get(index).ok_or_else(|| anyhow!("Buffer view {} doesn't exist", index))?;
+        let buffer = view.required_reference("buffer", self.buffers.len())?;
+        let offset = view.index("byteOffset")?.unwrap_or(0);
+        let length = view.required_index("byteLength")?;
+        let bytes = offset
+            .checked_add(length)
+            .and_then(|end| self.buffers[buffer].get(offset..end))
+            .ok_or_else(|| anyhow!("`{}` is past the end of buffer {}", view.path, buffer))?;
+        Ok((bytes, view.index("byteStride")?))
+    }
+
+    // Every element of an accessor, converted to numbers and normalized where
+    // it says so, with the number of components per element.
+    fn accessor(&self, index: usize) -> Result<(Vec<f64>, usize)> {
+        let accessors = self.root.objects("accessors")?;
+        let accessor = accessors.get(index).ok_or_else(|| anyhow!("Accessor {} doesn't exist", index))?;
+        let component_type = accessor.required_index("componentType")?;
+        let normalized = accessor.bool("normalized", false)?;
+        let count = accessor.required_index("count")?;
+        let components = match accessor.string("type")? {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err(accessor.invalid("type", "SCALAR or a vector type")),
+        };
+        let size = component_size(component_type).ok_or_else(|| accessor.invalid("componentType", "a known type"))?;
+        let element_size = size * components;
+
+        // The view must hold every element before they're allocated.
+        let view = match accessor.index("bufferView")? {
+            Some(view) => {
+                let (bytes, stride) = self.buffer_view(view)?;
+                let offset = accessor.index("byteOffset")?.unwrap_or(0);
+                let stride = stride.unwrap_or(element_size);
+                if stride < element_size {
+                    return Err(anyhow!("Buffer view {} has a stride shorter than `{}`'s elements", view, accessor.path));
+                }
+                let end = match count.checked_sub(1) {
+                    Some(last) => last
+                        .checked_mul(stride)
+                        .and_then(|start| start.checked_add(offset))
+                        .and_then(|start| start.checked_add(element_size)),
+                    None => Some(0),
+                };
+                if end.is_none_or(|end| end > bytes.len()) {
+                    return Err(anyhow!("`{}` reads past the end of buffer view {}", accessor.path, view));
+                }
+                Some((bytes, offset, stride))
+            }
+            None if count > MAX_ELEMENTS_WITHOUT_VIEW => {
+                return Err(anyhow!("`{}` has {} elements without a buffer view", accessor.path, count));
+            }
+            None => None,
+        };
+
+        let mut values = vec![0.0; count * components];
+        if let Some((bytes, offset, stride)) = view {
+            for (element, value) in values.chunks_exact_mut(components).enumerate() {
+                let start = offset + element * stride;
+                let element = &bytes[start..start + element_size];
+                for (component, bytes) in value.iter_mut().zip(element.chunks_exact(size)) {
+                    *component = read_component(bytes, component_type, normalized);
+                }
+            }
+        }
+
+        // Sparse accessors replace some elements of the base values.
+        if let Some(sparse) = accessor.object("sparse")? {
+            let sparse_count = sparse.required_index("count")?;
+            if sparse_count > count {
+                return Err(anyhow!("`{}` replaces {} of {} elements", sparse.path, sparse_count, count));
+            }
+            let indices = sparse.required_object("indices")?;
+            let sparse_values = sparse.required_object("values")?;
+            let index_type = indices.required_index("componentType")?;
+            let index_size =
+                component_size(index_type).ok_or_else(|| indices.invalid("componentType", "a known type"))?;
+
+            let read = |object: &Object, size: usize| -> Result<&[u8]> {
+                let (bytes, _) = self.buffer_view(object.required_index("bufferView")?)?;
+                let offset = object.index("byteOffset")?.unwrap_or(0);
+                (size * sparse_count)
+                    .checked_add(offset)
+                    .and_then(|end| bytes.get(offset..end))
+                    .ok_or_else(|| anyhow!("`{}` reads past its buffer view", object.path))
+            };
+            let index_bytes = read(&indices, index_size)?;
+            let value_bytes = read(&sparse_values, element_size)?;
+            let replacements = index_bytes.chunks_exact(index_size).zip(value_bytes.chunks_exact(element_size));
+            for (target, source) in replacements {
+                let target = read_component(target, index_type, false) as usize;
+                let value = values
+                    .get_mut(target * components..(target + 1) * components)
+                    .ok_or_else(|| anyhow!("`{}` replaces element {} of {}", sparse.path, target, count))?;
+                for (component, bytes) in value.iter_mut().zip(source.chunks_exact(size)) {
+                    *component = read_component(bytes, component_type, normalized);
+                }
+            }
+        }
+
+        Ok((values, components))
+    }
+}
+
+// A vertex attribute's values, `components` per vertex.
+struct Attribute {
+    values: Vec<f64>,
+    components: usize,
+}
+
+impl Attribute {
+    fn len(&self) -> usize {
+        self.values.len() / self.components
+    }
+
+    // The first `N` components of vertex `index`.
+    fn get<const N: usize>(&self, index: usize) -> [f32; N] {
+        std::array::from_fn(|n| self.values[index * self.components + n] as f32)
+    }
+}
+
+fn component_size(component_type: usize) -> Option<usize> {
+    match component_type {
+        5120 | 5121 => Some(1),
+        5122 | 5123 => Some(2),
+        5125 | 5126 => Some(4),
+        _ => None,
+    }
+}
+
+fn read_component(bytes: &[u8], component_type: usize, normalized: bool) -> f64 {
+    let (value, max) = match component_type {
+        5120 => (bytes[0] as i8 as f64, i8::MAX as f64),
+        5121 => (bytes[0] as f64, u8::MAX as f64),
+        5122 => (i16::from_le_bytes([bytes[0], bytes[1]]) as f64, i16::MAX as f64),
+        5123 => (u16::from_le_bytes([bytes[0], bytes[1]]) as f64, u16::MAX as f64),
+        5125 => return u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        _ => return f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    };
+    if normalized { (value / max).max(-1.0) } else { value }
+}
+
+fn parse_sampler(sampler: &Object) -> Result<Sampler> {
+    // The filter, and the mipmap mode for filters that use mipmaps.
+    let filter = |key: &str| -> Result<Option<(vk::Filter, Option<vk::SamplerMipmapMode>)>> {
+        let filter = match sampler.index(key)? {
+            None => return Ok(None),
+            Some(9728) => (vk::Filter::NEAREST, None),
+            Some(9729) => (vk::Filter::LINEAR, None),
+            Some(9984) => (vk::Filter::NEAREST, Some(vk::SamplerMipmapMode::NEAREST)),
+            Some(9985) => (vk::Filter::LINEAR, Some(vk::SamplerMipmapMode::NEAREST)),
+            Some(9986) => (vk::Filter::NEAREST, Some(vk::SamplerMipmapMode::LINEAR)),
+            Some(9987) => (vk::Filter::LINEAR, Some(vk::SamplerMipmapMode::LINEAR)),
+            Some(_) => return Err(sampler.invalid(key, "a filter")),
+        };
+        Ok(Some(filter))
+    };
+    let wrap = |key: &str| match sampler.index(key)? {
+        None | Some(10497) => Ok(vk::SamplerAddressMode::REPEAT),
+        Some(33071) => Ok(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+        Some(33648) => Ok(vk::SamplerAddressMode::MIRRORED_REPEAT),
+        Some(_) => Err(sampler.invalid(key, "a wrap mode")),
+    };
+
+    let defaults = Sampler::default();
+    let (mag_filter, _) = filter("magFilter")?.unwrap_or((defaults.mag_filter, None));
+    let (min_filter, mipmap_mode) = match filter("minFilter")? {
+        Some(filter) => filter,
+        None => (defaults.min_filter, Some(defaults.mipmap_mode)),
+    };
+    Ok(Sampler {
+        mag_filter,
+        min_filter,
+        // Without mipmaps only the base level is sampled, so the mode is moot.
+        mipmap_mode: mipmap_mode.unwrap_or(vk::SamplerMipmapMode::NEAREST),
+        mipmaps: mipmap_mode.is_some(),
+        address_mode_u: wrap("wrapS")?,
+        address_mode_v: wrap("wrapT")?,
+    })
+}
+
+// `matrix`, or `translation` * `rotation` * `scale`.
+fn node_transform(node: &Object) -> Result<Mat4> {
+    if node.value.get("matrix").is_some() {
+        let m = node.numbers("matrix", [0.0; 16])?;
+        let column = |c: usize| vec4(m[c * 4], m[c * 4 + 1], m[c * 4 + 2], m[c * 4 + 3]);
+        return Ok(Mat4::from_cols(column(0), column(1), column(2), column(3)));
+    }
+
+    let [tx, ty, tz] = node.numbers("translation", [0.0; 3])?;
+    let [x, y, z, w] = node.numbers("rotation", [0.0, 0.0, 0.0, 1.0])?;
+    let [sx, sy, sz] = node.numbers("scale", [1.0; 3])?;
+    let rotation = Quaternion::new(w, x, y, z);
+    let rotation = if rotation.magnitude2() > 0.0 { rotation.normalize() } else { Quaternion::new(1.0, 0.0, 0.0, 0.0) };
+    Ok(Mat4::from_translation(Vec3::new(tx, ty, tz)) * Mat4::from(rotation) * Mat4::from_nonuniform_scale(sx, sy, sz))
+}
+
+// Multiplies each node's transform by its parents', checking the nodes form
+// trees. Returns the nodes without a parent.
+fn resolve_world_transforms(nodes: &mut [Node]) -> Result<Vec<usize>> {
+    let mut parents = vec![None; nodes.len()];
+    for (parent, node) in nodes.iter().enumerate() {
+        for &child in &node.children {
+            if parents[child].replace(parent).is_some() {
+                return Err(anyhow!("Node {} has more than one parent", child));
+            }
+        }
+    }
+
+    let roots = (0..nodes.len()).filter(|n| parents[*n].is_none()).collect::<Vec<_>>();
+    let mut visited = vec![false; nodes.len()];
+    let mut stack = roots.clone();
+    while let Some(node) = stack.pop() {
+        visited[node] = true;
+        let world = nodes[node].world;
+        for child in nodes[node].children.clone() {
+            nodes[child].world = world * nodes[child].local;
+            stack.push(child);
+        }
+    }
+    if let Some(node) = visited.iter().position(|v| !v) {
+        return Err(anyhow!("Node {} is part of a cycle", node));
+    }
+
+    Ok(roots)
+}
+
+// `Some` for `data:` URIs, with their media type.
+fn decode_data_uri(uri: &str) -> Result<Option<(String, Vec<u8>)>> {
+    let Some(rest) = uri.strip_prefix("data:") else { return Ok(None) };
+    let (header, payload) = rest.split_once(',').ok_or_else(|| anyhow!("Malformed data URI"))?;
+    let Some(mime_type) = header.strip_suffix(";base64") else {
+        return Err(anyhow!("Only base64 data URIs are supported"));
+    };
+    Ok(Some((mime_type.to_string(), decode_base64(payload)?)))
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(anyhow!("Invalid base64 character {:?}", c as char)),
+        };
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+            bits &= (1 << count) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+// Relative URIs may percent-encode characters such as spaces.
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], escape) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::Deg;
+
+    // A triangle at (0, 0, 0), (1, 0, 0) and (0, 1, 0), scaled by 2 and moved
+    // along +X by its parent. `POSITIONS` is its position accessor.
+    const TRIANGLE: &str = r#"{
+        "asset": {"version": "2.0"},
+        "buffers": [{"uri": "DATA", "byteLength": 42}],
+        "bufferViews": [
+            {"buffer": 0, "byteLength": 36},
+            {"buffer": 0, "byteOffset": 36, "byteLength": 6}
+        ],
+        "accessors": [
+            POSITIONS,
+            {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+        ],
+        "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [0.5, 1, 1, 1]}}],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1, "material": 0}]}],
+        "nodes": [{"translation": [1, 0, 0], "children": [1]}, {"scale": [2, 2, 2], "mesh": 0}],
+        "scenes": [{"nodes": [0]}]
+    }"#;
+    const POSITIONS: &str = r#"{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}"#;
+
+    fn triangle_buffer() -> Vec<u8> {
+        let positions = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let indices = [0u16, 1, 2];
+        let mut bytes = positions.iter().flat_map(|p| p.to_le_bytes()).collect::<Vec<_>>();
+        bytes.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+        bytes
+    }
+
+    fn base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, b)| bits | (*b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                let c = if i <= chunk.len() { ALPHABET[(bits >> (18 - 6 * i)) as usize & 63] } else { b'=' };
+                text.push(c as char);
+            }
+        }
+        text
+    }
+
+    fn import(json: &str, buffer: &[u8]) -> Result<Scene> {
+        let uri = format!("data:application/octet-stream;base64,{}", base64(buffer));
+        let document = serde_json::from_str::<Value>(&json.replace("DATA", &uri))?;
+        Importer::new(&document, Path::new(""), None)?.import()
+    }
+
+    fn import_positions(positions: &str) -> Result<Scene> {
+        import(&TRIANGLE.replace("POSITIONS", positions), &triangle_buffer())
+    }
+
+    fn error(result: Result<Scene>) -> String {
+        match result {
+            Ok(_) => panic!("imported a malformed glTF"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn decodes_base64() {
+        for bytes in [&b""[..], b"a", b"ab", b"abc", b"abcd", &[0, 255, 128, 7, 64]] {
+            assert_eq!(decode_base64(&base64(bytes)).unwrap(), bytes);
+        }
+        assert!(decode_base64("ab$c").is_err());
+        assert_eq!(decode_data_uri("data:image/png;base64,AAE=").unwrap(), Some(("image/png".into(), vec![0, 1])));
+        assert!(decode_data_uri("data:text/plain,hello").is_err());
+        assert_eq!(decode_data_uri("image.png").unwrap(), None);
+        assert_eq!(decode_uri("my%20image%2Fa.png"), "my image/a.png");
+    }
+
+    #[test]
+    fn flattens_the_node_hierarchy() {
+        let scene = import_positions(POSITIONS).unwrap();
+        assert_eq!(scene.roots, [0]);
+        assert_eq!(scene.scene_nodes(), [0, 1]);
+
+        let (mesh, sections) = scene.flatten(Mat4::from_scale(1.0));
+        let positions = mesh.vertices.iter().map(|v| v.pos).collect::<Vec<_>>();
+        assert_eq!(positions, [vec3(1.0, 0.0, 0.0), vec3(3.0, 0.0, 0.0), vec3(1.0, 2.0, 0.0)]);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert_eq!(sections, [Section { indices: 0..3, material: Some(0) }]);
+        // The base color factor is in the vertex colors, and the missing
+        // normals are smoothed from the face.
+        assert_eq!(mesh.vertices[0].color, vec3(0.5, 1.0, 1.0));
+        assert_eq!(mesh.vertices[0].normal, vec3(0.0, 0.0, 1.0));
+
+        // Mirroring turns the winding around.
+        let (mesh, _) = scene.flatten(Mat4::from_nonuniform_scale(-1.0, 1.0, 1.0));
+        assert_eq!(mesh.indices, [0, 2, 1]);
+    }
+
+    #[test]
+    fn shares_samplers_between_textures() {
+        let json = TRIANGLE.replace("POSITIONS", POSITIONS).replace(
+            r#""scenes""#,
+            r#""images": [{"uri": "texture.png"}],
+            "samplers": [{"magFilter": 9728, "wrapS": 33071}, {"magFilter": 9728, "wrapS": 33071}, {}],
+            "textures": [
+                {"source": 0, "sampler": 0},
+                {"source": 0, "sampler": 1},
+                {"source": 0},
+                {"source": 0, "sampler": 2}
+            ],
+            "scenes""#,
+        );
+        let scene = import(&json, &triangle_buffer()).unwrap();
+        // The last texture is an extra one, such as white, with the default
+        // sampler like the two textures without their own parameters.
+        assert_eq!(scene.sampler_owners(&[1, 4, 2, 2, 1]), [1, 1, 2, 2, 2]);
+        assert_eq!(scene.sampler_owners(&[3, 1, 1, 1, 5]), [0, 0, 4, 4, 4]);
+    }
+
+    #[test]
+    fn min_filters_without_mipmaps_sample_the_base_level() {
+        let json = TRIANGLE.replace("POSITIONS", POSITIONS).replace(
+            r#""scenes""#,
+            r#""images": [{"uri": "texture.png"}],
+            "samplers": [{"minFilter": 9728}, {"minFilter": 9729}, {"minFilter": 9984}, {}],
+            "textures": [
+                {"source": 0, "sampler": 0},
+                {"source": 0, "sampler": 1},
+                {"source": 0, "sampler": 2},
+                {"source": 0, "sampler": 3}
+            ],
+            "scenes""#,
+        );
+        let scene = import(&json, &triangle_buffer()).unwrap();
+        let samplers = scene.textures.iter().map(|t| (t.sampler.min_filter, t.sampler.mipmaps)).collect::<Vec<_>>();
+        assert_eq!(samplers, [
+            (vk::Filter::NEAREST, false),
+            (vk::Filter::LINEAR, false),
+            (vk::Filter::NEAREST, true),
+            (vk::Filter::LINEAR, true),
+        ]);
+        assert_eq!(scene.textures[2].sampler.mipmap_mode, vk::SamplerMipmapMode::NEAREST);
+    }
+
+    #[test]
+    fn splits_sections_by_material() {
+        let json = TRIANGLE.replace("POSITIONS", POSITIONS).replace(
+            r#""primitives": [{"attributes": {"POSITION": 0}, "indices": 1, "material": 0}]"#,
+            r#""primitives": [
+                {"attributes": {"POSITION": 0}, "indices": 1, "material": 0},
+                {"attributes": {"POSITION": 0}, "indices": 1, "material": 0},
+                {"attributes": {"POSITION": 0}, "mode": 6},
+                {"attributes": {"POSITION": 0}, "mode": 0}
+            ]"#,
+        );
+        let (mesh, sections) = import(&json, &triangle_buffer()).unwrap().flatten(Mat4::from_scale(1.0));
+        assert_eq!(mesh.indices, [0, 1, 2, 3, 4, 5, 7, 8, 6]);
+        assert_eq!(
+            sections,
+            [Section { indices: 0..6, material: Some(0) }, Section { indices: 6..9, material: None }]
+        );
+    }
+
+    #[test]
+    fn converts_strips_and_fans() {
+        let positions = r#"{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}"#;
+        let strip = TRIANGLE.replace("POSITIONS", positions).replace(r#""indices": 1"#, r#""mode": 5"#);
+        let scene = import(&strip, &triangle_buffer()).unwrap();
+        assert_eq!(scene.meshes[0].primitives[0].mesh.indices, [0, 1, 2]);
+
+        // Four strip indices are two triangles, the second wound the other way.
+        let mut buffer = triangle_buffer();
+        buffer.extend([0u16, 1, 2, 1].iter().flat_map(|i| i.to_le_bytes()));
+        let json = TRIANGLE
+            .replace("POSITIONS", positions)
+            .replace(r#""byteLength": 42"#, r#""byteLength": 50"#)
+            .replace(r#""byteLength": 6}"#, r#""byteLength": 14}"#)
+            .replace(r#""count": 3, "type": "SCALAR""#, r#""count": 7, "type": "SCALAR""#);
+        let scene = import(&json.replace(r#""indices": 1, "#, r#""indices": 1, "mode": 5, "#), &buffer).unwrap();
+        assert_eq!(scene.meshes[0].primitives[0].mesh.indices[..6], [0, 1, 2, 2, 1, 0]);
+        let scene = import(&json.replace(r#""indices": 1, "#, r#""indices": 1, "mode": 6, "#), &buffer).unwrap();
+        assert_eq!(scene.meshes[0].primitives[0].mesh.indices[..6], [1, 2, 0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn reads_sparse_accessors() {
+        // Zeros but for the third position, replaced from the views of the
+        // triangle: index 2 from the index view, and the last position.
+        let positions = r#"{"componentType": 5126, "count": 3, "type": "VEC3", "sparse": {
+            "count": 1,
+            "indices": {"bufferView": 1, "byteOffset": 4, "componentType": 5123},
+            "values": {"bufferView": 0, "byteOffset": 24}
+        }}"#;
+        let scene = import_positions(positions).unwrap();
+        let positions = scene.meshes[0].primitives[0].mesh.vertices.iter().map(|v| v.pos).collect::<Vec<_>>();
+        assert_eq!(positions, [vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)]);
+    }
+
+    #[test]
+    fn rejects_accessors_past_their_views() {
+        let cases = [
+            (r#""count": 4, "type": "VEC3""#, "reads past the end of buffer view 0"),
+            (r#""count": 1e18, "type": "VEC3""#, "reads past the end of buffer view 0"),
+            (r#""count": 3, "byteOffset": 1e30, "type": "VEC3""#, "reads past the end of buffer view 0"),
+            (r#""count": 3, "type": "VEC4""#, "reads past the end of buffer view 0"),
+        ];
+        for (replacement, expected) in cases {
+            let positions = POSITIONS.replace(r#""count": 3, "type": "VEC3""#, replacement);
+            let error = error(import_positions(&positions));
+            assert!(error.ends_with(expected), "{}: {}", replacement, error);
+        }
+
+        let json = TRIANGLE.replace("POSITIONS", POSITIONS);
+        let stride = json.replace(r#"{"buffer": 0, "byteLength": 36}"#, r#"{"buffer": 0, "byteLength": 36, "byteStride": 4}"#);
+        assert_eq!(
+            error(import(&stride, &triangle_buffer())),
+            "Buffer view 0 has a stride shorter than `accessors[0]`'s elements"
+        );
+        let offset = json.replace(r#""byteOffset": 36"#, r#""byteOffset": 1e30"#);
+        assert_eq!(error(import(&offset, &triangle_buffer())), "`bufferViews[1]` is past the end of buffer 0");
+
+        let empty = r#"{"componentType": 5126, "count": 1e12, "type": "VEC3"}"#;
+        assert_eq!(error(import_positions(empty)), "`accessors[0]` has 1000000000000 elements without a buffer view");
+        let sparse = r#"{"componentType": 5126, "count": 3, "type": "VEC3", "sparse": {
+            "count": 1e15,
+            "indices": {"bufferView": 1, "componentType": 5123},
+            "values": {"bufferView": 0}
+        }}"#;
+        assert_eq!(error(import_positions(sparse)), "`accessors[0].sparse` replaces 1000000000000000 of 3 elements");
+    }
+
+    #[test]
+    fn rejects_malformed_scenes() {
+        let json = TRIANGLE.replace("POSITIONS", POSITIONS);
+        let buffer = triangle_buffer();
+        let indices = json.replace(r#""count": 3, "type": "SCALAR""#, r#""count": 3, "byteOffset": 2, "type": "SCALAR""#);
+        assert!(error(import(&indices, &buffer)).contains("past the end of buffer view 1"));
+        let mut out_of_range = buffer.clone();
+        out_of_range[40] = 3;
+        assert_eq!(error(import(&json, &out_of_range)), "`meshes[0].primitives[0]` indexes vertex 3 of 3");
+        for index_type in ["5122", "5126", r#"5123, "normalized": true"#] {
+            let indices = json.replace(r#"5123, "count": 3"#, &format!(r#"{}, "count": 3"#, index_type));
+            assert_eq!(
+                error(import(&indices, &buffer)),
+                "`meshes[0].primitives[0].indices` must be an unsigned byte, short or int accessor that isn't normalized"
+            );
+        }
+
+        let cycle = json.replace(r#""mesh": 0}"#, r#""mesh": 0, "children": [0]}"#);
+        assert_eq!(error(import(&cycle, &buffer)), "Node 0 is part of a cycle");
+        let shared = json.replace(r#""mesh": 0}"#, r#""mesh": 0}, {"children": [1]}"#);
+        assert_eq!(error(import(&shared, &buffer)), "Node 1 has more than one parent");
+        let material = json.replace(r#""material": 0"#, r#""material": 1"#);
+        assert_eq!(error(import(&material, &buffer)), "`meshes[0].primitives[0].material` is 1, but there are only 1");
+        let short = json.replace(r#""byteLength": 42"#, r#""byteLength": 43"#);
+        assert_eq!(error(import(&short, &buffer)), "`buffers[0]` has 42 bytes; expected 43");
+        let required = json.replace(r#""asset""#, r#""extensionsRequired": ["KHR_draco_mesh_compression"], "asset""#);
+        assert_eq!(error(import(&required, &buffer)), "Requires unsupported extensions: KHR_draco_mesh_compression");
+        let version = json.replace(r#""version": "2.0""#, r#""version": "1.0""#);
+        assert_eq!(error(import(&version, &buffer)), "glTF version 1.0 isn't supported");
+    }
+
+    #[test]
+    fn splits_glb_chunks() {
+        let json = b"{}  ";
+        let bin = [1, 2, 3, 4];
+        let mut glb = GLB_MAGIC.to_vec();
+        glb.extend(2u32.to_le_bytes());
+        glb.extend(((GLB_HEADER_SIZE + 2 * CHUNK_HEADER_SIZE + json.len() + bin.len()) as u32).to_le_bytes());
+        for (chunk_type, chunk) in [(CHUNK_JSON, &json[..]), (CHUNK_BIN, &bin[..])] {
+            glb.extend((chunk.len() as u32).to_le_bytes());
+            glb.extend(chunk_type.to_le_bytes());
+            glb.extend(chunk);
+        }
+        assert_eq!(parse_glb(&glb).unwrap(), (&json[..], Some(&bin[..])));
+
+        let mut truncated = glb.clone();
+        truncated[8] += 4;
+        assert_eq!(parse_glb(&truncated).unwrap_err().to_string(), "GLB is shorter than its header says");
+        let mut chunk = glb.clone();
+        chunk[GLB_HEADER_SIZE + CHUNK_HEADER_SIZE + json.len()] = 8;
+        assert_eq!(parse_glb(&chunk).unwrap_err().to_string(), "GLB chunk is truncated");
+        let mut version = glb;
+        version[4] = 1;
+        assert_eq!(parse_glb(&version).unwrap_err().to_string(), "GLB version 1 isn't supported");
+    }
+
+    #[test]
+    fn places_the_camera_and_lights() {
+        let json = TRIANGLE.replace("POSITIONS", POSITIONS).replace(
+            r#""scenes": [{"nodes": [0]}]"#,
+            r#""scenes": [{"nodes": [0, 2, 3, 4, 5]}],
+            "cameras": [{"type": "perspective", "perspective": {"yfov": 0.5, "znear": 0.1}}],
+            "extensions": {"KHR_lights_punctual": {"lights": [
+                {"type": "point", "color": [1, 0.5, 0], "range": 1},
+                {"type": "directional"}
+            ]}}"#,
+        );
+        let json = json.replace(
+            r#"{"scale": [2, 2, 2], "mesh": 0}"#,
+            r#"{"scale": [2, 2, 2], "mesh": 0},
+            {"translation": [0, 0, 5], "camera": 0},
+            {"translation": [0, 0, 1], "extensions": {"KHR_lights_punctual": {"light": 0}}},
+            {"translation": [0, 0, 9], "extensions": {"KHR_lights_punctual": {"light": 0}}},
+            {"extensions": {"KHR_lights_punctual": {"light": 1}}}"#,
+        );
+        let scene = import(&json, &triangle_buffer()).unwrap();
+
+        // Looking down -Z from (0, 0, 5), turned to +Z up.
+        let turn = Mat4::from_angle_x(Deg(90.0));
+        let camera = scene.camera(turn, 100.0).unwrap();
+        assert!((camera.eye - point3(0.0, -5.0, 0.0)).magnitude() < 1e-5);
+        assert!((camera.target - point3(0.0, -4.0, 0.0)).magnitude() < 1e-5);
+        assert!((camera.up - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+        assert_eq!((camera.near, camera.far), (0.1, 100.0));
+
+        // Lights stay in the mesh's space; the directional one is left out.
+        let lights = scene.deferred_lights(turn);
+        assert_eq!(lights.len(), 2);
+        for (light, y) in lights.iter().zip([-1.0, -9.0]) {
+            let [px, py, pz, radius] = light.position;
+            assert!(px.abs() < 1e-5 && (py - y).abs() < 1e-5 && pz.abs() < 1e-5, "{:?}", light.position);
+            assert_eq!(radius, 1.0);
+            assert_eq!(light.color, [1.0, 0.5, 0.0, 1.0]);
+        }
+    }
+}
@@ -0,0 +1,411 @@
+use anyhow::{Result, Context};
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::AppData;
+use crate::core;
+use crate::core::config::RenderPath;
+use crate::core::deferred::DeferredPipelines;
+use crate::core::pipeline::GraphicsPipeline;
+use crate::core::post::PostPipeline;
+use crate::core::specialization::SpecializedPipelines;
+use crate::core::vertex::Vertex;
+
+// Both texture binding variants are watched; only the one in use is rebuilt.
+const DEFERRED_SHADERS: [&str; 5] = [
+    core::deferred::GBUFFER_VERT,
+    core::deferred::GBUFFER_FRAG,
+    core::deferred::GBUFFER_COMBINED_FRAG,
+    core::deferred::LIGHTING_VERT,
+    core::deferred::LIGHTING_FRAG,
+];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub const MAIN_PIPELINE: &str = "main";
+pub const DEFERRED_PIPELINES: &str = "deferred";
+pub const DEBUG_PIPELINE: &str = "debug";
+pub const POST_PIPELINES: &str = "post";
+pub const SKYBOX_PIPELINE: &str = "skybox";
+
+const PIPELINES: [&str; 5] = [MAIN_PIPELINE, DEFERRED_PIPELINES, DEBUG_PIPELINE, POST_PIPELINES, SKYBOX_PIPELINE];
+
+#[derive(Clone, Debug)]
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    pipelines: Vec<&'static str>,
+}
+
+#[derive(Clone, Debug)]
+struct WatchedShader {
+    name: String,
+    file: WatchedFile,
+    // Files the source pulled in through `#include` when it last compiled.
+    includes: Vec<PathBuf>,
+}
+
+// Polls shader source and include mtimes and reports which pipelines need a
+// rebuild. `mipmap.comp` and `equirect.comp` aren't watched: they're loaded,
+// and recompiled when stale, every time a texture or cube map is created.
+#[derive(Clone, Debug)]
+pub struct ShaderWatcher {
+    shaders: Vec<WatchedShader>,
+    // Watched for the pipelines of every shader including them.
+    includes: Vec<WatchedFile>,
+    last_poll: Instant,
+}
+
+impl Default for ShaderWatcher {
+    fn default() -> Self {
+        Self { shaders: Vec::new(), includes: Vec::new(), last_poll: Instant::now() }
+    }
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Self {
+        let mut watcher = Self::default();
+        watcher.watch(core::pipeline::VERT_SHADER, MAIN_PIPELINE);
+        watcher.watch(core::pipeline::FRAG_SHADER, MAIN_PIPELINE);
+        watcher.watch(core::pipeline::COMBINED_FRAG_SHADER, MAIN_PIPELINE);
+        for shader in DEFERRED_SHADERS {
+            watcher.watch(shader, DEFERRED_PIPELINES);
+        }
+        watcher.watch(core::debug_view::DEBUG_VERT, DEBUG_PIPELINE);
+        watcher.watch(core::debug_view::DEBUG_FRAG, DEBUG_PIPELINE);
+        watcher.watch(core::skybox::SKYBOX_VERT, SKYBOX_PIPELINE);
+        watcher.watch(core::skybox::SKYBOX_FRAG, SKYBOX_PIPELINE);
+        watcher.watch(core::post::FULLSCREEN_VERT, POST_PIPELINES);
+        for shader in core::post::EFFECT_SHADERS {
+            watcher.watch(shader, POST_PIPELINES);
+        }
+        // The embedded SPIR-V doesn't say what it included.
+        watcher.refresh_includes(&PIPELINES);
+        watcher
+    }
+
+    pub fn watch(&mut self, name: &str, pipeline: &'static str) {
+        let path = core::shaders::absolute_source_path(name);
+        match self.shaders.iter_mut().find(|s| s.file.path == path) {
+            Some(shader) => {
+                if !shader.file.pipelines.contains(&pipeline) {
+                    shader.file.pipelines.push(pipeline);
+                }
+            }
+            None => {
+                let modified = modified_time(&path);
+                let file = WatchedFile { path, modified, pipelines: vec![pipeline] };
+                self.shaders.push(WatchedShader { name: name.to_string(), file, includes: Vec::new() });
+            }
+        }
+    }
+
+    // Relearns the includes of the given pipelines' shaders, which are cache
+    // hits right after a rebuild. Shaders that fail to compile keep their last
+    // known includes.
+    pub fn refresh_includes(&mut self, pipelines: &[&'static str]) {
+        for shader in &mut self.shaders {
+            if !shader.file.pipelines.iter().any(|p| pipelines.contains(p)) {
+                continue;
+            }
+            let kind = match core::compiler::shader_kind(&shader.name) {
+                Ok(kind) => kind,
+                Err(e) => {
+                    warn!("{:#}", e);
+                    continue;
+                }
+            };
+            let options = core::compiler::ShaderCompileOptions::default();
+            match core::compiler::compile_shader_with_options(&core::shaders::source_path(&shader.name), kind, &options) {
+                Ok(compiled) => shader.includes = compiled.includes,
+                Err(e) => debug!("Keeping the last known includes of {}: {:#}", shader.name, e),
+            }
+        }
+
+        let mut includes: Vec<WatchedFile> = Vec::new();
+        for shader in &self.shaders {
+            for path in &shader.includes {
+                match includes.iter_mut().find(|i| i.path == *path) {
+                    Some(include) => {
+                        for pipeline in &shader.file.pipelines {
+                            if !include.pipelines.contains(pipeline) {
+                                include.pipelines.push(pipeline);
+                            }
+                        }
+                    }
+                    None => {
+                        // Files that were already watched keep their mtime, so
+                        // a change isn't lost between polls.
+                        let modified = match self.includes.iter().find(|i| i.path == *path) {
+                            Some(include) => include.modified,
+                            None => modified_time(path),
+                        };
+                        includes.push(WatchedFile { path: path.clone(), modified, pipelines: shader.file.pipelines.clone() });
+                    }
+                }
+            }
+        }
+        self.includes = includes;
+    }
+
+    // Returns the pipelines whose shaders or includes changed since the last
+    // poll.
+    pub fn poll(&mut self) -> Vec<&'static str> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = Vec::new();
+        for file in self.shaders.iter_mut().map(|s| &mut s.file).chain(&mut self.includes) {
+            let modified = modified_time(&file.path);
+            if modified != file.modified {
+                file.modified = modified;
+                info!("Shader changed: {}", file.path.display());
+                for pipeline in &file.pipelines {
+                    if !changed.contains(pipeline) {
+                        changed.push(*pipeline);
+                    }
+                }
+            }
+        }
+        changed
+    }
+}
+
+fn compile(name: &str) -> Result<Vec<u32>> {
+    core::compiler::compile_shader(&core::shaders::source_path(name), core::compiler::shader_kind(name)?)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Pipelines built from the new shaders, not yet in use.
+enum Rebuilt {
+    Main {
+        vert_shader: Vec<u32>,
+        frag_shader: Vec<u32>,
+        descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+        pipeline_layout: vk::PipelineLayout,
+        pipeline: vk::Pipeline,
+    },
+    Deferred {
+        shaders: [Vec<u32>; 4],
+        pipelines: DeferredPipelines,
+    },
+    Debug {
+        vert_shader: Vec<u32>,
+        frag_shader: Vec<u32>,
+        pipelines: SpecializedPipelines,
+    },
+    Skybox {
+        vert_shader: Vec<u32>,
+        frag_shader: Vec<u32>,
+        pipeline: GraphicsPipeline,
+    },
+    Post {
+        shaders: HashMap<&'static str, Vec<u32>>,
+        pipelines: Vec<PostPipeline>,
+    },
+}
+
+impl Rebuilt {
+    // Replaces the pipelines in use, which the device must be done with.
+    unsafe fn swap(self, device: &Device, data: &mut AppData) -> Result<()> {
+        let name = match self {
+            Rebuilt::Main { vert_shader, frag_shader, descriptor_set_layouts, pipeline_layout, pipeline } => {
+                device.destroy_pipeline(data.pipeline, None);
+                device.destroy_pipeline_layout(data.pipeline_layout, None);
+                data.descriptor_set_layouts = descriptor_set_layouts;
+                data.pipeline_layout = pipeline_layout;
+                data.pipeline = pipeline;
+                data.vert_shader = vert_shader;
+                data.frag_shader = frag_shader;
+                MAIN_PIPELINE
+            }
+            Rebuilt::Deferred { shaders, pipelines } => {
+                data.deferred.pipelines.destroy(device);
+                data.deferred.pipelines = pipelines;
+                let [gbuffer_vert, gbuffer_frag, lighting_vert, lighting_frag] = shaders;
+                data.deferred.gbuffer_vert = gbuffer_vert;
+                data.deferred.gbuffer_frag = gbuffer_frag;
+                data.deferred.lighting_vert = lighting_vert;
+                data.deferred.lighting_frag = lighting_frag;
+                DEFERRED_PIPELINES
+            }
+            Rebuilt::Debug { vert_shader, frag_shader, pipelines } => {
+                core::debug_view::destroy_pipeline(device, data);
+                data.debug.pipelines = pipelines;
+                data.debug.vert_shader = vert_shader;
+                data.debug.frag_shader = frag_shader;
+                DEBUG_PIPELINE
+            }
+            Rebuilt::Skybox { vert_shader, frag_shader, pipeline } => {
+                core::skybox::destroy_pipeline(device, data);
+                data.skybox.pipeline = pipeline;
+                data.skybox.vert_shader = vert_shader;
+                data.skybox.frag_shader = frag_shader;
+                SKYBOX_PIPELINE
+            }
+            Rebuilt::Post { shaders, pipelines } => {
+                // The descriptor sets are allocated with the pipelines' layouts.
+                core::post::destroy_descriptor_sets(device, data);
+                data.post.pipelines.drain(..).for_each(|p| p.destroy(device));
+                data.post.pipelines = pipelines;
+                data.post.shaders = shaders;
+                core::post::create_descriptor_sets(device, data)?;
+                POST_PIPELINES
+            }
+        };
+
+        info!("Rebuilt `{}`.", name);
+        Ok(())
+    }
+
+    unsafe fn destroy(self, device: &Device) {
+        match self {
+            Rebuilt::Main { pipeline_layout, pipeline, .. } => {
+                device.destroy_pipeline(pipeline, None);
+                device.destroy_pipeline_layout(pipeline_layout, None);
+            }
+            Rebuilt::Deferred { pipelines, .. } => pipelines.destroy(device),
+            Rebuilt::Debug { mut pipelines, .. } => pipelines.destroy(device),
+            Rebuilt::Skybox { pipeline, .. } => pipeline.destroy(device),
+            Rebuilt::Post { pipelines, .. } => pipelines.into_iter().for_each(|p| p.destroy(device)),
+        }
+    }
+}
+
+// Recompiles and rebuilds the given pipelines. Every new pipeline is built
+// before any is replaced, so if one fails to compile or build all the last
+// good pipelines stay in use.
+pub unsafe fn rebuild_pipelines(instance: &Instance, device: &Device, data: &mut AppData, pipelines: &[&'static str]) -> Result<()> {
+    let mut rebuilt = Vec::new();
+    for pipeline in pipelines {
+        let result = match (*pipeline, data.render_path) {
+            (MAIN_PIPELINE, RenderPath::Forward) => rebuild_main_pipeline(device, data).map(Some),
+            (DEFERRED_PIPELINES, RenderPath::Deferred) => rebuild_deferred_pipelines(device, data).map(Some),
+            (DEBUG_PIPELINE, RenderPath::Forward) => rebuild_debug_pipeline(device, data).map(Some),
+            (POST_PIPELINES, RenderPath::Forward) => rebuild_post_pipelines(device, data),
+            (SKYBOX_PIPELINE, RenderPath::Forward) => rebuild_skybox_pipeline(device, data),
+            // Belongs to the other render path.
+            (MAIN_PIPELINE | DEFERRED_PIPELINES | DEBUG_PIPELINE | POST_PIPELINES | SKYBOX_PIPELINE, _) => Ok(None),
+            _ => {
+                warn!("No rebuild handler for pipeline `{}`.", pipeline);
+                Ok(None)
+            }
+        };
+        match result {
+            Ok(pipeline) => rebuilt.extend(pipeline),
+            Err(e) => {
+                rebuilt.into_iter().for_each(|r| r.destroy(device));
+                return Err(e);
+            }
+        }
+    }
+
+    if rebuilt.is_empty() {
+        return Ok(());
+    }
+
+    // Once anything is swapped the recorded command buffers are stale, so they
+    // are re-recorded even if a later swap fails.
+    device.device_wait_idle()?;
+    let mut result = Ok(());
+    for pipeline in rebuilt {
+        if let Err(e) = pipeline.swap(device, data) {
+            result = result.and(Err(e));
+        }
+    }
+
+    core::commands::destroy_command_buffers(device, data);
+    core::commands::create_command_buffers(instance, device, data)?;
+
+    result
+}
+
+unsafe fn rebuild_main_pipeline(device: &Device, data: &mut AppData) -> Result<Rebuilt> {
+    let vert_shader = compile(core::pipeline::VERT_SHADER).context("Failed to compile vertex shader")?;
+    let frag_shader = compile(core::pipeline::frag_shader(data)).context("Failed to compile fragment shader")?;
+
+    let state = core::pipeline::PipelineState::forward(data);
+    let (descriptor_set_layouts, pipeline_layout, pipeline) =
+        core::pipeline::build_pipeline::<Vertex>(device, data, &vert_shader, &frag_shader, state)?;
+
+    Ok(Rebuilt::Main { vert_shader, frag_shader, descriptor_set_layouts, pipeline_layout, pipeline })
+}
+
+unsafe fn rebuild_deferred_pipelines(device: &Device, data: &mut AppData) -> Result<Rebuilt> {
+    let names = [
+        core::deferred::GBUFFER_VERT,
+        core::deferred::gbuffer_frag(data),
+        core::deferred::LIGHTING_VERT,
+        core::deferred::LIGHTING_FRAG,
+    ];
+    let [gbuffer_vert, gbuffer_frag, lighting_vert, lighting_frag] = names
+        .map(|name| compile(name).with_context(|| format!("Failed to compile {}", name)));
+    let shaders = [gbuffer_vert?, gbuffer_frag?, lighting_vert?, lighting_frag?];
+
+    let pipelines = core::deferred::build_pipelines(device, data, [&shaders[0], &shaders[1], &shaders[2], &shaders[3]])?;
+
+    Ok(Rebuilt::Deferred { shaders, pipelines })
+}
+
+unsafe fn rebuild_debug_pipeline(device: &Device, data: &mut AppData) -> Result<Rebuilt> {
+    let vert_shader = compile(core::debug_view::DEBUG_VERT).context("Failed to compile debug vertex shader")?;
+    let frag_shader = compile(core::debug_view::DEBUG_FRAG).context("Failed to compile debug fragment shader")?;
+
+    // Only the active view is built with the new shaders; the others are built
+    // when selected again. The cached views stay in use until the swap.
+    let previous = (
+        std::mem::replace(&mut data.debug.vert_shader, vert_shader),
+        std::mem::replace(&mut data.debug.frag_shader, frag_shader),
+        std::mem::take(&mut data.debug.pipelines),
+    );
+    let result = core::debug_view::create_pipeline(device, data);
+    let rebuilt = Rebuilt::Debug {
+        vert_shader: std::mem::take(&mut data.debug.vert_shader),
+        frag_shader: std::mem::take(&mut data.debug.frag_shader),
+        pipelines: std::mem::take(&mut data.debug.pipelines),
+    };
+    (data.debug.vert_shader, data.debug.frag_shader, data.debug.pipelines) = previous;
+
+    match result {
+        Ok(()) => Ok(rebuilt),
+        Err(e) => {
+            rebuilt.destroy(device);
+            Err(e)
+        }
+    }
+}
+
+unsafe fn rebuild_skybox_pipeline(device: &Device, data: &mut AppData) -> Result<Option<Rebuilt>> {
+    if !core::skybox::is_enabled(data) {
+        return Ok(None);
+    }
+
+    let vert_shader = compile(core::skybox::SKYBOX_VERT).context("Failed to compile skybox vertex shader")?;
+    let frag_shader = compile(core::skybox::SKYBOX_FRAG).context("Failed to compile skybox fragment shader")?;
+    let pipeline = GraphicsPipeline::build::<()>(device, data, &vert_shader, &frag_shader, core::skybox::state(data))?;
+
+    Ok(Some(Rebuilt::Skybox { vert_shader, frag_shader, pipeline }))
+}
+
+unsafe fn rebuild_post_pipelines(device: &Device, data: &mut AppData) -> Result<Option<Rebuilt>> {
+    if !core::post::is_enabled(data) {
+        return Ok(None);
+    }
+
+    let mut shaders = HashMap::new();
+    for name in std::iter::once(core::post::FULLSCREEN_VERT).chain(core::post::EFFECT_SHADERS) {
+        shaders.insert(name, compile(name).with_context(|| format!("Failed to compile {}", name))?);
+    }
+    let pipelines = core::post::build_pipelines(device, data, &shaders)?;
+
+    Ok(Some(Rebuilt::Post { shaders, pipelines }))
+}
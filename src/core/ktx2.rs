@@ -0,0 +1,206 @@
+use anyhow::{Result, anyhow};
+use vulkanalia::prelude::v1_0::*;
+
+use std::path::Path;
+
+use crate::core;
+use crate::core::texture::Prebaked;
+
+const IDENTIFIER: [u8; 12] = [0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n'];
+
+// The header and index before the level index.
+const HEADER_SIZE: usize = 80;
+// Byte offset, byte length and uncompressed byte length of each level.
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+pub fn load(path: &Path) -> Result<Prebaked> {
+    parse(&std::fs::read(path)?)
+}
+
+// Reads a KTX2 file without supercompression. Levels hold each layer's faces
+// in turn, which matches the array layers of a Vulkan cube map.
+pub fn parse(bytes: &[u8]) -> Result<Prebaked> {
+    if bytes.get(..IDENTIFIER.len()) != Some(&IDENTIFIER[..]) {
+        return Err(anyhow!("Not a KTX2 file"));
+    }
+
+    let format = vk::Format::from_raw(read_u32(bytes, 12)? as i32);
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?;
+    let depth = read_u32(bytes, 28)?;
+    let layer_count = read_u32(bytes, 32)?;
+    let face_count = read_u32(bytes, 36)?;
+    let level_count = read_u32(bytes, 40)?;
+    let supercompression = read_u32(bytes, 44)?;
+
+    match supercompression {
+        0 => {}
+        1 => return Err(anyhow!("BasisLZ supercompressed KTX2 files aren't supported")),
+        2 => return Err(anyhow!("Zstandard supercompressed KTX2 files aren't supported")),
+        3 => return Err(anyhow!("ZLIB supercompressed KTX2 files aren't supported")),
+        scheme => return Err(anyhow!("Unknown KTX2 supercompression scheme {}", scheme)),
+    }
+    if format == vk::Format::UNDEFINED {
+        return Err(anyhow!("KTX2 files without a Vulkan format (Basis Universal) aren't supported"));
+    }
+    if depth > 1 {
+        return Err(anyhow!("3D KTX2 textures aren't supported"));
+    }
+    if face_count != 1 && face_count != 6 {
+        return Err(anyhow!("KTX2 file has {} faces; expected 1 or 6", face_count));
+    }
+    let info = core::block_compression::block_info(format)
+        .ok_or_else(|| anyhow!("KTX2 format {:?} isn't supported", format))?;
+
+    // 1D textures have a height of 0, and a level count of 0 asks the loader to
+    // generate mips, which prebaked formats can't; only level 0 is loaded then.
+    if width == 0 {
+        return Err(anyhow!("KTX2 file has a width of 0"));
+    }
+    let extent = vk::Extent2D { width, height: height.max(1) };
+    let level_count = level_count.max(1);
+    if level_count > core::mipmap::mip_levels(extent) {
+        return Err(anyhow!(
+            "KTX2 file has {} levels, but a {}x{} mip chain has only {}",
+            level_count,
+            extent.width,
+            extent.height,
+            core::mipmap::mip_levels(extent),
+        ));
+    }
+    let layers = layer_count
+        .max(1)
+        .checked_mul(face_count)
+        .ok_or_else(|| anyhow!("KTX2 file has too many layers ({})", layer_count))?;
+
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+        let entry = HEADER_SIZE + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+        let offset = read_u64(bytes, entry)?;
+        let length = read_u64(bytes, entry + 8)?;
+
+        let expected = info
+            .checked_size(core::mipmap::level_extent(extent, level), layers)
+            .ok_or_else(|| anyhow!("KTX2 level {} is too large", level))?;
+        if length < expected as u64 {
+            return Err(anyhow!("KTX2 level {} has {} bytes; expected {}", level, length, expected));
+        }
+        let texels = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| bytes.get(offset..offset.checked_add(expected)?))
+            .ok_or_else(|| anyhow!("KTX2 level {} is past the end of the file", level))?;
+        levels.push(texels.to_vec());
+    }
+
+    Ok(Prebaked { format, extent, layers, cube: face_count == 6, levels })
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    let field = bytes.get(offset..offset + 4).ok_or_else(|| anyhow!("KTX2 header is truncated"))?;
+    Ok(u32::from_le_bytes(field.try_into().unwrap()))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    let field = bytes.get(offset..offset + 8).ok_or_else(|| anyhow!("KTX2 level index is truncated"))?;
+    Ok(u64::from_le_bytes(field.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 4x4 RGBA8 file with one level, one layer and one face.
+    fn header(level_count: u32) -> Vec<u8> {
+        let mut bytes = IDENTIFIER.to_vec();
+        for field in [vk::Format::R8G8B8A8_UNORM.as_raw() as u32, 1, 4, 4, 0, 0, 1, level_count, 0] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.resize(HEADER_SIZE, 0);
+        bytes
+    }
+
+    fn with_levels(mut bytes: Vec<u8>, levels: &[(u64, u64)]) -> Vec<u8> {
+        for (offset, length) in levels {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        match parse(bytes) {
+            Ok(_) => panic!("parsed a malformed KTX2 file"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_levels() {
+        let offset = (HEADER_SIZE + 3 * LEVEL_INDEX_ENTRY_SIZE) as u64;
+        let mut bytes = with_levels(header(3), &[(offset, 64), (offset + 64, 16), (offset + 80, 4)]);
+        bytes.extend((0..84).map(|i| i as u8));
+
+        let prebaked = parse(&bytes).unwrap();
+        assert_eq!(prebaked.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(prebaked.extent, vk::Extent2D { width: 4, height: 4 });
+        assert_eq!((prebaked.layers, prebaked.cube), (1, false));
+        assert_eq!(prebaked.levels.iter().map(Vec::len).collect::<Vec<_>>(), [64, 16, 4]);
+        assert_eq!(prebaked.levels[2], [80, 81, 82, 83]);
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        let offset = (HEADER_SIZE + LEVEL_INDEX_ENTRY_SIZE) as u64;
+        let mut bytes = with_levels(header(1), &[(offset, 64)]);
+        bytes.extend([0; 64]);
+        assert!(parse(&bytes).is_ok());
+
+        assert_eq!(error(&bytes[..8]), "Not a KTX2 file");
+        assert_eq!(error(&bytes[..30]), "KTX2 header is truncated");
+        assert_eq!(error(&bytes[..HEADER_SIZE + 12]), "KTX2 level index is truncated");
+        assert_eq!(error(&bytes[..bytes.len() - 1]), "KTX2 level 0 is past the end of the file");
+    }
+
+    #[test]
+    fn rejects_bad_counts() {
+        let offset = (HEADER_SIZE + LEVEL_INDEX_ENTRY_SIZE) as u64;
+        let mut valid = with_levels(header(1), &[(offset, 64)]);
+        valid.extend([0; 64]);
+
+        let mut bytes = valid.clone();
+        set_u32(&mut bytes, 20, 0);
+        assert_eq!(error(&bytes), "KTX2 file has a width of 0");
+
+        let mut bytes = valid.clone();
+        set_u32(&mut bytes, 40, 4);
+        assert_eq!(error(&bytes), "KTX2 file has 4 levels, but a 4x4 mip chain has only 3");
+
+        let mut bytes = valid.clone();
+        set_u32(&mut bytes, 36, 5);
+        assert_eq!(error(&bytes), "KTX2 file has 5 faces; expected 1 or 6");
+
+        let mut bytes = valid.clone();
+        set_u32(&mut bytes, 32, u32::MAX);
+        set_u32(&mut bytes, 36, 6);
+        assert!(error(&bytes).starts_with("KTX2 file has too many layers"));
+
+        let mut bytes = valid.clone();
+        set_u32(&mut bytes, 20, u32::MAX);
+        set_u32(&mut bytes, 24, u32::MAX);
+        set_u32(&mut bytes, 32, u32::MAX);
+        assert_eq!(error(&bytes), "KTX2 level 0 is too large");
+
+        let mut bytes = valid.clone();
+        bytes[HEADER_SIZE + 8..HEADER_SIZE + 16].copy_from_slice(&63u64.to_le_bytes());
+        assert_eq!(error(&bytes), "KTX2 level 0 has 63 bytes; expected 64");
+
+        let mut bytes = valid;
+        bytes[HEADER_SIZE..HEADER_SIZE + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(error(&bytes), "KTX2 level 0 is past the end of the file");
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+use vulkanalia::Version;
+
+use crate::AppData;
+use crate::core;
+use crate::core::compute::ComputePipeline;
+use crate::core::descriptor::DescriptorAllocator;
+use crate::core::push_constants::PushConstants;
+
+pub const MIPMAP_COMP: &str = "mipmap.comp";
+
+// The format the compute fallback loads and stores each level as.
+const STORAGE_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+// Levels down to 1x1.
+pub fn mip_levels(extent: vk::Extent2D) -> u32 {
+    u32::BITS - extent.width.max(extent.height).max(1).leading_zeros()
+}
+
+pub fn level_extent(extent: vk::Extent2D, level: u32) -> vk::Extent2D {
+    vk::Extent2D { width: (extent.width >> level).max(1), height: (extent.height >> level).max(1) }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MipGeneration {
+    // `vkCmdBlitImage` with linear filtering, which decodes sRGB before
+    // filtering and encodes it again when writing.
+    Blit,
+    // `mipmap.comp` through RGBA8 storage views of each level.
+    Compute,
+}
+
+impl MipGeneration {
+    // `None` when the device can neither blit nor run the compute fallback.
+    pub unsafe fn get(instance: &Instance, data: &AppData, format: vk::Format) -> Option<Self> {
+        let properties = instance.get_physical_device_format_properties(data.physical_device, format);
+        let blit = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        if properties.optimal_tiling_features.contains(blit) {
+            return Some(Self::Blit);
+        }
+
+        let storage = instance.get_physical_device_format_properties(data.physical_device, STORAGE_FORMAT);
+        if !storage.optimal_tiling_features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE) {
+            return None;
+        }
+        // Storage views of an sRGB image need `EXTENDED_USAGE`, core in 1.1.
+        let device_version = Version::from(instance.get_physical_device_properties(data.physical_device).api_version);
+        let extended_usage = device_version.min(instance.version()) >= Version::new(1, 1, 0);
+        match format {
+            STORAGE_FORMAT => Some(Self::Compute),
+            vk::Format::R8G8B8A8_SRGB if extended_usage => Some(Self::Compute),
+            _ => None,
+        }
+    }
+
+    pub fn image_usage(self) -> vk::ImageUsageFlags {
+        match self {
+            Self::Blit => vk::ImageUsageFlags::TRANSFER_SRC,
+            Self::Compute => vk::ImageUsageFlags::STORAGE,
+        }
+    }
+
+    pub fn image_flags(self, format: vk::Format) -> vk::ImageCreateFlags {
+        match self {
+            Self::Compute if format != STORAGE_FORMAT => {
+                vk::ImageCreateFlags::MUTABLE_FORMAT | vk::ImageCreateFlags::EXTENDED_USAGE
+            }
+            _ => vk::ImageCreateFlags::empty(),
+        }
+    }
+}
+
+unsafe fn level_barrier(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    levels: (u32, u32),
+    layouts: (vk::ImageLayout, vk::ImageLayout),
+    access: (vk::AccessFlags, vk::AccessFlags),
+    stages: (vk::PipelineStageFlags, vk::PipelineStageFlags),
+) {
+    let subresource = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(levels.0)
+        .level_count(levels.1)
+        .base_array_layer(0)
+        .layer_count(1);
+    let barrier = vk::ImageMemoryBarrier::builder()
+        .old_layout(layouts.0)
+        .new_layout(layouts.1)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource)
+        .src_access_mask(access.0)
+        .dst_access_mask(access.1);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        stages.0,
+        stages.1,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier],
+    );
+}
+
+// Expects every level in TRANSFER_DST_OPTIMAL with level 0 written, and leaves
+// them all in SHADER_READ_ONLY_OPTIMAL.
+pub unsafe fn record_blits(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    extent: vk::Extent2D,
+    mip_levels: u32,
+) {
+    for level in 1..mip_levels {
+        level_barrier(
+            device,
+            command_buffer,
+            image,
+            (level - 1, 1),
+            (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            (vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::TRANSFER_READ),
+            (vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::TRANSFER),
+        );
+
+        let (src, dst) = (level_extent(extent, level - 1), level_extent(extent, level));
+        let subresource = |level| {
+            vk::ImageSubresourceLayers::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(level)
+                .base_array_layer(0)
+                .layer_count(1)
+        };
+        let corner = |extent: vk::Extent2D| vk::Offset3D { x: extent.width as i32, y: extent.height as i32, z: 1 };
+        let blit = vk::ImageBlit::builder()
+            .src_offsets([vk::Offset3D::default(), corner(src)])
+            .src_subresource(subresource(level - 1))
+            .dst_offsets([vk::Offset3D::default(), corner(dst)])
+            .dst_subresource(subresource(level));
+        device.cmd_blit_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[blit],
+            vk::Filter::LINEAR,
+        );
+
+        level_barrier(
+            device,
+            command_buffer,
+            image,
+            (level - 1, 1),
+            (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (vk::AccessFlags::TRANSFER_READ, vk::AccessFlags::SHADER_READ),
+            (vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER),
+        );
+    }
+
+    level_barrier(
+        device,
+        command_buffer,
+        image,
+        (mip_levels - 1, 1),
+        (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        (vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::SHADER_READ),
+        (vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER),
+    );
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct MipmapParams {
+    srgb: u32,
+}
+
+// SAFETY: a single `u32`.
+unsafe impl PushConstants for MipmapParams {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::COMPUTE;
+}
+
+// What the compute fallback needs while it records, created before the one-off
+// submission and destroyed after it.
+#[derive(Clone, Debug, Default)]
+pub struct ComputeMipmaps {
+    pipeline: ComputePipeline,
+    allocator: DescriptorAllocator,
+    views: Vec<vk::ImageView>,
+    // One per generated level, reading the level above it.
+    sets: Vec<vk::DescriptorSet>,
+}
+
+impl ComputeMipmaps {
+    pub unsafe fn new(device: &Device, data: &AppData, image: vk::Image, mip_levels: u32) -> Result<Self> {
+        let pipeline = ComputePipeline::from_shader(device, data, MIPMAP_COMP)?;
+        let mut mipmaps = Self { pipeline, ..Default::default() };
+        if let Err(e) = mipmaps.create_sets(device, image, mip_levels) {
+            mipmaps.destroy(device);
+            return Err(e);
+        }
+        Ok(mipmaps)
+    }
+
+    unsafe fn create_sets(&mut self, device: &Device, image: vk::Image, mip_levels: u32) -> Result<()> {
+        for level in 0..mip_levels {
+            let subresource = vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(level)
+                .level_count(1)
+                .base_array_layer(0)
+                .layer_count(1);
+            let info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::_2D)
+                .format(STORAGE_FORMAT)
+                .subresource_range(subresource);
+            self.views.push(device.create_image_view(&info, None)?);
+        }
+
+        for level in 1..mip_levels as usize {
+            let set = self.allocator.allocate(device, self.pipeline.set_layouts[0])?;
+            core::compute::write_storage_image(device, set, 0, self.views[level - 1]);
+            core::compute::write_storage_image(device, set, 1, self.views[level]);
+            self.sets.push(set);
+        }
+
+        Ok(())
+    }
+
+    // Expects every level in TRANSFER_DST_OPTIMAL with level 0 written, and
+    // leaves them all in SHADER_READ_ONLY_OPTIMAL.
+    pub unsafe fn record(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        extent: vk::Extent2D,
+        srgb: bool,
+    ) {
+        let mip_levels = self.views.len() as u32;
+        level_barrier(
+            device,
+            command_buffer,
+            image,
+            (0, mip_levels),
+            (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::GENERAL),
+            (vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE),
+            (vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::COMPUTE_SHADER),
+        );
+
+        let params = MipmapParams { srgb: srgb as u32 };
+        for (level, set) in (1..mip_levels).zip(&self.sets) {
+            self.pipeline.bind(device, command_buffer, &[*set]);
+            core::push_constants::push(device, command_buffer, self.pipeline.layout, &params);
+            let dst = level_extent(extent, level);
+            self.pipeline.dispatch(device, command_buffer, [dst.width, dst.height, 1]);
+
+            level_barrier(
+                device,
+                command_buffer,
+                image,
+                (level, 1),
+                (vk::ImageLayout::GENERAL, vk::ImageLayout::GENERAL),
+                (vk::AccessFlags::SHADER_WRITE, vk::AccessFlags::SHADER_READ),
+                (vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::COMPUTE_SHADER),
+            );
+        }
+
+        level_barrier(
+            device,
+            command_buffer,
+            image,
+            (0, mip_levels),
+            (vk::ImageLayout::GENERAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (vk::AccessFlags::SHADER_WRITE, vk::AccessFlags::SHADER_READ),
+            (vk::PipelineStageFlags::COMPUTE_SHADER, vk::PipelineStageFlags::FRAGMENT_SHADER),
+        );
+    }
+
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        self.sets.clear();
+        self.allocator.destroy(device);
+        self.views.drain(..).for_each(|v| device.destroy_image_view(v, None));
+        self.pipeline.destroy(device);
+    }
+}
@@ -1,8 +1,40 @@
 pub mod config;
 
 pub mod pipeline;
+pub mod push_constants;
+pub mod specialization;
 pub mod swapchain;
 pub mod commands;
+#[cfg(feature = "runtime-shaders")]
 pub mod compiler;
+#[cfg(feature = "runtime-shaders")]
+pub mod hot_reload;
+#[cfg(feature = "runtime-shaders")]
+pub mod shader_cache;
+pub mod shaders;
+pub mod reflect;
 pub mod vertex;
+pub mod vertex_input;
+pub mod model;
+pub mod gltf;
 pub mod descriptor;
+pub mod bindless;
+pub mod uniform;
+pub mod image;
+pub mod texture;
+pub mod cubemap;
+pub mod skybox;
+pub mod block_compression;
+pub mod ktx2;
+pub mod dds;
+pub mod mipmap;
+pub mod depth;
+pub mod msaa;
+pub mod render_graph;
+pub mod deferred;
+pub mod dynamic_rendering;
+pub mod debug_view;
+pub mod post;
+pub mod compute;
+#[cfg(test)]
+pub mod test_util;
@@ -0,0 +1,282 @@
+use anyhow::{Context, Result, anyhow};
+use cgmath::{Deg, InnerSpace, vec2, vec3};
+use log::*;
+use vulkanalia::prelude::v1_0::*;
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::Path;
+
+use crate::AppData;
+use crate::core;
+use crate::core::bindless::{ImageIndex, SamplerIndex};
+use crate::core::pipeline::ObjectConstants;
+use crate::core::vertex::Vertex;
+
+type Vec3 = cgmath::Vector3<f32>;
+type Mat4 = cgmath::Matrix4<f32>;
+
+// An indexed triangle list, ready for `create_vertex_buffer` and
+// `create_index_buffer`.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn quad() -> Self {
+        Self { vertices: core::vertex::VERTICES.to_vec(), indices: core::vertex::INDICES.to_vec() }
+    }
+}
+
+// A run of the scene's indices and the texture it's drawn with: its bindless
+// texture and sampler, or without bindless textures its set at
+// `core::texture::TEXTURE_SET`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Draw {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub texture: ImageIndex,
+    pub sampler: SamplerIndex,
+    pub texture_set: vk::DescriptorSet,
+}
+
+impl Draw {
+    // Pushes the draw's constants and binds its texture set if it has one.
+    pub unsafe fn bind(&self, device: &Device, command_buffer: vk::CommandBuffer, layout: vk::PipelineLayout, model: Mat4) {
+        if self.texture_set != vk::DescriptorSet::null() {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                core::texture::TEXTURE_SET,
+                &[self.texture_set],
+                &[],
+            );
+        }
+        let constants = ObjectConstants { model, texture: self.texture.0, sampler: self.sampler.0 };
+        core::push_constants::push(device, command_buffer, layout, &constants);
+    }
+}
+
+// The uniform and bindless sets the scene binds from set 0, leaving out the
+// bindless table when draws bind their own texture sets.
+pub fn scene_sets(bindless_textures: bool, sets: &[vk::DescriptorSet; 2]) -> &[vk::DescriptorSet] {
+    if bindless_textures { sets } else { &sets[..1] }
+}
+
+// `core::config::MODEL`, or the quad without one, and sets `AppData::draws`
+// for it. OBJ models and the quad are one draw with the scene texture. glTF
+// scenes are turned from their +Y up to the camera's +Z up and bring their
+// textures, camera and lights (see `core::gltf::Scene::create`).
+pub unsafe fn load_scene(instance: &Instance, device: &Device, data: &mut AppData) -> Result<Mesh> {
+    let mesh = match core::config::MODEL.map(|path| Path::new(core::config::PROJECT_ROOT).join(path)) {
+        None => Mesh::quad(),
+        Some(path) => {
+            let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+            if let Some("gltf" | "glb") = extension.as_deref() {
+                let scene = core::gltf::load(&path)?;
+                let mesh = scene.create(instance, device, data, Mat4::from_angle_x(Deg(90.0)))?;
+                if mesh.indices.is_empty() {
+                    return Err(anyhow!("glTF {} has no triangles in its scene", path.display()));
+                }
+                return Ok(mesh);
+            }
+            load_obj(&path)?
+        }
+    };
+
+    data.draws = vec![Draw {
+        first_index: 0,
+        index_count: mesh.indices.len() as u32,
+        texture: data.texture_index,
+        sampler: data.sampler_index,
+        texture_set: data.texture_set,
+    }];
+    Ok(mesh)
+}
+
+// Reads every object in a Wavefront OBJ file into one mesh. Faces are
+// triangulated and corners sharing position, normal and texture coordinate
+// indices become one vertex. Vertices are colored by the OBJ's vertex colors,
+// else their MTL material's diffuse color, else white. Normals the file leaves
+// out are smoothed from the faces around each vertex.
+pub fn load_obj(path: &Path) -> Result<Mesh> {
+    let options = tobj::LoadOptions { triangulate: true, ignore_points: true, ignore_lines: true, ..Default::default() };
+    let (models, materials) =
+        tobj::load_obj(path, &options).with_context(|| format!("Failed to load model {}", path.display()))?;
+    let materials = materials.unwrap_or_else(|e| {
+        warn!("Failed to load materials of {}, drawing it white: {}", path.display(), e);
+        Vec::new()
+    });
+
+    let mut mesh = Mesh::default();
+    let mut needs_normal = Vec::new();
+    let mut unique = HashMap::new();
+    for (model_index, model) in models.iter().enumerate() {
+        let obj = &model.mesh;
+        let diffuse = obj.material_id.and_then(|id| materials.get(id)).map_or([1.0; 3], |m| m.diffuse);
+
+        for (corner, &position_index) in obj.indices.iter().enumerate() {
+            let normal_index = obj.normal_indices.get(corner).copied();
+            let texcoord_index = obj.texcoord_indices.get(corner).copied();
+            let index = match unique.entry((model_index, position_index, normal_index, texcoord_index)) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    let vertex = (|| -> Result<Vertex> {
+                        let [x, y, z] = attribute(&obj.positions, position_index, "position")?;
+                        let [r, g, b] = if obj.vertex_color.is_empty() {
+                            diffuse
+                        } else {
+                            attribute(&obj.vertex_color, position_index, "vertex color")?
+                        };
+                        let [u, v] = match texcoord_index {
+                            Some(index) => attribute(&obj.texcoords, index, "texture coordinate")?,
+                            None => [0.0; 2],
+                        };
+                        let [nx, ny, nz] = match normal_index {
+                            Some(index) => attribute(&obj.normals, index, "normal")?,
+                            None => [0.0; 3],
+                        };
+                        // OBJ puts v = 0 at the bottom of the image, Vulkan at the top.
+                        Ok(Vertex::new(vec3(x, y, z), vec3(nx, ny, nz), vec2(u, 1.0 - v), vec3(r, g, b)))
+                    })()
+                    .with_context(|| format!("Malformed object `{}` in {}", model.name, path.display()))?;
+
+                    mesh.vertices.push(vertex);
+                    needs_normal.push(normal_index.is_none());
+                    *entry.insert(mesh.vertices.len() as u32 - 1)
+                }
+            };
+            mesh.indices.push(index);
+        }
+    }
+
+    if mesh.indices.is_empty() {
+        return Err(anyhow!("Model {} has no faces", path.display()));
+    }
+    if needs_normal.contains(&true) {
+        smooth_normals(&mut mesh, &needs_normal);
+    }
+
+    Ok(mesh)
+}
+
+fn attribute<const N: usize>(values: &[f32], index: u32, name: &str) -> Result<[f32; N]> {
+    let start = index as usize * N;
+    values
+        .get(start..start + N)
+        .map(|v| v.try_into().unwrap())
+        .ok_or_else(|| anyhow!("{} index {} is out of range ({} {}s)", name, index, values.len() / N, name))
+}
+
+// Area-weighted face normals, summed into the vertices without one.
+pub fn smooth_normals(mesh: &mut Mesh, needs_normal: &[bool]) {
+    let mut sums = vec![Vec3::new(0.0, 0.0, 0.0); mesh.vertices.len()];
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].pos);
+        let normal = (b - a).cross(c - a);
+        triangle.iter().for_each(|i| sums[*i as usize] += normal);
+    }
+
+    for ((vertex, sum), needs_normal) in mesh.vertices.iter_mut().zip(sums).zip(needs_normal) {
+        if *needs_normal {
+            vertex.normal = if sum.magnitude2() > 0.0 { sum.normalize() } else { vec3(0.0, 0.0, 1.0) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_util::TempDir;
+
+    const QUAD: &str = "\
+mtllib quad.mtl
+o quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0.25
+vt 1 0.25
+vt 1 1
+vt 0 1
+vn 0 0 1
+usemtl tinted
+f 1/1/1 2/2/1 3/3/1 4/4/1
+";
+
+    const MTL: &str = "newmtl tinted\nKd 0.5 0.25 1.0\n";
+
+    #[test]
+    fn merges_shared_corners_and_flips_v() {
+        let dir = TempDir::new("model-quad", &[("quad.obj", QUAD), ("quad.mtl", MTL)]);
+        let mesh = load_obj(&dir.join("quad.obj")).unwrap();
+
+        // Two triangles sharing the diagonal's corners.
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+        assert!(mesh.indices.iter().all(|i| (*i as usize) < mesh.vertices.len()));
+        let bottom_left = mesh.vertices.iter().find(|v| v.pos == vec3(0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(bottom_left.tex_coord, vec2(0.0, 0.75));
+        assert_eq!(bottom_left.normal, vec3(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn colors_vertices_by_material_else_white() {
+        let dir = TempDir::new("model-colors", &[("quad.obj", QUAD), ("quad.mtl", MTL)]);
+        let mesh = load_obj(&dir.join("quad.obj")).unwrap();
+        assert!(mesh.vertices.iter().all(|v| v.color == vec3(0.5, 0.25, 1.0)));
+
+        // The material library is missing, so the quad falls back to white.
+        let dir = TempDir::new("model-white", &[("quad.obj", QUAD)]);
+        let mesh = load_obj(&dir.join("quad.obj")).unwrap();
+        assert!(mesh.vertices.iter().all(|v| v.color == vec3(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let dir = TempDir::new("model-range", &[("bad.obj", "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 5\n")]);
+        let path = dir.join("bad.obj");
+        let error = format!("{:#}", load_obj(&path).unwrap_err());
+        assert!(error.contains(&path.display().to_string()), "{}", error);
+
+        let error = attribute::<3>(&[0.0; 6], 2, "position").unwrap_err();
+        assert_eq!(error.to_string(), "position index 2 is out of range (2 positions)");
+    }
+
+    #[test]
+    fn smooths_missing_normals() {
+        // A flat quad without normals faces +Z everywhere.
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+        let dir = TempDir::new("model-smooth", &[("flat.obj", obj)]);
+        let mesh = load_obj(&dir.join("flat.obj")).unwrap();
+        assert!(mesh.vertices.iter().all(|v| (v.normal - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-6));
+
+        // Two faces folded along the y axis, the second twice the area of the
+        // first: the shared edge averages them by area, the other corner needing
+        // a normal takes its face's, one with a normal keeps it and an unused one
+        // falls back to +Z.
+        let vertex = |x, y, z| Vertex::new(vec3(x, y, z), vec3(0.0, 1.0, 0.0), vec2(0.0, 0.0), vec3(1.0, 1.0, 1.0));
+        let mut mesh = Mesh {
+            vertices: vec![
+                vertex(0.0, 0.0, 0.0),
+                vertex(0.0, 1.0, 0.0),
+                vertex(1.0, 0.0, 0.0),
+                vertex(0.0, 0.0, 2.0),
+                vertex(5.0, 5.0, 5.0),
+            ],
+            indices: vec![0, 2, 1, 0, 1, 3],
+        };
+        smooth_normals(&mut mesh, &[true, true, true, false, true]);
+        let normals = mesh.vertices.iter().map(|v| v.normal).collect::<Vec<_>>();
+        let edge = vec3(2.0, 0.0, 1.0).normalize();
+        assert!((normals[0] - edge).magnitude() < 1e-6, "{:?}", normals);
+        assert!((normals[1] - edge).magnitude() < 1e-6, "{:?}", normals);
+        assert_eq!(normals[2], vec3(0.0, 0.0, 1.0));
+        assert_eq!(normals[3], vec3(0.0, 1.0, 0.0));
+        assert_eq!(normals[4], vec3(0.0, 0.0, 1.0));
+    }
+}
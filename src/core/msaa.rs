@@ -0,0 +1,136 @@
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+use crate::AppData;
+use crate::core;
+
+const SAMPLE_COUNTS: &[vk::SampleCountFlags] = &[
+    vk::SampleCountFlags::_64,
+    vk::SampleCountFlags::_32,
+    vk::SampleCountFlags::_16,
+    vk::SampleCountFlags::_8,
+    vk::SampleCountFlags::_4,
+    vk::SampleCountFlags::_2,
+    vk::SampleCountFlags::_1,
+];
+
+pub fn is_enabled(data: &AppData) -> bool {
+    data.msaa_samples != vk::SampleCountFlags::_1
+}
+
+// Sample counts usable for both the color and the depth attachment.
+pub unsafe fn get_supported_sample_counts(instance: &Instance, data: &AppData) -> vk::SampleCountFlags {
+    let properties = instance.get_physical_device_properties(data.physical_device);
+    properties.limits.framebuffer_color_sample_counts
+        & properties.limits.framebuffer_depth_sample_counts
+}
+
+// The highest supported sample count not above `requested`.
+pub unsafe fn get_sample_count(instance: &Instance, data: &AppData, requested: u32) -> vk::SampleCountFlags {
+    let supported = get_supported_sample_counts(instance, data);
+    SAMPLE_COUNTS
+        .iter()
+        .cloned()
+        .find(|c| c.bits() <= requested && supported.contains(*c))
+        .unwrap_or(vk::SampleCountFlags::_1)
+}
+
+// The next supported sample count after the current one, wrapping to 1x.
+pub unsafe fn get_next_sample_count(instance: &Instance, data: &AppData) -> vk::SampleCountFlags {
+    let supported = get_supported_sample_counts(instance, data);
+    SAMPLE_COUNTS
+        .iter()
+        .rev()
+        .cloned()
+        .find(|c| c.bits() > data.msaa_samples.bits() && supported.contains(*c))
+        .unwrap_or(vk::SampleCountFlags::_1)
+}
+
+pub unsafe fn create_color_objects(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+) -> Result<()> {
+    if !is_enabled(data) {
+        return Ok(());
+    }
+
+    let (color_image, color_image_memory) = core::image::create_image(
+        instance,
+        device,
+        data,
+        data.swapchain_extent.width,
+        data.swapchain_extent.height,
+        1,
+        data.msaa_samples,
+        core::post::scene_format(data),
+        vk::ImageTiling::OPTIMAL,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    )?;
+
+    data.color_image = color_image;
+    data.color_image_memory = color_image_memory;
+    data.color_image_view = core::image::create_image_view(
+        device,
+        data.color_image,
+        core::post::scene_format(data),
+        vk::ImageAspectFlags::COLOR,
+        1,
+    )?;
+
+    Ok(())
+}
+
+pub unsafe fn destroy_color_objects(device: &Device, data: &mut AppData) {
+    device.destroy_image_view(data.color_image_view, None);
+    device.free_memory(data.color_image_memory, None);
+    device.destroy_image(data.color_image, None);
+    data.color_image_view = vk::ImageView::null();
+    data.color_image_memory = vk::DeviceMemory::null();
+    data.color_image = vk::Image::null();
+}
+
+pub unsafe fn set_msaa_samples(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    samples: vk::SampleCountFlags,
+) -> Result<()> {
+    if data.render_path == core::config::RenderPath::Deferred {
+        warn!("MSAA is not supported on the deferred path.");
+        return Ok(());
+    }
+
+    let samples = get_sample_count(instance, data, samples.bits());
+    if samples == data.msaa_samples {
+        return Ok(());
+    }
+
+    info!("Switching MSAA from {:?} to {:?}.", data.msaa_samples, samples);
+    device.device_wait_idle()?;
+    core::swapchain::destroy_render_targets(device, data);
+    data.msaa_samples = samples;
+    core::swapchain::create_render_targets(instance, device, data)
+}
+
+pub unsafe fn set_sample_shading(
+    instance: &Instance,
+    device: &Device,
+    data: &mut AppData,
+    enabled: bool,
+) -> Result<()> {
+    if enabled && !data.sample_shading_supported {
+        warn!("Sample shading requested but not supported by the device.");
+        return Ok(());
+    }
+    if enabled == data.sample_shading {
+        return Ok(());
+    }
+
+    device.device_wait_idle()?;
+    core::swapchain::destroy_render_targets(device, data);
+    data.sample_shading = enabled;
+    core::swapchain::create_render_targets(instance, device, data)
+}
@@ -1,15 +1,210 @@
-use anyhow::{Result, Context};
+use anyhow::{Result, Context, anyhow};
 use vulkanalia::prelude::v1_0::*;
 use vulkanalia::bytecode::Bytecode;
 
 use crate::AppData;
 use crate::core;
+use crate::core::bindless::BINDLESS_SET;
+use crate::core::push_constants::PushConstants;
+use crate::core::specialization::StageSpecializations;
+use crate::core::vertex::Vertex;
+use crate::core::vertex_input::VertexInput;
+
+type Mat4 = cgmath::Matrix4<f32>;
+
+pub const VERT_SHADER: &str = "shader.vert";
+pub const FRAG_SHADER: &str = "shader.frag";
+pub const COMBINED_FRAG_SHADER: &str = "shader_combined.frag";
+
+// The scene fragment shader for the way textures are bound.
+pub fn frag_shader(data: &AppData) -> &'static str {
+    if data.bindless_textures { FRAG_SHADER } else { COMBINED_FRAG_SHADER }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: vk::CompareOp,
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self { test: true, write: true, compare_op: vk::CompareOp::LESS }
+    }
+}
+
+impl DepthState {
+    pub const DISABLED: Self = Self { test: false, write: false, compare_op: vk::CompareOp::ALWAYS };
+    pub const READ_ONLY: Self = Self { test: true, write: false, compare_op: vk::CompareOp::LESS_OR_EQUAL };
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Opaque,
+    Alpha,
+    Additive,
+}
+
+// Everything about a pipeline that isn't its shaders.
+#[derive(Copy, Clone, Debug)]
+pub struct PipelineState {
+    // Null for dynamic rendering, which takes the attachment formats instead.
+    pub render_pass: vk::RenderPass,
+    pub subpass: u32,
+    pub color_attachments: u32,
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+    pub depth: DepthState,
+    pub blend: BlendMode,
+    pub polygon_mode: vk::PolygonMode,
+    // The scene keeps its counter-clockwise world space winding through the
+    // y-flipped projection; fullscreen triangles are clockwise on screen.
+    pub front_face: vk::FrontFace,
+    // Set 0 is the per-frame `core::descriptor::uniform_bindings`.
+    pub uniforms: bool,
+    // Set 1 is the `core::bindless` table, with the shaders' arrays specialized
+    // to its size.
+    pub bindless: bool,
+    // Set 1 is `core::texture::texture_bindings`, the draw's texture.
+    pub textures: bool,
+    // The typed block the pipeline is pushed with, see `PushConstants::range`.
+    // Without one the layout takes the ranges reflected from the shaders.
+    pub push_constants: Option<vk::PushConstantRange>,
+}
+
+impl PipelineState {
+    pub fn forward(data: &AppData) -> Self {
+        Self {
+            render_pass: data.render_pass,
+            subpass: 0,
+            color_attachments: 1,
+            color_format: core::post::scene_format(data),
+            depth_format: data.depth_format,
+            samples: data.msaa_samples,
+            depth: DepthState::default(),
+            blend: BlendMode::Alpha,
+            polygon_mode: vk::PolygonMode::FILL,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            uniforms: true,
+            bindless: data.bindless_textures,
+            textures: !data.bindless_textures,
+            push_constants: Some(ObjectConstants::range()),
+        }
+    }
+}
+
+// Per-draw data of the scene shaders: the object transform, then the
+// fragment shader's texture and sampler in the bindless table, which the
+// combined image sampler shaders leave unread.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ObjectConstants {
+    pub model: Mat4,
+    pub texture: u32,
+    pub sampler: u32,
+}
+
+// SAFETY: `repr(C)` with a 64-byte matrix and two `u32`s, so no padding.
+unsafe impl PushConstants for ObjectConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX.union(vk::ShaderStageFlags::FRAGMENT);
+}
+
+// A pipeline together with the layouts it was built with. Builders take the
+// `VertexInput` the pipeline reads, `()` for fullscreen passes that generate
+// their vertices in the shader.
+#[derive(Clone, Debug, Default)]
+pub struct GraphicsPipeline {
+    pub set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+}
+
+impl GraphicsPipeline {
+    pub unsafe fn build<V: VertexInput>(
+        device: &Device,
+        data: &AppData,
+        vert_binary: &[u32],
+        frag_binary: &[u32],
+        state: PipelineState,
+    ) -> Result<Self> {
+        Self::build_specialized::<V>(device, data, vert_binary, frag_binary, state, &StageSpecializations::default())
+    }
+
+    pub unsafe fn build_specialized<V: VertexInput>(
+        device: &Device,
+        data: &AppData,
+        vert_binary: &[u32],
+        frag_binary: &[u32],
+        state: PipelineState,
+        specializations: &StageSpecializations,
+    ) -> Result<Self> {
+        let (set_layouts, layout, pipeline) =
+            build_specialized_pipeline::<V>(device, data, vert_binary, frag_binary, state, specializations)?;
+        Ok(Self { set_layouts, layout, pipeline })
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.layout, None);
+    }
+}
+
+pub fn load_shaders(data: &mut AppData) -> Result<()> {
+    data.vert_shader = core::shaders::load_shader(VERT_SHADER)
+        .context("Failed to load vertex shader")?;
+    data.frag_shader = core::shaders::load_shader(frag_shader(data))
+        .context("Failed to load fragment shader")?;
+
+    Ok(())
+}
 
 pub unsafe fn create_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
-    let vert_binary = core::compiler::compile_shader("src/shader/shader.vert", shaderc::ShaderKind::Vertex)
-        .context("Failed to compile vertex shader")?;
-    let frag_binary = core::compiler::compile_shader("src/shader/shader.frag", shaderc::ShaderKind::Fragment)
-        .context("Failed to compile fragment shader")?;
+    let (descriptor_set_layouts, pipeline_layout, pipeline) =
+        build_pipeline::<Vertex>(device, data, &data.vert_shader, &data.frag_shader, PipelineState::forward(data))?;
+
+    data.descriptor_set_layouts = descriptor_set_layouts;
+    data.pipeline_layout = pipeline_layout;
+    data.pipeline = pipeline;
+
+    Ok(())
+}
+
+// Builds the pipeline objects without touching `data`, so a failed rebuild
+// leaves the current pipeline in place.
+pub unsafe fn build_pipeline<V: VertexInput>(
+    device: &Device,
+    data: &AppData,
+    vert_binary: &[u32],
+    frag_binary: &[u32],
+    state: PipelineState,
+) -> Result<(Vec<vk::DescriptorSetLayout>, vk::PipelineLayout, vk::Pipeline)> {
+    build_specialized_pipeline::<V>(device, data, vert_binary, frag_binary, state, &StageSpecializations::default())
+}
+
+pub unsafe fn build_specialized_pipeline<V: VertexInput>(
+    device: &Device,
+    data: &AppData,
+    vert_binary: &[u32],
+    frag_binary: &[u32],
+    state: PipelineState,
+    specializations: &StageSpecializations,
+) -> Result<(Vec<vk::DescriptorSetLayout>, vk::PipelineLayout, vk::Pipeline)> {
+    let vert_reflection = core::reflect::reflect_shader(vert_binary)
+        .context("Failed to reflect vertex shader")?;
+    let frag_reflection = core::reflect::reflect_shader(frag_binary)
+        .context("Failed to reflect fragment shader")?;
+    specializations.vertex.validate(&vert_reflection)?;
+    specializations.fragment.validate(&frag_reflection)?;
+    let specializations = if state.bindless {
+        &StageSpecializations {
+            vertex: data.bindless.specialize(&specializations.vertex, &vert_reflection),
+            fragment: data.bindless.specialize(&specializations.fragment, &frag_reflection),
+        }
+    } else {
+        specializations
+    };
 
     let vert_temp_slice = unsafe {
         std::slice::from_raw_parts(vert_binary.as_ptr() as *const u8, vert_binary.len() * 4)
@@ -18,134 +213,287 @@ pub unsafe fn create_pipeline(device: &Device, data: &mut AppData) -> Result<()>
         std::slice::from_raw_parts(frag_binary.as_ptr() as *const u8, frag_binary.len() * 4)
     };
 
-    let vert_shader_module = create_shader_module(device, vert_temp_slice)?;
-    let frag_shader_module = create_shader_module(device, frag_temp_slice)?;
+    // Check everything against the reflection before creating any Vulkan
+    // objects, so the error paths below only clean up what they made.
+    let (binding_descriptions, attribute_descriptions) = V::descriptions();
+    core::reflect::validate_vertex_input(&vert_reflection, &binding_descriptions, &attribute_descriptions)
+        .with_context(|| format!("{} does not match vertex shader inputs", std::any::type_name::<V>()))?;
+    let reflections = &[&vert_reflection, &frag_reflection];
+    if state.uniforms {
+        core::reflect::validate_descriptor_set_layout(reflections, 0, &core::descriptor::uniform_bindings())?;
+    }
+    if state.bindless && state.textures {
+        return Err(anyhow!("The bindless table and the texture set both take set {}", BINDLESS_SET));
+    }
+    if state.textures {
+        core::reflect::validate_descriptor_set_layout(
+            reflections,
+            core::texture::TEXTURE_SET,
+            &core::texture::texture_bindings(),
+        )?;
+    }
+    let mut external_sets = Vec::new();
+    if state.bindless {
+        core::reflect::validate_descriptor_set_layout(reflections, BINDLESS_SET, &data.bindless.bindings())?;
+        external_sets.push((BINDLESS_SET, data.bindless.layout));
+    }
+    let reflected_ranges = core::reflect::push_constant_ranges(reflections);
+    let push_constant_ranges = match state.push_constants {
+        Some(range) => {
+            core::push_constants::validate(data, range)?;
+            core::push_constants::validate_reflection(range, &reflected_ranges)
+                .context("Push constant struct does not match the shaders")?;
+            vec![range]
+        }
+        None => reflected_ranges,
+    };
+    let descriptor_set_layouts =
+        core::descriptor::create_descriptor_set_layouts_from_reflection(
+            device,
+            &data.descriptors.layout_cache,
+            reflections,
+            &external_sets,
+        )?;
+    let layout_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(&descriptor_set_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+    let pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+
+    let vert_shader_module = match create_shader_module(device, vert_temp_slice) {
+        Ok(module) => module,
+        Err(e) => {
+            device.destroy_pipeline_layout(pipeline_layout, None);
+            return Err(e);
+        }
+    };
+    let frag_shader_module = match create_shader_module(device, frag_temp_slice) {
+        Ok(module) => module,
+        Err(e) => {
+            device.destroy_shader_module(vert_shader_module, None);
+            device.destroy_pipeline_layout(pipeline_layout, None);
+            return Err(e);
+        }
+    };
 
+    let (vert_entries, vert_data) = specializations.vertex.entries();
+    let vert_specialization = vk::SpecializationInfo::builder()
+        .map_entries(&vert_entries)
+        .data(&vert_data);
     let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
         .stage(vk::ShaderStageFlags::VERTEX)
         .module(vert_shader_module)
-        .name(b"main\0");
+        .name(b"main\0")
+        .specialization_info(&vert_specialization);
 
+    let (frag_entries, frag_data) = specializations.fragment.entries();
+    let frag_specialization = vk::SpecializationInfo::builder()
+        .map_entries(&frag_entries)
+        .data(&frag_data);
     let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
         .stage(vk::ShaderStageFlags::FRAGMENT)
         .module(frag_shader_module)
-        .name(b"main\0");
+        .name(b"main\0")
+        .specialization_info(&frag_specialization);
 
-    let binding_descriptions = &[core::vertex::Vertex::binding_descriptions()];
-    let attribute_descriptions = core::vertex::Vertex::attribute_descriptions();
     let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
-        .vertex_binding_descriptions(binding_descriptions)
+        .vertex_binding_descriptions(&binding_descriptions)
         .vertex_attribute_descriptions(&attribute_descriptions);
 
     let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
         .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
         .primitive_restart_enable(false);
 
-    let viewport = vk::Viewport::builder()
-        .x(0.0)
-        .y(0.0)
-        .width(data.swapchain_extent.width as f32)
-        .height(data.swapchain_extent.height as f32)
-        .min_depth(0.0)
-        .max_depth(1.0);
-
-    let scissor = vk::Rect2D::builder()
-        .offset(vk::Offset2D { x: 0, y: 0 })
-        .extent(data.swapchain_extent);
-
-    let viewports = &[viewport];
-    let scissors = &[scissor];
+    // Viewport and scissor are set when recording, so pipelines outlive resizes.
     let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-        .viewports(viewports)
-        .scissors(scissors);
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+        .dynamic_states(dynamic_states);
 
     let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
         .depth_clamp_enable(false)
         .rasterizer_discard_enable(false)
-        .polygon_mode(vk::PolygonMode::FILL)
+        .polygon_mode(state.polygon_mode)
         .line_width(1.0)
         .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::CLOCKWISE)
+        .front_face(state.front_face)
         .depth_bias_enable(false);
 
     let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
-        .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::_1);
-
+        .sample_shading_enable(data.sample_shading && state.samples != vk::SampleCountFlags::_1)
+        .min_sample_shading(0.2)
+        .rasterization_samples(state.samples);
+
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(state.depth.test)
+        .depth_write_enable(state.depth.write)
+        .depth_compare_op(state.depth.compare_op)
+        .depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0)
+        .max_depth_bounds(1.0)
+        .stencil_test_enable(false);
+
+    let (src_color_blend_factor, dst_color_blend_factor) = match state.blend {
+        BlendMode::Additive => (vk::BlendFactor::ONE, vk::BlendFactor::ONE),
+        _ => (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+    };
     let attachment = vk::PipelineColorBlendAttachmentState::builder()
         .color_write_mask(vk::ColorComponentFlags::all())
-        .blend_enable(true)
-        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .blend_enable(state.blend != BlendMode::Opaque)
+        .src_color_blend_factor(src_color_blend_factor)
+        .dst_color_blend_factor(dst_color_blend_factor)
         .color_blend_op(vk::BlendOp::ADD)
         .src_alpha_blend_factor(vk::BlendFactor::ONE)
         .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-        .alpha_blend_op(vk::BlendOp::ADD);
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .build();
 
-    let attachments = &[attachment];
+    let attachments = vec![attachment; state.color_attachments as usize];
     let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
         .logic_op_enable(false)
         .logic_op(vk::LogicOp::COPY)
-        .attachments(attachments)
+        .attachments(&attachments)
         .blend_constants([0.0, 0.0, 0.0, 0.0]);
 
-    let layout_info = vk::PipelineLayoutCreateInfo::builder();
-    data.pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let color_formats = vec![state.color_format; state.color_attachments as usize];
+    let stencil_format = if core::depth::has_stencil_component(state.depth_format) {
+        state.depth_format
+    } else {
+        vk::Format::UNDEFINED
+    };
+    let mut rendering_info = vk::PipelineRenderingCreateInfo::builder()
+        .color_attachment_formats(&color_formats)
+        .depth_attachment_format(state.depth_format)
+        .stencil_attachment_format(stencil_format);
 
     let stages = &[vert_stage, frag_stage];
-    let info = vk::GraphicsPipelineCreateInfo::builder()
+    let mut info = vk::GraphicsPipelineCreateInfo::builder()
         .stages(stages)
         .vertex_input_state(&vertex_input_state)
         .input_assembly_state(&input_assembly_state)
         .viewport_state(&viewport_state)
         .rasterization_state(&rasterization_state)
         .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
         .color_blend_state(&color_blend_state)
-        .layout(data.pipeline_layout)
-        .render_pass(data.render_pass)
-        .subpass(0);
+        .dynamic_state(&dynamic_state)
+        .layout(pipeline_layout)
+        .render_pass(state.render_pass)
+        .subpass(state.subpass);
+    if state.render_pass.is_null() {
+        info = info.push_next(&mut rendering_info);
+    }
 
-    data.pipeline = device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)?.0[0];
+    let result = device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None);
 
     device.destroy_shader_module(vert_shader_module, None);
     device.destroy_shader_module(frag_shader_module, None);
 
-    Ok(())
+    match result {
+        Ok((pipelines, _)) => Ok((descriptor_set_layouts, pipeline_layout, pipelines[0])),
+        Err(e) => {
+            device.destroy_pipeline_layout(pipeline_layout, None);
+            Err(e.into())
+        }
+    }
 }
 
+pub unsafe fn set_viewport(device: &Device, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
+    let viewport = vk::Viewport::builder()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+    let scissor = vk::Rect2D::builder()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(extent);
+
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+}
+
+// Without dynamic rendering pipelines are created against this render pass. Frames are recorded with the
+// compatible render passes the render graph builds from the same attachments.
 pub unsafe fn create_render_pass(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    // With MSAA the multisampled color target is only resolved, never stored.
+    let msaa = core::msaa::is_enabled(data);
+    let color_format = core::post::scene_format(data);
     let color_attachment = vk::AttachmentDescription::builder()
-        .format(data.swapchain_format)
-        .samples(vk::SampleCountFlags::_1)
+        .format(color_format)
+        .samples(data.msaa_samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::STORE)
+        .store_op(if msaa { vk::AttachmentStoreOp::DONT_CARE } else { vk::AttachmentStoreOp::STORE })
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+        .final_layout(if msaa { vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL } else { vk::ImageLayout::PRESENT_SRC_KHR });
+
+    data.depth_format = core::depth::get_depth_format(instance, data)?;
+    let depth_stencil_attachment = vk::AttachmentDescription::builder()
+        .format(data.depth_format)
+        .samples(data.msaa_samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::CLEAR)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
 
     let color_attachment_ref =  vk::AttachmentReference::builder()
         .attachment(0)
         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
 
+    let depth_stencil_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let color_resolve_attachment = vk::AttachmentDescription::builder()
+        .format(color_format)
+        .samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+
+    let color_resolve_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
     let color_attachments = &[color_attachment_ref];
-    let subpass = vk::SubpassDescription::builder()
+    let resolve_attachments = &[color_resolve_attachment_ref];
+    let mut subpass = vk::SubpassDescription::builder()
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-        .color_attachments(color_attachments);
+        .color_attachments(color_attachments)
+        .depth_stencil_attachment(&depth_stencil_attachment_ref);
+    if msaa {
+        subpass = subpass.resolve_attachments(resolve_attachments);
+    }
 
     let dependency = vk::SubpassDependency::builder()
         .src_subpass(vk::SUBPASS_EXTERNAL)
         .dst_subpass(0)
-        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
         .src_access_mask(vk::AccessFlags::empty())
-        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
-
-    let attachments = &[color_attachment];
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+
+    let attachments = if msaa {
+        vec![color_attachment, depth_stencil_attachment, color_resolve_attachment]
+    } else {
+        vec![color_attachment, depth_stencil_attachment]
+    };
     let subpasses = &[subpass];
     let dependencies = &[dependency];
     let info = vk::RenderPassCreateInfo::builder()
-        .attachments(attachments)
+        .attachments(&attachments)
         .subpasses(subpasses)
         .dependencies(dependencies);
 
@@ -154,26 +502,7 @@ pub unsafe fn create_render_pass(instance: &Instance, device: &Device, data: &mu
     Ok(())
 }
 
-pub unsafe fn create_framebuffers(device: &Device, data: &mut AppData) -> Result<()> {
-    data.framebuffers = data
-        .swapchain_image_views
-        .iter()
-        .map(|i| {
-            let attachments = &[*i];
-            let create_info = vk::FramebufferCreateInfo::builder()
-                .render_pass(data.render_pass)
-                .attachments(attachments)
-                .width(data.swapchain_extent.width)
-                .height(data.swapchain_extent.height)
-                .layers(1);
-
-            device.create_framebuffer(&create_info, None)
-        })
-    .collect::<Result<Vec<_>, _>>()?;
-    Ok(())
-}
-
-unsafe fn create_shader_module(device: &Device, bytecode: &[u8],) -> Result<vk::ShaderModule> {
+pub unsafe fn create_shader_module(device: &Device, bytecode: &[u8],) -> Result<vk::ShaderModule> {
     let bytecode = Bytecode::new(bytecode).unwrap();
     let info = vk::ShaderModuleCreateInfo::builder()
         .code(bytecode.code())
@@ -0,0 +1,734 @@
+use anyhow::{Result, Context};
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+use std::collections::HashMap;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use crate::AppData;
+use crate::MAX_FRAMES_IN_FLIGHT;
+use crate::core;
+use crate::core::compute::ComputePipeline;
+use crate::core::config::RenderPath;
+use crate::core::debug_view::DebugView;
+use crate::core::descriptor::DescriptorAllocator;
+use crate::core::pipeline::{BlendMode, DepthState, GraphicsPipeline, PipelineState};
+use crate::core::render_graph::{ImageAccess, ImageDesc, ImageHandle, LoadOp, RenderGraph};
+use crate::core::specialization::Specialization;
+
+pub const FULLSCREEN_VERT: &str = "fullscreen.vert";
+pub const TONEMAP_FRAG: &str = "tonemap.frag";
+pub const COLOR_GRADING_FRAG: &str = "color_grading.frag";
+pub const FXAA_FRAG: &str = "fxaa.frag";
+pub const VIGNETTE_FRAG: &str = "vignette.frag";
+pub const GAMMA_FRAG: &str = "gamma.frag";
+pub const SHARPEN_COMP: &str = "sharpen.comp";
+
+pub const EFFECT_SHADERS: [&str; 6] = [TONEMAP_FRAG, COLOR_GRADING_FRAG, FXAA_FRAG, SHARPEN_COMP, VIGNETTE_FRAG, GAMMA_FRAG];
+
+// The scene and every effect render into this format.
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+// Workgroup size of the compute effects, which specializes their
+// `local_size_x_id`/`local_size_y_id` constants.
+pub const COMPUTE_WORKGROUP_SIZE: [u32; 2] = [8, 8];
+
+pub const LUT_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+pub const LUT_SIZE: u32 = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PostEffect {
+    ToneMap { exposure: f32 },
+    // Blends towards the 3D LUT set with `set_lut`.
+    ColorGrading { strength: f32 },
+    Fxaa,
+    // A compute pass writing its output as a storage image.
+    Sharpen { strength: f32 },
+    Vignette { strength: f32, radius: f32 },
+    Gamma { gamma: f32 },
+}
+
+impl PostEffect {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ToneMap { .. } => "tone map",
+            Self::ColorGrading { .. } => "color grading",
+            Self::Fxaa => "fxaa",
+            Self::Sharpen { .. } => "sharpen",
+            Self::Vignette { .. } => "vignette",
+            Self::Gamma { .. } => "gamma",
+        }
+    }
+
+    pub fn shader(&self) -> &'static str {
+        match self {
+            Self::ToneMap { .. } => TONEMAP_FRAG,
+            Self::ColorGrading { .. } => COLOR_GRADING_FRAG,
+            Self::Fxaa => FXAA_FRAG,
+            Self::Sharpen { .. } => SHARPEN_COMP,
+            Self::Vignette { .. } => VIGNETTE_FRAG,
+            Self::Gamma { .. } => GAMMA_FRAG,
+        }
+    }
+
+    pub fn is_compute(&self) -> bool {
+        matches!(self, Self::Sharpen { .. })
+    }
+
+    // The push constant block of the effect's shader.
+    fn params(&self, data: &AppData) -> Vec<f32> {
+        match *self {
+            Self::ToneMap { exposure } => vec![exposure],
+            Self::ColorGrading { strength } => vec![strength],
+            Self::Fxaa => Vec::new(),
+            Self::Sharpen { strength } => vec![strength],
+            Self::Vignette { strength, radius } => vec![strength, radius],
+            Self::Gamma { gamma } => vec![gamma, if is_srgb(data.swapchain_format) { 1.0 } else { 0.0 }],
+        }
+    }
+}
+
+pub fn default_chain() -> Vec<PostEffect> {
+    vec![
+        PostEffect::ToneMap { exposure: 1.0 },
+        PostEffect::ColorGrading { strength: 1.0 },
+        PostEffect::Fxaa,
+        PostEffect::Sharpen { strength: 0.25 },
+        PostEffect::Vignette { strength: 0.35, radius: 0.6 },
+        PostEffect::Gamma { gamma: 2.2 },
+    ]
+}
+
+#[derive(Clone, Debug)]
+pub enum PostPipeline {
+    Graphics(GraphicsPipeline),
+    Compute(ComputePipeline),
+}
+
+impl PostPipeline {
+    fn set_layout(&self) -> vk::DescriptorSetLayout {
+        match self {
+            Self::Graphics(pipeline) => pipeline.set_layouts[0],
+            Self::Compute(pipeline) => pipeline.set_layouts[0],
+        }
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        match self {
+            Self::Graphics(pipeline) => pipeline.destroy(device),
+            Self::Compute(pipeline) => pipeline.destroy(device),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PostData {
+    pub chain: Vec<PostEffect>,
+    pub shaders: HashMap<&'static str, Vec<u32>>,
+
+    // Without dynamic rendering the pipelines are created against this render pass.
+    pub render_pass: vk::RenderPass,
+    // One per effect in `chain`.
+    pub pipelines: Vec<PostPipeline>,
+
+    pub sampler: vk::Sampler,
+    // Reset whenever the sets are rebuilt, keeping its pools.
+    pub descriptor_allocator: DescriptorAllocator,
+    // Indexed by command buffer (see `core::commands::command_buffer`), then
+    // by effect. Written while recording, since the inputs are graph transients.
+    pub descriptor_sets: Vec<Vec<vk::DescriptorSet>>,
+
+    pub lut_image: vk::Image,
+    pub lut_image_memory: vk::DeviceMemory,
+    pub lut_image_view: vk::ImageView,
+}
+
+fn is_srgb(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+// The final blit needs the swapchain images as transfer destinations.
+pub fn is_enabled(data: &AppData) -> bool {
+    core::config::POST_PROCESSING
+        && data.render_path == RenderPath::Forward
+        && data.swapchain_usage.contains(vk::ImageUsageFlags::TRANSFER_DST)
+}
+
+// The format the forward scene renders into.
+pub fn scene_format(data: &AppData) -> vk::Format {
+    if is_enabled(data) { HDR_FORMAT } else { data.swapchain_format }
+}
+
+pub fn load_shaders(data: &mut AppData) -> Result<()> {
+    for name in std::iter::once(FULLSCREEN_VERT).chain(EFFECT_SHADERS) {
+        let shader = core::shaders::load_shader(name).with_context(|| format!("Failed to load {}", name))?;
+        data.post.shaders.insert(name, shader);
+    }
+    data.post.chain = default_chain();
+
+    Ok(())
+}
+
+// The sampler and the identity LUT, which live as long as the device.
+pub unsafe fn create_resources(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    if core::config::POST_PROCESSING && data.render_path == RenderPath::Deferred {
+        warn!("Post-processing is not supported on the deferred path.");
+    }
+
+    let info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .max_lod(0.0);
+    data.post.sampler = device.create_sampler(&info, None)?;
+
+    create_lut(instance, device, data, LUT_SIZE, &identity_lut(LUT_SIZE)?)
+}
+
+pub unsafe fn destroy_resources(device: &Device, data: &mut AppData) {
+    destroy_lut(device, data);
+    device.destroy_sampler(data.post.sampler, None);
+    data.post.descriptor_allocator.destroy(device);
+}
+
+// The bytes in a `size`³ cube of RGBA8 texels. A LUT needs at least two
+// entries per axis to interpolate between.
+fn lut_len(size: u32) -> Result<usize> {
+    if size < 2 {
+        return Err(anyhow::anyhow!("LUT size {} is below the minimum of 2", size));
+    }
+    let size = size as usize;
+    size.checked_mul(size)
+        .and_then(|n| n.checked_mul(size))
+        .and_then(|n| n.checked_mul(4))
+        .ok_or_else(|| anyhow::anyhow!("LUT of size {} is too large", size))
+}
+
+// RGBA8 texels with red varying fastest, indexed by gamma encoded color.
+pub fn identity_lut(size: u32) -> Result<Vec<u8>> {
+    let mut texels = Vec::with_capacity(lut_len(size)?);
+    let scale = 255.0 / (size - 1) as f32;
+    texels.extend(
+        (0..size)
+            .flat_map(|b| (0..size).flat_map(move |g| (0..size).map(move |r| (r, g, b))))
+            .flat_map(|(r, g, b)| {
+                [(r as f32 * scale).round() as u8, (g as f32 * scale).round() as u8, (b as f32 * scale).round() as u8, 255]
+            }),
+    );
+    Ok(texels)
+}
+
+// Replaces the color grading LUT, a `size`³ cube of RGBA8 texels.
+pub unsafe fn set_lut(instance: &Instance, device: &Device, data: &mut AppData, size: u32, texels: &[u8]) -> Result<()> {
+    let expected = lut_len(size)?;
+    if texels.len() != expected {
+        return Err(anyhow::anyhow!("LUT of size {} needs {} bytes, got {}", size, expected, texels.len()));
+    }
+    let max_size = instance.get_physical_device_properties(data.physical_device).limits.max_image_dimension_3d;
+    if size > max_size {
+        return Err(anyhow::anyhow!("LUT of size {} is larger than the device's 3D image limit {}", size, max_size));
+    }
+
+    device.device_wait_idle()?;
+    destroy_lut(device, data);
+    create_lut(instance, device, data, size, texels)?;
+
+    core::commands::destroy_command_buffers(device, data);
+    core::commands::create_command_buffers(instance, device, data)
+}
+
+unsafe fn create_lut(instance: &Instance, device: &Device, data: &mut AppData, size: u32, texels: &[u8]) -> Result<()> {
+    let info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::_3D)
+        .extent(vk::Extent3D { width: size, height: size, depth: size })
+        .mip_levels(1)
+        .array_layers(1)
+        .format(LUT_FORMAT)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+        .samples(vk::SampleCountFlags::_1)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    data.post.lut_image = device.create_image(&info, None)?;
+
+    let requirements = device.get_image_memory_requirements(data.post.lut_image);
+    let info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(requirements.size)
+        .memory_type_index(core::vertex::get_memory_type_index(
+            instance,
+            data,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            requirements,
+        )?);
+    data.post.lut_image_memory = device.allocate_memory(&info, None)?;
+    device.bind_image_memory(data.post.lut_image, data.post.lut_image_memory, 0)?;
+
+    upload_lut(instance, device, data, size, texels)?;
+
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1);
+    let info = vk::ImageViewCreateInfo::builder()
+        .image(data.post.lut_image)
+        .view_type(vk::ImageViewType::_3D)
+        .format(LUT_FORMAT)
+        .subresource_range(subresource_range);
+    data.post.lut_image_view = device.create_image_view(&info, None)?;
+
+    Ok(())
+}
+
+unsafe fn upload_lut(instance: &Instance, device: &Device, data: &AppData, size: u32, texels: &[u8]) -> Result<()> {
+    let (staging_buffer, staging_buffer_memory) = core::vertex::create_buffer(
+        instance,
+        device,
+        data,
+        texels.len() as u64,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+    let memory = device.map_memory(staging_buffer_memory, 0, texels.len() as u64, vk::MemoryMapFlags::empty())?;
+    memcpy(texels.as_ptr(), memory.cast(), texels.len());
+    device.unmap_memory(staging_buffer_memory);
+
+    let info = vk::CommandBufferAllocateInfo::builder()
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_pool(data.command_pool)
+        .command_buffer_count(1);
+    let command_buffer = device.allocate_command_buffers(&info)?[0];
+    let info = vk::CommandBufferBeginInfo::builder()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    device.begin_command_buffer(command_buffer, &info)?;
+
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1);
+    let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(data.post.lut_image)
+            .subresource_range(subresource_range)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .build()
+    };
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TOP_OF_PIPE,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier(
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+        )],
+    );
+
+    let subresource = vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(subresource)
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(vk::Extent3D { width: size, height: size, depth: size });
+    device.cmd_copy_buffer_to_image(
+        command_buffer,
+        staging_buffer,
+        data.post.lut_image,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        &[region],
+    );
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        vk::PipelineStageFlags::FRAGMENT_SHADER,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier(
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+        )],
+    );
+
+    device.end_command_buffer(command_buffer)?;
+    let command_buffers = &[command_buffer];
+    let info = vk::SubmitInfo::builder().command_buffers(command_buffers);
+    device.queue_submit(data.graphics_queue, &[info], vk::Fence::null())?;
+    device.queue_wait_idle(data.graphics_queue)?;
+
+    device.free_command_buffers(data.command_pool, &[command_buffer]);
+    device.destroy_buffer(staging_buffer, None);
+    device.free_memory(staging_buffer_memory, None);
+
+    Ok(())
+}
+
+unsafe fn destroy_lut(device: &Device, data: &mut AppData) {
+    device.destroy_image_view(data.post.lut_image_view, None);
+    device.free_memory(data.post.lut_image_memory, None);
+    device.destroy_image(data.post.lut_image, None);
+}
+
+pub unsafe fn create_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    if !is_enabled(data) {
+        return Ok(());
+    }
+
+    if data.dynamic_rendering.is_none() {
+        create_render_pass(device, data)?;
+    }
+    data.post.pipelines = build_pipelines(device, data, &data.post.shaders)?;
+
+    Ok(())
+}
+
+// Builds a pipeline for every effect in the chain from `shaders`, keyed by name.
+pub unsafe fn build_pipelines(
+    device: &Device,
+    data: &AppData,
+    shaders: &HashMap<&'static str, Vec<u32>>,
+) -> Result<Vec<PostPipeline>> {
+    let state = PipelineState {
+        render_pass: data.post.render_pass,
+        subpass: 0,
+        color_attachments: 1,
+        color_format: HDR_FORMAT,
+        depth_format: vk::Format::UNDEFINED,
+        samples: vk::SampleCountFlags::_1,
+        depth: DepthState::DISABLED,
+        blend: BlendMode::Opaque,
+        polygon_mode: vk::PolygonMode::FILL,
+        front_face: vk::FrontFace::CLOCKWISE,
+        uniforms: false,
+        bindless: false,
+        textures: false,
+        push_constants: None,
+    };
+
+    let mut pipelines = Vec::new();
+    for effect in &data.post.chain {
+        let pipeline = if effect.is_compute() {
+            let [x, y] = COMPUTE_WORKGROUP_SIZE;
+            let specialization = Specialization::new().set(0, x).set(1, y);
+            ComputePipeline::build_specialized(device, data, &shaders[effect.shader()], &specialization)
+                .map(PostPipeline::Compute)
+        } else {
+            GraphicsPipeline::build::<()>(device, data, &shaders[FULLSCREEN_VERT], &shaders[effect.shader()], state)
+                .map(PostPipeline::Graphics)
+        };
+        let pipeline = pipeline.with_context(|| format!("Failed to build {} pipeline", effect.name()));
+        match pipeline {
+            Ok(pipeline) => pipelines.push(pipeline),
+            Err(e) => {
+                pipelines.iter().for_each(|p| p.destroy(device));
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(pipelines)
+}
+
+pub unsafe fn destroy_pipelines(device: &Device, data: &mut AppData) {
+    data.post.pipelines.drain(..).for_each(|p| p.destroy(device));
+    device.destroy_render_pass(data.post.render_pass, None);
+    data.post.render_pass = vk::RenderPass::null();
+}
+
+// Compatible with the single color attachment passes the graph builds.
+unsafe fn create_render_pass(device: &Device, data: &mut AppData) -> Result<()> {
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(HDR_FORMAT)
+        .samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+    let color_attachment_ref = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+    let color_attachments = &[color_attachment_ref];
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments);
+
+    let attachments = &[color_attachment];
+    let subpasses = &[subpass];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses);
+    data.post.render_pass = device.create_render_pass(&info, None)?;
+
+    Ok(())
+}
+
+pub unsafe fn create_descriptor_sets(device: &Device, data: &mut AppData) -> Result<()> {
+    let layouts = data.post.pipelines.iter().map(|p| p.set_layout()).collect::<Vec<_>>();
+    // Written while recording, so each command buffer gets its own.
+    for _ in 0..MAX_FRAMES_IN_FLIGHT * data.swapchain_images.len() {
+        let sets = layouts
+            .iter()
+            .map(|l| data.post.descriptor_allocator.allocate(device, *l))
+            .collect::<Result<Vec<_>>>()?;
+        data.post.descriptor_sets.push(sets);
+    }
+
+    Ok(())
+}
+
+pub unsafe fn destroy_descriptor_sets(device: &Device, data: &mut AppData) {
+    data.post.descriptor_allocator.reset(device);
+    data.post.descriptor_sets.clear();
+}
+
+// Adds a pass per effect reading the previous result, then blits the last one
+// to the swapchain image. Debug views skip the effects to show raw values.
+pub fn add_passes(
+    graph: &mut RenderGraph,
+    data: &AppData,
+    frame: usize,
+    image_index: usize,
+    scene: ImageHandle,
+    swapchain: ImageHandle,
+) {
+    let extent = data.swapchain_extent;
+    let desc = ImageDesc { format: HDR_FORMAT, extent, samples: vk::SampleCountFlags::_1 };
+    let chain = if data.debug_view == DebugView::None { data.post.chain.as_slice() } else { &[] };
+
+    let mut input = scene;
+    for (index, effect) in chain.iter().enumerate() {
+        let output = graph.create_image(effect.name(), desc);
+        let descriptor_set = data.post.descriptor_sets[frame * data.swapchain_images.len() + image_index][index];
+        match &data.post.pipelines[index] {
+            PostPipeline::Graphics(pipeline) => {
+                add_graphics_pass(graph, data, effect, pipeline, descriptor_set, input, output)
+            }
+            PostPipeline::Compute(pipeline) => {
+                add_compute_pass(graph, data, effect, pipeline, descriptor_set, input, output)
+            }
+        }
+        input = output;
+    }
+
+    graph
+        .add_pass("blit")
+        .read_image(input, ImageAccess::TransferSrc)
+        .write_image(swapchain, ImageAccess::TransferDst)
+        .execute(move |device, command_buffer, context| unsafe {
+            let subresource = vk::ImageSubresourceLayers::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(0)
+                .base_array_layer(0)
+                .layer_count(1)
+                .build();
+            let corner = vk::Offset3D { x: extent.width as i32, y: extent.height as i32, z: 1 };
+            let region = vk::ImageBlit::builder()
+                .src_subresource(subresource)
+                .src_offsets([vk::Offset3D::default(), corner])
+                .dst_subresource(subresource)
+                .dst_offsets([vk::Offset3D::default(), corner]);
+            device.cmd_blit_image(
+                command_buffer,
+                context.image(input),
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                context.image(swapchain),
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+                vk::Filter::NEAREST,
+            );
+        });
+}
+
+fn add_graphics_pass(
+    graph: &mut RenderGraph,
+    data: &AppData,
+    effect: &PostEffect,
+    pipeline: &GraphicsPipeline,
+    descriptor_set: vk::DescriptorSet,
+    input: ImageHandle,
+    output: ImageHandle,
+) {
+    let (pipeline, layout) = (pipeline.pipeline, pipeline.layout);
+    let (sampler, lut_view) = (data.post.sampler, data.post.lut_image_view);
+    let uses_lut = matches!(effect, PostEffect::ColorGrading { .. });
+    let params = effect.params(data);
+
+    graph
+        .add_pass(effect.name())
+        .read_image(input, ImageAccess::Sampled(vk::PipelineStageFlags::FRAGMENT_SHADER))
+        .color_attachment(output, LoadOp::DontCare)
+        .execute(move |device, command_buffer, context| unsafe {
+            let input_info = [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(context.view(input))
+                .sampler(sampler)
+                .build()];
+            let lut_info = [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(lut_view)
+                .sampler(sampler)
+                .build()];
+            let write = |binding, image_info: &[vk::DescriptorImageInfo]| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(image_info)
+                    .build()
+            };
+            let mut writes = vec![write(0, &input_info)];
+            if uses_lut {
+                writes.push(write(1, &lut_info));
+            }
+            device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+
+            core::pipeline::set_viewport(device, command_buffer, context.extent());
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            if !params.is_empty() {
+                let bytes = std::slice::from_raw_parts(params.as_ptr() as *const u8, size_of_val(params.as_slice()));
+                device.cmd_push_constants(command_buffer, layout, vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+            }
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        });
+}
+
+// The graph orders the dispatch against the neighbouring render passes from
+// the declared compute stage accesses.
+fn add_compute_pass(
+    graph: &mut RenderGraph,
+    data: &AppData,
+    effect: &PostEffect,
+    pipeline: &ComputePipeline,
+    descriptor_set: vk::DescriptorSet,
+    input: ImageHandle,
+    output: ImageHandle,
+) {
+    let pipeline = pipeline.clone();
+    let (sampler, extent) = (data.post.sampler, data.swapchain_extent);
+    let params = effect.params(data);
+
+    graph
+        .add_pass(effect.name())
+        .read_image(input, ImageAccess::Sampled(vk::PipelineStageFlags::COMPUTE_SHADER))
+        .write_image(output, ImageAccess::StorageWrite(vk::PipelineStageFlags::COMPUTE_SHADER))
+        .execute(move |device, command_buffer, context| unsafe {
+            let input_info = [vk::DescriptorImageInfo::builder()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(context.view(input))
+                .sampler(sampler)
+                .build()];
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .image_info(&input_info);
+            device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+            core::compute::write_storage_image(device, descriptor_set, 1, context.view(output));
+
+            pipeline.bind(device, command_buffer, &[descriptor_set]);
+            if !params.is_empty() {
+                let bytes = std::slice::from_raw_parts(params.as_ptr() as *const u8, size_of_val(params.as_slice()));
+                pipeline.push_constants(device, command_buffer, bytes);
+            }
+            pipeline.dispatch(device, command_buffer, [extent.width, extent.height, 1]);
+        });
+}
+
+pub unsafe fn set_chain(instance: &Instance, device: &Device, data: &mut AppData, chain: Vec<PostEffect>) -> Result<()> {
+    if !is_enabled(data) {
+        warn!("Post-processing is not enabled.");
+        return Ok(());
+    }
+
+    info!("Post-processing chain: {:?}", chain.iter().map(|e| e.name()).collect::<Vec<_>>());
+    device.device_wait_idle()?;
+    core::commands::destroy_command_buffers(device, data);
+    destroy_descriptor_sets(device, data);
+    destroy_pipelines(device, data);
+    data.post.chain = chain;
+    create_pipelines(device, data)?;
+    create_descriptor_sets(device, data)?;
+    core::commands::create_command_buffers(instance, device, data)
+}
+
+// Parameters are pushed when a command buffer is recorded, so this only marks
+// them stale and each is re-recorded before its next submission.
+pub fn set_exposure(data: &mut AppData, exposure: f32) {
+    for effect in &mut data.post.chain {
+        if let PostEffect::ToneMap { exposure: current } = effect {
+            *current = exposure;
+        }
+    }
+    info!("Exposure: {:.2}", exposure);
+    core::commands::invalidate_command_buffers(data);
+}
+
+pub fn exposure(data: &AppData) -> Option<f32> {
+    data.post.chain.iter().find_map(|e| match e {
+        PostEffect::ToneMap { exposure } => Some(*exposure),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_lut_spans_the_color_cube() {
+        let lut = identity_lut(2).unwrap();
+        assert_eq!(lut.len(), 2 * 2 * 2 * 4);
+        assert_eq!(lut[..8], [0, 0, 0, 255, 255, 0, 0, 255]);
+        assert_eq!(lut[lut.len() - 4..], [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn rejects_degenerate_and_oversized_luts() {
+        assert!(identity_lut(0).is_err());
+        assert!(identity_lut(1).is_err());
+        assert_eq!(lut_len(1025).unwrap(), 1025 * 1025 * 1025 * 4);
+        assert!(lut_len(u32::MAX).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{Result, anyhow};
+use vulkanalia::prelude::v1_0::*;
+
+use std::mem::size_of;
+
+use crate::AppData;
+
+/// A `#[repr(C)]` struct laid out like a shader's `push_constant` block, pushed
+/// whole starting at offset 0. Blocks spanning stages put each stage's members
+/// at their `layout(offset = ...)` in the struct.
+///
+/// # Safety
+///
+/// `bytes` reads the whole struct, so every byte of it must be initialized: the
+/// struct is `#[repr(C)]` with no padding between or after its fields, and its
+/// fields are plain data (floats, integers and arrays or matrices of them) with
+/// no padding of their own. Spell out explicit padding fields where the shader
+/// layout leaves gaps.
+pub unsafe trait PushConstants: Copy + 'static {
+    // Every stage that declares part of the block.
+    const STAGES: vk::ShaderStageFlags;
+
+    fn range() -> vk::PushConstantRange {
+        vk::PushConstantRange::builder()
+            .stage_flags(Self::STAGES)
+            .offset(0)
+            .size(size_of::<Self>() as u32)
+            .build()
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+}
+
+// Checks a block against the device limit before a pipeline is built with it.
+pub fn validate(data: &AppData, range: vk::PushConstantRange) -> Result<()> {
+    if !range.size.is_multiple_of(4) {
+        return Err(anyhow!("Push constants are {} bytes, which is not a multiple of 4", range.size));
+    }
+    if range.offset + range.size > data.max_push_constants_size {
+        return Err(anyhow!(
+            "Push constants are {} bytes, but the device allows only {}",
+            range.offset + range.size,
+            data.max_push_constants_size,
+        ));
+    }
+
+    Ok(())
+}
+
+// Checks that `range` covers every block the shaders declare, so a struct
+// that falls behind its GLSL block is caught when the pipeline is built.
+pub fn validate_reflection(range: vk::PushConstantRange, reflected: &[vk::PushConstantRange]) -> Result<()> {
+    for block in reflected {
+        if !range.stage_flags.contains(block.stage_flags) {
+            return Err(anyhow!("Push constants for {:?} are not declared for {:?}", range.stage_flags, block.stage_flags));
+        }
+        if block.offset + block.size > range.offset + range.size {
+            return Err(anyhow!(
+                "{:?} push constants end at byte {}, but the Rust struct is only {} bytes",
+                block.stage_flags,
+                block.offset + block.size,
+                range.offset + range.size,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+pub unsafe fn push<T: PushConstants>(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    layout: vk::PipelineLayout,
+    constants: &T,
+) {
+    device.cmd_push_constants(command_buffer, layout, T::STAGES, 0, constants.bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct Model {
+        model: [[f32; 4]; 4],
+        tint: [f32; 4],
+    }
+
+    unsafe impl PushConstants for Model {
+        const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX.union(vk::ShaderStageFlags::FRAGMENT);
+    }
+
+    fn block(stage_flags: vk::ShaderStageFlags, offset: u32, size: u32) -> vk::PushConstantRange {
+        vk::PushConstantRange::builder().stage_flags(stage_flags).offset(offset).size(size).build()
+    }
+
+    #[test]
+    fn rejects_structs_past_the_device_limit() {
+        let mut data = AppData { max_push_constants_size: 128, ..Default::default() };
+        assert_eq!(Model::range().size, 80);
+        validate(&data, Model::range()).unwrap();
+
+        data.max_push_constants_size = 64;
+        let error = validate(&data, Model::range()).unwrap_err();
+        assert!(error.to_string().contains("80 bytes, but the device allows only 64"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_ranges_shorter_than_the_reflected_blocks() {
+        let vertex = block(vk::ShaderStageFlags::VERTEX, 0, 64);
+        validate_reflection(Model::range(), &[vertex, block(vk::ShaderStageFlags::FRAGMENT, 64, 16)]).unwrap();
+
+        // The fragment block grew a member the struct doesn't have.
+        let fragment = block(vk::ShaderStageFlags::FRAGMENT, 64, 32);
+        let error = validate_reflection(Model::range(), &[vertex, fragment]).unwrap_err();
+        assert!(error.to_string().contains("end at byte 96, but the Rust struct is only 80 bytes"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_stages_the_struct_does_not_declare() {
+        let compute = block(vk::ShaderStageFlags::COMPUTE, 0, 16);
+        assert!(validate_reflection(Model::range(), &[compute]).is_err());
+
+        let vertex_only = block(vk::ShaderStageFlags::VERTEX, 0, 80);
+        let fragment = block(vk::ShaderStageFlags::FRAGMENT, 64, 16);
+        assert!(validate_reflection(vertex_only, &[fragment]).is_err());
+    }
+}
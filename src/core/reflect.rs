@@ -0,0 +1,1195 @@
+use anyhow::{Result, anyhow};
+use vulkanalia::prelude::v1_0::*;
+use thiserror::Error;
+use log::*;
+
+use std::collections::{BTreeMap, HashMap};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// Opcodes
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT_TRUE: u32 = 41;
+const OP_CONSTANT_FALSE: u32 = 42;
+const OP_CONSTANT: u32 = 43;
+const OP_CONSTANT_COMPOSITE: u32 = 44;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_SPEC_CONSTANT_COMPOSITE: u32 = 51;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+// Decorations
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+const DECORATION_INPUT_ATTACHMENT_INDEX: u32 = 43;
+
+const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+
+// Storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_OUTPUT: u32 = 3;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+// Deeper than any real shader's types; stops malformed or self-referencing
+// modules from overflowing the stack.
+const MAX_TYPE_DEPTH: u32 = 64;
+
+#[derive(Debug, Error)]
+#[error("Vertex input mismatch: {}", .0.join("; "))]
+pub struct VertexInputMismatch(pub Vec<String>);
+
+#[derive(Debug, Error)]
+#[error("Descriptor layout mismatch (set {set}): {}", .errors.join("; "))]
+pub struct DescriptorLayoutMismatch {
+    pub set: u32,
+    pub errors: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct InterfaceVariable {
+    pub name: String,
+    pub location: u32,
+    pub format: vk::Format,
+}
+
+#[derive(Clone, Debug)]
+pub struct DescriptorBinding {
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    // `None` for runtime-sized arrays, which only a layout made for them can
+    // hold.
+    pub count: Option<u32>,
+    pub input_attachment_index: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PushConstantBlock {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpecializationValue {
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Float(f32),
+    // 64-bit and other scalar types are kept as raw words.
+    Raw(u64),
+}
+
+#[derive(Clone, Debug)]
+pub struct SpecializationConstant {
+    pub name: String,
+    pub id: u32,
+    pub default: SpecializationValue,
+    pub size: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub entry_point: String,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    pub push_constants: Vec<PushConstantBlock>,
+    pub specialization_constants: Vec<SpecializationConstant>,
+    pub workgroup_size: Option<[u32; 3]>,
+    // The specialization constant ids that override each workgroup dimension.
+    pub workgroup_size_ids: [Option<u32>; 3],
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { storage: u32, pointee: u32 },
+    AccelerationStructure,
+}
+
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    member_names: HashMap<(u32, u32), String>,
+    decorations: HashMap<u32, HashMap<u32, Vec<u32>>>,
+    member_decorations: HashMap<(u32, u32), HashMap<u32, Vec<u32>>>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, (u32, Vec<u32>)>,
+    composites: HashMap<u32, Vec<u32>>,
+    spec_constants: Vec<(u32, u32, Vec<u32>)>,
+    variables: Vec<(u32, u32, u32)>,
+    entry_points: Vec<(u32, u32, String)>,
+    local_size: Option<[u32; 3]>,
+    local_size_ids: Option<[u32; 3]>,
+}
+
+impl Module {
+    fn decoration(&self, id: u32, decoration: u32) -> Option<&[u32]> {
+        self.decorations.get(&id)?.get(&decoration).map(|v| v.as_slice())
+    }
+
+    fn member_decoration(&self, id: u32, member: u32, decoration: u32) -> Option<&[u32]> {
+        self.member_decorations.get(&(id, member))?.get(&decoration).map(|v| v.as_slice())
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names.get(&id).cloned().unwrap_or_default()
+    }
+
+    fn constant_u32(&self, id: u32) -> Option<u32> {
+        self.constants.get(&id).and_then(|(_, v)| v.first().copied())
+    }
+
+    fn is_built_in(&self, type_id: u32, id: u32) -> bool {
+        if self.decoration(id, DECORATION_BUILT_IN).is_some() {
+            return true;
+        }
+        // gl_PerVertex style blocks carry BuiltIn on their members.
+        match self.types.get(&type_id) {
+            Some(Type::Struct { members }) => (0..members.len() as u32)
+                .any(|m| self.member_decoration(type_id, m, DECORATION_BUILT_IN).is_some()),
+            _ => false,
+        }
+    }
+
+    fn format_of(&self, type_id: u32) -> Option<vk::Format> {
+        let (base, count) = match self.types.get(&type_id)? {
+            Type::Vector { component, count } => (*component, *count),
+            // A matrix reads as its column vector; anything else as a column
+            // is malformed.
+            Type::Matrix { column, .. } => match self.types.get(column)? {
+                Type::Vector { component, count } => (*component, *count),
+                _ => return None,
+            },
+            _ => (type_id, 1),
+        };
+        let format = match (self.types.get(&base)?, count) {
+            (Type::Float { width: 32 }, 1) => vk::Format::R32_SFLOAT,
+            (Type::Float { width: 32 }, 2) => vk::Format::R32G32_SFLOAT,
+            (Type::Float { width: 32 }, 3) => vk::Format::R32G32B32_SFLOAT,
+            (Type::Float { width: 32 }, 4) => vk::Format::R32G32B32A32_SFLOAT,
+            (Type::Float { width: 64 }, 1) => vk::Format::R64_SFLOAT,
+            (Type::Float { width: 64 }, 2) => vk::Format::R64G64_SFLOAT,
+            (Type::Float { width: 64 }, 3) => vk::Format::R64G64B64_SFLOAT,
+            (Type::Float { width: 64 }, 4) => vk::Format::R64G64B64A64_SFLOAT,
+            (Type::Float { width: 16 }, 1) => vk::Format::R16_SFLOAT,
+            (Type::Float { width: 16 }, 2) => vk::Format::R16G16_SFLOAT,
+            (Type::Float { width: 16 }, 3) => vk::Format::R16G16B16_SFLOAT,
+            (Type::Float { width: 16 }, 4) => vk::Format::R16G16B16A16_SFLOAT,
+            (Type::Int { width: 32, signed: true }, 1) => vk::Format::R32_SINT,
+            (Type::Int { width: 32, signed: true }, 2) => vk::Format::R32G32_SINT,
+            (Type::Int { width: 32, signed: true }, 3) => vk::Format::R32G32B32_SINT,
+            (Type::Int { width: 32, signed: true }, 4) => vk::Format::R32G32B32A32_SINT,
+            (Type::Int { width: 32, signed: false }, 1) => vk::Format::R32_UINT,
+            (Type::Int { width: 32, signed: false }, 2) => vk::Format::R32G32_UINT,
+            (Type::Int { width: 32, signed: false }, 3) => vk::Format::R32G32B32_UINT,
+            (Type::Int { width: 32, signed: false }, 4) => vk::Format::R32G32B32A32_UINT,
+            _ => vk::Format::UNDEFINED,
+        };
+        Some(format)
+    }
+
+    fn size_of(&self, type_id: u32) -> Result<u32> {
+        self.nested_size_of(type_id, 0)
+            .ok_or_else(|| anyhow!("SPIR-V type {} is nested too deeply or too large", type_id))
+    }
+
+    fn nested_size_of(&self, type_id: u32, depth: u32) -> Option<u32> {
+        if depth > MAX_TYPE_DEPTH {
+            return None;
+        }
+        let size_of = |id: u32| self.nested_size_of(id, depth + 1);
+        Some(match self.types.get(&type_id) {
+            Some(Type::Bool) => 4,
+            Some(Type::Int { width, .. }) | Some(Type::Float { width }) => width / 8,
+            Some(Type::Vector { component, count }) => size_of(*component)?.checked_mul(*count)?,
+            Some(Type::Matrix { column, count }) => size_of(*column)?.checked_mul(*count)?,
+            Some(Type::Array { element, length }) => {
+                let stride = match self.decoration(type_id, DECORATION_ARRAY_STRIDE).and_then(|s| s.first()) {
+                    Some(stride) => *stride,
+                    None => size_of(*element)?,
+                };
+                stride.checked_mul(*length)?
+            }
+            Some(Type::Struct { members }) => members.iter().enumerate().try_fold(0, |end: u32, (i, m)| {
+                let offset = self
+                    .member_decoration(type_id, i as u32, DECORATION_OFFSET)
+                    .and_then(|o| o.first().copied())
+                    .unwrap_or(0);
+                let size = match (self.types.get(m), self.member_decoration(type_id, i as u32, DECORATION_MATRIX_STRIDE)) {
+                    (Some(Type::Matrix { count, .. }), Some(stride)) => stride[0].checked_mul(*count)?,
+                    _ => size_of(*m)?,
+                };
+                Some(end.max(offset.checked_add(size)?))
+            })?,
+            _ => 0,
+        })
+    }
+
+    fn descriptor_type(&self, storage: u32, type_id: u32) -> Option<(vk::DescriptorType, Option<u32>)> {
+        let (base, count) = match self.types.get(&type_id)? {
+            Type::Array { element, length } => (*element, Some(*length)),
+            Type::RuntimeArray { element } => (*element, None),
+            _ => (type_id, Some(1)),
+        };
+        let descriptor_type = match (storage, self.types.get(&base)?) {
+            (STORAGE_UNIFORM_CONSTANT, Type::SampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (STORAGE_UNIFORM_CONSTANT, Type::Sampler) => vk::DescriptorType::SAMPLER,
+            (STORAGE_UNIFORM_CONSTANT, Type::AccelerationStructure) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            (STORAGE_UNIFORM_CONSTANT, Type::Image { dim, sampled }) => match (*dim, *sampled) {
+                (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            (STORAGE_UNIFORM, Type::Struct { .. }) => {
+                if self.decoration(base, DECORATION_BUFFER_BLOCK).is_some() {
+                    vk::DescriptorType::STORAGE_BUFFER
+                } else {
+                    vk::DescriptorType::UNIFORM_BUFFER
+                }
+            }
+            (STORAGE_STORAGE_BUFFER, _) => vk::DescriptorType::STORAGE_BUFFER,
+            _ => return None,
+        };
+        Some((descriptor_type, count))
+    }
+}
+
+fn parse_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), i + 1);
+            }
+            bytes.push(byte);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+fn stage_of(execution_model: u32) -> Result<vk::ShaderStageFlags> {
+    Ok(match execution_model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        _ => return Err(anyhow!("Unsupported SPIR-V execution model {}", execution_model)),
+    })
+}
+
+// The fewest operand words each instruction we read can have, so truncated
+// instructions are rejected instead of indexed past their end.
+fn min_operands(opcode: u32, ops: &[u32]) -> usize {
+    // Decorations we read a literal from carry one more operand.
+    let decoration_operands = |decoration: Option<&u32>| match decoration {
+        Some(
+            &(DECORATION_SPEC_ID
+            | DECORATION_ARRAY_STRIDE
+            | DECORATION_MATRIX_STRIDE
+            | DECORATION_BUILT_IN
+            | DECORATION_LOCATION
+            | DECORATION_BINDING
+            | DECORATION_DESCRIPTOR_SET
+            | DECORATION_OFFSET
+            | DECORATION_INPUT_ATTACHMENT_INDEX),
+        ) => 1,
+        _ => 0,
+    };
+
+    match opcode {
+        OP_TYPE_BOOL | OP_TYPE_SAMPLER | OP_TYPE_STRUCT | OP_TYPE_ACCELERATION_STRUCTURE => 1,
+        OP_NAME | OP_TYPE_FLOAT | OP_TYPE_SAMPLED_IMAGE | OP_TYPE_RUNTIME_ARRAY => 2,
+        OP_CONSTANT_TRUE | OP_CONSTANT_FALSE | OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE => 2,
+        OP_CONSTANT_COMPOSITE | OP_SPEC_CONSTANT_COMPOSITE => 2,
+        OP_MEMBER_NAME | OP_ENTRY_POINT | OP_TYPE_INT | OP_TYPE_VECTOR | OP_TYPE_MATRIX => 3,
+        OP_TYPE_ARRAY | OP_TYPE_POINTER | OP_CONSTANT | OP_SPEC_CONSTANT | OP_VARIABLE => 3,
+        OP_TYPE_IMAGE => 8,
+        OP_EXECUTION_MODE => match ops.get(1) {
+            Some(&(EXECUTION_MODE_LOCAL_SIZE | EXECUTION_MODE_LOCAL_SIZE_ID)) => 5,
+            _ => 2,
+        },
+        OP_DECORATE => 2 + decoration_operands(ops.get(1)),
+        OP_MEMBER_DECORATE => 3 + decoration_operands(ops.get(2)),
+        _ => 0,
+    }
+}
+
+fn parse_module(binary: &[u32]) -> Result<Module> {
+    if binary.len() < 5 || binary[0] != SPIRV_MAGIC {
+        return Err(anyhow!("Invalid SPIR-V module (bad magic number)"));
+    }
+
+    let mut module = Module::default();
+    let mut cursor = 5;
+    while cursor < binary.len() {
+        let word_count = (binary[cursor] >> 16) as usize;
+        let opcode = binary[cursor] & 0xFFFF;
+        if word_count == 0 || cursor + word_count > binary.len() {
+            return Err(anyhow!("Truncated SPIR-V instruction at word {}", cursor));
+        }
+        let ops = &binary[cursor + 1..cursor + word_count];
+        if ops.len() < min_operands(opcode, ops) {
+            return Err(anyhow!(
+                "SPIR-V instruction {} at word {} has {} operands, expected at least {}",
+                opcode,
+                cursor,
+                ops.len(),
+                min_operands(opcode, ops)
+            ));
+        }
+        cursor += word_count;
+
+        match opcode {
+            OP_NAME => {
+                module.names.insert(ops[0], parse_string(&ops[1..]).0);
+            }
+            OP_MEMBER_NAME => {
+                module.member_names.insert((ops[0], ops[1]), parse_string(&ops[2..]).0);
+            }
+            OP_ENTRY_POINT => {
+                let (name, _) = parse_string(&ops[2..]);
+                module.entry_points.push((ops[0], ops[1], name));
+            }
+            OP_EXECUTION_MODE => match ops[1] {
+                EXECUTION_MODE_LOCAL_SIZE => module.local_size = Some([ops[2], ops[3], ops[4]]),
+                EXECUTION_MODE_LOCAL_SIZE_ID => module.local_size_ids = Some([ops[2], ops[3], ops[4]]),
+                _ => {}
+            },
+            OP_TYPE_BOOL => {
+                module.types.insert(ops[0], Type::Bool);
+            }
+            OP_TYPE_INT => {
+                module.types.insert(ops[0], Type::Int { width: ops[1], signed: ops[2] != 0 });
+            }
+            OP_TYPE_FLOAT => {
+                module.types.insert(ops[0], Type::Float { width: ops[1] });
+            }
+            OP_TYPE_VECTOR => {
+                module.types.insert(ops[0], Type::Vector { component: ops[1], count: ops[2] });
+            }
+            OP_TYPE_MATRIX => {
+                module.types.insert(ops[0], Type::Matrix { column: ops[1], count: ops[2] });
+            }
+            OP_TYPE_IMAGE => {
+                module.types.insert(ops[0], Type::Image { dim: ops[2], sampled: ops[6] });
+            }
+            OP_TYPE_SAMPLER => {
+                module.types.insert(ops[0], Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                module.types.insert(ops[0], Type::SampledImage);
+            }
+            OP_TYPE_ARRAY => {
+                let length = module.constant_u32(ops[2]).unwrap_or(1);
+                module.types.insert(ops[0], Type::Array { element: ops[1], length });
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                module.types.insert(ops[0], Type::RuntimeArray { element: ops[1] });
+            }
+            OP_TYPE_STRUCT => {
+                module.types.insert(ops[0], Type::Struct { members: ops[1..].to_vec() });
+            }
+            OP_TYPE_POINTER => {
+                module.types.insert(ops[0], Type::Pointer { storage: ops[1], pointee: ops[2] });
+            }
+            OP_TYPE_ACCELERATION_STRUCTURE => {
+                module.types.insert(ops[0], Type::AccelerationStructure);
+            }
+            OP_CONSTANT => {
+                module.constants.insert(ops[1], (ops[0], ops[2..].to_vec()));
+            }
+            OP_CONSTANT_TRUE | OP_CONSTANT_FALSE => {
+                let value = (opcode == OP_CONSTANT_TRUE) as u32;
+                module.constants.insert(ops[1], (ops[0], vec![value]));
+            }
+            OP_SPEC_CONSTANT => {
+                module.constants.insert(ops[1], (ops[0], ops[2..].to_vec()));
+                module.spec_constants.push((ops[0], ops[1], ops[2..].to_vec()));
+            }
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE => {
+                let value = (opcode == OP_SPEC_CONSTANT_TRUE) as u32;
+                module.constants.insert(ops[1], (ops[0], vec![value]));
+                module.spec_constants.push((ops[0], ops[1], vec![value]));
+            }
+            OP_CONSTANT_COMPOSITE | OP_SPEC_CONSTANT_COMPOSITE => {
+                module.composites.insert(ops[1], ops[2..].to_vec());
+            }
+            OP_VARIABLE => {
+                module.variables.push((ops[0], ops[1], ops[2]));
+            }
+            OP_DECORATE => {
+                module
+                    .decorations
+                    .entry(ops[0])
+                    .or_default()
+                    .insert(ops[1], ops[2..].to_vec());
+            }
+            OP_MEMBER_DECORATE => {
+                module
+                    .member_decorations
+                    .entry((ops[0], ops[1]))
+                    .or_default()
+                    .insert(ops[2], ops[3..].to_vec());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(module)
+}
+
+pub fn reflect_shader(binary: &[u32]) -> Result<ShaderReflection> {
+    let module = parse_module(binary)?;
+
+    let (execution_model, _, entry_point) = module
+        .entry_points
+        .first()
+        .cloned()
+        .ok_or_else(|| anyhow!("SPIR-V module has no entry point"))?;
+    if module.entry_points.len() > 1 {
+        warn!("SPIR-V module has {} entry points, reflecting `{}` only.", module.entry_points.len(), entry_point);
+    }
+    let stage = stage_of(execution_model)?;
+
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    let mut descriptor_bindings = Vec::new();
+    let mut push_constants = Vec::new();
+
+    for &(pointer_type, id, storage) in &module.variables {
+        let pointee = match module.types.get(&pointer_type) {
+            Some(Type::Pointer { pointee, .. }) => *pointee,
+            _ => continue,
+        };
+        let name = module.name(id);
+
+        match storage {
+            STORAGE_INPUT | STORAGE_OUTPUT => {
+                if module.is_built_in(pointee, id) {
+                    continue;
+                }
+                let Some(location) = module.decoration(id, DECORATION_LOCATION).map(|l| l[0]) else {
+                    continue;
+                };
+                let variables = match module.types.get(&pointee) {
+                    // Matrices take a location per column.
+                    Some(Type::Matrix { column, count }) => {
+                        if !(2..=4).contains(count) || location.checked_add(*count).is_none() {
+                            return Err(anyhow!("Interface matrix `{}` at location {} has {} columns", name, location, count));
+                        }
+                        let format = module.format_of(*column).unwrap_or(vk::Format::UNDEFINED);
+                        (0..*count)
+                            .map(|i| InterfaceVariable { name: format!("{}[{}]", name, i), location: location + i, format })
+                            .collect()
+                    }
+                    _ => {
+                        let format = module.format_of(pointee).unwrap_or(vk::Format::UNDEFINED);
+                        vec![InterfaceVariable { name, location, format }]
+                    }
+                };
+                if storage == STORAGE_INPUT {
+                    inputs.extend(variables);
+                } else {
+                    outputs.extend(variables);
+                }
+            }
+            STORAGE_PUSH_CONSTANT => {
+                let offset = match module.types.get(&pointee) {
+                    Some(Type::Struct { members }) => (0..members.len() as u32)
+                        .filter_map(|m| module.member_decoration(pointee, m, DECORATION_OFFSET).map(|o| o[0]))
+                        .min()
+                        .unwrap_or(0),
+                    _ => 0,
+                };
+                let size = module.size_of(pointee)? - offset;
+                push_constants.push(PushConstantBlock { name, offset, size });
+            }
+            STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                let Some((descriptor_type, count)) = module.descriptor_type(storage, pointee) else {
+                    continue;
+                };
+                let name = if name.is_empty() { module.name(pointee) } else { name };
+                descriptor_bindings.push(DescriptorBinding {
+                    name,
+                    set: module.decoration(id, DECORATION_DESCRIPTOR_SET).map_or(0, |d| d[0]),
+                    binding: module.decoration(id, DECORATION_BINDING).map_or(0, |b| b[0]),
+                    descriptor_type,
+                    count,
+                    input_attachment_index: module
+                        .decoration(id, DECORATION_INPUT_ATTACHMENT_INDEX)
+                        .map(|i| i[0]),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    inputs.sort_by_key(|v| v.location);
+    outputs.sort_by_key(|v| v.location);
+    descriptor_bindings.sort_by_key(|b| (b.set, b.binding));
+
+    let mut specialization_constants = Vec::new();
+    for (type_id, id, value) in &module.spec_constants {
+        let Some(spec_id) = module.decoration(*id, DECORATION_SPEC_ID).map(|s| s[0]) else {
+            continue;
+        };
+        let (default, size) = match module.types.get(type_id) {
+            Some(Type::Bool) => (SpecializationValue::Bool(value[0] != 0), 4),
+            Some(Type::Int { width: 32, signed: true }) => (SpecializationValue::Int(value[0] as i32), 4),
+            Some(Type::Int { width: 32, signed: false }) => (SpecializationValue::UInt(value[0]), 4),
+            Some(Type::Float { width: 32 }) => (SpecializationValue::Float(f32::from_bits(value[0])), 4),
+            _ => {
+                let raw = value.iter().rev().fold(0u64, |acc, w| (acc << 32) | *w as u64);
+                (SpecializationValue::Raw(raw), module.size_of(*type_id)?)
+            }
+        };
+        specialization_constants.push(SpecializationConstant { name: module.name(*id), id: spec_id, default, size });
+    }
+    specialization_constants.sort_by_key(|c| c.id);
+
+    // A constant decorated with the WorkgroupSize built-in overrides the execution mode.
+    let workgroup_size_constant = module
+        .composites
+        .iter()
+        .find(|(id, _)| module.decoration(**id, DECORATION_BUILT_IN).map(|b| b[0]) == Some(BUILT_IN_WORKGROUP_SIZE))
+        .map(|(_, parts)| parts);
+    let workgroup_size = match workgroup_size_constant {
+        Some(parts) => {
+            let sizes = parts.iter().map(|p| module.constant_u32(*p)).collect::<Option<Vec<_>>>();
+            let size: [u32; 3] = sizes
+                .as_deref()
+                .and_then(|s| s.get(..3))
+                .and_then(|s| s.try_into().ok())
+                .ok_or_else(|| anyhow!("The WorkgroupSize constant isn't made of three integer constants"))?;
+            Some(size)
+        }
+        None => module.local_size,
+    };
+    let workgroup_size = workgroup_size.or_else(|| {
+            let ids = module.local_size_ids?;
+            Some([
+                module.constant_u32(ids[0])?,
+                module.constant_u32(ids[1])?,
+                module.constant_u32(ids[2])?,
+            ])
+        });
+    let workgroup_size_parts = workgroup_size_constant
+        .map(|p| p.as_slice())
+        .or(module.local_size_ids.as_ref().map(|i| i.as_slice()));
+    let workgroup_size_ids = match workgroup_size_parts {
+        Some(parts) if parts.len() == 3 => [0, 1, 2].map(|i| module.decoration(parts[i], DECORATION_SPEC_ID).map(|s| s[0])),
+        _ => [None; 3],
+    };
+
+    Ok(ShaderReflection {
+        stage,
+        entry_point,
+        inputs,
+        outputs,
+        descriptor_bindings,
+        push_constants,
+        specialization_constants,
+        workgroup_size,
+        workgroup_size_ids,
+    })
+}
+
+// The size in bytes of a vertex format, for every format `numeric_format`
+// knows.
+pub fn format_size(format: vk::Format) -> Option<u32> {
+    Some(match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_SINT | vk::Format::R8_UINT => 1,
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SNORM | vk::Format::R8G8_SINT | vk::Format::R8G8_UINT => 2,
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SNORM | vk::Format::R8G8B8A8_SINT | vk::Format::R8G8B8A8_UINT => 4,
+        vk::Format::B8G8R8A8_UNORM | vk::Format::A2B10G10R10_UNORM_PACK32 => 4,
+        vk::Format::R16_UNORM | vk::Format::R16_SNORM | vk::Format::R16_SINT | vk::Format::R16_UINT => 2,
+        vk::Format::R16_SFLOAT => 2,
+        vk::Format::R16G16_UNORM | vk::Format::R16G16_SNORM | vk::Format::R16G16_SINT | vk::Format::R16G16_UINT => 4,
+        vk::Format::R16G16_SFLOAT => 4,
+        vk::Format::R16G16B16_SFLOAT => 6,
+        vk::Format::R16G16B16A16_UNORM | vk::Format::R16G16B16A16_SNORM => 8,
+        vk::Format::R16G16B16A16_SINT | vk::Format::R16G16B16A16_UINT | vk::Format::R16G16B16A16_SFLOAT => 8,
+        vk::Format::R32_SFLOAT | vk::Format::R32_SINT | vk::Format::R32_UINT => 4,
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_SINT | vk::Format::R32G32_UINT => 8,
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_SINT | vk::Format::R32G32B32_UINT => 12,
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_SINT | vk::Format::R32G32B32A32_UINT => 16,
+        vk::Format::R64_SFLOAT => 8,
+        vk::Format::R64G64_SFLOAT => 16,
+        vk::Format::R64G64B64_SFLOAT => 24,
+        vk::Format::R64G64B64A64_SFLOAT => 32,
+        _ => return None,
+    })
+}
+
+// How the shader sees a format: normalized and scaled formats read as floats.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum NumericType {
+    Float,
+    Double,
+    SInt,
+    UInt,
+}
+
+// The numeric type and component count of a vertex format.
+fn numeric_format(format: vk::Format) -> Option<(NumericType, u32)> {
+    use NumericType::*;
+    Some(match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R16_UNORM | vk::Format::R16_SNORM => (Float, 1),
+        vk::Format::R16_SFLOAT | vk::Format::R32_SFLOAT => (Float, 1),
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SNORM | vk::Format::R16G16_UNORM | vk::Format::R16G16_SNORM => (Float, 2),
+        vk::Format::R16G16_SFLOAT | vk::Format::R32G32_SFLOAT => (Float, 2),
+        vk::Format::R16G16B16_SFLOAT | vk::Format::R32G32B32_SFLOAT => (Float, 3),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SNORM | vk::Format::B8G8R8A8_UNORM => (Float, 4),
+        vk::Format::R16G16B16A16_UNORM | vk::Format::R16G16B16A16_SNORM => (Float, 4),
+        vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32B32A32_SFLOAT => (Float, 4),
+        vk::Format::R64_SFLOAT => (Double, 1),
+        vk::Format::R64G64_SFLOAT => (Double, 2),
+        vk::Format::R64G64B64_SFLOAT => (Double, 3),
+        vk::Format::R64G64B64A64_SFLOAT => (Double, 4),
+        vk::Format::R8_SINT | vk::Format::R16_SINT | vk::Format::R32_SINT => (SInt, 1),
+        vk::Format::R8G8_SINT | vk::Format::R16G16_SINT | vk::Format::R32G32_SINT => (SInt, 2),
+        vk::Format::R32G32B32_SINT => (SInt, 3),
+        vk::Format::R8G8B8A8_SINT | vk::Format::R16G16B16A16_SINT | vk::Format::R32G32B32A32_SINT => (SInt, 4),
+        vk::Format::R8_UINT | vk::Format::R16_UINT | vk::Format::R32_UINT => (UInt, 1),
+        vk::Format::R8G8_UINT | vk::Format::R16G16_UINT | vk::Format::R32G32_UINT => (UInt, 2),
+        vk::Format::R32G32B32_UINT => (UInt, 3),
+        vk::Format::R8G8B8A8_UINT | vk::Format::R16G16B16A16_UINT | vk::Format::R32G32B32A32_UINT => (UInt, 4),
+        _ => return None,
+    })
+}
+
+// Whether a vertex attribute in `attribute` format can feed a shader input
+// reflected as `input`: the same numeric type and component count, so a
+// `vec4` can read packed UNORM colors.
+fn formats_match(attribute: vk::Format, input: vk::Format) -> bool {
+    match (numeric_format(attribute), numeric_format(input)) {
+        (Some(attribute), Some(input)) => attribute == input,
+        _ => attribute == input,
+    }
+}
+
+// Tightly packed vertex input state in location order, for vertex types
+// whose fields follow the shader inputs one to one.
+pub fn vertex_input_from_reflection(
+    reflection: &ShaderReflection,
+    binding: u32,
+) -> (vk::VertexInputBindingDescription, Vec<vk::VertexInputAttributeDescription>) {
+    let mut offset = 0;
+    let attributes = reflection
+        .inputs
+        .iter()
+        .map(|input| {
+            let attribute = vk::VertexInputAttributeDescription::builder()
+                .binding(binding)
+                .location(input.location)
+                .format(input.format)
+                .offset(offset)
+                .build();
+            // Unsized (unreflected) inputs are reported by `validate_vertex_input`.
+            offset += format_size(input.format).unwrap_or(0);
+            attribute
+        })
+        .collect::<Vec<_>>();
+
+    let binding = vk::VertexInputBindingDescription::builder()
+        .binding(binding)
+        .stride(offset)
+        .input_rate(vk::VertexInputRate::VERTEX)
+        .build();
+
+    (binding, attributes)
+}
+
+pub fn validate_vertex_input(
+    reflection: &ShaderReflection,
+    bindings: &[vk::VertexInputBindingDescription],
+    attributes: &[vk::VertexInputAttributeDescription],
+) -> Result<()> {
+    let mut errors = Vec::new();
+
+    for input in &reflection.inputs {
+        match attributes.iter().find(|a| a.location == input.location) {
+            None => errors.push(format!("shader input `{}` (location {}) has no vertex attribute", input.name, input.location)),
+            Some(attribute) if !formats_match(attribute.format, input.format) => errors.push(format!(
+                "location {} (`{}`) is {:?} in the shader but {:?} in the vertex type",
+                input.location, input.name, input.format, attribute.format,
+            )),
+            Some(_) => {}
+        }
+    }
+
+    for attribute in attributes {
+        match bindings.iter().find(|b| b.binding == attribute.binding) {
+            None => errors.push(format!("attribute at location {} uses undeclared binding {}", attribute.location, attribute.binding)),
+            Some(binding) => match format_size(attribute.format) {
+                None => errors.push(format!(
+                    "attribute at location {} has unsupported format {:?}",
+                    attribute.location, attribute.format,
+                )),
+                Some(size) if attribute.offset + size > binding.stride => errors.push(format!(
+                    "attribute at location {} ends at byte {} past the stride {} of binding {}",
+                    attribute.location,
+                    attribute.offset + size,
+                    binding.stride,
+                    binding.binding,
+                )),
+                Some(_) => {}
+            },
+        }
+        if !reflection.inputs.iter().any(|i| i.location == attribute.location) {
+            warn!("Vertex attribute at location {} is not consumed by the shader.", attribute.location);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(VertexInputMismatch(errors)))
+    }
+}
+
+// A descriptor binding merged across stages.
+struct MergedBinding {
+    name: String,
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    count: Option<u32>,
+    stages: vk::ShaderStageFlags,
+}
+
+fn merge_descriptor_bindings(reflections: &[&ShaderReflection]) -> Result<BTreeMap<u32, Vec<MergedBinding>>> {
+    let mut sets: BTreeMap<u32, Vec<MergedBinding>> = BTreeMap::new();
+
+    for reflection in reflections {
+        for binding in &reflection.descriptor_bindings {
+            let bindings = sets.entry(binding.set).or_default();
+            match bindings.iter_mut().find(|b| b.binding == binding.binding) {
+                Some(existing) => {
+                    if existing.descriptor_type != binding.descriptor_type || existing.count != binding.count {
+                        return Err(anyhow!(
+                            "Descriptor (set {}, binding {}) is declared as {:?}[{}] and {:?}[{}] in different stages",
+                            binding.set,
+                            binding.binding,
+                            existing.descriptor_type,
+                            existing.count.map_or(String::new(), |c| c.to_string()),
+                            binding.descriptor_type,
+                            binding.count.map_or(String::new(), |c| c.to_string()),
+                        ));
+                    }
+                    existing.stages |= reflection.stage;
+                }
+                None => bindings.push(MergedBinding {
+                    name: binding.name.clone(),
+                    binding: binding.binding,
+                    descriptor_type: binding.descriptor_type,
+                    count: binding.count,
+                    stages: reflection.stage,
+                }),
+            }
+        }
+    }
+
+    Ok(sets)
+}
+
+// Merges the descriptor bindings of all stages into per-set layout bindings,
+// leaving out `external_sets`, whose layouts come from elsewhere. Runtime-sized
+// arrays can only be in those.
+pub fn descriptor_set_layout_bindings(
+    reflections: &[&ShaderReflection],
+    external_sets: &[u32],
+) -> Result<BTreeMap<u32, Vec<vk::DescriptorSetLayoutBinding>>> {
+    let mut sets = BTreeMap::new();
+    for (set, merged) in merge_descriptor_bindings(reflections)? {
+        if external_sets.contains(&set) {
+            continue;
+        }
+        let bindings = merged
+            .iter()
+            .map(|b| {
+                let count = b.count.ok_or_else(|| {
+                    anyhow!(
+                        "Descriptor `{}` (set {}, binding {}) is a runtime-sized array, which needs an external layout",
+                        b.name,
+                        set,
+                        b.binding,
+                    )
+                })?;
+                Ok(vk::DescriptorSetLayoutBinding::builder()
+                    .binding(b.binding)
+                    .descriptor_type(b.descriptor_type)
+                    .descriptor_count(count)
+                    .stage_flags(b.stages)
+                    .build())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        sets.insert(set, bindings);
+    }
+
+    Ok(sets)
+}
+
+pub fn validate_descriptor_set_layout(
+    reflections: &[&ShaderReflection],
+    set: u32,
+    bindings: &[vk::DescriptorSetLayoutBinding],
+) -> Result<()> {
+    let reflected = merge_descriptor_bindings(reflections)?.remove(&set).unwrap_or_default();
+    let mut errors = Vec::new();
+
+    for expected in &reflected {
+        match bindings.iter().find(|b| b.binding == expected.binding) {
+            None => errors.push(format!("binding {} is used by the shaders but missing from the layout", expected.binding)),
+            Some(actual) => {
+                // A runtime-sized array takes whatever count the layout has.
+                let expected_count = expected.count.unwrap_or(1);
+                if actual.descriptor_type != expected.descriptor_type {
+                    errors.push(format!(
+                        "binding {} is {:?} in the shaders but {:?} in the layout",
+                        expected.binding, expected.descriptor_type, actual.descriptor_type,
+                    ));
+                }
+                if actual.descriptor_count < expected_count {
+                    errors.push(format!(
+                        "binding {} needs {} descriptors but the layout has {}",
+                        expected.binding, expected_count, actual.descriptor_count,
+                    ));
+                }
+                if !actual.stage_flags.contains(expected.stages) {
+                    errors.push(format!(
+                        "binding {} is used in {:?} but only visible to {:?}",
+                        expected.binding, expected.stages, actual.stage_flags,
+                    ));
+                }
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(DescriptorLayoutMismatch { set, errors }))
+    }
+}
+
+// One range per stage, covering every push constant member that stage reads.
+pub fn push_constant_ranges(reflections: &[&ShaderReflection]) -> Vec<vk::PushConstantRange> {
+    reflections
+        .iter()
+        .filter_map(|reflection| {
+            let start = reflection.push_constants.iter().map(|p| p.offset).min()?;
+            let end = reflection.push_constants.iter().map(|p| p.offset + p.size).max()?;
+            Some(
+                vk::PushConstantRange::builder()
+                    .stage_flags(reflection.stage)
+                    .offset(start)
+                    .size(end - start)
+                    .build(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::shaders::spirv_words;
+
+    fn words(bytes: &[u8]) -> Vec<u32> {
+        spirv_words(bytes).unwrap()
+    }
+
+    fn header() -> Vec<u32> {
+        vec![SPIRV_MAGIC, 0x0001_0000, 0, 100, 0]
+    }
+
+    // A module with entry point `main` (id 1) of `execution_model` followed by
+    // `instructions`.
+    fn module(execution_model: u32, instructions: &[(u32, &[u32])]) -> Vec<u32> {
+        let mut binary = header();
+        binary.extend([(5 << 16) | OP_ENTRY_POINT, execution_model, 1, u32::from_le_bytes(*b"main"), 0]);
+        for (opcode, operands) in instructions {
+            binary.push(((operands.len() as u32 + 1) << 16) | opcode);
+            binary.extend(*operands);
+        }
+        binary
+    }
+
+    fn compute_module(instructions: &[(u32, &[u32])]) -> Vec<u32> {
+        module(5, instructions)
+    }
+
+    #[test]
+    fn reflects_vertex_shader() {
+        let reflection = reflect_shader(&words(include_bytes!("../../shaders/shader.vert.spv"))).unwrap();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(reflection.entry_point, "main");
+        let inputs = reflection.inputs.iter().map(|i| (i.location, i.format)).collect::<Vec<_>>();
+        assert_eq!(inputs, [
+            (0, vk::Format::R32G32B32_SFLOAT),
+            (1, vk::Format::R32G32B32_SFLOAT),
+            (2, vk::Format::R32G32_SFLOAT),
+            (3, vk::Format::R32G32B32_SFLOAT),
+        ]);
+    }
+
+    #[test]
+    fn vertex_input_matches_numeric_type_and_matrix_columns() {
+        use crate::core::vertex_input::{VertexInput, vertex_layout};
+
+        vertex_layout! {
+            #[derive(Copy, Clone, Debug)]
+            struct Color {
+                #[location(0)] color: [u8; 4],
+            }
+        }
+        vertex_layout! {
+            instance;
+            #[derive(Copy, Clone, Debug)]
+            struct Model {
+                #[location(1)] model: cgmath::Matrix4<f32>,
+            }
+        }
+
+        // layout(location = 0) in vec4 color; layout(location = 1) in mat4 model;
+        let binary = module(0, &[
+            (OP_TYPE_FLOAT, &[2, 32]),
+            (OP_TYPE_VECTOR, &[3, 2, 4]),
+            (OP_TYPE_MATRIX, &[4, 3, 4]),
+            (OP_TYPE_POINTER, &[5, STORAGE_INPUT, 3]),
+            (OP_TYPE_POINTER, &[6, STORAGE_INPUT, 4]),
+            (OP_VARIABLE, &[5, 7, STORAGE_INPUT]),
+            (OP_VARIABLE, &[6, 8, STORAGE_INPUT]),
+            (OP_DECORATE, &[7, DECORATION_LOCATION, 0]),
+            (OP_DECORATE, &[8, DECORATION_LOCATION, 1]),
+        ]);
+        let reflection = reflect_shader(&binary).unwrap();
+        let inputs = reflection.inputs.iter().map(|i| (i.location, i.format)).collect::<Vec<_>>();
+        assert_eq!(inputs, [
+            (0, vk::Format::R32G32B32A32_SFLOAT),
+            (1, vk::Format::R32G32B32A32_SFLOAT),
+            (2, vk::Format::R32G32B32A32_SFLOAT),
+            (3, vk::Format::R32G32B32A32_SFLOAT),
+            (4, vk::Format::R32G32B32A32_SFLOAT),
+        ]);
+
+        let (bindings, attributes) = <(Color, Model)>::descriptions();
+        validate_vertex_input(&reflection, &bindings, &attributes).unwrap();
+
+        // Integer and wrongly sized colors don't feed a `vec4`.
+        for format in [vk::Format::R8G8B8A8_UINT, vk::Format::R32G32B32_SFLOAT] {
+            let mut attributes = attributes.clone();
+            attributes[0].format = format;
+            assert!(validate_vertex_input(&reflection, &bindings, &attributes).is_err(), "{:?} was accepted", format);
+        }
+    }
+
+    #[test]
+    fn rejects_attributes_past_the_stride() {
+        // layout(location = 0) in vec2 uv;
+        let binary = module(0, &[
+            (OP_TYPE_FLOAT, &[2, 32]),
+            (OP_TYPE_VECTOR, &[3, 2, 2]),
+            (OP_TYPE_POINTER, &[4, STORAGE_INPUT, 3]),
+            (OP_VARIABLE, &[4, 5, STORAGE_INPUT]),
+            (OP_DECORATE, &[5, DECORATION_LOCATION, 0]),
+        ]);
+        let reflection = reflect_shader(&binary).unwrap();
+
+        let bindings = [vk::VertexInputBindingDescription::builder().binding(0).stride(4).build()];
+        let attribute = |offset, format| {
+            vk::VertexInputAttributeDescription::builder().binding(0).location(0).format(format).offset(offset).build()
+        };
+
+        validate_vertex_input(&reflection, &bindings, &[attribute(2, vk::Format::R8G8_UNORM)]).unwrap();
+        let error = validate_vertex_input(&reflection, &bindings, &[attribute(3, vk::Format::R8G8_UNORM)]).unwrap_err();
+        assert!(error.to_string().contains("ends at byte 5 past the stride 4"), "{}", error);
+
+        // Formats without a known size are reported rather than assumed empty.
+        let error = validate_vertex_input(&reflection, &bindings, &[attribute(0, vk::Format::R4G4_UNORM_PACK8)]).unwrap_err();
+        assert!(error.to_string().contains("unsupported format"), "{}", error);
+    }
+
+    #[test]
+    fn reflects_compute_workgroup_size() {
+        let binary = compute_module(&[(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_LOCAL_SIZE, 8, 8, 1])]);
+        let reflection = reflect_shader(&binary).unwrap();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::COMPUTE);
+        assert_eq!(reflection.workgroup_size, Some([8, 8, 1]));
+    }
+
+    #[test]
+    fn rejects_short_workgroup_size_constant() {
+        let binary = compute_module(&[
+            (OP_TYPE_INT, &[2, 32, 0]),
+            (OP_CONSTANT, &[2, 3, 8]),
+            (OP_TYPE_VECTOR, &[4, 2, 3]),
+            (OP_CONSTANT_COMPOSITE, &[4, 5, 3, 3]),
+            (OP_DECORATE, &[5, DECORATION_BUILT_IN, BUILT_IN_WORKGROUP_SIZE]),
+        ]);
+        assert!(reflect_shader(&binary).is_err());
+    }
+
+    #[test]
+    fn rejects_self_referencing_struct() {
+        let binary = compute_module(&[
+            (OP_TYPE_STRUCT, &[2, 2]),
+            (OP_TYPE_POINTER, &[3, STORAGE_PUSH_CONSTANT, 2]),
+            (OP_VARIABLE, &[3, 4, STORAGE_PUSH_CONSTANT]),
+        ]);
+        assert!(reflect_shader(&binary).is_err());
+    }
+
+    #[test]
+    fn rejects_self_referencing_matrix_input() {
+        let binary = module(0, &[
+            (OP_TYPE_MATRIX, &[2, 2, 4]),
+            (OP_TYPE_POINTER, &[3, STORAGE_INPUT, 2]),
+            (OP_VARIABLE, &[3, 4, STORAGE_INPUT]),
+            (OP_DECORATE, &[4, DECORATION_LOCATION, 0]),
+        ]);
+        let reflection = reflect_shader(&binary).unwrap();
+        assert!(reflection.inputs.iter().all(|i| i.format == vk::Format::UNDEFINED));
+
+        // Column counts outside 2..=4 would claim arbitrarily many locations.
+        let binary = module(0, &[
+            (OP_TYPE_FLOAT, &[2, 32]),
+            (OP_TYPE_VECTOR, &[3, 2, 4]),
+            (OP_TYPE_MATRIX, &[4, 3, u32::MAX]),
+            (OP_TYPE_POINTER, &[5, STORAGE_INPUT, 4]),
+            (OP_VARIABLE, &[5, 6, STORAGE_INPUT]),
+            (OP_DECORATE, &[6, DECORATION_LOCATION, 0]),
+        ]);
+        assert!(reflect_shader(&binary).is_err());
+    }
+
+    #[test]
+    fn reports_runtime_arrays_as_runtime_sized() {
+        let binary = compute_module(&[
+            (OP_TYPE_SAMPLER, &[2]),
+            (OP_TYPE_RUNTIME_ARRAY, &[3, 2]),
+            (OP_TYPE_POINTER, &[4, STORAGE_UNIFORM_CONSTANT, 3]),
+            (OP_VARIABLE, &[4, 5, STORAGE_UNIFORM_CONSTANT]),
+            (OP_DECORATE, &[5, DECORATION_DESCRIPTOR_SET, 1]),
+            (OP_DECORATE, &[5, DECORATION_BINDING, 0]),
+        ]);
+        let reflection = reflect_shader(&binary).unwrap();
+        assert_eq!(reflection.descriptor_bindings[0].descriptor_type, vk::DescriptorType::SAMPLER);
+        assert_eq!(reflection.descriptor_bindings[0].count, None);
+
+        // Only an external layout can hold it.
+        assert!(descriptor_set_layout_bindings(&[&reflection], &[]).is_err());
+        assert!(descriptor_set_layout_bindings(&[&reflection], &[1]).unwrap().is_empty());
+
+        let layout = |count| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(count)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        };
+        assert!(validate_descriptor_set_layout(&[&reflection], 1, &[layout(32)]).is_ok());
+        assert!(validate_descriptor_set_layout(&[&reflection], 1, &[layout(0)]).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut binary = header();
+        binary[0] = 0;
+        assert!(parse_module(&binary).is_err());
+        assert!(parse_module(&[SPIRV_MAGIC]).is_err());
+    }
+
+    #[test]
+    fn rejects_instruction_past_end() {
+        let mut binary = header();
+        binary.extend([(4 << 16) | OP_TYPE_INT, 1, 32]);
+        assert!(parse_module(&binary).is_err());
+    }
+
+    #[test]
+    fn rejects_short_instructions() {
+        for (opcode, operands) in [
+            (OP_EXECUTION_MODE, vec![1, EXECUTION_MODE_LOCAL_SIZE, 8]),
+            (OP_TYPE_IMAGE, vec![1, 2, 1, 0, 0, 0]),
+            (OP_TYPE_POINTER, vec![1, STORAGE_UNIFORM]),
+            (OP_DECORATE, vec![1, DECORATION_LOCATION]),
+            (OP_MEMBER_DECORATE, vec![1, 0, DECORATION_OFFSET]),
+            (OP_VARIABLE, vec![1]),
+        ] {
+            let mut binary = header();
+            binary.push(((operands.len() as u32 + 1) << 16) | opcode);
+            binary.extend(operands);
+            assert!(parse_module(&binary).is_err(), "opcode {} was accepted", opcode);
+        }
+    }
+
+    #[test]
+    fn truncated_modules_never_panic() {
+        let binary = words(include_bytes!("../../shaders/shader.vert.spv"));
+        for len in 0..binary.len() {
+            let _ = reflect_shader(&binary[..len]);
+        }
+        for i in 5..binary.len() {
+            // Shrinking an instruction's word count leaves its operands short.
+            let mut damaged = binary.clone();
+            damaged[i] = (damaged[i] & 0xFFFF) | (1 << 16);
+            let _ = reflect_shader(&damaged);
+        }
+    }
+}
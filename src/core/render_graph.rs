@@ -0,0 +1,1586 @@
+use anyhow::{Result, anyhow};
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+
+use crate::AppData;
+use crate::core;
+use crate::core::dynamic_rendering::DynamicRendering;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImageHandle(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BufferHandle(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PassHandle(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageAccess {
+    ColorAttachment,
+    DepthAttachment,
+    // Depth test without depth writes.
+    DepthReadAttachment,
+    ResolveAttachment,
+    Sampled(vk::PipelineStageFlags),
+    StorageRead(vk::PipelineStageFlags),
+    StorageWrite(vk::PipelineStageFlags),
+    TransferSrc,
+    TransferDst,
+}
+
+impl ImageAccess {
+    pub fn is_write(self) -> bool {
+        matches!(
+            self,
+            Self::ColorAttachment
+                | Self::DepthAttachment
+                | Self::ResolveAttachment
+                | Self::StorageWrite(_)
+                | Self::TransferDst
+        )
+    }
+
+    fn state(self) -> ResourceState {
+        let fragment_tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+        let (layout, stages, access) = match self {
+            Self::ColorAttachment | Self::ResolveAttachment => (
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ),
+            Self::DepthAttachment => (
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                fragment_tests,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ),
+            Self::DepthReadAttachment => (
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+                fragment_tests,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+            ),
+            Self::Sampled(stages) => (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                stages,
+                vk::AccessFlags::SHADER_READ,
+            ),
+            Self::StorageRead(stages) => (vk::ImageLayout::GENERAL, stages, vk::AccessFlags::SHADER_READ),
+            Self::StorageWrite(stages) => (
+                vk::ImageLayout::GENERAL,
+                stages,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            ),
+            Self::TransferSrc => (
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            Self::TransferDst => (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+        };
+        ResourceState { layout, stages, access }
+    }
+
+    fn usage(self) -> vk::ImageUsageFlags {
+        match self {
+            Self::ColorAttachment | Self::ResolveAttachment => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            Self::DepthAttachment | Self::DepthReadAttachment => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            Self::Sampled(_) => vk::ImageUsageFlags::SAMPLED,
+            Self::StorageRead(_) | Self::StorageWrite(_) => vk::ImageUsageFlags::STORAGE,
+            Self::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            Self::TransferDst => vk::ImageUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BufferAccess {
+    Vertex,
+    Index,
+    Indirect,
+    Uniform(vk::PipelineStageFlags),
+    StorageRead(vk::PipelineStageFlags),
+    StorageWrite(vk::PipelineStageFlags),
+    TransferSrc,
+    TransferDst,
+}
+
+impl BufferAccess {
+    pub fn is_write(self) -> bool {
+        matches!(self, Self::StorageWrite(_) | Self::TransferDst)
+    }
+
+    fn state(self) -> ResourceState {
+        let (stages, access) = match self {
+            Self::Vertex => (vk::PipelineStageFlags::VERTEX_INPUT, vk::AccessFlags::VERTEX_ATTRIBUTE_READ),
+            Self::Index => (vk::PipelineStageFlags::VERTEX_INPUT, vk::AccessFlags::INDEX_READ),
+            Self::Indirect => (vk::PipelineStageFlags::DRAW_INDIRECT, vk::AccessFlags::INDIRECT_COMMAND_READ),
+            Self::Uniform(stages) => (stages, vk::AccessFlags::UNIFORM_READ),
+            Self::StorageRead(stages) => (stages, vk::AccessFlags::SHADER_READ),
+            Self::StorageWrite(stages) => (stages, vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE),
+            Self::TransferSrc => (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ),
+            Self::TransferDst => (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE),
+        };
+        ResourceState { layout: vk::ImageLayout::UNDEFINED, stages, access }
+    }
+
+    fn usage(self) -> vk::BufferUsageFlags {
+        match self {
+            Self::Vertex => vk::BufferUsageFlags::VERTEX_BUFFER,
+            Self::Index => vk::BufferUsageFlags::INDEX_BUFFER,
+            Self::Indirect => vk::BufferUsageFlags::INDIRECT_BUFFER,
+            Self::Uniform(_) => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            Self::StorageRead(_) | Self::StorageWrite(_) => vk::BufferUsageFlags::STORAGE_BUFFER,
+            Self::TransferSrc => vk::BufferUsageFlags::TRANSFER_SRC,
+            Self::TransferDst => vk::BufferUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+// Layout and synchronization scope of a resource at the graph boundary.
+// Buffers ignore `layout`. For an initial state, `access` lists writes that
+// still have to be made visible.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ResourceState {
+    pub layout: vk::ImageLayout,
+    pub stages: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+}
+
+impl ResourceState {
+    // Nothing to wait for and nothing worth keeping.
+    pub const NONE: Self = Self {
+        layout: vk::ImageLayout::UNDEFINED,
+        stages: vk::PipelineStageFlags::TOP_OF_PIPE,
+        access: vk::AccessFlags::empty(),
+    };
+
+    pub const PRESENT: Self = Self {
+        layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        stages: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        access: vk::AccessFlags::empty(),
+    };
+
+    // Contents are discarded, but the previous user's stages and writes are
+    // still waited on.
+    pub const fn discard(stages: vk::PipelineStageFlags, access: vk::AccessFlags) -> Self {
+        Self { layout: vk::ImageLayout::UNDEFINED, stages, access }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum LoadOp {
+    Load,
+    DontCare,
+    ClearColor([f32; 4]),
+    ClearDepth(f32, u32),
+}
+
+impl LoadOp {
+    fn vk(self) -> vk::AttachmentLoadOp {
+        match self {
+            Self::Load => vk::AttachmentLoadOp::LOAD,
+            Self::DontCare => vk::AttachmentLoadOp::DONT_CARE,
+            Self::ClearColor(_) | Self::ClearDepth(..) => vk::AttachmentLoadOp::CLEAR,
+        }
+    }
+
+    fn clear_value(self) -> vk::ClearValue {
+        match self {
+            Self::ClearColor(float32) => vk::ClearValue { color: vk::ClearColorValue { float32 } },
+            Self::ClearDepth(depth, stencil) => vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue { depth, stencil },
+            },
+            _ => vk::ClearValue::default(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl ImageDesc {
+    fn aspects(&self) -> vk::ImageAspectFlags {
+        if core::depth::is_depth_format(self.format) {
+            core::depth::depth_aspects(self.format)
+        } else {
+            vk::ImageAspectFlags::COLOR
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum ResourceKind {
+    Image { desc: ImageDesc, imported: Option<(vk::Image, vk::ImageView)> },
+    Buffer { size: vk::DeviceSize, imported: Option<vk::Buffer> },
+}
+
+#[derive(Clone, Debug)]
+struct Resource {
+    name: String,
+    kind: ResourceKind,
+    initial: ResourceState,
+    // Resources with a final state are graph outputs: they are never culled
+    // and end in that state.
+    output: Option<ResourceState>,
+}
+
+impl Resource {
+    fn is_transient(&self) -> bool {
+        matches!(
+            self.kind,
+            ResourceKind::Image { imported: None, .. } | ResourceKind::Buffer { imported: None, .. }
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Access {
+    Image(ImageAccess),
+    Buffer(BufferAccess),
+}
+
+impl Access {
+    fn is_write(self) -> bool {
+        match self {
+            Self::Image(access) => access.is_write(),
+            Self::Buffer(access) => access.is_write(),
+        }
+    }
+
+    fn state(self) -> ResourceState {
+        match self {
+            Self::Image(access) => access.state(),
+            Self::Buffer(access) => access.state(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ResourceUse {
+    resource: usize,
+    access: Access,
+    // Whether the previous contents matter. Cleared or fully overwritten
+    // attachments don't keep earlier writers alive.
+    reads: bool,
+}
+
+type ExecuteFn<'a> = Box<dyn Fn(&Device, vk::CommandBuffer, &PassContext) + 'a>;
+
+#[derive(Default)]
+struct PassDesc {
+    name: String,
+    uses: Vec<ResourceUse>,
+    colors: Vec<(ImageHandle, LoadOp)>,
+    depth: Option<(ImageHandle, LoadOp, ImageAccess)>,
+    resolves: Vec<ImageHandle>,
+    side_effects: bool,
+}
+
+impl PassDesc {
+    fn has_attachments(&self) -> bool {
+        !self.colors.is_empty() || self.depth.is_some()
+    }
+
+    // Attachments in render pass order: colors, depth, resolves.
+    fn attachments(&self) -> Vec<(ImageHandle, LoadOp, ImageAccess)> {
+        self.colors
+            .iter()
+            .map(|(h, load)| (*h, *load, ImageAccess::ColorAttachment))
+            .chain(self.depth)
+            .chain(self.resolves.iter().map(|h| (*h, LoadOp::DontCare, ImageAccess::ResolveAttachment)))
+            .collect()
+    }
+}
+
+struct Pass<'a> {
+    desc: PassDesc,
+    execute: ExecuteFn<'a>,
+}
+
+impl std::ops::Deref for Pass<'_> {
+    type Target = PassDesc;
+
+    fn deref(&self) -> &PassDesc {
+        &self.desc
+    }
+}
+
+// A frame described as passes that declare the resources they use. Compiling
+// culls passes whose results are never used, allocates transient resources
+// (sharing memory between those with disjoint lifetimes), works out the
+// barriers and, unless rendering dynamically, creates render passes and
+// framebuffers for attachment passes.
+//
+// Passes are ordered by the resources they share, not by declaration: a pass
+// that only reads a resource runs after every pass that writes it, and the
+// writers of one resource run in the order they were declared. Otherwise
+// independent passes keep their declaration order. Compiling fails if the
+// dependencies form a cycle.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<Resource>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ImageHandle {
+        ImageHandle(self.add_resource(name, ResourceKind::Image { desc, imported: None }, ResourceState::NONE, None))
+    }
+
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        image: vk::Image,
+        view: vk::ImageView,
+        desc: ImageDesc,
+        initial: ResourceState,
+        output: Option<ResourceState>,
+    ) -> ImageHandle {
+        let kind = ResourceKind::Image { desc, imported: Some((image, view)) };
+        ImageHandle(self.add_resource(name, kind, initial, output))
+    }
+
+    pub fn create_buffer(&mut self, name: &str, size: vk::DeviceSize) -> BufferHandle {
+        BufferHandle(self.add_resource(name, ResourceKind::Buffer { size, imported: None }, ResourceState::NONE, None))
+    }
+
+    pub fn import_buffer(
+        &mut self,
+        name: &str,
+        buffer: vk::Buffer,
+        size: vk::DeviceSize,
+        initial: ResourceState,
+        output: Option<ResourceState>,
+    ) -> BufferHandle {
+        let kind = ResourceKind::Buffer { size, imported: Some(buffer) };
+        BufferHandle(self.add_resource(name, kind, initial, output))
+    }
+
+    fn add_resource(&mut self, name: &str, kind: ResourceKind, initial: ResourceState, output: Option<ResourceState>) -> usize {
+        self.resources.push(Resource { name: name.to_string(), kind, initial, output });
+        self.resources.len() - 1
+    }
+
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        PassBuilder {
+            graph: self,
+            pass: PassDesc { name: name.to_string(), ..Default::default() },
+        }
+    }
+
+    pub unsafe fn compile(&self, instance: &Instance, device: &Device, data: &AppData) -> Result<CompiledGraph> {
+        self.validate()?;
+
+        let (schedule, culled) = self.schedule()?;
+        for pass in &culled {
+            debug!("Render graph: culled pass `{}`.", self.passes[*pass].name);
+        }
+        let lifetimes = self.lifetimes(&schedule);
+
+        let mut compiled = CompiledGraph {
+            culled,
+            images: vec![vk::Image::null(); self.resources.len()],
+            views: vec![vk::ImageView::null(); self.resources.len()],
+            buffers: vec![vk::Buffer::null(); self.resources.len()],
+            memory_blocks: vec![None; self.resources.len()],
+            ..Default::default()
+        };
+
+        // Build into `compiled` so a failure part way still frees everything.
+        match self.build(instance, device, data, &schedule, &lifetimes, &mut compiled) {
+            Ok(()) => Ok(compiled),
+            Err(e) => {
+                compiled.destroy(device);
+                Err(e)
+            }
+        }
+    }
+
+    unsafe fn build(
+        &self,
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+        schedule: &[usize],
+        lifetimes: &[Option<(usize, usize)>],
+        compiled: &mut CompiledGraph,
+    ) -> Result<()> {
+        for (index, resource) in self.resources.iter().enumerate() {
+            match resource.kind {
+                ResourceKind::Image { imported: Some((image, view)), .. } => {
+                    compiled.images[index] = image;
+                    compiled.views[index] = view;
+                }
+                ResourceKind::Buffer { imported: Some(buffer), .. } => compiled.buffers[index] = buffer,
+                _ => {}
+            }
+        }
+
+        let previous_occupants = self.allocate_transients(instance, device, data, lifetimes, compiled)?;
+        let (pass_barriers, final_barriers) = self.barriers(schedule, lifetimes, &previous_occupants)?;
+
+        for ((position, pass_index), barriers) in schedule.iter().enumerate().zip(pass_barriers) {
+            let pass = &self.passes[*pass_index];
+            let (render_pass, framebuffer, extent, rendering) = match data.dynamic_rendering {
+                _ if !pass.has_attachments() => {
+                    (vk::RenderPass::null(), vk::Framebuffer::null(), vk::Extent2D::default(), None)
+                }
+                Some(mode) => {
+                    let extent = self.image_desc(pass.attachments()[0].0).extent;
+                    let rendering = self.describe_rendering(mode, pass, position, lifetimes);
+                    (vk::RenderPass::null(), vk::Framebuffer::null(), extent, Some(rendering))
+                }
+                None => {
+                    let (render_pass, framebuffer, extent) = self.create_render_pass(device, pass, position, lifetimes, compiled)?;
+                    (render_pass, framebuffer, extent, None)
+                }
+            };
+
+            compiled.passes.push(CompiledPass {
+                index: *pass_index,
+                barriers,
+                render_pass,
+                framebuffer,
+                rendering,
+                extent,
+                clear_values: pass.attachments().iter().map(|(_, load, _)| load.clear_value()).collect(),
+            });
+        }
+
+        compiled.final_barriers = final_barriers;
+
+        Ok(())
+    }
+
+    // The live passes in execution order, and the culled ones.
+    fn schedule(&self) -> Result<(Vec<usize>, Vec<usize>)> {
+        let order = self.sort()?;
+        let live = self.cull(&order);
+        let schedule = order.iter().copied().filter(|p| live[*p]).collect();
+        let culled = (0..self.passes.len()).filter(|p| !live[*p]).collect();
+        Ok((schedule, culled))
+    }
+
+    // The passes each pass has to run after.
+    fn dependencies(&self) -> Vec<Vec<usize>> {
+        let mut writers = vec![Vec::new(); self.resources.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for u in pass.uses.iter().filter(|u| u.access.is_write()) {
+                if writers[u.resource].last() != Some(&index) {
+                    writers[u.resource].push(index);
+                }
+            }
+        }
+
+        self.passes
+            .iter()
+            .enumerate()
+            .map(|(index, pass)| {
+                let mut dependencies = pass
+                    .uses
+                    .iter()
+                    .filter_map(|u| {
+                        let writers = &writers[u.resource];
+                        match writers.iter().position(|w| *w == index) {
+                            Some(position) => position.checked_sub(1).map(|p| writers[p]),
+                            None => writers.last().copied(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                dependencies.sort();
+                dependencies.dedup();
+                dependencies
+            })
+            .collect()
+    }
+
+    // Topological order of all passes, taking the earliest declared pass
+    // whenever several are ready.
+    fn sort(&self) -> Result<Vec<usize>> {
+        let dependencies = self.dependencies();
+        let mut dependents = vec![Vec::new(); self.passes.len()];
+        let mut waiting = dependencies.iter().map(|d| d.len()).collect::<Vec<_>>();
+        for (index, dependencies) in dependencies.iter().enumerate() {
+            for dependency in dependencies {
+                dependents[*dependency].push(index);
+            }
+        }
+
+        let mut ready = (0..self.passes.len()).filter(|p| waiting[*p] == 0).collect::<BTreeSet<_>>();
+        let mut order = Vec::with_capacity(self.passes.len());
+        while let Some(pass) = ready.pop_first() {
+            order.push(pass);
+            for dependent in &dependents[pass] {
+                waiting[*dependent] -= 1;
+                if waiting[*dependent] == 0 {
+                    ready.insert(*dependent);
+                }
+            }
+        }
+
+        if order.len() < self.passes.len() {
+            // Every pass left waits on another one left, so following any of
+            // them must come back around.
+            let mut path = vec![(0..self.passes.len()).find(|p| waiting[*p] > 0).unwrap()];
+            loop {
+                let last = *path.last().unwrap();
+                let next = dependencies[last].iter().copied().find(|d| waiting[*d] > 0).unwrap();
+                if let Some(start) = path.iter().position(|p| *p == next) {
+                    let cycle = path[start..].iter().rev().chain(path.last()).map(|p| format!("`{}`", self.passes[*p].name));
+                    return Err(anyhow!("Render graph passes depend on each other in a cycle: {}.", cycle.collect::<Vec<_>>().join(" -> ")));
+                }
+                path.push(next);
+            }
+        }
+
+        Ok(order)
+    }
+
+    // First and last scheduled use of every resource.
+    fn lifetimes(&self, schedule: &[usize]) -> Vec<Option<(usize, usize)>> {
+        let mut lifetimes = vec![None::<(usize, usize)>; self.resources.len()];
+        for (position, pass) in schedule.iter().enumerate() {
+            for u in &self.passes[*pass].uses {
+                let lifetime = lifetimes[u.resource].get_or_insert((position, position));
+                lifetime.1 = position;
+            }
+        }
+        lifetimes
+    }
+
+    // The barriers before each scheduled pass, and those that leave outputs in
+    // their final state.
+    fn barriers(
+        &self,
+        schedule: &[usize],
+        lifetimes: &[Option<(usize, usize)>],
+        previous_occupants: &[Option<usize>],
+    ) -> Result<(Vec<Vec<Barrier>>, Vec<Barrier>)> {
+        let mut states = self.resources.iter().map(|r| TrackedState::from(r.initial)).collect::<Vec<_>>();
+        let mut pass_barriers = Vec::with_capacity(schedule.len());
+
+        for (position, pass_index) in schedule.iter().enumerate() {
+            let mut barriers = Vec::new();
+            for (resource, access, is_write) in self.merged_uses(&self.passes[*pass_index])? {
+                if lifetimes[resource].is_some_and(|(first, _)| first == position) && self.resources[resource].is_transient() {
+                    // Memory may still be in use by the previous resource aliasing it.
+                    states[resource] = match previous_occupants[resource] {
+                        Some(previous) => TrackedState { layout: vk::ImageLayout::UNDEFINED, ..states[previous] },
+                        None => TrackedState::from(ResourceState::NONE),
+                    };
+                }
+                if let Some(barrier) = states[resource].transition(resource, access, is_write) {
+                    barriers.push(barrier);
+                }
+            }
+            pass_barriers.push(barriers);
+        }
+
+        let final_barriers = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(|(index, resource)| states[index].transition(index, resource.output?, true))
+            .collect();
+
+        Ok((pass_barriers, final_barriers))
+    }
+
+    fn validate(&self) -> Result<()> {
+        for pass in &self.passes {
+            if !pass.resolves.is_empty() && pass.resolves.len() != pass.colors.len() {
+                return Err(anyhow!(
+                    "Pass `{}` has {} resolve attachments for {} color attachments.",
+                    pass.name,
+                    pass.resolves.len(),
+                    pass.colors.len(),
+                ));
+            }
+
+            let extents = pass
+                .attachments()
+                .iter()
+                .map(|(h, ..)| self.image_desc(*h).extent)
+                .collect::<Vec<_>>();
+            if extents.windows(2).any(|e| e[0] != e[1]) {
+                return Err(anyhow!("Pass `{}` has attachments of different sizes.", pass.name));
+            }
+        }
+        Ok(())
+    }
+
+    // Walks the sorted passes backwards, keeping a pass only if something
+    // later (or a graph output) reads what it writes.
+    fn cull(&self, order: &[usize]) -> Vec<bool> {
+        let mut needed = self.resources.iter().map(|r| r.output.is_some()).collect::<Vec<_>>();
+        let mut live = vec![false; self.passes.len()];
+
+        for &index in order.iter().rev() {
+            let pass = &self.passes[index];
+            live[index] = pass.side_effects
+                || pass.uses.iter().any(|u| u.access.is_write() && needed[u.resource]);
+            if !live[index] {
+                continue;
+            }
+
+            // Earlier contents of a fully overwritten resource are dead.
+            for u in pass.uses.iter().filter(|u| u.access.is_write() && !u.reads) {
+                needed[u.resource] = false;
+            }
+            for u in pass.uses.iter().filter(|u| u.reads) {
+                needed[u.resource] = true;
+            }
+        }
+        live
+    }
+
+    // A resource used several ways by one pass gets a single combined state.
+    fn merged_uses(&self, pass: &PassDesc) -> Result<Vec<(usize, ResourceState, bool)>> {
+        let mut merged: Vec<(usize, ResourceState, bool)> = Vec::new();
+        for u in &pass.uses {
+            let state = u.access.state();
+            match merged.iter_mut().find(|(r, ..)| *r == u.resource) {
+                Some((_, existing, is_write)) => {
+                    if existing.layout != state.layout {
+                        return Err(anyhow!(
+                            "`{}` is used with conflicting layouts ({:?} and {:?}) in pass `{}`.",
+                            self.resources[u.resource].name,
+                            existing.layout,
+                            state.layout,
+                            pass.name,
+                        ));
+                    }
+                    existing.stages |= state.stages;
+                    existing.access |= state.access;
+                    *is_write |= u.access.is_write();
+                }
+                None => merged.push((u.resource, state, u.access.is_write())),
+            }
+        }
+        Ok(merged)
+    }
+
+    // Creates the transient resources and binds each to a memory block shared
+    // with other transients whose lifetimes don't overlap. Returns, for each
+    // resource, the one that used its memory before it.
+    unsafe fn allocate_transients(
+        &self,
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+        lifetimes: &[Option<(usize, usize)>],
+        compiled: &mut CompiledGraph,
+    ) -> Result<Vec<Option<usize>>> {
+        let mut transients = (0..self.resources.len())
+            .filter(|r| self.resources[*r].is_transient())
+            .filter_map(|r| lifetimes[r].map(|lifetime| (r, lifetime)))
+            .collect::<Vec<_>>();
+        transients.sort_by_key(|(_, (first, _))| *first);
+
+        let mut blocks: Vec<MemoryBlock> = Vec::new();
+        let mut previous_occupants = vec![None; self.resources.len()];
+
+        for (resource, (first, last)) in transients {
+            let usage = self.passes.iter().flat_map(|p| &p.uses).filter(|u| u.resource == resource);
+            let requirements = match self.resources[resource].kind {
+                ResourceKind::Image { desc, .. } => {
+                    let usage = usage.fold(vk::ImageUsageFlags::empty(), |flags, u| match u.access {
+                        Access::Image(access) => flags | access.usage(),
+                        Access::Buffer(_) => flags,
+                    });
+                    let info = vk::ImageCreateInfo::builder()
+                        .image_type(vk::ImageType::_2D)
+                        .extent(vk::Extent3D { width: desc.extent.width, height: desc.extent.height, depth: 1 })
+                        .mip_levels(1)
+                        .array_layers(1)
+                        .format(desc.format)
+                        .tiling(vk::ImageTiling::OPTIMAL)
+                        .initial_layout(vk::ImageLayout::UNDEFINED)
+                        .usage(usage)
+                        .samples(desc.samples)
+                        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+                    let image = device.create_image(&info, None)?;
+                    compiled.images[resource] = image;
+                    compiled.owned_images.push(image);
+                    device.get_image_memory_requirements(image)
+                }
+                ResourceKind::Buffer { size, .. } => {
+                    let usage = usage.fold(vk::BufferUsageFlags::empty(), |flags, u| match u.access {
+                        Access::Buffer(access) => flags | access.usage(),
+                        Access::Image(_) => flags,
+                    });
+                    let info = vk::BufferCreateInfo::builder()
+                        .size(size)
+                        .usage(usage)
+                        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+                    let buffer = device.create_buffer(&info, None)?;
+                    compiled.buffers[resource] = buffer;
+                    compiled.owned_buffers.push(buffer);
+                    device.get_buffer_memory_requirements(buffer)
+                }
+            };
+
+            let block = blocks
+                .iter()
+                .position(|b| b.last_use < first && b.requirements.memory_type_bits & requirements.memory_type_bits != 0);
+            let block = match block {
+                Some(index) => {
+                    let b = &mut blocks[index];
+                    previous_occupants[resource] = b.occupants.last().copied();
+                    b.requirements.size = b.requirements.size.max(requirements.size);
+                    b.requirements.alignment = b.requirements.alignment.max(requirements.alignment);
+                    b.requirements.memory_type_bits &= requirements.memory_type_bits;
+                    b.last_use = last;
+                    b.occupants.push(resource);
+                    index
+                }
+                None => {
+                    blocks.push(MemoryBlock { requirements, last_use: last, occupants: vec![resource] });
+                    blocks.len() - 1
+                }
+            };
+            compiled.memory_blocks[resource] = Some(block);
+        }
+
+        for block in &blocks {
+            let info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(block.requirements.size)
+                .memory_type_index(core::vertex::get_memory_type_index(
+                    instance,
+                    data,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    block.requirements,
+                )?);
+            let memory = device.allocate_memory(&info, None)?;
+            compiled.memory.push(memory);
+
+            for resource in &block.occupants {
+                match self.resources[*resource].kind {
+                    ResourceKind::Image { desc, .. } => {
+                        device.bind_image_memory(compiled.images[*resource], memory, 0)?;
+                        let view = core::image::create_image_view(device, compiled.images[*resource], desc.format, desc.aspects(), 1)?;
+                        compiled.views[*resource] = view;
+                        compiled.owned_views.push(view);
+                    }
+                    ResourceKind::Buffer { .. } => device.bind_buffer_memory(compiled.buffers[*resource], memory, 0)?,
+                }
+            }
+        }
+
+        let aliased = blocks.iter().map(|b| b.occupants.len() - 1).sum::<usize>();
+        debug!("Render graph: {} transient resources in {} memory blocks.", aliased + blocks.len(), blocks.len());
+
+        Ok(previous_occupants)
+    }
+
+    // Attachments keep their layout through the render pass: barriers outside
+    // it do the transitions.
+    unsafe fn create_render_pass(
+        &self,
+        device: &Device,
+        pass: &PassDesc,
+        position: usize,
+        lifetimes: &[Option<(usize, usize)>],
+        compiled: &mut CompiledGraph,
+    ) -> Result<(vk::RenderPass, vk::Framebuffer, vk::Extent2D)> {
+        let attachments = pass.attachments();
+
+        let descriptions = attachments
+            .iter()
+            .map(|(h, load, access)| {
+                let desc = self.image_desc(*h);
+                let store_op = self.store_op(*h, position, lifetimes);
+                let (stencil_load_op, stencil_store_op) = if core::depth::has_stencil_component(desc.format) {
+                    (load.vk(), store_op)
+                } else {
+                    (vk::AttachmentLoadOp::DONT_CARE, vk::AttachmentStoreOp::DONT_CARE)
+                };
+                let layout = access.state().layout;
+                vk::AttachmentDescription::builder()
+                    .format(desc.format)
+                    .samples(desc.samples)
+                    .load_op(load.vk())
+                    .store_op(store_op)
+                    .stencil_load_op(stencil_load_op)
+                    .stencil_store_op(stencil_store_op)
+                    .initial_layout(layout)
+                    .final_layout(layout)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        let reference = |index: usize| {
+            vk::AttachmentReference::builder()
+                .attachment(index as u32)
+                .layout(attachments[index].2.state().layout)
+                .build()
+        };
+        let color_count = pass.colors.len();
+        let depth_count = pass.depth.iter().count();
+        let color_attachments = (0..color_count).map(reference).collect::<Vec<_>>();
+        let resolve_attachments = (0..pass.resolves.len())
+            .map(|i| reference(color_count + depth_count + i))
+            .collect::<Vec<_>>();
+        let depth_stencil_attachment = pass.depth.map(|_| reference(color_count));
+
+        let mut subpass = vk::SubpassDescription::builder()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachments);
+        if !resolve_attachments.is_empty() {
+            subpass = subpass.resolve_attachments(&resolve_attachments);
+        }
+        if let Some(depth_stencil_attachment) = &depth_stencil_attachment {
+            subpass = subpass.depth_stencil_attachment(depth_stencil_attachment);
+        }
+
+        let subpasses = &[subpass];
+        let info = vk::RenderPassCreateInfo::builder()
+            .attachments(&descriptions)
+            .subpasses(subpasses);
+        let render_pass = device.create_render_pass(&info, None)?;
+        compiled.render_passes.push(render_pass);
+
+        let extent = self.image_desc(attachments[0].0).extent;
+        let views = attachments.iter().map(|(h, ..)| compiled.views[h.0]).collect::<Vec<_>>();
+        let info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass)
+            .attachments(&views)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = device.create_framebuffer(&info, None)?;
+        compiled.framebuffers.push(framebuffer);
+
+        Ok((render_pass, framebuffer, extent))
+    }
+
+    // The attachments of a dynamic rendering pass. Layouts are the ones the
+    // barriers leave them in, as with render passes.
+    fn describe_rendering(
+        &self,
+        mode: DynamicRendering,
+        pass: &PassDesc,
+        position: usize,
+        lifetimes: &[Option<(usize, usize)>],
+    ) -> Rendering {
+        let attachment = |handle: ImageHandle, load: LoadOp, access: ImageAccess| RenderingAttachment {
+            resource: handle.0,
+            layout: access.state().layout,
+            load_op: load.vk(),
+            store_op: self.store_op(handle, position, lifetimes),
+            clear_value: load.clear_value(),
+            resolve: None,
+        };
+
+        let colors = pass
+            .colors
+            .iter()
+            .enumerate()
+            .map(|(i, (h, load))| RenderingAttachment {
+                resolve: pass.resolves.get(i).map(|r| r.0),
+                ..attachment(*h, *load, ImageAccess::ColorAttachment)
+            })
+            .collect();
+        let depth = pass.depth.map(|(h, load, access)| attachment(h, load, access));
+        let stencil = pass.depth.is_some_and(|(h, ..)| core::depth::has_stencil_component(self.image_desc(h).format));
+
+        Rendering { mode, colors, depth, stencil }
+    }
+
+    // Only store what is read later or leaves the graph.
+    fn store_op(&self, handle: ImageHandle, position: usize, lifetimes: &[Option<(usize, usize)>]) -> vk::AttachmentStoreOp {
+        let keep = self.resources[handle.0].output.is_some()
+            || lifetimes[handle.0].is_some_and(|(_, last)| last > position);
+        if keep { vk::AttachmentStoreOp::STORE } else { vk::AttachmentStoreOp::DONT_CARE }
+    }
+
+    fn image_desc(&self, handle: ImageHandle) -> ImageDesc {
+        match self.resources[handle.0].kind {
+            ResourceKind::Image { desc, .. } => desc,
+            ResourceKind::Buffer { .. } => unreachable!("image handle refers to a buffer"),
+        }
+    }
+
+    // `compiled` must come from `compile` on this graph.
+    pub unsafe fn record(&self, device: &Device, command_buffer: vk::CommandBuffer, compiled: &CompiledGraph) {
+        for pass in &compiled.passes {
+            self.record_barriers(device, command_buffer, compiled, &pass.barriers);
+
+            if let Some(rendering) = &pass.rendering {
+                self.begin_rendering(device, command_buffer, compiled, pass.extent, rendering);
+            } else if !pass.render_pass.is_null() {
+                let render_area = vk::Rect2D::builder()
+                    .offset(vk::Offset2D::default())
+                    .extent(pass.extent);
+                let info = vk::RenderPassBeginInfo::builder()
+                    .render_pass(pass.render_pass)
+                    .framebuffer(pass.framebuffer)
+                    .render_area(render_area)
+                    .clear_values(&pass.clear_values);
+                device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+            }
+
+            let context = PassContext { compiled, pass };
+            (self.passes[pass.index].execute)(device, command_buffer, &context);
+
+            if let Some(rendering) = &pass.rendering {
+                core::dynamic_rendering::cmd_end_rendering(device, rendering.mode, command_buffer);
+            } else if !pass.render_pass.is_null() {
+                device.cmd_end_render_pass(command_buffer);
+            }
+        }
+
+        self.record_barriers(device, command_buffer, compiled, &compiled.final_barriers);
+    }
+
+    unsafe fn begin_rendering(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        compiled: &CompiledGraph,
+        extent: vk::Extent2D,
+        rendering: &Rendering,
+    ) {
+        let info = |attachment: &RenderingAttachment| {
+            let mut info = vk::RenderingAttachmentInfo::builder()
+                .image_view(compiled.views[attachment.resource])
+                .image_layout(attachment.layout)
+                .load_op(attachment.load_op)
+                .store_op(attachment.store_op)
+                .clear_value(attachment.clear_value);
+            // Resolves only come from the color formats used here, which all average.
+            if let Some(resolve) = attachment.resolve {
+                info = info
+                    .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                    .resolve_image_view(compiled.views[resolve])
+                    .resolve_image_layout(ImageAccess::ResolveAttachment.state().layout);
+            }
+            info.build()
+        };
+        let color_attachments = rendering.colors.iter().map(info).collect::<Vec<_>>();
+        let depth_attachment = rendering.depth.as_ref().map(info);
+
+        let render_area = vk::Rect2D::builder()
+            .offset(vk::Offset2D::default())
+            .extent(extent);
+        let mut rendering_info = vk::RenderingInfo::builder()
+            .render_area(render_area)
+            .layer_count(1)
+            .color_attachments(&color_attachments);
+        if let Some(depth_attachment) = &depth_attachment {
+            rendering_info = rendering_info.depth_attachment(depth_attachment);
+            if rendering.stencil {
+                rendering_info = rendering_info.stencil_attachment(depth_attachment);
+            }
+        }
+
+        core::dynamic_rendering::cmd_begin_rendering(device, rendering.mode, command_buffer, &rendering_info);
+    }
+
+    unsafe fn record_barriers(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        compiled: &CompiledGraph,
+        barriers: &[Barrier],
+    ) {
+        if barriers.is_empty() {
+            return;
+        }
+
+        let mut src_stages = vk::PipelineStageFlags::empty();
+        let mut dst_stages = vk::PipelineStageFlags::empty();
+        let mut image_barriers = Vec::new();
+        let mut buffer_barriers = Vec::new();
+
+        for barrier in barriers {
+            src_stages |= barrier.src_stages;
+            dst_stages |= barrier.dst_stages;
+            match self.resources[barrier.resource].kind {
+                ResourceKind::Image { desc, .. } => {
+                    let subresource_range = vk::ImageSubresourceRange::builder()
+                        .aspect_mask(desc.aspects())
+                        .base_mip_level(0)
+                        .level_count(1)
+                        .base_array_layer(0)
+                        .layer_count(1);
+                    image_barriers.push(
+                        vk::ImageMemoryBarrier::builder()
+                            .old_layout(barrier.old_layout)
+                            .new_layout(barrier.new_layout)
+                            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                            .image(compiled.images[barrier.resource])
+                            .subresource_range(subresource_range)
+                            .src_access_mask(barrier.src_access)
+                            .dst_access_mask(barrier.dst_access),
+                    );
+                }
+                ResourceKind::Buffer { .. } => buffer_barriers.push(
+                    vk::BufferMemoryBarrier::builder()
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .buffer(compiled.buffers[barrier.resource])
+                        .offset(0)
+                        .size(vk::WHOLE_SIZE)
+                        .src_access_mask(barrier.src_access)
+                        .dst_access_mask(barrier.dst_access),
+                ),
+            }
+        }
+
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stages,
+            dst_stages,
+            vk::DependencyFlags::empty(),
+            &[] as &[vk::MemoryBarrier],
+            &buffer_barriers,
+            &image_barriers,
+        );
+    }
+
+    // Graphviz DOT of the compiled graph: passes are boxes (dashed when
+    // culled), resources are ellipses, edges carry the access and any layout
+    // transition.
+    pub fn to_dot(&self, compiled: &CompiledGraph) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph render_graph {{");
+        let _ = writeln!(dot, "    rankdir=LR;");
+        let _ = writeln!(dot, "    node [fontname=\"monospace\", fontsize=10];");
+        let _ = writeln!(dot, "    edge [fontname=\"monospace\", fontsize=9];");
+
+        for (position, pass) in compiled.passes.iter().enumerate() {
+            let decl = &self.passes[pass.index];
+            let _ = writeln!(
+                dot,
+                "    pass{} [shape=box, style=filled, fillcolor=lightblue, label=\"{}: {}\\n{} barriers\"];",
+                pass.index,
+                position,
+                escape(&decl.name),
+                pass.barriers.len(),
+            );
+        }
+        for index in &compiled.culled {
+            let _ = writeln!(
+                dot,
+                "    pass{} [shape=box, style=dashed, fontcolor=gray, label=\"{}\\n(culled)\"];",
+                index,
+                escape(&self.passes[*index].name),
+            );
+        }
+
+        for (index, resource) in self.resources.iter().enumerate() {
+            let description = match resource.kind {
+                ResourceKind::Image { desc, .. } => format!(
+                    "{:?} {}x{} x{}",
+                    desc.format,
+                    desc.extent.width,
+                    desc.extent.height,
+                    desc.samples.bits(),
+                ),
+                ResourceKind::Buffer { size, .. } => format!("{} bytes", size),
+            };
+            let lifetime = match (resource.is_transient(), compiled.memory_blocks[index]) {
+                (true, Some(block)) => format!("transient, memory block {}", block),
+                (true, None) => "transient, unused".to_string(),
+                (false, _) if resource.output.is_some() => "imported, output".to_string(),
+                (false, _) => "imported".to_string(),
+            };
+            let style = if resource.is_transient() { "dashed" } else { "solid" };
+            let _ = writeln!(
+                dot,
+                "    res{} [shape=ellipse, style={}, label=\"{}\\n{}\\n{}\"];",
+                index,
+                style,
+                escape(&resource.name),
+                description,
+                lifetime,
+            );
+        }
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            let compiled_pass = compiled.passes.iter().find(|p| p.index == index);
+            for u in &pass.uses {
+                let transition = compiled_pass
+                    .and_then(|p| p.barriers.iter().find(|b| b.resource == u.resource))
+                    .filter(|b| b.old_layout != b.new_layout)
+                    .map(|b| format!("\\n{:?} -> {:?}", b.old_layout, b.new_layout))
+                    .unwrap_or_default();
+                let access = match u.access {
+                    Access::Image(access) => format!("{:?}", access),
+                    Access::Buffer(access) => format!("{:?}", access),
+                };
+                if u.reads || !u.access.is_write() {
+                    let _ = writeln!(dot, "    res{} -> pass{} [label=\"{}{}\"];", u.resource, index, access, transition);
+                }
+                if u.access.is_write() {
+                    let _ = writeln!(dot, "    pass{} -> res{} [label=\"{}{}\"];", index, u.resource, access, transition);
+                }
+            }
+        }
+
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+}
+
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: PassDesc,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    pub fn color_attachment(mut self, image: ImageHandle, load: LoadOp) -> Self {
+        self.pass.colors.push((image, load));
+        self.use_image(image, ImageAccess::ColorAttachment, matches!(load, LoadOp::Load))
+    }
+
+    pub fn depth_attachment(mut self, image: ImageHandle, load: LoadOp, write: bool) -> Self {
+        let access = if write { ImageAccess::DepthAttachment } else { ImageAccess::DepthReadAttachment };
+        self.pass.depth = Some((image, load, access));
+        self.use_image(image, access, !write || matches!(load, LoadOp::Load))
+    }
+
+    // Resolves the color attachment with the same index.
+    pub fn resolve_attachment(mut self, image: ImageHandle) -> Self {
+        self.pass.resolves.push(image);
+        self.use_image(image, ImageAccess::ResolveAttachment, false)
+    }
+
+    pub fn read_image(self, image: ImageHandle, access: ImageAccess) -> Self {
+        self.use_image(image, access, true)
+    }
+
+    // Storage writes may read back what they don't overwrite, so they keep
+    // earlier writers alive.
+    pub fn write_image(self, image: ImageHandle, access: ImageAccess) -> Self {
+        let reads = matches!(access, ImageAccess::StorageWrite(_));
+        self.use_image(image, access, reads)
+    }
+
+    pub fn read_buffer(mut self, buffer: BufferHandle, access: BufferAccess) -> Self {
+        self.pass.uses.push(ResourceUse { resource: buffer.0, access: Access::Buffer(access), reads: true });
+        self
+    }
+
+    pub fn write_buffer(mut self, buffer: BufferHandle, access: BufferAccess) -> Self {
+        let reads = matches!(access, BufferAccess::StorageWrite(_));
+        self.pass.uses.push(ResourceUse { resource: buffer.0, access: Access::Buffer(access), reads });
+        self
+    }
+
+    // Never culled, e.g. for passes that write to host-visible memory.
+    pub fn side_effects(mut self) -> Self {
+        self.pass.side_effects = true;
+        self
+    }
+
+    fn use_image(mut self, image: ImageHandle, access: ImageAccess, reads: bool) -> Self {
+        self.pass.uses.push(ResourceUse { resource: image.0, access: Access::Image(access), reads });
+        self
+    }
+
+    pub fn execute(self, execute: impl Fn(&Device, vk::CommandBuffer, &PassContext) + 'a) -> PassHandle {
+        self.graph.passes.push(Pass { desc: self.pass, execute: Box::new(execute) });
+        PassHandle(self.graph.passes.len() - 1)
+    }
+}
+
+pub struct PassContext<'c> {
+    compiled: &'c CompiledGraph,
+    pass: &'c CompiledPass,
+}
+
+impl PassContext<'_> {
+    pub fn image(&self, handle: ImageHandle) -> vk::Image {
+        self.compiled.images[handle.0]
+    }
+
+    pub fn view(&self, handle: ImageHandle) -> vk::ImageView {
+        self.compiled.views[handle.0]
+    }
+
+    pub fn buffer(&self, handle: BufferHandle) -> vk::Buffer {
+        self.compiled.buffers[handle.0]
+    }
+
+    // Null for passes without attachments and with dynamic rendering.
+    pub fn render_pass(&self) -> vk::RenderPass {
+        self.pass.render_pass
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.pass.extent
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Barrier {
+    resource: usize,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_stages: vk::PipelineStageFlags,
+    src_access: vk::AccessFlags,
+    dst_stages: vk::PipelineStageFlags,
+    dst_access: vk::AccessFlags,
+}
+
+// Where a resource stands while walking the schedule: the stages and access
+// of the last write (or of the barrier that last changed the layout), which
+// stages and accesses that write has been made visible to, and the stages
+// that read it since, which the next write or layout change waits for.
+#[derive(Copy, Clone, Debug)]
+struct TrackedState {
+    layout: vk::ImageLayout,
+    write_stages: vk::PipelineStageFlags,
+    write_access: vk::AccessFlags,
+    visible_stages: vk::PipelineStageFlags,
+    visible_access: vk::AccessFlags,
+    read_stages: vk::PipelineStageFlags,
+}
+
+impl From<ResourceState> for TrackedState {
+    fn from(state: ResourceState) -> Self {
+        // Without a write there's nothing to make visible.
+        let (visible_stages, visible_access) = if state.access.is_empty() {
+            (vk::PipelineStageFlags::all(), vk::AccessFlags::all())
+        } else {
+            (vk::PipelineStageFlags::empty(), vk::AccessFlags::empty())
+        };
+        Self {
+            layout: state.layout,
+            write_stages: state.stages,
+            write_access: state.access,
+            visible_stages,
+            visible_access,
+            read_stages: vk::PipelineStageFlags::empty(),
+        }
+    }
+}
+
+impl TrackedState {
+    fn transition(&mut self, resource: usize, next: ResourceState, is_write: bool) -> Option<Barrier> {
+        if self.layout == next.layout && !is_write {
+            self.read_stages |= next.stages;
+            // Reads the last write is already visible to need nothing.
+            if self.visible_stages.contains(next.stages) && self.visible_access.contains(next.access) {
+                return None;
+            }
+            self.visible_stages |= next.stages;
+            self.visible_access |= next.access;
+            return Some(Barrier {
+                resource,
+                old_layout: self.layout,
+                new_layout: next.layout,
+                src_stages: or_top_of_pipe(self.write_stages),
+                src_access: self.write_access,
+                dst_stages: next.stages,
+                dst_access: next.access,
+            });
+        }
+
+        // Writes and layout changes wait for the last write and every read since.
+        let barrier = Barrier {
+            resource,
+            old_layout: self.layout,
+            new_layout: next.layout,
+            src_stages: or_top_of_pipe(self.write_stages | self.read_stages),
+            src_access: self.write_access,
+            dst_stages: next.stages,
+            dst_access: next.access,
+        };
+        *self = if is_write {
+            Self {
+                layout: next.layout,
+                write_stages: next.stages,
+                write_access: next.access,
+                visible_stages: vk::PipelineStageFlags::empty(),
+                visible_access: vk::AccessFlags::empty(),
+                read_stages: vk::PipelineStageFlags::empty(),
+            }
+        } else {
+            // Later reads in other stages chain onto the stages that waited
+            // for the layout change.
+            Self {
+                layout: next.layout,
+                write_stages: next.stages,
+                write_access: vk::AccessFlags::empty(),
+                visible_stages: next.stages,
+                visible_access: next.access,
+                read_stages: next.stages,
+            }
+        };
+        Some(barrier)
+    }
+}
+
+fn or_top_of_pipe(stages: vk::PipelineStageFlags) -> vk::PipelineStageFlags {
+    if stages.is_empty() { vk::PipelineStageFlags::TOP_OF_PIPE } else { stages }
+}
+
+#[derive(Clone, Debug)]
+struct MemoryBlock {
+    requirements: vk::MemoryRequirements,
+    last_use: usize,
+    occupants: Vec<usize>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct RenderingAttachment {
+    resource: usize,
+    layout: vk::ImageLayout,
+    load_op: vk::AttachmentLoadOp,
+    store_op: vk::AttachmentStoreOp,
+    clear_value: vk::ClearValue,
+    resolve: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct Rendering {
+    mode: DynamicRendering,
+    colors: Vec<RenderingAttachment>,
+    depth: Option<RenderingAttachment>,
+    stencil: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+struct CompiledPass {
+    index: usize,
+    barriers: Vec<Barrier>,
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    // Set instead of a render pass when rendering dynamically.
+    rendering: Option<Rendering>,
+    extent: vk::Extent2D,
+    clear_values: Vec<vk::ClearValue>,
+}
+
+// The Vulkan objects and schedule produced by `RenderGraph::compile`. Owns the
+// transient resources, render passes and framebuffers until `destroy`.
+#[derive(Clone, Debug, Default)]
+pub struct CompiledGraph {
+    passes: Vec<CompiledPass>,
+    culled: Vec<usize>,
+    final_barriers: Vec<Barrier>,
+
+    // Indexed by resource, null where the resource is the other kind or unused.
+    images: Vec<vk::Image>,
+    views: Vec<vk::ImageView>,
+    buffers: Vec<vk::Buffer>,
+    memory_blocks: Vec<Option<usize>>,
+
+    owned_images: Vec<vk::Image>,
+    owned_views: Vec<vk::ImageView>,
+    owned_buffers: Vec<vk::Buffer>,
+    memory: Vec<vk::DeviceMemory>,
+    render_passes: Vec<vk::RenderPass>,
+    framebuffers: Vec<vk::Framebuffer>,
+}
+
+impl CompiledGraph {
+    pub fn render_pass(&self, pass: PassHandle) -> Option<vk::RenderPass> {
+        self.passes.iter().find(|p| p.index == pass.0).map(|p| p.render_pass)
+    }
+
+    pub fn is_culled(&self, pass: PassHandle) -> bool {
+        self.culled.contains(&pass.0)
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        self.framebuffers.iter().for_each(|f| device.destroy_framebuffer(*f, None));
+        self.render_passes.iter().for_each(|r| device.destroy_render_pass(*r, None));
+        self.owned_views.iter().for_each(|v| device.destroy_image_view(*v, None));
+        self.owned_images.iter().for_each(|i| device.destroy_image(*i, None));
+        self.owned_buffers.iter().for_each(|b| device.destroy_buffer(*b, None));
+        self.memory.iter().for_each(|m| device.free_memory(*m, None));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESC: ImageDesc = ImageDesc {
+        format: vk::Format::R8G8B8A8_UNORM,
+        extent: vk::Extent2D { width: 4, height: 4 },
+        samples: vk::SampleCountFlags::_1,
+    };
+    const FRAGMENT: vk::PipelineStageFlags = vk::PipelineStageFlags::FRAGMENT_SHADER;
+    const CLEAR: LoadOp = LoadOp::ClearColor([0.0; 4]);
+
+    fn swapchain(graph: &mut RenderGraph) -> ImageHandle {
+        graph.import_image("swapchain", vk::Image::null(), vk::ImageView::null(), DESC, ResourceState::NONE, Some(ResourceState::PRESENT))
+    }
+
+    fn names<'g>(graph: &'g RenderGraph, passes: &[usize]) -> Vec<&'g str> {
+        passes.iter().map(|p| graph.passes[*p].name.as_str()).collect()
+    }
+
+    #[test]
+    fn runs_readers_after_writers() {
+        let mut graph = RenderGraph::new();
+        let swapchain = swapchain(&mut graph);
+        let scene = graph.create_image("scene", DESC);
+        let depth = graph.create_image("depth", ImageDesc { format: vk::Format::D32_SFLOAT, ..DESC });
+
+        // Declared before the passes whose output it reads.
+        graph
+            .add_pass("post")
+            .read_image(scene, ImageAccess::Sampled(FRAGMENT))
+            .color_attachment(swapchain, LoadOp::DontCare)
+            .execute(|_, _, _| {});
+        graph
+            .add_pass("prepass")
+            .depth_attachment(depth, LoadOp::ClearDepth(1.0, 0), true)
+            .execute(|_, _, _| {});
+        graph
+            .add_pass("scene")
+            .depth_attachment(depth, LoadOp::Load, false)
+            .color_attachment(scene, CLEAR)
+            .execute(|_, _, _| {});
+
+        let (schedule, culled) = graph.schedule().unwrap();
+        assert_eq!(names(&graph, &schedule), ["prepass", "scene", "post"]);
+        assert!(culled.is_empty());
+    }
+
+    #[test]
+    fn keeps_declaration_order_between_writers() {
+        let mut graph = RenderGraph::new();
+        let swapchain = swapchain(&mut graph);
+        graph.add_pass("first").color_attachment(swapchain, CLEAR).execute(|_, _, _| {});
+        graph.add_pass("second").color_attachment(swapchain, LoadOp::Load).execute(|_, _, _| {});
+        graph.add_pass("third").color_attachment(swapchain, LoadOp::Load).execute(|_, _, _| {});
+
+        let (schedule, _) = graph.schedule().unwrap();
+        assert_eq!(names(&graph, &schedule), ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let mut graph = RenderGraph::new();
+        let a = graph.create_image("a", DESC);
+        let b = graph.create_image("b", DESC);
+        graph
+            .add_pass("ping")
+            .read_image(a, ImageAccess::Sampled(FRAGMENT))
+            .color_attachment(b, CLEAR)
+            .side_effects()
+            .execute(|_, _, _| {});
+        graph
+            .add_pass("pong")
+            .read_image(b, ImageAccess::Sampled(FRAGMENT))
+            .color_attachment(a, CLEAR)
+            .side_effects()
+            .execute(|_, _, _| {});
+
+        let error = graph.schedule().unwrap_err().to_string();
+        assert!(error.contains("cycle") && error.contains("`ping`") && error.contains("`pong`"), "{}", error);
+    }
+
+    #[test]
+    fn culls_passes_whose_results_are_unused() {
+        let mut graph = RenderGraph::new();
+        let swapchain = swapchain(&mut graph);
+        let history = graph.create_image("history", DESC);
+        let scene = graph.create_image("scene", DESC);
+
+        graph.add_pass("history").color_attachment(history, CLEAR).execute(|_, _, _| {});
+        graph.add_pass("overwritten").color_attachment(scene, CLEAR).execute(|_, _, _| {});
+        graph.add_pass("scene").color_attachment(scene, CLEAR).execute(|_, _, _| {});
+        // Kept for its side effects, and keeps what it reads alive.
+        graph.add_pass("readback").read_image(history, ImageAccess::TransferSrc).side_effects().execute(|_, _, _| {});
+        graph
+            .add_pass("blit")
+            .read_image(scene, ImageAccess::TransferSrc)
+            .write_image(swapchain, ImageAccess::TransferDst)
+            .execute(|_, _, _| {});
+
+        let (schedule, culled) = graph.schedule().unwrap();
+        assert_eq!(names(&graph, &schedule), ["history", "scene", "readback", "blit"]);
+        assert_eq!(names(&graph, &culled), ["overwritten"]);
+
+        let mut graph = RenderGraph::new();
+        let unused = graph.create_image("unused", DESC);
+        graph.add_pass("unused").color_attachment(unused, CLEAR).execute(|_, _, _| {});
+        assert_eq!(graph.schedule().unwrap(), (vec![], vec![0]));
+    }
+
+    #[test]
+    fn places_barriers_between_writes_and_reads() {
+        let mut graph = RenderGraph::new();
+        let swapchain = swapchain(&mut graph);
+        let scene = graph.create_image("scene", DESC);
+        graph.add_pass("scene").color_attachment(scene, CLEAR).execute(|_, _, _| {});
+        graph
+            .add_pass("post")
+            .read_image(scene, ImageAccess::Sampled(FRAGMENT))
+            .color_attachment(swapchain, LoadOp::DontCare)
+            .execute(|_, _, _| {});
+        graph.add_pass("overlay").read_image(scene, ImageAccess::Sampled(FRAGMENT)).side_effects().execute(|_, _, _| {});
+
+        let (schedule, _) = graph.schedule().unwrap();
+        let lifetimes = graph.lifetimes(&schedule);
+        let (barriers, final_barriers) = graph.barriers(&schedule, &lifetimes, &[None, None]).unwrap();
+
+        let read = barriers[1].iter().find(|b| b.resource == scene.0).unwrap();
+        assert_eq!(read.old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(read.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(read.src_stages, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+        assert!(read.src_access.contains(vk::AccessFlags::COLOR_ATTACHMENT_WRITE));
+        assert_eq!((read.dst_stages, read.dst_access), (FRAGMENT, vk::AccessFlags::SHADER_READ));
+
+        // A second read in the same layout needs no barrier.
+        assert!(barriers[2].is_empty());
+
+        assert_eq!(final_barriers.len(), 1);
+        assert_eq!(final_barriers[0].resource, swapchain.0);
+        assert_eq!(final_barriers[0].new_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+    }
+
+    #[test]
+    fn places_barriers_before_reads_in_new_stages() {
+        let mut graph = RenderGraph::new();
+        let scene = graph.create_image("scene", DESC);
+        graph.add_pass("scene").color_attachment(scene, CLEAR).execute(|_, _, _| {});
+        graph.add_pass("post").read_image(scene, ImageAccess::Sampled(FRAGMENT)).side_effects().execute(|_, _, _| {});
+        graph
+            .add_pass("histogram")
+            .read_image(scene, ImageAccess::Sampled(vk::PipelineStageFlags::COMPUTE_SHADER))
+            .side_effects()
+            .execute(|_, _, _| {});
+
+        let (schedule, _) = graph.schedule().unwrap();
+        let lifetimes = graph.lifetimes(&schedule);
+        let (barriers, _) = graph.barriers(&schedule, &lifetimes, &[None]).unwrap();
+        assert_eq!(barriers[1].len(), 1);
+
+        // The compute read waits on the fragment read that followed the layout change.
+        let [read] = barriers[2].as_slice() else { panic!("expected one barrier, got {:?}", barriers[2]) };
+        assert_eq!(read.old_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(read.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(read.src_stages, FRAGMENT);
+        assert_eq!(read.dst_stages, vk::PipelineStageFlags::COMPUTE_SHADER);
+        assert_eq!(read.dst_access, vk::AccessFlags::SHADER_READ);
+    }
+}
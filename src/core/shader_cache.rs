@@ -0,0 +1,299 @@
+use anyhow::{Context, Result};
+use shaderc::ShaderKind;
+use log::*;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::core;
+use crate::core::compiler::{CompiledShader, ShaderCompileOptions};
+
+const CACHE_DIR: &str = "target/shader-cache";
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CacheStats {
+    pub memory_hits: u32,
+    pub disk_hits: u32,
+    pub misses: u32,
+    // Entries dropped because an included file changed.
+    pub invalidations: u32,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} memory hits, {} disk hits, {} misses, {} invalidations",
+            self.memory_hits, self.disk_hits, self.misses, self.invalidations,
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    spirv: Vec<u32>,
+    // Resolved includes and the checksum of their contents at compile time.
+    includes: Vec<(PathBuf, u64)>,
+}
+
+impl CacheEntry {
+    fn is_current(&self) -> bool {
+        self.includes
+            .iter()
+            .all(|(path, hash)| std::fs::read(path).is_ok_and(|c| core::shaders::checksum(&c) == *hash))
+    }
+
+    fn compiled(&self) -> CompiledShader {
+        CompiledShader {
+            spirv: self.spirv.clone(),
+            includes: self.includes.iter().map(|(p, _)| p.clone()).collect(),
+        }
+    }
+}
+
+// Compiled SPIR-V keyed by a checksum of the source, stage and compile options.
+// Includes are only known after compiling, so entries record them and are
+// checked against the files on every lookup.
+#[derive(Debug, Default)]
+pub struct ShaderCache {
+    dir: Option<PathBuf>,
+    entries: HashMap<u64, CacheEntry>,
+    stats: CacheStats,
+}
+
+pub fn global() -> &'static Mutex<ShaderCache> {
+    static CACHE: OnceLock<Mutex<ShaderCache>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        let dir = std::env::var_os("SHADER_CACHE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(core::config::PROJECT_ROOT).join(CACHE_DIR));
+        Mutex::new(ShaderCache::new(dir))
+    })
+}
+
+impl ShaderCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            warn!("Shader cache directory {} unavailable, caching in memory only: {}", dir.display(), e);
+            return Self::in_memory();
+        }
+        Self { dir: Some(dir), ..Default::default() }
+    }
+
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        if let Some(dir) = &self.dir
+            && let Err(e) = std::fs::remove_dir_all(dir).and_then(|_| std::fs::create_dir_all(dir))
+        {
+            warn!("Failed to clear shader cache {}: {}", dir.display(), e);
+        }
+    }
+
+    pub fn compile(
+        &mut self,
+        source: &str,
+        path: &Path,
+        kind: ShaderKind,
+        options: &ShaderCompileOptions,
+    ) -> Result<CompiledShader> {
+        let key = cache_key(source, path, kind, options);
+
+        if let Some(entry) = self.entries.get(&key) {
+            if entry.is_current() {
+                self.stats.memory_hits += 1;
+                return Ok(entry.compiled());
+            }
+            self.stats.invalidations += 1;
+            self.entries.remove(&key);
+        } else if let Some(entry) = self.load(key) {
+            if entry.is_current() {
+                self.stats.disk_hits += 1;
+                let compiled = entry.compiled();
+                self.entries.insert(key, entry);
+                return Ok(compiled);
+            }
+            self.stats.invalidations += 1;
+        }
+
+        self.stats.misses += 1;
+        let compiled = core::compiler::compile_source(source, path, kind, options)?;
+
+        let mut includes = compiled.includes.clone();
+        includes.sort();
+        includes.dedup();
+        let includes = includes
+            .into_iter()
+            .map(|p| {
+                let hash = std::fs::read(&p).map(|c| core::shaders::checksum(&c)).unwrap_or_default();
+                (p, hash)
+            })
+            .collect();
+        let entry = CacheEntry { spirv: compiled.spirv.clone(), includes };
+
+        if let Err(e) = self.store(key, &entry) {
+            warn!("Failed to persist shader cache entry for {}: {:#}", path.display(), e);
+        }
+        self.entries.insert(key, entry);
+
+        Ok(compiled)
+    }
+
+    fn load(&self, key: u64) -> Option<CacheEntry> {
+        let dir = self.dir.as_ref()?;
+        let bytes = std::fs::read(dir.join(format!("{:016x}.spv", key))).ok()?;
+        let deps = std::fs::read_to_string(dir.join(format!("{:016x}.deps", key))).ok()?;
+
+        // A damaged or half-written entry reads as a miss and is recompiled.
+        let mut lines = deps.lines();
+        let spirv_hash = lines.next()?.strip_prefix("spirv ")?;
+        if u64::from_str_radix(spirv_hash, 16).ok()? != core::shaders::checksum(&bytes) {
+            warn!("Shader cache entry {:016x} is corrupt, recompiling.", key);
+            return None;
+        }
+        let includes = lines
+            .map(|line| {
+                let (hash, path) = line.split_once(' ')?;
+                Some((PathBuf::from(path), u64::from_str_radix(hash, 16).ok()?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let spirv = core::shaders::spirv_words(&bytes).ok()?;
+
+        Some(CacheEntry { spirv, includes })
+    }
+
+    fn store(&self, key: u64, entry: &CacheEntry) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+
+        let bytes = entry.spirv.iter().flat_map(|w| w.to_le_bytes()).collect::<Vec<_>>();
+        let mut deps = String::new();
+        writeln!(deps, "spirv {:016x}", core::shaders::checksum(&bytes))?;
+        for (path, hash) in &entry.includes {
+            writeln!(deps, "{:016x} {}", hash, path.display())?;
+        }
+
+        // Deps go last: an entry without its deps file is never loaded.
+        std::fs::write(dir.join(format!("{:016x}.spv", key)), bytes)
+            .context("Failed to write cached SPIR-V")?;
+        std::fs::write(dir.join(format!("{:016x}.deps", key)), deps)
+            .context("Failed to write cached dependencies")?;
+
+        Ok(())
+    }
+}
+
+fn cache_key(source: &str, path: &Path, kind: ShaderKind, options: &ShaderCompileOptions) -> u64 {
+    let mut key = String::new();
+    // The path matters for relative includes and for error messages.
+    let _ = writeln!(key, "{}", path.display());
+    let _ = writeln!(key, "{:?}", kind);
+    let _ = writeln!(key, "{:?} {:?} {}", options.optimization, options.target_env, options.debug_info);
+    for dir in &options.include_dirs {
+        let _ = writeln!(key, "-I{}", dir.display());
+    }
+    for (name, value) in &options.defines {
+        let _ = writeln!(key, "-D{}={:?}", name, value);
+    }
+    key.push_str(source);
+
+    core::shaders::checksum(key.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_util::TempDir;
+
+    const FRAGMENT: &str = "#version 450\n#include \"color.glsl\"\nlayout(location = 0) out vec4 outColor;\nvoid main() { outColor = COLOR; }\n";
+
+    fn options(dir: &Path) -> ShaderCompileOptions {
+        ShaderCompileOptions { include_dirs: vec![dir.to_path_buf()], ..Default::default() }
+    }
+
+    #[test]
+    fn key_depends_on_source_and_defines() {
+        let path = Path::new("shader.frag");
+        let options = ShaderCompileOptions::default();
+        let key = cache_key(FRAGMENT, path, ShaderKind::Fragment, &options);
+        assert_eq!(key, cache_key(FRAGMENT, path, ShaderKind::Fragment, &options));
+
+        let edited = FRAGMENT.replace("COLOR;", "COLOR * 0.5;");
+        assert_ne!(key, cache_key(&edited, path, ShaderKind::Fragment, &options));
+        let defined = options.clone().define("COLOR", Some("vec4(1.0)"));
+        assert_ne!(key, cache_key(FRAGMENT, path, ShaderKind::Fragment, &defined));
+        let redefined = options.clone().define("COLOR", Some("vec4(0.0)"));
+        assert_ne!(
+            cache_key(FRAGMENT, path, ShaderKind::Fragment, &defined),
+            cache_key(FRAGMENT, path, ShaderKind::Fragment, &redefined),
+        );
+    }
+
+    #[test]
+    fn include_changes_invalidate_entries() {
+        let dir = TempDir::new("shader-cache-includes", &[("color.glsl", "#define COLOR vec4(1.0)\n")]);
+        let path = dir.join("shader.frag");
+        let mut cache = ShaderCache::in_memory();
+
+        let first = cache.compile(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir)).unwrap();
+        cache.compile(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir)).unwrap();
+        assert_eq!((cache.stats().misses, cache.stats().memory_hits), (1, 1));
+
+        std::fs::write(dir.join("color.glsl"), "#define COLOR vec4(0.5)\n").unwrap();
+        let second = cache.compile(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir)).unwrap();
+        assert_eq!((cache.stats().misses, cache.stats().invalidations), (2, 1));
+        assert_ne!(first.spirv, second.spirv);
+    }
+
+    #[test]
+    fn reloads_entries_from_disk() {
+        let dir = TempDir::new("shader-cache-disk", &[("color.glsl", "#define COLOR vec4(1.0)\n")]);
+        let path = dir.join("shader.frag");
+        let compiled = ShaderCache::new(dir.join("cache"))
+            .compile(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir))
+            .unwrap();
+
+        let mut cache = ShaderCache::new(dir.join("cache"));
+        let reloaded = cache.compile(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir)).unwrap();
+        assert_eq!(cache.stats().disk_hits, 1);
+        assert_eq!(reloaded.spirv, compiled.spirv);
+        assert_eq!(reloaded.includes, compiled.includes);
+    }
+
+    #[test]
+    fn recovers_from_corrupt_entries() {
+        let dir = TempDir::new("shader-cache-corrupt", &[("color.glsl", "#define COLOR vec4(1.0)\n")]);
+        let path = dir.join("shader.frag");
+        let compiled = ShaderCache::new(dir.join("cache"))
+            .compile(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir))
+            .unwrap();
+        let key = cache_key(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir));
+        let spv = dir.join("cache").join(format!("{:016x}.spv", key));
+        let deps = dir.join("cache").join(format!("{:016x}.deps", key));
+
+        for (file, contents) in [(&spv, &b"\x03\x02\x23\x07garbage!"[..]), (&spv, b"abc"), (&deps, b"not a deps file")] {
+            std::fs::write(file, contents).unwrap();
+            let mut cache = ShaderCache::new(dir.join("cache"));
+            let recompiled = cache.compile(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir)).unwrap();
+            assert_eq!((cache.stats().disk_hits, cache.stats().misses), (0, 1));
+            assert_eq!(recompiled.spirv, compiled.spirv);
+        }
+
+        // The recompiled entry replaced the damaged one.
+        let mut cache = ShaderCache::new(dir.join("cache"));
+        cache.compile(FRAGMENT, &path, ShaderKind::Fragment, &options(&dir)).unwrap();
+        assert_eq!(cache.stats().disk_hits, 1);
+    }
+}
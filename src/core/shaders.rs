@@ -0,0 +1,126 @@
+use anyhow::{Result, anyhow};
+use log::*;
+
+use std::path::{Path, PathBuf};
+
+use crate::core;
+
+pub const SHADER_SOURCE_DIR: &str = "src/shader";
+
+pub struct EmbeddedShader {
+    pub name: &'static str,
+    pub spirv: &'static [u8],
+    // `None` when precompiled SPIR-V had no entry in `shaders/manifest.txt`.
+    pub manifest: Option<ManifestEntry>,
+}
+
+pub struct ManifestEntry {
+    pub checksum: u64,
+    // Checksum of the source the SPIR-V was compiled from.
+    pub source_checksum: u64,
+}
+
+// Generated by build.rs from every shader in `src/shader`.
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
+
+// FNV-1a, the same checksum build.rs records in the manifest.
+pub fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub fn source_path(name: &str) -> String {
+    format!("{}/{}", SHADER_SOURCE_DIR, name)
+}
+
+pub fn absolute_source_path(name: &str) -> PathBuf {
+    Path::new(core::config::PROJECT_ROOT).join(source_path(name))
+}
+
+pub fn spirv_words(bytes: &[u8]) -> Result<Vec<u32>> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(anyhow!("SPIR-V size {} is not a multiple of 4", bytes.len()));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect())
+}
+
+// Loads the SPIR-V for a shader in `src/shader`, preferring the copy compiled at
+// build time. SPIR-V that is missing, doesn't match its manifest checksum or was
+// built from an older source is recompiled with `runtime-shaders` and rejected
+// without it.
+pub fn load_shader(name: &str) -> Result<Vec<u32>> {
+    let embedded = EMBEDDED_SHADERS.iter().find(|s| s.name == name);
+    let source_checksum = std::fs::read(absolute_source_path(name)).ok().map(|s| checksum(&s));
+
+    match check_embedded(name, embedded, source_checksum) {
+        Ok(spirv) => {
+            debug!("Loaded precompiled shader `{}`.", name);
+            spirv_words(spirv)
+        }
+        #[cfg(feature = "runtime-shaders")]
+        Err(e) => {
+            info!("{}, compiling at runtime.", e);
+            core::compiler::compile_shader(&source_path(name), core::compiler::shader_kind(name)?)
+        }
+        #[cfg(not(feature = "runtime-shaders"))]
+        Err(e) => Err(e.context("Rebuild with glslc or enable the `runtime-shaders` feature")),
+    }
+}
+
+// The embedded SPIR-V for `name` if it matches its manifest entry and, when the
+// source is available, was compiled from the current source.
+fn check_embedded(name: &str, embedded: Option<&EmbeddedShader>, source_checksum: Option<u64>) -> Result<&'static [u8]> {
+    let embedded = embedded.ok_or_else(|| anyhow!("No precompiled SPIR-V for shader `{}`", name))?;
+    let manifest = embedded
+        .manifest
+        .as_ref()
+        .ok_or_else(|| anyhow!("Precompiled shader `{}` has no manifest entry", name))?;
+    if checksum(embedded.spirv) != manifest.checksum {
+        return Err(anyhow!("Checksum mismatch for precompiled shader `{}`", name));
+    }
+    if source_checksum.is_some_and(|c| c != manifest.source_checksum) {
+        return Err(anyhow!("Shader `{}` changed since it was precompiled", name));
+    }
+    Ok(embedded.spirv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SPIRV: &[u8] = &[3, 2, 35, 7];
+
+    fn shader(manifest: Option<ManifestEntry>) -> EmbeddedShader {
+        EmbeddedShader { name: "test.vert", spirv: SPIRV, manifest }
+    }
+
+    #[test]
+    fn accepts_matching_manifest_entry() {
+        let embedded = shader(Some(ManifestEntry { checksum: checksum(SPIRV), source_checksum: 1 }));
+        assert!(check_embedded("test.vert", Some(&embedded), Some(1)).is_ok());
+        // Shipped builds have no sources to compare against.
+        assert!(check_embedded("test.vert", Some(&embedded), None).is_ok());
+    }
+
+    #[test]
+    fn rejects_missing_or_mismatched_spirv() {
+        assert!(check_embedded("test.vert", None, None).is_err());
+        assert!(check_embedded("test.vert", Some(&shader(None)), None).is_err());
+        let corrupt = shader(Some(ManifestEntry { checksum: checksum(SPIRV) ^ 1, source_checksum: 1 }));
+        assert!(check_embedded("test.vert", Some(&corrupt), None).is_err());
+        let stale = shader(Some(ManifestEntry { checksum: checksum(SPIRV), source_checksum: 1 }));
+        assert!(check_embedded("test.vert", Some(&stale), Some(2)).is_err());
+    }
+
+    #[test]
+    fn every_embedded_shader_matches_the_manifest() {
+        for shader in EMBEDDED_SHADERS {
+            let source_checksum = std::fs::read(absolute_source_path(shader.name)).ok().map(|s| checksum(&s));
+            assert!(check_embedded(shader.name, Some(shader), source_checksum).is_ok(), "{}", shader.name);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{Result, Context};
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+use std::path::Path;
+
+use crate::AppData;
+use crate::core;
+use crate::core::config::{RenderPath, SkyboxSource};
+use crate::core::descriptor::DescriptorBuilder;
+use crate::core::pipeline::{BlendMode, DepthState, GraphicsPipeline, PipelineState};
+use crate::core::texture::{ColorSpace, Texture};
+
+pub const SKYBOX_VERT: &str = "skybox.vert";
+pub const SKYBOX_FRAG: &str = "skybox.frag";
+
+#[derive(Clone, Debug, Default)]
+pub struct SkyboxData {
+    pub vert_shader: Vec<u32>,
+    pub frag_shader: Vec<u32>,
+    // The cube map and the set binding it at set 1, kept for the app's lifetime.
+    pub texture: Texture,
+    pub set: vk::DescriptorSet,
+    pub pipeline: GraphicsPipeline,
+}
+
+// What the scene pass draws after its geometry.
+#[derive(Copy, Clone, Debug)]
+pub struct SkyboxBinding {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    set: vk::DescriptorSet,
+}
+
+impl SkyboxBinding {
+    // One fullscreen triangle; the depth test keeps it behind the geometry.
+    pub unsafe fn draw(&self, device: &Device, command_buffer: vk::CommandBuffer, uniform_set: vk::DescriptorSet) {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.layout,
+            0,
+            &[uniform_set, self.set],
+            &[],
+        );
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+}
+
+pub fn is_enabled(data: &AppData) -> bool {
+    data.render_path == RenderPath::Forward && !data.skybox.texture.image.is_null()
+}
+
+// On the far plane, so it only passes where the depth is still cleared.
+pub fn state(data: &AppData) -> PipelineState {
+    PipelineState {
+        depth: DepthState::READ_ONLY,
+        blend: BlendMode::Opaque,
+        front_face: vk::FrontFace::CLOCKWISE,
+        // Set 1 is the skybox's own cube map.
+        bindless: false,
+        textures: false,
+        push_constants: None,
+        ..PipelineState::forward(data)
+    }
+}
+
+pub fn load_shaders(data: &mut AppData) -> Result<()> {
+    data.skybox.vert_shader = core::shaders::load_shader(SKYBOX_VERT).context("Failed to load skybox vertex shader")?;
+    data.skybox.frag_shader = core::shaders::load_shader(SKYBOX_FRAG).context("Failed to load skybox fragment shader")?;
+
+    Ok(())
+}
+
+pub unsafe fn create_skybox(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let Some(source) = core::config::SKYBOX else {
+        return Ok(());
+    };
+    if data.render_path == RenderPath::Deferred {
+        warn!("Skyboxes are not supported on the deferred path.");
+        return Ok(());
+    }
+
+    let root = Path::new(core::config::PROJECT_ROOT);
+    data.skybox.texture = match source {
+        SkyboxSource::Faces(paths) => {
+            core::cubemap::from_faces(instance, device, data, &paths.map(|p| root.join(p)), ColorSpace::Srgb)?
+        }
+        SkyboxSource::Equirect(path) => core::cubemap::from_equirect(
+            instance,
+            device,
+            data,
+            root.join(path),
+            ColorSpace::Srgb,
+            core::config::SKYBOX_FACE_SIZE,
+        )?,
+    };
+
+    let info = data.skybox.texture.descriptor_info();
+    let descriptors = &mut data.descriptors;
+    let (set, _) = DescriptorBuilder::new(&mut descriptors.layout_cache, &mut descriptors.allocator)
+        .bind_image(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT, info)
+        .build(device)?;
+    data.skybox.set = set;
+
+    Ok(())
+}
+
+// The set is freed with the descriptor allocator.
+pub unsafe fn destroy_skybox(device: &Device, data: &mut AppData) {
+    data.skybox.texture.destroy(device);
+    data.skybox.texture = Texture::default();
+}
+
+pub unsafe fn create_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    if !is_enabled(data) {
+        return Ok(());
+    }
+
+    let (vert_shader, frag_shader) = (&data.skybox.vert_shader, &data.skybox.frag_shader);
+    data.skybox.pipeline = GraphicsPipeline::build::<()>(device, data, vert_shader, frag_shader, state(data))
+        .context("Failed to build skybox pipeline")?;
+
+    Ok(())
+}
+
+pub unsafe fn destroy_pipeline(device: &Device, data: &mut AppData) {
+    data.skybox.pipeline.destroy(device);
+    data.skybox.pipeline = GraphicsPipeline::default();
+}
+
+pub fn binding(data: &AppData) -> Option<SkyboxBinding> {
+    if !is_enabled(data) || data.skybox.pipeline.pipeline.is_null() {
+        return None;
+    }
+
+    Some(SkyboxBinding {
+        pipeline: data.skybox.pipeline.pipeline,
+        layout: data.skybox.pipeline.layout,
+        set: data.skybox.set,
+    })
+}
@@ -0,0 +1,260 @@
+use anyhow::{Result, anyhow};
+use vulkanalia::prelude::v1_0::*;
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::AppData;
+use crate::core::pipeline::{GraphicsPipeline, PipelineState};
+use crate::core::vertex_input::VertexInput;
+use crate::core::reflect::{ShaderReflection, SpecializationValue};
+
+impl From<bool> for SpecializationValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i32> for SpecializationValue {
+    fn from(value: i32) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<u32> for SpecializationValue {
+    fn from(value: u32) -> Self {
+        Self::UInt(value)
+    }
+}
+
+impl From<f32> for SpecializationValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl SpecializationValue {
+    // The bytes the shader sees; booleans are `VkBool32`.
+    fn bytes(self) -> Vec<u8> {
+        match self {
+            Self::Bool(value) => (value as u32).to_ne_bytes().to_vec(),
+            Self::Int(value) => value.to_ne_bytes().to_vec(),
+            Self::UInt(value) => value.to_ne_bytes().to_vec(),
+            Self::Float(value) => value.to_ne_bytes().to_vec(),
+            Self::Raw(value) => value.to_ne_bytes().to_vec(),
+        }
+    }
+
+    fn same_type(self, other: Self) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+}
+
+// Specialization constant values for one shader stage, by `constant_id`.
+// Constants left out keep the defaults compiled into the shader.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Specialization {
+    values: BTreeMap<u32, SpecializationValue>,
+}
+
+impl Specialization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, id: u32, value: impl Into<SpecializationValue>) -> Self {
+        self.values.insert(id, value.into());
+        self
+    }
+
+    pub fn get(&self, id: u32) -> Option<SpecializationValue> {
+        self.values.get(&id).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // Every value must name a constant of the same type in the shader.
+    pub fn validate(&self, reflection: &ShaderReflection) -> Result<()> {
+        for (id, value) in &self.values {
+            let constant = reflection
+                .specialization_constants
+                .iter()
+                .find(|c| c.id == *id)
+                .ok_or_else(|| anyhow!("{:?} shader has no specialization constant {}", reflection.stage, id))?;
+            if !value.same_type(constant.default) {
+                return Err(anyhow!(
+                    "Specialization constant {} (`{}`) is {:?}, got {:?}",
+                    id,
+                    constant.name,
+                    constant.default,
+                    value,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // The map entries and data a `vk::SpecializationInfo` points at.
+    pub fn entries(&self) -> (Vec<vk::SpecializationMapEntry>, Vec<u8>) {
+        let mut entries = Vec::new();
+        let mut data = Vec::new();
+        for (id, value) in &self.values {
+            let bytes = value.bytes();
+            entries.push(
+                vk::SpecializationMapEntry::builder()
+                    .constant_id(*id)
+                    .offset(data.len() as u32)
+                    .size(bytes.len())
+                    .build(),
+            );
+            data.extend_from_slice(&bytes);
+        }
+        (entries, data)
+    }
+
+    // Identifies the values by their bytes, so floats hash and compare.
+    pub fn key(&self) -> SpecializationKey {
+        self.values.iter().map(|(id, value)| (*id, value.bytes())).collect()
+    }
+}
+
+pub type SpecializationKey = Vec<(u32, Vec<u8>)>;
+
+// The values for each stage of a graphics pipeline.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StageSpecializations {
+    pub vertex: Specialization,
+    pub fragment: Specialization,
+}
+
+impl StageSpecializations {
+    pub fn key(&self) -> (SpecializationKey, SpecializationKey) {
+        (self.vertex.key(), self.fragment.key())
+    }
+}
+
+// Pipelines of one pair of shaders, built once per distinct set of
+// specialization values. The state should only vary with the values.
+#[derive(Clone, Debug, Default)]
+pub struct SpecializedPipelines {
+    pipelines: HashMap<(SpecializationKey, SpecializationKey), GraphicsPipeline>,
+}
+
+impl SpecializedPipelines {
+    pub fn get(&self, specializations: &StageSpecializations) -> Option<&GraphicsPipeline> {
+        self.pipelines.get(&specializations.key())
+    }
+
+    pub unsafe fn get_or_build<V: VertexInput>(
+        &mut self,
+        device: &Device,
+        data: &AppData,
+        vert_binary: &[u32],
+        frag_binary: &[u32],
+        state: PipelineState,
+        specializations: &StageSpecializations,
+    ) -> Result<&GraphicsPipeline> {
+        let key = specializations.key();
+        if !self.pipelines.contains_key(&key) {
+            let pipeline =
+                GraphicsPipeline::build_specialized::<V>(device, data, vert_binary, frag_binary, state, specializations)?;
+            self.pipelines.insert(key.clone(), pipeline);
+        }
+        Ok(&self.pipelines[&key])
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        self.pipelines.drain().for_each(|(_, p)| p.destroy(device));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::reflect::SpecializationConstant;
+
+    fn reflection(constants: &[(u32, SpecializationValue)]) -> ShaderReflection {
+        ShaderReflection {
+            stage: vk::ShaderStageFlags::FRAGMENT,
+            entry_point: "main".into(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            descriptor_bindings: Vec::new(),
+            push_constants: Vec::new(),
+            specialization_constants: constants
+                .iter()
+                .map(|(id, default)| SpecializationConstant {
+                    name: format!("constant_{}", id),
+                    id: *id,
+                    default: *default,
+                    size: 4,
+                })
+                .collect(),
+            workgroup_size: None,
+            workgroup_size_ids: [None; 3],
+        }
+    }
+
+    #[test]
+    fn packs_entries_in_id_order() {
+        let specialization = Specialization::new().set(7, 2.5f32).set(0, true).set(3, -4i32);
+        let (entries, data) = specialization.entries();
+
+        let layout = entries.iter().map(|e| (e.constant_id, e.offset, e.size)).collect::<Vec<_>>();
+        assert_eq!(layout, [(0, 0, 4), (3, 4, 4), (7, 8, 4)]);
+        assert_eq!(data.len(), 12);
+        assert_eq!(data[0..4], 1u32.to_ne_bytes());
+        assert_eq!(data[4..8], (-4i32).to_ne_bytes());
+        assert_eq!(data[8..12], 2.5f32.to_ne_bytes());
+    }
+
+    #[test]
+    fn setting_an_id_twice_keeps_the_last_value() {
+        let specialization = Specialization::new().set(1, 1u32).set(1, 2u32);
+        assert_eq!(specialization.get(1), Some(SpecializationValue::UInt(2)));
+
+        let (entries, data) = specialization.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(data, 2u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn validates_ids_and_types() {
+        let reflection = reflection(&[(0, SpecializationValue::Bool(false)), (1, SpecializationValue::Float(1.0))]);
+
+        Specialization::new().validate(&reflection).unwrap();
+        Specialization::new().set(0, true).set(1, 0.5f32).validate(&reflection).unwrap();
+
+        let error = Specialization::new().set(2, 1u32).validate(&reflection).unwrap_err();
+        assert!(error.to_string().contains("no specialization constant 2"), "{}", error);
+        let error = Specialization::new().set(1, 1u32).validate(&reflection).unwrap_err();
+        assert!(error.to_string().contains("`constant_1`"), "{}", error);
+        assert!(Specialization::new().set(0, 1i32).validate(&reflection).is_err());
+    }
+
+    #[test]
+    fn keys_compare_by_value() {
+        let a = Specialization::new().set(0, 1.0f32).set(1, true);
+        let b = Specialization::new().set(1, true).set(0, 1.0f32);
+        assert_eq!(a.key(), b.key());
+
+        assert_ne!(a.key(), Specialization::new().set(0, 2.0f32).set(1, true).key());
+        assert_ne!(a.key(), Specialization::new().set(0, 1.0f32).key());
+        // The same bits under another id are a different specialization.
+        assert_ne!(a.key(), Specialization::new().set(2, 1.0f32).set(1, true).key());
+
+        let stages = StageSpecializations { vertex: a.clone(), fragment: Specialization::new() };
+        let swapped = StageSpecializations { vertex: Specialization::new(), fragment: a };
+        assert_ne!(stages.key(), swapped.key());
+    }
+}
@@ -7,6 +7,7 @@ use vulkanalia::vk::KhrSurfaceExtensionInstanceCommands;
 use crate::AppData;
 use crate::QueueFamilyIndices;
 use crate::core;
+use crate::core::config::RenderPath;
 
 
 #[derive(Clone, Debug)]
@@ -66,6 +67,10 @@ pub unsafe fn create_swapchain(
         vk::SharingMode::EXCLUSIVE
     };
 
+    // Transfers let post-processing blit its result into the swapchain.
+    let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | (support.capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_DST);
+
     let info = vk::SwapchainCreateInfoKHR::builder()
         .surface(data.surface)
         .min_image_count(image_count)
@@ -73,7 +78,7 @@ pub unsafe fn create_swapchain(
         .image_color_space(surface_format.color_space)
         .image_extent(extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_usage(usage)
         .image_sharing_mode(image_sharing_mode)
         .queue_family_indices(&queue_family_indices)
         .pre_transform(support.capabilities.current_transform)
@@ -84,6 +89,7 @@ pub unsafe fn create_swapchain(
     data.swapchain = device.create_swapchain_khr(&info, None)?;
     data.swapchain_images = device.get_swapchain_images_khr(data.swapchain)?;
     data.swapchain_format = surface_format.format;
+    data.swapchain_usage = usage;
     data.swapchain_extent = extent;
 
     Ok(())
@@ -125,34 +131,123 @@ pub unsafe fn create_swapchain_image_views(
 
 pub unsafe fn recreate_swapchain(instance: &Instance, device: &Device, window: &Window, data: &mut AppData) -> Result<()> {
     device.device_wait_idle()?;
+    // With dynamic rendering the forward pipeline only depends on the swapchain
+    // format, so a resize keeps it and rebuilds what is sized to the swapchain.
+    let keep_pipeline = data.dynamic_rendering.is_some() && data.render_path == RenderPath::Forward;
+    let old_format = data.swapchain_format;
     let old_swapchain = data.swapchain;
-    cleanup_swapchain_resources(device, data);
-    core::swapchain::create_swapchain(window, &instance, &device, data, old_swapchain)?;
+    if keep_pipeline {
+        destroy_sized_targets(device, data);
+    } else {
+        destroy_render_targets(device, data);
+    }
+    destroy_swapchain_image_views(device, data);
+    core::swapchain::create_swapchain(window, instance, device, data, old_swapchain)?;
     if old_swapchain != vk::SwapchainKHR::null() {
         device.destroy_swapchain_khr(old_swapchain, None);
     }
-    core::swapchain::create_swapchain_image_views(&device, data)?;
+    core::swapchain::create_swapchain_image_views(device, data)?;
 
-    core::pipeline::create_render_pass(&instance, &device, data)?;
-    core::pipeline::create_pipeline(&device, data)?;
-    core::pipeline::create_framebuffers(&device, data)?;
-
-    core::commands::create_command_buffers(&device, data)?;
+    if keep_pipeline && data.swapchain_format == old_format {
+        create_sized_targets(instance, device, data)?;
+    } else {
+        if keep_pipeline {
+            destroy_pipeline(device, data);
+        }
+        create_render_targets(instance, device, data)?;
+    }
     data.images_in_flight.resize(data.swapchain_images.len(), vk::Fence::null());
     Ok(())
 }
 
+// Everything that depends on the swapchain images or on the render settings
+// (sample count, attachment formats) but not on the swapchain itself.
+pub unsafe fn create_render_targets(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    match data.render_path {
+        RenderPath::Forward => {
+            if data.dynamic_rendering.is_some() {
+                data.depth_format = core::depth::get_depth_format(instance, data)?;
+            } else {
+                core::pipeline::create_render_pass(instance, device, data)?;
+            }
+            core::pipeline::create_pipeline(device, data)?;
+            core::debug_view::create_pipeline(device, data)?;
+            core::post::create_pipelines(device, data)?;
+            core::skybox::create_pipeline(device, data)?;
+            create_sized_targets(instance, device, data)?;
+        }
+        RenderPath::Deferred => {
+            core::deferred::create_render_pass(instance, device, data)?;
+            core::deferred::create_pipelines(device, data)?;
+            core::depth::create_depth_objects(instance, device, data)?;
+            create_uniforms(instance, device, data)?;
+            core::deferred::create_light_buffers(instance, device, data)?;
+            core::deferred::create_gbuffer(instance, device, data)?;
+            core::commands::create_command_buffers(instance, device, data)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub unsafe fn destroy_render_targets(device: &Device, data: &mut AppData) {
+    // The deferred views of the depth image go before it.
+    core::deferred::destroy_render_targets(device, data);
+    destroy_sized_targets(device, data);
+    destroy_pipeline(device, data);
+    device.destroy_render_pass(data.render_pass, None);
+    data.render_pass = vk::RenderPass::null();
+}
+
+// The forward path's images sized to the swapchain, and the command buffers
+// recorded against them.
+unsafe fn create_sized_targets(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    core::msaa::create_color_objects(instance, device, data)?;
+    core::depth::create_depth_objects(instance, device, data)?;
+    core::post::create_descriptor_sets(device, data)?;
+    create_uniforms(instance, device, data)?;
+    core::commands::create_command_buffers(instance, device, data)
+}
+
+unsafe fn destroy_sized_targets(device: &Device, data: &mut AppData) {
+    core::commands::destroy_command_buffers(device, data);
+    destroy_uniforms(device, data);
+    core::post::destroy_descriptor_sets(device, data);
+    core::depth::destroy_depth_objects(device, data);
+    core::msaa::destroy_color_objects(device, data);
+}
+
+// A uniform buffer and descriptor set per swapchain image.
+unsafe fn create_uniforms(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    core::uniform::create_uniform_buffers(instance, device, data)?;
+    core::descriptor::create_descriptor_sets(device, data)
+}
+
+unsafe fn destroy_uniforms(device: &Device, data: &mut AppData) {
+    core::descriptor::destroy_descriptor_sets(device, data);
+    core::uniform::destroy_uniform_buffers(device, data);
+}
+
+unsafe fn destroy_pipeline(device: &Device, data: &mut AppData) {
+    device.destroy_pipeline(data.pipeline, None);
+    device.destroy_pipeline_layout(data.pipeline_layout, None);
+    data.descriptor_set_layouts.clear();
+    core::debug_view::destroy_pipeline(device, data);
+    core::post::destroy_pipelines(device, data);
+    core::skybox::destroy_pipeline(device, data);
+}
+
 pub unsafe fn destroy_swapchain(device: &Device, data: &mut AppData){
     cleanup_swapchain_resources(device, data);
     device.destroy_swapchain_khr(data.swapchain, None);
 }
 
 unsafe fn cleanup_swapchain_resources(device: &Device, data: &mut AppData) {
-    device.free_command_buffers(data.command_pool, &data.command_buffers);
-    data.framebuffers.iter().for_each(|f| device.destroy_framebuffer(*f, None));
-    device.destroy_pipeline(data.pipeline, None);
-    device.destroy_pipeline_layout(data.pipeline_layout, None);
-    device.destroy_render_pass(data.render_pass, None);
+    destroy_render_targets(device, data);
+    destroy_swapchain_image_views(device, data);
+}
+
+unsafe fn destroy_swapchain_image_views(device: &Device, data: &mut AppData) {
     data.swapchain_image_views.iter().for_each(|v| device.destroy_image_view(*v, None));
 }
 
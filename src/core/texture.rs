@@ -0,0 +1,799 @@
+use anyhow::{Context, Result, anyhow};
+use log::*;
+use vulkanalia::prelude::v1_0::*;
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use crate::AppData;
+use crate::core;
+use crate::core::descriptor::{DescriptorBuilder, Descriptors};
+use crate::core::mipmap::{ComputeMipmaps, MipGeneration};
+
+// Set 1 of the scene shaders without bindless textures, the draw's texture.
+pub const TEXTURE_SET: u32 = 1;
+
+pub const TEXTURE_PATH: &str = "resources/texture.png";
+
+pub fn texture_bindings() -> [vk::DescriptorSetLayoutBinding; 1] {
+    let sampler_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .build();
+
+    [sampler_binding]
+}
+
+// How the texels are encoded. Color textures are sRGB and are decoded to
+// linear by the sampler; data such as normal maps is already linear.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+// Tightly packed RGBA texels, 8 or 16 bits per channel.
+#[derive(Clone, Debug)]
+pub struct Pixels {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub data: Vec<u8>,
+}
+
+// Decodes any PNG to RGBA. Palettes, low bit depths and `tRNS` transparency
+// are expanded to 8 bits. 16-bit images stay 16-bit when `keep_16_bit` is set;
+// there is no 16-bit sRGB format, so their color is linearized here instead.
+pub fn decode_png(path: &Path, color_space: ColorSpace, keep_16_bit: bool) -> Result<Pixels> {
+    decode_png_from(BufReader::new(File::open(path)?), color_space, keep_16_bit)
+}
+
+// `decode_png` for a PNG already in memory, such as one embedded in a glTF file.
+pub fn decode_png_bytes(bytes: &[u8], color_space: ColorSpace, keep_16_bit: bool) -> Result<Pixels> {
+    decode_png_from(bytes, color_space, keep_16_bit)
+}
+
+fn decode_png_from(reader: impl Read, color_space: ColorSpace, keep_16_bit: bool) -> Result<Pixels> {
+    let mut decoder = png::Decoder::new(reader);
+    let transformations = if keep_16_bit {
+        png::Transformations::EXPAND
+    } else {
+        png::Transformations::EXPAND | png::Transformations::STRIP_16
+    };
+    decoder.set_transformations(transformations);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    buffer.truncate(info.buffer_size());
+
+    let (width, height) = (info.width, info.height);
+    let (color_type, bit_depth) = reader.output_color_type();
+    let pixels = match bit_depth {
+        png::BitDepth::Eight => {
+            let format = match color_space {
+                ColorSpace::Srgb => vk::Format::R8G8B8A8_SRGB,
+                ColorSpace::Linear => vk::Format::R8G8B8A8_UNORM,
+            };
+            let data = to_rgba(&buffer, color_type, u8::MAX)?;
+            Pixels { width, height, format, data }
+        }
+        png::BitDepth::Sixteen => {
+            let samples = buffer.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect::<Vec<_>>();
+            let mut texels = to_rgba(&samples, color_type, u16::MAX)?;
+            if color_space == ColorSpace::Srgb {
+                texels.chunks_exact_mut(4).for_each(|t| t[..3].iter_mut().for_each(|c| *c = srgb_to_linear(*c)));
+            }
+            let data = texels.iter().flat_map(|t| t.to_ne_bytes()).collect();
+            Pixels { width, height, format: vk::Format::R16G16B16A16_UNORM, data }
+        }
+        depth => return Err(anyhow!("Unexpected {:?} bit depth after expansion", depth)),
+    };
+
+    Ok(pixels)
+}
+
+// Texels with their mip chain baked in, as loaded from a KTX2 or DDS file. Each
+// level holds all of its array layers back to back; cube maps are six layers,
+// +X, -X, +Y, -Y, +Z, -Z.
+#[derive(Clone, Debug)]
+pub struct Prebaked {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub layers: u32,
+    pub cube: bool,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl Prebaked {
+    // Decodes every level and layer to RGBA8 for devices that can't sample the
+    // block-compressed format.
+    pub fn decompress(&self) -> Result<Self> {
+        let format = core::block_compression::decompressed_format(self.format)
+            .ok_or_else(|| anyhow!("No CPU decoder for {:?}", self.format))?;
+        let info = core::block_compression::block_info(self.format)
+            .ok_or_else(|| anyhow!("Unknown block size for {:?}", self.format))?;
+
+        let mut levels = Vec::with_capacity(self.levels.len());
+        for (level, texels) in self.levels.iter().enumerate() {
+            let extent = core::mipmap::level_extent(self.extent, level as u32);
+            let size = info.image_size(extent);
+            let mut decoded = Vec::new();
+            for layer in texels.chunks_exact(size).take(self.layers as usize) {
+                decoded.extend(core::block_compression::decompress(self.format, extent, layer)?);
+            }
+            levels.push(decoded);
+        }
+
+        Ok(Self { format, levels, ..self.clone() })
+    }
+}
+
+fn to_rgba<T: Copy>(samples: &[T], color_type: png::ColorType, opaque: T) -> Result<Vec<T>> {
+    let texels = match color_type {
+        png::ColorType::Grayscale => samples.iter().flat_map(|g| [*g, *g, *g, opaque]).collect(),
+        png::ColorType::GrayscaleAlpha => samples.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Rgb => samples.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], opaque]).collect(),
+        png::ColorType::Rgba => samples.to_vec(),
+        png::ColorType::Indexed => return Err(anyhow!("Palette was not expanded")),
+    };
+    Ok(texels)
+}
+
+fn srgb_to_linear(value: u16) -> u16 {
+    let c = value as f32 / u16::MAX as f32;
+    let linear = if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+    (linear * u16::MAX as f32).round() as u16
+}
+
+unsafe fn supports_sampling(instance: &Instance, data: &AppData, format: vk::Format) -> bool {
+    let properties = instance.get_physical_device_format_properties(data.physical_device, format);
+    properties.optimal_tiling_features.contains(
+        vk::FormatFeatureFlags::SAMPLED_IMAGE
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+            | vk::FormatFeatureFlags::TRANSFER_DST,
+    )
+}
+
+// A sampled image with its mip chain, view and sampler. PNGs are 2D; KTX2 and
+// DDS files can also be 2D arrays or cube maps.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Texture {
+    pub image: vk::Image,
+    pub image_memory: vk::DeviceMemory,
+    pub image_view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub mip_levels: u32,
+    pub layers: u32,
+    pub view_type: vk::ImageViewType,
+}
+
+impl Texture {
+    // Loads a PNG, KTX2 or DDS file by its extension. `color_space` is ignored
+    // by formats that record it themselves.
+    pub unsafe fn from_file(
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+        path: impl AsRef<Path>,
+        color_space: ColorSpace,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("png") => Self::from_png(instance, device, data, path, color_space),
+            Some("ktx2") => Self::from_ktx2(instance, device, data, path),
+            Some("dds") => Self::from_dds(instance, device, data, path, color_space),
+            _ => Err(anyhow!("Unknown texture file type {}", path.display())),
+        }
+    }
+
+    pub unsafe fn from_png(
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+        path: impl AsRef<Path>,
+        color_space: ColorSpace,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let keep_16_bit = supports_sampling(instance, data, vk::Format::R16G16B16A16_UNORM);
+        let pixels = decode_png(path, color_space, keep_16_bit)
+            .with_context(|| format!("Failed to decode texture {}", path.display()))?;
+        Self::from_pixels(instance, device, data, &pixels)
+            .with_context(|| format!("Failed to upload texture {}", path.display()))
+    }
+
+    pub unsafe fn from_png_bytes(
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+        bytes: &[u8],
+        color_space: ColorSpace,
+    ) -> Result<Self> {
+        let keep_16_bit = supports_sampling(instance, data, vk::Format::R16G16B16A16_UNORM);
+        let pixels = decode_png_bytes(bytes, color_space, keep_16_bit).context("Failed to decode texture")?;
+        Self::from_pixels(instance, device, data, &pixels).context("Failed to upload texture")
+    }
+
+    pub unsafe fn from_ktx2(instance: &Instance, device: &Device, data: &AppData, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let prebaked =
+            core::ktx2::load(path).with_context(|| format!("Failed to load texture {}", path.display()))?;
+        Self::from_prebaked(instance, device, data, &prebaked)
+            .with_context(|| format!("Failed to upload texture {}", path.display()))
+    }
+
+    pub unsafe fn from_dds(
+        instance: &Instance,
+        device: &Device,
+        data: &AppData,
+        path: impl AsRef<Path>,
+        color_space: ColorSpace,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let prebaked = core::dds::load(path, color_space)
+            .with_context(|| format!("Failed to load texture {}", path.display()))?;
+        Self::from_prebaked(instance, device, data, &prebaked)
+            .with_context(|| format!("Failed to upload texture {}", path.display()))
+    }
+
+    pub unsafe fn from_pixels(instance: &Instance, device: &Device, data: &AppData, pixels: &Pixels) -> Result<Self> {
+        if !supports_sampling(instance, data, pixels.format) {
+            return Err(anyhow!("{:?} can't be sampled with linear filtering", pixels.format));
+        }
+
+        let extent = vk::Extent2D { width: pixels.width, height: pixels.height };
+        let mut mip_levels = core::mipmap::mip_levels(extent);
+        let generation = match mip_levels {
+            1 => None,
+            _ => MipGeneration::get(instance, data, pixels.format),
+        };
+        if generation.is_none() && mip_levels > 1 {
+            warn!("Can't generate mipmaps for {:?}: no linear blits and no compute fallback.", pixels.format);
+            mip_levels = 1;
+        }
+        let (image, image_memory) = create_image(
+            instance,
+            device,
+            data,
+            extent,
+            pixels.format,
+            (mip_levels, 1),
+            generation.map_or(vk::ImageCreateFlags::empty(), |g| g.image_flags(pixels.format)),
+            generation.map_or(vk::ImageUsageFlags::empty(), |g| g.image_usage()),
+        )?;
+        let mut texture = Self {
+            image,
+            image_memory,
+            extent,
+            format: pixels.format,
+            mip_levels,
+            layers: 1,
+            view_type: vk::ImageViewType::_2D,
+            ..Default::default()
+        };
+
+        let result = (|| -> Result<()> {
+            upload(instance, device, data, &texture, generation, &pixels.data)?;
+            texture.image_view = create_image_view(device, &texture)?;
+            texture.sampler =
+                create_sampler(device, data, vk::Filter::LINEAR, vk::SamplerAddressMode::REPEAT, mip_levels)?;
+            Ok(())
+        })();
+        if let Err(e) = result {
+            texture.destroy(device);
+            return Err(e);
+        }
+
+        Ok(texture)
+    }
+
+    // Uploads every prebaked level as is. Formats the device can't sample are
+    // decompressed on the CPU where there's a decoder for them.
+    pub unsafe fn from_prebaked(instance: &Instance, device: &Device, data: &AppData, prebaked: &Prebaked) -> Result<Self> {
+        let properties = instance.get_physical_device_format_properties(data.physical_device, prebaked.format);
+        // The levels are copied in and then sampled.
+        let required = vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST;
+        let decompressed;
+        let prebaked = if properties.optimal_tiling_features.contains(required) {
+            prebaked
+        } else {
+            decompressed = prebaked
+                .decompress()
+                .with_context(|| format!("{:?} can't be uploaded and sampled on this device", prebaked.format))?;
+            warn!(
+                "{:?} can't be uploaded and sampled on this device, decompressed to {:?}.",
+                prebaked.format, decompressed.format,
+            );
+            &decompressed
+        };
+
+        let view_type = match (prebaked.cube, prebaked.layers) {
+            (true, 6) => vk::ImageViewType::CUBE,
+            (true, _) => return Err(anyhow!("Cube map arrays aren't supported")),
+            (false, 1) => vk::ImageViewType::_2D,
+            (false, _) => vk::ImageViewType::_2D_ARRAY,
+        };
+        let mip_levels = prebaked.levels.len() as u32;
+        let flags = if prebaked.cube { vk::ImageCreateFlags::CUBE_COMPATIBLE } else { vk::ImageCreateFlags::empty() };
+        let (image, image_memory) = create_image(
+            instance,
+            device,
+            data,
+            prebaked.extent,
+            prebaked.format,
+            (mip_levels, prebaked.layers),
+            flags,
+            vk::ImageUsageFlags::empty(),
+        )?;
+        let mut texture = Self {
+            image,
+            image_memory,
+            extent: prebaked.extent,
+            format: prebaked.format,
+            mip_levels,
+            layers: prebaked.layers,
+            view_type,
+            ..Default::default()
+        };
+
+        // Formats without linear filtering, mostly 32-bit float, fall back to nearest.
+        let properties = instance.get_physical_device_format_properties(data.physical_device, prebaked.format);
+        let filter = if properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR) {
+            vk::Filter::LINEAR
+        } else {
+            vk::Filter::NEAREST
+        };
+        let address_mode = if prebaked.cube { vk::SamplerAddressMode::CLAMP_TO_EDGE } else { vk::SamplerAddressMode::REPEAT };
+
+        let result = (|| -> Result<()> {
+            upload_levels(instance, device, data, &texture, &prebaked.levels)?;
+            texture.image_view = create_image_view(device, &texture)?;
+            texture.sampler = create_sampler(device, data, filter, address_mode, mip_levels)?;
+            Ok(())
+        })();
+        if let Err(e) = result {
+            texture.destroy(device);
+            return Err(e);
+        }
+
+        Ok(texture)
+    }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::builder()
+            .image_view(self.image_view)
+            .sampler(self.sampler)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .build()
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_sampler(self.sampler, None);
+        device.destroy_image_view(self.image_view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.image_memory, None);
+    }
+}
+
+pub unsafe fn create_image(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    (mip_levels, layers): (u32, u32),
+    flags: vk::ImageCreateFlags,
+    usage: vk::ImageUsageFlags,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    let info = vk::ImageCreateInfo::builder()
+        .flags(flags)
+        .image_type(vk::ImageType::_2D)
+        .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+        .mip_levels(mip_levels)
+        .array_layers(layers)
+        .format(format)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST | usage)
+        .samples(vk::SampleCountFlags::_1)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    let image = device.create_image(&info, None)?;
+
+    let requirements = device.get_image_memory_requirements(image);
+    let info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(requirements.size)
+        .memory_type_index(core::vertex::get_memory_type_index(
+            instance,
+            data,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            requirements,
+        )?);
+    let image_memory = device.allocate_memory(&info, None)?;
+    device.bind_image_memory(image, image_memory, 0)?;
+
+    Ok((image, image_memory))
+}
+
+pub unsafe fn create_image_view(device: &Device, texture: &Texture) -> Result<vk::ImageView> {
+    let subresource_range = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(texture.mip_levels)
+        .base_array_layer(0)
+        .layer_count(texture.layers);
+    let info = vk::ImageViewCreateInfo::builder()
+        .image(texture.image)
+        .view_type(texture.view_type)
+        .format(texture.format)
+        .subresource_range(subresource_range);
+
+    Ok(device.create_image_view(&info, None)?)
+}
+
+// Fills a host-visible staging buffer with `texels` and passes it to `f`,
+// destroying it afterwards whether or not `f` succeeded.
+unsafe fn with_staging_buffer(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    texels: &[u8],
+    f: impl FnOnce(vk::Buffer) -> Result<()>,
+) -> Result<()> {
+    let size = texels.len() as u64;
+    let (staging_buffer, staging_buffer_memory) = core::vertex::create_buffer(
+        instance,
+        device,
+        data,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+
+    let result = (|| -> Result<()> {
+        let memory = device.map_memory(staging_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+        memcpy(texels.as_ptr(), memory.cast(), texels.len());
+        device.unmap_memory(staging_buffer_memory);
+        f(staging_buffer)
+    })();
+
+    device.destroy_buffer(staging_buffer, None);
+    device.free_memory(staging_buffer_memory, None);
+    result
+}
+
+// Copies the texels into level 0 through a staging buffer and generates the
+// other levels from it, leaving the image ready to sample.
+unsafe fn upload(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    texture: &Texture,
+    generation: Option<MipGeneration>,
+    texels: &[u8],
+) -> Result<()> {
+    let mut compute_mipmaps = None;
+    let result = with_staging_buffer(instance, device, data, texels, |staging_buffer| {
+        if generation == Some(MipGeneration::Compute) {
+            compute_mipmaps = Some(ComputeMipmaps::new(device, data, texture.image, texture.mip_levels)?);
+        }
+
+        core::compute::run_once(device, data, |command_buffer| {
+            transition_layout(
+                device,
+                command_buffer,
+                texture.image,
+                (texture.mip_levels, 1),
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            )?;
+
+            let subresource = vk::ImageSubresourceLayers::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(0)
+                .base_array_layer(0)
+                .layer_count(1);
+            let region = vk::BufferImageCopy::builder()
+                .buffer_offset(0)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(subresource)
+                .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+                .image_extent(vk::Extent3D { width: texture.extent.width, height: texture.extent.height, depth: 1 });
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer,
+                texture.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+
+            match &compute_mipmaps {
+                Some(mipmaps) => {
+                    let srgb = texture.format == vk::Format::R8G8B8A8_SRGB;
+                    mipmaps.record(device, command_buffer, texture.image, texture.extent, srgb);
+                }
+                None if generation == Some(MipGeneration::Blit) => {
+                    core::mipmap::record_blits(device, command_buffer, texture.image, texture.extent, texture.mip_levels);
+                }
+                None => transition_layout(
+                    device,
+                    command_buffer,
+                    texture.image,
+                    (texture.mip_levels, 1),
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                )?,
+            }
+            Ok(())
+        })
+    });
+
+    if let Some(mut mipmaps) = compute_mipmaps {
+        mipmaps.destroy(device);
+    }
+    result
+}
+
+// Copies every level, all layers at once, leaving the image ready to sample.
+unsafe fn upload_levels(
+    instance: &Instance,
+    device: &Device,
+    data: &AppData,
+    texture: &Texture,
+    levels: &[Vec<u8>],
+) -> Result<()> {
+    let mut regions = Vec::with_capacity(levels.len());
+    let mut offset = 0;
+    for (level, texels) in levels.iter().enumerate() {
+        let extent = core::mipmap::level_extent(texture.extent, level as u32);
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(level as u32)
+            .base_array_layer(0)
+            .layer_count(texture.layers);
+        let region = vk::BufferImageCopy::builder()
+            .buffer_offset(offset as u64)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(subresource)
+            .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+            .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+            .build();
+        regions.push(region);
+        offset += texels.len();
+    }
+
+    // Every level is whole blocks, so each offset stays block aligned.
+    with_staging_buffer(instance, device, data, &levels.concat(), |staging_buffer| {
+        core::compute::run_once(device, data, |command_buffer| {
+            transition_layout(
+                device,
+                command_buffer,
+                texture.image,
+                (texture.mip_levels, texture.layers),
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            )?;
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer,
+                texture.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            );
+            transition_layout(
+                device,
+                command_buffer,
+                texture.image,
+                (texture.mip_levels, texture.layers),
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )
+        })
+    })
+}
+
+// The transitions around a transfer upload, covering every mip level and layer.
+pub unsafe fn transition_layout(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    (mip_levels, layers): (u32, u32),
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) -> Result<()> {
+    let (src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask) = match (old_layout, new_layout) {
+        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
+            vk::AccessFlags::empty(),
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+        ),
+        (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::AccessFlags::SHADER_READ,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+        ),
+        _ => return Err(anyhow!("Unsupported image layout transition {:?} -> {:?}", old_layout, new_layout)),
+    };
+
+    let subresource = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(mip_levels)
+        .base_array_layer(0)
+        .layer_count(layers);
+    let barrier = vk::ImageMemoryBarrier::builder()
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource)
+        .src_access_mask(src_access_mask)
+        .dst_access_mask(dst_access_mask);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        src_stage_mask,
+        dst_stage_mask,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier],
+    );
+
+    Ok(())
+}
+
+// Samples every one of `mip_levels` levels, anisotropically when the device
+// supports it.
+pub unsafe fn create_sampler(
+    device: &Device,
+    data: &AppData,
+    filter: vk::Filter,
+    address_mode: vk::SamplerAddressMode,
+    mip_levels: u32,
+) -> Result<vk::Sampler> {
+    let info = vk::SamplerCreateInfo::builder()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .address_mode_u(address_mode)
+        .address_mode_v(address_mode)
+        .address_mode_w(address_mode)
+        .anisotropy_enable(data.sampler_anisotropy_supported)
+        .max_anisotropy(if data.sampler_anisotropy_supported { data.max_sampler_anisotropy } else { 1.0 })
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .compare_op(vk::CompareOp::ALWAYS)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .mip_lod_bias(0.0)
+        .min_lod(0.0)
+        .max_lod(mip_levels as f32);
+
+    Ok(device.create_sampler(&info, None)?)
+}
+
+// A set binding `texture` at `TEXTURE_SET`, allocated for the app's lifetime.
+pub unsafe fn create_texture_set(device: &Device, descriptors: &mut Descriptors, texture: &Texture) -> Result<vk::DescriptorSet> {
+    let (set, _) = DescriptorBuilder::new(&mut descriptors.layout_cache, &mut descriptors.allocator)
+        .bind_image(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT, texture.descriptor_info())
+        .build(device)?;
+
+    Ok(set)
+}
+
+// The scene's texture, added to the bindless table or bound through its own
+// set (see `AppData::bindless_textures`) for the app's lifetime.
+pub unsafe fn create_texture(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let path = Path::new(core::config::PROJECT_ROOT).join(TEXTURE_PATH);
+    data.texture = Texture::from_png(instance, device, data, path, ColorSpace::Srgb)?;
+    if data.bindless_textures {
+        data.texture_index = data.bindless.add_sampled_image(device, data.texture.image_view)?;
+        data.sampler_index = data.bindless.add_sampler(device, data.texture.sampler)?;
+    } else {
+        data.texture_set = create_texture_set(device, &mut data.descriptors, &data.texture)?;
+    }
+
+    Ok(())
+}
+
+// With the glTF scene's textures. Their table slots are freed with the table
+// and their sets with the descriptor allocator.
+pub unsafe fn destroy_texture(device: &Device, data: &mut AppData) {
+    data.texture.destroy(device);
+    data.texture = Texture::default();
+    data.scene_textures.drain(..).for_each(|t| t.destroy(device));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::shaders::spirv_words;
+
+    // A `width` x 1 PNG of `data`, with `palette` and `trns` chunks if given.
+    fn encode_png(width: u32, color: png::ColorType, depth: png::BitDepth, palette: &[u8], trns: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, 1);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        if !palette.is_empty() {
+            encoder.set_palette(palette.to_vec());
+        }
+        if !trns.is_empty() {
+            encoder.set_trns(trns.to_vec());
+        }
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn decodes_rgba8_in_the_requested_color_space() {
+        let texels = [10, 20, 30, 40, 50, 60, 70, 80];
+        let png = encode_png(2, png::ColorType::Rgba, png::BitDepth::Eight, &[], &[], &texels);
+
+        let pixels = decode_png_from(png.as_slice(), ColorSpace::Srgb, false).unwrap();
+        assert_eq!((pixels.width, pixels.height, pixels.format), (2, 1, vk::Format::R8G8B8A8_SRGB));
+        assert_eq!(pixels.data, texels);
+        let pixels = decode_png_from(png.as_slice(), ColorSpace::Linear, false).unwrap();
+        assert_eq!(pixels.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(pixels.data, texels);
+    }
+
+    #[test]
+    fn expands_gray_alpha_and_indexed_to_rgba8() {
+        let png = encode_png(2, png::ColorType::GrayscaleAlpha, png::BitDepth::Eight, &[], &[], &[100, 200, 50, 25]);
+        let pixels = decode_png_from(png.as_slice(), ColorSpace::Linear, false).unwrap();
+        assert_eq!(pixels.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(pixels.data, [100, 100, 100, 200, 50, 50, 50, 25]);
+
+        // Two 1-bit indices, the first entry translucent through `tRNS`.
+        let palette = [255, 0, 0, 0, 255, 0];
+        let png = encode_png(2, png::ColorType::Indexed, png::BitDepth::One, &palette, &[128], &[0b1000_0000]);
+        let pixels = decode_png_from(png.as_slice(), ColorSpace::Srgb, false).unwrap();
+        assert_eq!(pixels.format, vk::Format::R8G8B8A8_SRGB);
+        assert_eq!(pixels.data, [0, 255, 0, 255, 255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn keeps_16_bit_srgb_as_linear_unorm() {
+        let samples: [u16; 6] = [0, 0x8000, u16::MAX, 0x1234, 0x00ff, 0xff00];
+        let data = samples.iter().flat_map(|s| s.to_be_bytes()).collect::<Vec<_>>();
+        let png = encode_png(2, png::ColorType::Rgb, png::BitDepth::Sixteen, &[], &[], &data);
+
+        let pixels = decode_png_from(png.as_slice(), ColorSpace::Srgb, true).unwrap();
+        assert_eq!((pixels.width, pixels.height, pixels.format), (2, 1, vk::Format::R16G16B16A16_UNORM));
+        let texels = pixels.data.chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect::<Vec<_>>();
+        let linear = samples.map(srgb_to_linear);
+        assert_eq!(texels, [linear[0], linear[1], linear[2], u16::MAX, linear[3], linear[4], linear[5], u16::MAX]);
+        assert_eq!((texels[0], texels[2]), (0, u16::MAX));
+
+        // Without `keep_16_bit` the samples are cut to their high bytes.
+        let pixels = decode_png_from(png.as_slice(), ColorSpace::Srgb, false).unwrap();
+        assert_eq!(pixels.format, vk::Format::R8G8B8A8_SRGB);
+        assert_eq!(pixels.data, [0, 0x80, 0xff, 0xff, 0x12, 0x00, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn combined_shaders_read_the_texture_set() {
+        let shaders: [&[u8]; 2] = [
+            include_bytes!("../../shaders/shader_combined.frag.spv"),
+            include_bytes!("../../shaders/gbuffer_combined.frag.spv"),
+        ];
+        for shader in shaders {
+            let reflection = core::reflect::reflect_shader(&spirv_words(shader).unwrap()).unwrap();
+            core::reflect::validate_descriptor_set_layout(&[&reflection], TEXTURE_SET, &texture_bindings()).unwrap();
+            let bindings = core::reflect::descriptor_set_layout_bindings(&[&reflection], &[]).unwrap();
+            assert_eq!(bindings.keys().copied().collect::<Vec<_>>(), [TEXTURE_SET]);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+use cgmath::{point3, vec3, Deg, SquareMatrix};
+
+use std::mem::size_of;
+use std::ptr::copy_nonoverlapping as memcpy;
+
+use crate::AppData;
+use crate::MAX_FRAMES_IN_FLIGHT;
+use crate::core;
+use crate::core::vertex::UniformBufferObject;
+
+type Point3 = cgmath::Point3<f32>;
+type Vec3 = cgmath::Vector3<f32>;
+type Mat4 = cgmath::Matrix4<f32>;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    pub eye: Point3,
+    pub target: Point3,
+    pub up: Vec3,
+    pub fov: Deg<f32>,
+    // Also the range the depth debug view linearizes with.
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            eye: point3(2.0, 2.0, 2.0),
+            target: point3(0.0, 0.0, 0.0),
+            up: vec3(0.0, 0.0, 1.0),
+            fov: Deg(45.0),
+            near: 0.1,
+            far: 10.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    // cgmath targets OpenGL, so the result is flipped to Vulkan's downward y
+    // and mapped to its [0, 1] depth range.
+    pub fn proj(&self, extent: vk::Extent2D) -> Mat4 {
+        let correction = Mat4::new(
+            1.0,  0.0, 0.0, 0.0,
+            0.0, -1.0, 0.0, 0.0,
+            0.0,  0.0, 0.5, 0.0,
+            0.0,  0.0, 0.5, 1.0,
+        );
+        let aspect = extent.width as f32 / extent.height.max(1) as f32;
+        correction * cgmath::perspective(self.fov, aspect, self.near, self.far)
+    }
+
+    pub fn uniforms(&self, model: Mat4, extent: vk::Extent2D) -> UniformBufferObject {
+        let proj = self.proj(extent);
+        let inverse_proj = proj.invert().unwrap_or_else(Mat4::identity);
+        UniformBufferObject { model, view: self.view(), proj, inverse_proj }
+    }
+}
+
+// One buffer per frame in flight. A frame writes its buffer once its fence has
+// signaled, and the command buffers recorded for it bind `descriptor_sets[frame]`.
+pub unsafe fn create_uniform_buffers(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let (uniform_buffer, uniform_buffer_memory) = core::vertex::create_buffer(
+            instance,
+            device,
+            data,
+            size_of::<UniformBufferObject>() as u64,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+
+        data.uniform_buffers.push(uniform_buffer);
+        data.uniform_buffers_memory.push(uniform_buffer_memory);
+    }
+
+    Ok(())
+}
+
+pub unsafe fn destroy_uniform_buffers(device: &Device, data: &mut AppData) {
+    data.uniform_buffers.drain(..).for_each(|b| device.destroy_buffer(b, None));
+    data.uniform_buffers_memory.drain(..).for_each(|m| device.free_memory(m, None));
+}
+
+// Writes the uniforms frame in flight `frame` reads.
+pub unsafe fn update_uniform_buffer(
+    device: &Device,
+    data: &AppData,
+    frame: usize,
+    ubo: &UniformBufferObject,
+) -> Result<()> {
+    let memory = device.map_memory(
+        data.uniform_buffers_memory[frame],
+        0,
+        size_of::<UniformBufferObject>() as u64,
+        vk::MemoryMapFlags::empty(),
+    )?;
+    memcpy(ubo, memory.cast(), 1);
+    device.unmap_memory(data.uniform_buffers_memory[frame]);
+
+    Ok(())
+}
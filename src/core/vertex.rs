@@ -1,78 +1,61 @@
 use anyhow::{Result, anyhow};
 use vulkanalia::prelude::v1_0::*;
-use cgmath::{vec2, vec3, point3, Deg};
+use cgmath::{vec2, vec3};
 
-use std::mem::size_of;
+use std::mem::size_of_val;
 use std::ptr::copy_nonoverlapping as memcpy;
 
 use crate::AppData;
+use crate::core::vertex_input::vertex_layout;
 
 type Vec2 = cgmath::Vector2<f32>;
 type Vec3 = cgmath::Vector3<f32>;
 
 type Mat4 = cgmath::Matrix4<f32>;
 
+// The quad drawn when `core::config::MODEL` is `None`, facing +z.
 pub static VERTICES: [Vertex; 4] = [
-    Vertex::new(vec2(-0.5, -0.5), vec3(0.0, 0.0, 1.0)),
-    Vertex::new(vec2(0.5, -0.5), vec3(0.0, 1.0, 0.0)),
-    Vertex::new(vec2(0.5, 0.5), vec3(1.0, 0.0, 0.0)),
-    Vertex::new(vec2(-0.5, 0.5), vec3(0.5, 0.0, 1.0)),
+    Vertex::new(vec3(-0.5, -0.5, 0.0), vec3(0.0, 0.0, 1.0), vec2(1.0, 0.0), vec3(0.0, 0.0, 1.0)),
+    Vertex::new(vec3(0.5, -0.5, 0.0), vec3(0.0, 0.0, 1.0), vec2(0.0, 0.0), vec3(0.0, 1.0, 0.0)),
+    Vertex::new(vec3(0.5, 0.5, 0.0), vec3(0.0, 0.0, 1.0), vec2(0.0, 1.0), vec3(1.0, 0.0, 0.0)),
+    Vertex::new(vec3(-0.5, 0.5, 0.0), vec3(0.0, 0.0, 1.0), vec2(1.0, 1.0), vec3(0.5, 0.0, 1.0)),
 ];
 
-pub const INDICES: &[u16] = &[0, 1, 2, 2, 3, 0];
+pub const INDICES: &[u32] = &[0, 1, 2, 2, 3, 0];
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
-pub struct Vertex {
-    pos: Vec2,
-    color: Vec3,
+vertex_layout! {
+    #[derive(Copy, Clone, Debug)]
+    pub struct Vertex {
+        #[location(0)] pub pos: Vec3,
+        #[location(3)] pub normal: Vec3,
+        #[location(2)] pub tex_coord: Vec2,
+        #[location(1)] pub color: Vec3,
+    }
 }
 
 impl Vertex {
-    const fn new(pos: Vec2, color: Vec3) -> Self {
-        Self { pos, color }
-    }
-
-    pub fn binding_descriptions() -> vk::VertexInputBindingDescription {
-        vk::VertexInputBindingDescription::builder()
-            .binding(0)
-            .stride(size_of::<Vertex>() as u32)
-            .input_rate(vk::VertexInputRate::VERTEX)
-            .build()
-    }
-    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
-        let pos = vk::VertexInputAttributeDescription::builder()
-            .binding(0)
-            .location(0)
-            .format(vk::Format::R32G32_SFLOAT)
-            .offset(0)
-            .build();
-
-        let color = vk::VertexInputAttributeDescription::builder()
-            .binding(0)
-            .location(1)
-            .format(vk::Format::R32G32B32_SFLOAT)
-            .offset(8)
-            .build();
-
-        [pos, color]
+    pub const fn new(pos: Vec3, normal: Vec3, tex_coord: Vec2, color: Vec3) -> Self {
+        Self { pos, normal, tex_coord, color }
     }
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct UniformBufferObject {
-    model: Mat4,
-    view: Mat4,
-    proj: Mat4,
+    pub model: Mat4,
+    pub view: Mat4,
+    pub proj: Mat4,
+    // For getting view space positions back from depth.
+    pub inverse_proj: Mat4,
 }
 
 pub unsafe fn create_vertex_buffer(
     instance: &Instance,
     device: &Device,
     data: &mut AppData,
+    vertices: &[Vertex],
 ) -> Result<()> {
-    let size = (size_of::<Vertex>() * VERTICES.len()) as u64;
+    let size = size_of_val(vertices) as u64;
 
     let (staging_buffer, staging_buffer_memory) = create_buffer(
         instance,
@@ -90,7 +73,7 @@ pub unsafe fn create_vertex_buffer(
         vk::MemoryMapFlags::empty(),
     )?;
 
-    memcpy(VERTICES.as_ptr(), memory.cast(), VERTICES.len());
+    memcpy(vertices.as_ptr(), memory.cast(), vertices.len());
     device.unmap_memory(staging_buffer_memory);
 
     let (vertex_buffer, vertex_buffer_memory) = create_buffer(
@@ -118,8 +101,9 @@ pub unsafe fn create_index_buffer(
     instance: &Instance,
     device: &Device,
     data: &mut AppData,
+    indices: &[u32],
 ) -> Result<()> {
-    let size = (size_of::<Vertex>() * INDICES.len()) as u64;
+    let size = size_of_val(indices) as u64;
 
     let (staging_buffer, staging_buffer_memory) = create_buffer(
         instance,
@@ -137,7 +121,7 @@ pub unsafe fn create_index_buffer(
         vk::MemoryMapFlags::empty(),
     )?;
 
-    memcpy(INDICES.as_ptr(), memory.cast(), INDICES.len());
+    memcpy(indices.as_ptr(), memory.cast(), indices.len());
 
     device.unmap_memory(staging_buffer_memory);
 
@@ -152,6 +136,7 @@ pub unsafe fn create_index_buffer(
 
     data.index_buffer = index_buffer;
     data.index_buffer_memory = index_buffer_memory;
+    data.index_count = indices.len() as u32;
 
     copy_buffer(device, data, staging_buffer, index_buffer, size)?;
 
@@ -160,10 +145,10 @@ pub unsafe fn create_index_buffer(
     Ok(())
 }
 
-unsafe fn create_buffer(
+pub unsafe fn create_buffer(
     instance: &Instance,
     device: &Device,
-    data: &mut AppData,
+    data: &AppData,
     size: vk::DeviceSize,
     usage: vk::BufferUsageFlags,
     properties: vk::MemoryPropertyFlags,
@@ -238,7 +223,7 @@ pub unsafe fn get_memory_type_index(
 
     (0..memory.memory_type_count)
         .find(|i| { 
-            let suitable = (requirements.memory_type_bits & (i << i)) != 0;
+            let suitable = (requirements.memory_type_bits & (1 << i)) != 0;
             let memory_type = memory.memory_types[*i as usize];
             suitable && memory_type.property_flags.contains(properties)
         })
@@ -0,0 +1,286 @@
+use vulkanalia::prelude::v1_0::*;
+
+use std::mem::size_of;
+
+// A field type a vertex attribute can be read into. Matrices take one shader
+// location per column.
+pub trait VertexFormat {
+    const FORMAT: vk::Format;
+    const LOCATIONS: u32 = 1;
+}
+
+macro_rules! vertex_formats {
+    ($($ty:ty => $format:ident),* $(,)?) => {
+        $(impl VertexFormat for $ty {
+            const FORMAT: vk::Format = vk::Format::$format;
+        })*
+    };
+}
+
+vertex_formats! {
+    f32 => R32_SFLOAT,
+    [f32; 2] => R32G32_SFLOAT,
+    [f32; 3] => R32G32B32_SFLOAT,
+    [f32; 4] => R32G32B32A32_SFLOAT,
+    cgmath::Vector2<f32> => R32G32_SFLOAT,
+    cgmath::Vector3<f32> => R32G32B32_SFLOAT,
+    cgmath::Vector4<f32> => R32G32B32A32_SFLOAT,
+    i32 => R32_SINT,
+    [i32; 2] => R32G32_SINT,
+    [i32; 3] => R32G32B32_SINT,
+    [i32; 4] => R32G32B32A32_SINT,
+    u32 => R32_UINT,
+    [u32; 2] => R32G32_UINT,
+    [u32; 3] => R32G32B32_UINT,
+    [u32; 4] => R32G32B32A32_UINT,
+    // Normalized, as for packed colors.
+    [u8; 4] => R8G8B8A8_UNORM,
+}
+
+impl VertexFormat for cgmath::Matrix4<f32> {
+    const FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+    const LOCATIONS: u32 = 4;
+}
+
+// One field of a vertex struct, starting at `location`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub format: vk::Format,
+    pub offset: u32,
+    pub locations: u32,
+    pub size: u32,
+}
+
+impl VertexAttribute {
+    pub fn of<T: VertexFormat>(location: u32, offset: usize) -> Self {
+        Self {
+            location,
+            format: T::FORMAT,
+            offset: offset as u32,
+            locations: T::LOCATIONS,
+            size: size_of::<T>() as u32,
+        }
+    }
+}
+
+// A `#[repr(C)]` struct read from one vertex buffer binding, usually
+// implemented with `vertex_layout!`.
+pub trait VertexLayout: Copy + 'static {
+    const INPUT_RATE: vk::VertexInputRate = vk::VertexInputRate::VERTEX;
+
+    fn attributes() -> Vec<VertexAttribute>;
+
+    fn binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(binding)
+            .stride(size_of::<Self>() as u32)
+            .input_rate(Self::INPUT_RATE)
+            .build()
+    }
+
+    fn attribute_descriptions(binding: u32) -> Vec<vk::VertexInputAttributeDescription> {
+        Self::attributes()
+            .into_iter()
+            .flat_map(|attribute| {
+                let column = attribute.size / attribute.locations;
+                (0..attribute.locations).map(move |i| {
+                    vk::VertexInputAttributeDescription::builder()
+                        .binding(binding)
+                        .location(attribute.location + i)
+                        .format(attribute.format)
+                        .offset(attribute.offset + i * column)
+                        .build()
+                })
+            })
+            .collect()
+    }
+}
+
+// Everything a pipeline reads from vertex buffers. A `VertexLayout` is binding
+// 0, a tuple of them gives each its position as the binding, and `()` reads
+// nothing, for passes that generate their vertices in the shader.
+pub trait VertexInput {
+    fn descriptions() -> (Vec<vk::VertexInputBindingDescription>, Vec<vk::VertexInputAttributeDescription>);
+}
+
+impl VertexInput for () {
+    fn descriptions() -> (Vec<vk::VertexInputBindingDescription>, Vec<vk::VertexInputAttributeDescription>) {
+        (Vec::new(), Vec::new())
+    }
+}
+
+impl<V: VertexLayout> VertexInput for V {
+    fn descriptions() -> (Vec<vk::VertexInputBindingDescription>, Vec<vk::VertexInputAttributeDescription>) {
+        (vec![V::binding_description(0)], V::attribute_descriptions(0))
+    }
+}
+
+macro_rules! vertex_input_tuples {
+    ($(($($layout:ident => $binding:literal),+)),* $(,)?) => {
+        $(impl<$($layout: VertexLayout),+> VertexInput for ($($layout,)+) {
+            fn descriptions() -> (Vec<vk::VertexInputBindingDescription>, Vec<vk::VertexInputAttributeDescription>) {
+                let bindings = vec![$($layout::binding_description($binding)),+];
+                let attributes = [$($layout::attribute_descriptions($binding)),+].concat();
+                (bindings, attributes)
+            }
+        })*
+    };
+}
+
+vertex_input_tuples! {
+    (A => 0, B => 1),
+    (A => 0, B => 1, C => 2),
+    (A => 0, B => 1, C => 2, D => 3),
+}
+
+// Declares a `#[repr(C)]` struct whose fields are vertex attributes at the
+// given shader locations, and implements `VertexLayout` for it from the
+// fields' types and offsets. Starting with `instance;` steps it per instance
+// instead of per vertex:
+//
+//     vertex_layout! {
+//         instance;
+//         #[derive(Copy, Clone, Debug)]
+//         pub struct Instance {
+//             #[location(4)] pub model: Mat4,
+//             #[location(8)] pub tint: Vec3,
+//         }
+//     }
+macro_rules! vertex_layout {
+    (instance; $($rest:tt)*) => {
+        $crate::core::vertex_input::vertex_layout!(@rate INSTANCE; $($rest)*);
+    };
+    (@rate $rate:ident;
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(#[location($location:literal)] $field_vis:vis $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        $vis struct $name {
+            $($field_vis $field: $ty),*
+        }
+
+        impl $crate::core::vertex_input::VertexLayout for $name {
+            const INPUT_RATE: vulkanalia::vk::VertexInputRate = vulkanalia::vk::VertexInputRate::$rate;
+
+            fn attributes() -> Vec<$crate::core::vertex_input::VertexAttribute> {
+                vec![$($crate::core::vertex_input::VertexAttribute::of::<$ty>(
+                    $location,
+                    std::mem::offset_of!($name, $field),
+                )),*]
+            }
+        }
+    };
+    ($($rest:tt)*) => {
+        $crate::core::vertex_input::vertex_layout!(@rate VERTEX; $($rest)*);
+    };
+}
+
+pub(crate) use vertex_layout;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::mem::offset_of;
+
+    use crate::core::vertex::Vertex;
+
+    type Vec2 = cgmath::Vector2<f32>;
+    type Vec3 = cgmath::Vector3<f32>;
+    type Mat4 = cgmath::Matrix4<f32>;
+
+    vertex_layout! {
+        #[derive(Copy, Clone, Debug)]
+        struct Packed {
+            #[location(0)] position: Vec3,
+            #[location(1)] color: [u8; 4],
+            #[location(2)] uv: Vec2,
+            #[location(3)] id: u32,
+        }
+    }
+
+    vertex_layout! {
+        instance;
+        #[derive(Copy, Clone, Debug)]
+        struct Instance {
+            #[location(4)] model: Mat4,
+            #[location(8)] tint: [f32; 3],
+        }
+    }
+
+    fn attribute(binding: u32, location: u32, format: vk::Format, offset: usize) -> vk::VertexInputAttributeDescription {
+        vk::VertexInputAttributeDescription::builder()
+            .binding(binding)
+            .location(location)
+            .format(format)
+            .offset(offset as u32)
+            .build()
+    }
+
+    #[test]
+    fn layouts_match_the_struct() {
+        let binding = Packed::binding_description(0);
+        assert_eq!((binding.stride, binding.input_rate), (size_of::<Packed>() as u32, vk::VertexInputRate::VERTEX));
+        assert_eq!(
+            Packed::attribute_descriptions(0),
+            [
+                attribute(0, 0, vk::Format::R32G32B32_SFLOAT, offset_of!(Packed, position)),
+                attribute(0, 1, vk::Format::R8G8B8A8_UNORM, offset_of!(Packed, color)),
+                attribute(0, 2, vk::Format::R32G32_SFLOAT, offset_of!(Packed, uv)),
+                attribute(0, 3, vk::Format::R32_UINT, offset_of!(Packed, id)),
+            ]
+        );
+
+        // The scene's vertex declares its locations out of field order.
+        let locations = Vertex::attribute_descriptions(0).iter().map(|a| (a.location, a.offset)).collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            [
+                (0, offset_of!(Vertex, pos) as u32),
+                (3, offset_of!(Vertex, normal) as u32),
+                (2, offset_of!(Vertex, tex_coord) as u32),
+                (1, offset_of!(Vertex, color) as u32),
+            ]
+        );
+        assert_eq!(Vertex::binding_description(0).stride, size_of::<Vertex>() as u32);
+    }
+
+    #[test]
+    fn matrices_take_a_location_per_column() {
+        let binding = Instance::binding_description(1);
+        assert_eq!((binding.stride, binding.input_rate), (size_of::<Instance>() as u32, vk::VertexInputRate::INSTANCE));
+        let model = offset_of!(Instance, model);
+        let column = size_of::<cgmath::Vector4<f32>>();
+        assert_eq!(
+            Instance::attribute_descriptions(1),
+            [
+                attribute(1, 4, vk::Format::R32G32B32A32_SFLOAT, model),
+                attribute(1, 5, vk::Format::R32G32B32A32_SFLOAT, model + column),
+                attribute(1, 6, vk::Format::R32G32B32A32_SFLOAT, model + 2 * column),
+                attribute(1, 7, vk::Format::R32G32B32A32_SFLOAT, model + 3 * column),
+                attribute(1, 8, vk::Format::R32G32B32_SFLOAT, offset_of!(Instance, tint)),
+            ]
+        );
+    }
+
+    #[test]
+    fn tuples_bind_in_order() {
+        let (bindings, attributes) = <(Packed, Instance)>::descriptions();
+        let rates = bindings.iter().map(|b| (b.binding, b.input_rate)).collect::<Vec<_>>();
+        assert_eq!(rates, [(0, vk::VertexInputRate::VERTEX), (1, vk::VertexInputRate::INSTANCE)]);
+        assert_eq!(attributes, [Packed::attribute_descriptions(0), Instance::attribute_descriptions(1)].concat());
+
+        let (bindings, attributes) = <(Instance, Packed, Packed)>::descriptions();
+        assert_eq!(bindings.iter().map(|b| b.binding).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(bindings[0].stride, size_of::<Instance>() as u32);
+        assert_eq!(attributes.iter().filter(|a| a.binding == 2).count(), 4);
+
+        let (bindings, attributes) = Packed::descriptions();
+        assert_eq!((bindings, attributes), (vec![Packed::binding_description(0)], Packed::attribute_descriptions(0)));
+        assert_eq!(<()>::descriptions(), (Vec::new(), Vec::new()));
+    }
+}
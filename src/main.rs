@@ -11,12 +11,15 @@ mod core;
 use anyhow::{Result,anyhow};
 
 use winit::dpi::LogicalSize;
-use winit::event::{Event,WindowEvent};
+use winit::event::{ElementState,Event,WindowEvent};
+use winit::keyboard::{KeyCode,PhysicalKey};
 use winit::event_loop::EventLoop;
 use winit::window::{Window,WindowBuilder};
 
 use log::*;
 
+use cgmath::{vec3, Deg, SquareMatrix};
+
 use vulkanalia::loader::{LibloadingLoader,LIBRARY};
 use vulkanalia::window as vk_window;
 use vulkanalia::prelude::v1_0::*;
@@ -29,6 +32,7 @@ use vulkanalia::vk::KhrSwapchainExtensionDeviceCommands;
 use std::collections::HashSet;
 use std::ffi::CStr;
 use std::os::raw::c_void;
+use std::time::Instant;
 
 use thiserror::Error;
 
@@ -39,6 +43,7 @@ const VALIDATION_LAYER: vk::ExtensionName = vk::ExtensionName::from_bytes(b"VK_L
 const DEVICE_EXTENSIONS: &[vk::ExtensionName] = &[vk::KHR_SWAPCHAIN_EXTENSION.name];
 
 pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+const WINDOW_TITLE: &str = "Vulkan Test";
 
 fn main() -> Result<()> {
     pretty_env_logger::init();
@@ -46,7 +51,7 @@ fn main() -> Result<()> {
     // Window
     let event_loop = EventLoop::new()?;
     let window = WindowBuilder::new()
-        .with_title("Vulkan Test")
+        .with_title(WINDOW_TITLE)
         .with_inner_size(LogicalSize::new(1024, 768))
         .build(&event_loop)?;
 
@@ -68,6 +73,13 @@ fn main() -> Result<()> {
                         app.resized = true;
                     }
                 },
+                WindowEvent::KeyboardInput { event, .. }
+                    if event.state == ElementState::Pressed && !event.repeat =>
+                {
+                    if let PhysicalKey::Code(code) = event.physical_key {
+                        unsafe { app.handle_key(code) }.unwrap();
+                    }
+                },
                 WindowEvent::CloseRequested => {
                     elwt.exit();
                         unsafe { app.destroy(); }
@@ -89,27 +101,83 @@ pub struct AppData {
 
     physical_device: vk::PhysicalDevice,
     graphics_queue: vk::Queue,
+    compute_queue: vk::Queue,
     surface: vk::SurfaceKHR,
     present_queue: vk::Queue,
 
     pub swapchain_format: vk::Format,
+    pub swapchain_usage: vk::ImageUsageFlags,
     pub swapchain_extent: vk::Extent2D,
     pub swapchain: vk::SwapchainKHR,
     pub swapchain_images: Vec<vk::Image>,
     pub swapchain_image_views: Vec<vk::ImageView>,
 
     pub render_pass: vk::RenderPass,
+    pub dynamic_rendering: Option<core::dynamic_rendering::DynamicRendering>,
+    pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    // The uniforms updated every frame, one set and buffer per frame in flight.
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptors: core::descriptor::Descriptors,
+    pub descriptor_indexing: Option<core::bindless::DescriptorIndexing>,
+    // Whether the scene samples its textures from the bindless table, see
+    // `core::config::BINDLESS_TEXTURES`.
+    pub bindless_textures: bool,
+    pub bindless: core::bindless::BindlessTable,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub uniform_buffers: Vec<vk::Buffer>,
+    pub uniform_buffers_memory: Vec<vk::DeviceMemory>,
+    pub camera: core::uniform::Camera,
+    pub texture: core::texture::Texture,
+    pub texture_index: core::bindless::ImageIndex,
+    pub sampler_index: core::bindless::SamplerIndex,
+    pub texture_set: vk::DescriptorSet,
+    // A glTF scene's textures, in the bindless table for the app's lifetime.
+    pub scene_textures: Vec<core::texture::Texture>,
+    pub skybox: core::skybox::SkyboxData,
+
+    pub render_path: core::config::RenderPath,
+    pub deferred: core::deferred::DeferredData,
+
+    pub post: core::post::PostData,
+    pub debug_view: core::debug_view::DebugView,
+    pub debug: core::debug_view::DebugViewData,
+    pub wireframe_supported: bool,
+    pub max_push_constants_size: u32,
+    pub sampler_anisotropy_supported: bool,
+    pub max_sampler_anisotropy: f32,
+
+    pub msaa_samples: vk::SampleCountFlags,
+    pub sample_shading: bool,
+    pub sample_shading_supported: bool,
+    pub color_image: vk::Image,
+    pub color_image_memory: vk::DeviceMemory,
+    pub color_image_view: vk::ImageView,
+
+    pub depth_format: vk::Format,
+    pub depth_image: vk::Image,
+    pub depth_image_memory: vk::DeviceMemory,
+    pub depth_image_view: vk::ImageView,
     pub pipeline_layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
-    pub framebuffers: Vec<vk::Framebuffer>,
+    pub vert_shader: Vec<u32>,
+    pub frag_shader: Vec<u32>,
+    pub render_graphs: Vec<core::render_graph::CompiledGraph>,
 
     pub command_pool: vk::CommandPool,
 
+    // Model matrices of the meshes the scene draws, pushed per draw.
+    pub object_transforms: Vec<cgmath::Matrix4<f32>>,
+    // The parts of the index buffer each of them is drawn in.
+    pub draws: Vec<core::model::Draw>,
     pub vertex_buffer: vk::Buffer,
     pub vertex_buffer_memory: vk::DeviceMemory,
     pub command_buffers: Vec<vk::CommandBuffer>,
+    // Command buffers to re-record before their next submission, see
+    // `core::commands::invalidate_command_buffers`.
+    pub stale_command_buffers: Vec<bool>,
     pub index_buffer: vk::Buffer,
     pub index_buffer_memory: vk::DeviceMemory,
+    pub index_count: u32,
 
     pub image_available_semaphores: Vec<vk::Semaphore>,
     pub render_finished_semaphores: Vec<vk::Semaphore>,
@@ -125,10 +193,14 @@ struct App {
     device: Device,
     frame: usize,
     resized: bool,
+    start: Instant,
+    #[cfg(feature = "runtime-shaders")]
+    shader_watcher: core::hot_reload::ShaderWatcher,
 }
 impl App {
     unsafe fn create(window: &Window) -> Result<Self> {
-        let mut data = AppData::default();
+        let mut data = AppData { render_path: core::config::render_path(), ..Default::default() };
+        info!("Using the {:?} render path.", data.render_path);
         let loader = LibloadingLoader::new(LIBRARY)?;
         let entry = Entry::new(loader).map_err(|b| anyhow!("{}", b))?;
         let instance = create_instance(window, &entry, &mut data)?;
@@ -139,14 +211,29 @@ impl App {
         core::swapchain::create_swapchain(window, &instance, &device, &mut data, vk::SwapchainKHR::null())?;
         core::swapchain::create_swapchain_image_views(&device, &mut data)?;
 
-        core::pipeline::create_render_pass(&instance, &device, &mut data)?;
-        core::pipeline::create_pipeline(&device, &mut data)?;
-        core::pipeline::create_framebuffers(&device, &mut data)?;
-
+        match data.render_path {
+            core::config::RenderPath::Forward => {
+                core::pipeline::load_shaders(&mut data)?;
+                core::debug_view::load_shaders(&mut data)?;
+                core::post::load_shaders(&mut data)?;
+                core::skybox::load_shaders(&mut data)?;
+            }
+            core::config::RenderPath::Deferred => {
+                core::deferred::load_shaders(&mut data)?;
+                core::deferred::set_lights(&mut data, &core::deferred::default_lights())?;
+            }
+        }
         core::commands::create_command_pool(&instance, &device, &mut data)?;
-        core::vertex::create_vertex_buffer(&instance, &device, &mut data)?;
-        core::vertex::create_index_buffer(&instance, &device, &mut data)?;
-        core::commands::create_command_buffers(&device, &mut data)?;
+        core::descriptor::create_descriptor_set_layout(&device, &mut data)?;
+        core::bindless::create_table(&instance, &device, &mut data)?;
+        core::texture::create_texture(&instance, &device, &mut data)?;
+        core::skybox::create_skybox(&instance, &device, &mut data)?;
+        core::post::create_resources(&instance, &device, &mut data)?;
+        let mesh = core::model::load_scene(&instance, &device, &mut data)?;
+        core::vertex::create_vertex_buffer(&instance, &device, &mut data, &mesh.vertices)?;
+        core::vertex::create_index_buffer(&instance, &device, &mut data, &mesh.indices)?;
+        data.object_transforms = vec![cgmath::Matrix4::identity()];
+        core::swapchain::create_render_targets(&instance, &device, &mut data)?;
         core::commands::create_sync_objects(&device, &mut data)?;
 
         Ok(Self { entry,
@@ -155,10 +242,73 @@ impl App {
             device, 
             frame: 0, 
             resized: false, 
+            start: Instant::now(),
+            #[cfg(feature = "runtime-shaders")]
+            shader_watcher: core::hot_reload::ShaderWatcher::new(),
         })
     }
 
+    #[cfg(feature = "runtime-shaders")]
+    unsafe fn reload_shaders(&mut self, window: &Window) {
+        let changed = self.shader_watcher.poll();
+        if changed.is_empty() {
+            return;
+        }
+
+        match core::hot_reload::rebuild_pipelines(&self.instance, &self.device, &mut self.data, &changed) {
+            Ok(()) => window.set_title(WINDOW_TITLE),
+            Err(e) => {
+                error!("Shader reload failed, keeping the last good pipeline: {:#}", e);
+                window.set_title(&format!("{} - shader error: {}", WINDOW_TITLE, e));
+            }
+        }
+
+        // An edit can add or drop includes, whether or not it compiled.
+        self.shader_watcher.refresh_includes(&changed);
+    }
+
+    unsafe fn handle_key(&mut self, code: KeyCode) -> Result<()> {
+        match code {
+            KeyCode::KeyM => {
+                let samples = core::msaa::get_next_sample_count(&self.instance, &self.data);
+                core::msaa::set_msaa_samples(&self.instance, &self.device, &mut self.data, samples)
+            }
+            KeyCode::KeyN => {
+                let enabled = !self.data.sample_shading;
+                core::msaa::set_sample_shading(&self.instance, &self.device, &mut self.data, enabled)
+            }
+            KeyCode::KeyP => {
+                let chain = if self.data.post.chain.is_empty() { core::post::default_chain() } else { Vec::new() };
+                core::post::set_chain(&self.instance, &self.device, &mut self.data, chain)
+            }
+            KeyCode::Equal | KeyCode::Minus => match core::post::exposure(&self.data) {
+                Some(exposure) => {
+                    let exposure = if code == KeyCode::Equal { exposure * 1.25 } else { exposure / 1.25 };
+                    core::post::set_exposure(&mut self.data, exposure);
+                    Ok(())
+                }
+                None => Ok(()),
+            },
+            KeyCode::KeyV => {
+                let view = core::debug_view::next_debug_view(&self.data);
+                core::debug_view::set_debug_view(&self.instance, &self.device, &mut self.data, view)
+            }
+            _ => Ok(()),
+        }
+    }
+
     unsafe fn render(&mut self, window: &Window) -> Result<()> {
+        #[cfg(feature = "runtime-shaders")]
+        self.reload_shaders(window);
+
+        // Without descriptor indexing, table updates invalidate the recorded
+        // command buffers.
+        if self.data.bindless.take_stale() {
+            self.device.device_wait_idle()?;
+            core::commands::destroy_command_buffers(&self.device, &mut self.data);
+            core::commands::create_command_buffers(&self.instance, &self.device, &mut self.data)?;
+        }
+
         let result = self.device.acquire_next_image_khr(
                 self.data.swapchain,
                 u64::MAX,
@@ -182,9 +332,16 @@ impl App {
 
         self.data.images_in_flight[image_index as usize] = self.data.in_flight_fences[self.frame];
 
+        core::commands::refresh_command_buffer(&self.instance, &self.device, &mut self.data, self.frame, image_index)?;
+
+        self.update_uniform_buffer()?;
+        if self.data.render_path == core::config::RenderPath::Deferred {
+            core::deferred::update_light_buffer(&self.device, &self.data, self.frame)?;
+        }
+
         let wait_semaphores = &[self.data.image_available_semaphores[self.frame]];
         let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let command_buffers = &[self.data.command_buffers[image_index as usize]];
+        let command_buffers = &[core::commands::command_buffer(&self.data, self.frame, image_index)];
         let signal_semaphores = &[self.data.render_finished_semaphores[self.frame]];
         let submit_info = vk::SubmitInfo::builder()
             .wait_semaphores(wait_semaphores)
@@ -223,8 +380,18 @@ impl App {
         Ok(())
     }
 
+    // Spins the scene a quarter turn per second around the camera's up axis.
+    unsafe fn update_uniform_buffer(&self) -> Result<()> {
+        let time = self.start.elapsed().as_secs_f32();
+        let model = cgmath::Matrix4::from_axis_angle(vec3(0.0, 0.0, 1.0), Deg(90.0) * time);
+        let ubo = self.data.camera.uniforms(model, self.data.swapchain_extent);
+        core::uniform::update_uniform_buffer(&self.device, &self.data, self.frame, &ubo)
+    }
+
     unsafe fn destroy(&mut self) {
         println!("Destroy app. ");
+        #[cfg(feature = "runtime-shaders")]
+        info!("Shader cache: {}", core::shader_cache::global().lock().unwrap_or_else(|e| e.into_inner()).stats());
         self.device.device_wait_idle().unwrap();
 
         core::swapchain::destroy_swapchain(&self.device, &mut self.data,);
@@ -232,6 +399,11 @@ impl App {
         self.data.in_flight_fences.iter().for_each(|f| self.device.destroy_fence(*f, None));
         self.data.render_finished_semaphores.iter().for_each(|s| self.device.destroy_semaphore(*s, None));
         self.data.image_available_semaphores.iter().for_each(|s| self.device.destroy_semaphore(*s, None));
+        core::post::destroy_resources(&self.device, &mut self.data);
+        core::skybox::destroy_skybox(&self.device, &mut self.data);
+        core::texture::destroy_texture(&self.device, &mut self.data);
+        core::descriptor::destroy_descriptors(&self.device, &mut self.data);
+        core::bindless::destroy_table(&self.device, &mut self.data);
         self.device.destroy_buffer(self.data.index_buffer, None);
         self.device.free_memory(self.data.index_buffer_memory, None);
         self.device.free_memory(self.data.vertex_buffer_memory, None);
@@ -258,9 +430,11 @@ impl QueueFamilyIndices {
         physical_device: vk::PhysicalDevice,
     ) -> Result<Self> {
         let properties = instance.get_physical_device_queue_family_properties(physical_device);
+        // Compute work is submitted to the graphics queue, where the render
+        // graph's barriers order it against rendering.
         let graphics = properties
             .iter()
-            .position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+            .position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE))
             .map(|i| i as u32);
 
         let mut present = None;
@@ -310,7 +484,7 @@ unsafe fn create_instance(window: &Window, entry: &Entry, data: &mut AppData) ->
         .application_version(vk::make_version(1, 0, 0))
         .engine_name(b"No Engine\0")
         .engine_version(vk::make_version(1, 0, 0))
-        .api_version(vk::make_version(1, 0, 0));
+        .api_version(core::dynamic_rendering::get_instance_version(entry)?.into());
 
     let mut extensions = vk_window::get_required_instance_extensions(window)
         .iter()
@@ -391,11 +565,22 @@ unsafe fn create_logical_device(entry: &Entry,
         vec![]
     };
 
-    let mut extensions = vec![];
+    let mut extensions = DEVICE_EXTENSIONS.to_vec();
+    extensions.extend(core::dynamic_rendering::device_extensions(instance, data.physical_device, data.dynamic_rendering));
+    extensions.extend(core::bindless::device_extensions(data.descriptor_indexing));
     if cfg!(target_os = "macos") && entry.version()? >= PORTABILITY_MACOS_VERSION {
-        extensions.push(vk::KHR_PORTABILITY_SUBSET_EXTENSION.name.as_ptr());
+        extensions.push(vk::KHR_PORTABILITY_SUBSET_EXTENSION.name);
     }
-    let features = vk::PhysicalDeviceFeatures::builder();
+    let extensions = extensions.iter().map(|n| n.as_ptr()).collect::<Vec<_>>();
+
+    let features = vk::PhysicalDeviceFeatures::builder()
+        .shader_sampled_image_array_dynamic_indexing(data.bindless_textures)
+        .sample_rate_shading(data.sample_shading_supported)
+        .fill_mode_non_solid(data.wireframe_supported)
+        .sampler_anisotropy(data.sampler_anisotropy_supported);
+    let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::builder()
+        .dynamic_rendering(true);
+    let mut descriptor_indexing_features = core::bindless::enabled_features();
 
     let queue_infos = unique_indices
         .iter()
@@ -406,19 +591,21 @@ unsafe fn create_logical_device(entry: &Entry,
     })
     .collect::<Vec<_>>();
 
-    let extensions = DEVICE_EXTENSIONS
-        .iter()
-        .map(|n| n.as_ptr())
-        .collect::<Vec<_>>();
-
-    let info = vk::DeviceCreateInfo::builder()
+    let mut info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_infos)
         .enabled_layer_names(&layers)
         .enabled_extension_names(&extensions) 
         .enabled_features(&features);
+    if data.dynamic_rendering.is_some() {
+        info = info.push_next(&mut dynamic_rendering_features);
+    }
+    if data.descriptor_indexing.is_some() {
+        info = info.push_next(&mut descriptor_indexing_features);
+    }
 
     let device = instance.create_device(data.physical_device, &info, None)?;
     data.graphics_queue = device.get_device_queue(indices.graphics, 0);
+    data.compute_queue = data.graphics_queue;
     data.present_queue = device.get_device_queue(indices.present, 0);
 
     Ok(device)
@@ -437,6 +624,36 @@ unsafe fn pick_physical_device(instance: &Instance, data: &mut AppData) -> Resul
         } else {
             info!("Selected physical device (`{}`).", properties.device_name);
             data.physical_device = physical_device;
+            // The G-buffer is read per pixel, so the deferred path renders without MSAA.
+            data.msaa_samples = match data.render_path {
+                core::config::RenderPath::Forward => core::msaa::get_sample_count(instance, data, core::config::MSAA_SAMPLES),
+                core::config::RenderPath::Deferred => {
+                    if core::config::MSAA_SAMPLES > 1 {
+                        warn!("MSAA is not supported on the deferred path.");
+                    }
+                    vk::SampleCountFlags::_1
+                }
+            };
+            let features = instance.get_physical_device_features(physical_device);
+            data.sample_shading_supported = features.sample_rate_shading == vk::TRUE;
+            data.wireframe_supported = features.fill_mode_non_solid == vk::TRUE;
+            // The scene picks its texture from the bindless table by push constant.
+            let dynamic_indexing = features.shader_sampled_image_array_dynamic_indexing == vk::TRUE;
+            data.bindless_textures = core::config::BINDLESS_TEXTURES && dynamic_indexing;
+            if core::config::BINDLESS_TEXTURES && !dynamic_indexing {
+                warn!("Sampled image arrays can't be indexed dynamically, binding a texture set per draw.");
+            }
+            data.sampler_anisotropy_supported = features.sampler_anisotropy == vk::TRUE;
+            data.max_sampler_anisotropy = properties.limits.max_sampler_anisotropy;
+            data.max_push_constants_size = properties.limits.max_push_constants_size;
+            data.sample_shading = core::config::SAMPLE_SHADING && data.sample_shading_supported;
+            info!("Using {:?} MSAA.", data.msaa_samples);
+            data.dynamic_rendering = core::dynamic_rendering::get_support(instance, physical_device)?;
+            match data.dynamic_rendering {
+                Some(mode) => info!("Using dynamic rendering ({:?}).", mode),
+                None => info!("Using render pass objects."),
+            }
+            data.descriptor_indexing = core::bindless::get_support(instance, physical_device)?;
             return Ok(());
         }
     }
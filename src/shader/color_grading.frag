@@ -0,0 +1,23 @@
+#version 450
+
+layout(binding = 0) uniform sampler2D inputImage;
+layout(binding = 1) uniform sampler3D lut;
+
+layout(push_constant) uniform Params {
+	float strength;
+} params;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	vec3 color = clamp(texture(inputImage, fragUv).rgb, 0.0, 1.0);
+
+	// The LUT is indexed with gamma encoded colors, sampling texel centers.
+	float size = float(textureSize(lut, 0).x);
+	vec3 coord = pow(color, vec3(1.0 / 2.2)) * ((size - 1.0) / size) + 0.5 / size;
+	vec3 graded = pow(texture(lut, coord).rgb, vec3(2.2));
+
+	outColor = vec4(mix(color, graded, params.strength), 1.0);
+}
@@ -0,0 +1,57 @@
+#version 450
+
+// Matches `DebugView::shader_mode`.
+const uint MODE_VERTEX_COLOR = 0;
+const uint MODE_FLAT_COLOR = 1;
+const uint MODE_NORMALS = 2;
+const uint MODE_UV_CHECKER = 3;
+const uint MODE_DEPTH = 4;
+const uint MODE_OVERDRAW = 5;
+
+// Set per debug view when the pipeline is built.
+layout(constant_id = 0) const uint MODE = MODE_VERTEX_COLOR;
+
+const float CHECKER_SIZE = 8.0;
+const vec3 OVERDRAW_STEP = vec3(0.2, 0.08, 0.02);
+
+// Follows the object transform `debug.vert` reads.
+layout(push_constant) uniform Params {
+	layout(offset = 64) float near;
+	float far;
+} params;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) flat in vec3 flatColor;
+layout(location = 2) in vec3 viewNormal;
+layout(location = 3) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	switch (MODE) {
+	case MODE_FLAT_COLOR:
+		outColor = vec4(flatColor, 1.0);
+		break;
+	case MODE_NORMALS:
+		// View space vertex normals.
+		vec3 normal = normalize(viewNormal);
+		outColor = vec4(normal * 0.5 + 0.5, 1.0);
+		break;
+	case MODE_UV_CHECKER:
+		ivec2 cell = ivec2(floor(fragUv * CHECKER_SIZE));
+		float checker = (cell.x + cell.y) % 2 == 0 ? 0.9 : 0.2;
+		outColor = vec4(mix(vec3(checker), vec3(fragUv, 0.0), 0.3), 1.0);
+		break;
+	case MODE_DEPTH:
+		float linear = params.near * params.far / (params.far - gl_FragCoord.z * (params.far - params.near));
+		outColor = vec4(vec3((linear - params.near) / (params.far - params.near)), 1.0);
+		break;
+	case MODE_OVERDRAW:
+		// Blended additively, so each layer brightens the pixel.
+		outColor = vec4(OVERDRAW_STEP, 1.0);
+		break;
+	default:
+		outColor = vec4(fragColor, 1.0);
+		break;
+	}
+}
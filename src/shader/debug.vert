@@ -0,0 +1,31 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+	mat4 model;
+	mat4 view;
+	mat4 proj;
+} ubo;
+
+layout(push_constant) uniform ObjectConstants {
+	mat4 model;
+} object;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec3 inNormal;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) flat out vec3 flatColor;
+layout(location = 2) out vec3 viewNormal;
+layout(location = 3) out vec2 fragUv;
+
+void main() {
+	mat4 modelView = ubo.view * ubo.model * object.model;
+	vec4 view = modelView * vec4(inPosition, 1.0);
+	gl_Position = ubo.proj * view;
+	fragColor = inColor;
+	flatColor = inColor;
+	fragUv = inTexCoord;
+	viewNormal = mat3(modelView) * inNormal;
+}
@@ -0,0 +1,52 @@
+#version 450
+
+layout(binding = 0) uniform sampler2D inputImage;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+const float EDGE_THRESHOLD_MIN = 1.0 / 32.0;
+const float EDGE_THRESHOLD_MAX = 1.0 / 8.0;
+const float REDUCE_MIN = 1.0 / 128.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float SPAN_MAX = 8.0;
+
+// Perceptual luma of a linear color.
+float luma(vec3 color) {
+	return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+vec3 sampleAt(vec2 uv) {
+	return texture(inputImage, uv).rgb;
+}
+
+void main() {
+	vec2 texel = 1.0 / vec2(textureSize(inputImage, 0));
+
+	vec3 rgbM = sampleAt(fragUv);
+	float lumaNW = luma(sampleAt(fragUv + vec2(-1.0, -1.0) * texel));
+	float lumaNE = luma(sampleAt(fragUv + vec2(1.0, -1.0) * texel));
+	float lumaSW = luma(sampleAt(fragUv + vec2(-1.0, 1.0) * texel));
+	float lumaSE = luma(sampleAt(fragUv + vec2(1.0, 1.0) * texel));
+	float lumaM = luma(rgbM);
+
+	float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+	float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+	if (lumaMax - lumaMin < max(EDGE_THRESHOLD_MIN, lumaMax * EDGE_THRESHOLD_MAX)) {
+		outColor = vec4(rgbM, 1.0);
+		return;
+	}
+
+	// Blur along the edge, perpendicular to the luma gradient.
+	vec2 dir = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+	float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+	float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+	dir = clamp(dir * rcpDirMin, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texel;
+
+	vec3 rgbA = 0.5 * (sampleAt(fragUv + dir * (1.0 / 3.0 - 0.5)) + sampleAt(fragUv + dir * (2.0 / 3.0 - 0.5)));
+	vec3 rgbB = rgbA * 0.5 + 0.25 * (sampleAt(fragUv - dir * 0.5) + sampleAt(fragUv + dir * 0.5));
+	float lumaB = luma(rgbB);
+
+	outColor = vec4(lumaB < lumaMin || lumaB > lumaMax ? rgbA : rgbB, 1.0);
+}
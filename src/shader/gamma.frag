@@ -0,0 +1,20 @@
+#version 450
+
+layout(binding = 0) uniform sampler2D inputImage;
+
+layout(push_constant) uniform Params {
+	float gamma;
+	// Non-zero when the final blit encodes to an sRGB swapchain.
+	float srgbTarget;
+} params;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	vec3 color = max(texture(inputImage, fragUv).rgb, vec3(0.0));
+	// An sRGB target is encoded on write, so only the difference to 2.2 is applied here.
+	float exponent = params.srgbTarget != 0.0 ? 2.2 / params.gamma : 1.0 / params.gamma;
+	outColor = vec4(pow(color, vec3(exponent)), 1.0);
+}
@@ -0,0 +1,30 @@
+#version 450
+
+// Sized to the bindless table when the pipeline is built.
+layout(constant_id = 100) const uint SAMPLED_IMAGE_COUNT = 1;
+layout(constant_id = 102) const uint SAMPLER_COUNT = 1;
+
+layout(set = 1, binding = 0) uniform texture2D textures[SAMPLED_IMAGE_COUNT];
+layout(set = 1, binding = 2) uniform sampler samplers[SAMPLER_COUNT];
+
+// Follows the object transform `gbuffer.vert` reads.
+layout(push_constant) uniform Material {
+	layout(offset = 64) uint textureIndex;
+	uint samplerIndex;
+} material;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outAlbedo;
+layout(location = 1) out vec4 outNormal;
+// r: roughness, g: metallic
+layout(location = 2) out vec4 outMaterial;
+
+void main() {
+	vec4 texel = texture(sampler2D(textures[material.textureIndex], samplers[material.samplerIndex]), fragTexCoord);
+	outAlbedo = vec4(fragColor, 1.0) * texel;
+	outNormal = vec4(normalize(fragNormal), 0.0);
+	outMaterial = vec4(0.4, 0.0, 0.0, 0.0);
+}
@@ -0,0 +1,20 @@
+#version 450
+
+// The draw's texture, bound at set 1 (`core::texture::TEXTURE_SET`).
+layout(set = 1, binding = 0) uniform sampler2D texSampler;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outAlbedo;
+layout(location = 1) out vec4 outNormal;
+// r: roughness, g: metallic
+layout(location = 2) out vec4 outMaterial;
+
+void main() {
+	vec4 texel = texture(texSampler, fragTexCoord);
+	outAlbedo = vec4(fragColor, 1.0) * texel;
+	outNormal = vec4(normalize(fragNormal), 0.0);
+	outMaterial = vec4(0.4, 0.0, 0.0, 0.0);
+}
@@ -0,0 +1,70 @@
+#version 450
+
+layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput inAlbedo;
+layout(input_attachment_index = 1, set = 0, binding = 1) uniform subpassInput inNormal;
+layout(input_attachment_index = 2, set = 0, binding = 2) uniform subpassInput inMaterial;
+layout(input_attachment_index = 3, set = 0, binding = 3) uniform subpassInput inDepth;
+
+struct Light {
+	// xyz: position in the scene's model space, w: radius
+	vec4 position;
+	// rgb: color, a: intensity
+	vec4 color;
+};
+
+// Read every frame, so lights can change without re-recording commands.
+layout(std430, set = 0, binding = 4) readonly buffer Lights {
+	uint lightCount;
+	float ambient;
+	Light lights[];
+};
+
+layout(set = 0, binding = 5) uniform UniformBufferObject {
+	mat4 model;
+	mat4 view;
+	mat4 proj;
+	mat4 inverseProj;
+} ubo;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	float depth = subpassLoad(inDepth).r;
+	if (depth >= 1.0) {
+		outColor = vec4(0.0, 0.0, 0.0, 1.0);
+		return;
+	}
+
+	vec3 albedo = subpassLoad(inAlbedo).rgb;
+	vec3 normal = normalize(subpassLoad(inNormal).xyz);
+	float roughness = subpassLoad(inMaterial).r;
+	// Shading happens in view space, where the G-buffer normals are. The
+	// position comes back from depth through the inverse projection, and the
+	// lights are moved there from the scene's model space.
+	vec4 viewPosition = ubo.inverseProj * vec4(fragUv * 2.0 - 1.0, depth, 1.0);
+	vec3 position = viewPosition.xyz / viewPosition.w;
+	vec3 view = normalize(-position);
+	mat4 modelView = ubo.view * ubo.model;
+
+	vec3 color = albedo * ambient;
+	for (uint i = 0; i < lightCount; ++i) {
+		vec3 lightPosition = (modelView * vec4(lights[i].position.xyz, 1.0)).xyz;
+		vec3 toLight = lightPosition - position;
+		float distance = length(toLight);
+		float radius = lights[i].position.w;
+		if (distance >= radius) {
+			continue;
+		}
+
+		vec3 l = toLight / distance;
+		float attenuation = (1.0 - distance / radius) * (1.0 - distance / radius);
+		float diffuse = max(dot(normal, l), 0.0);
+		float shininess = mix(128.0, 4.0, roughness);
+		float specular = pow(max(dot(normal, normalize(l + view)), 0.0), shininess) * (1.0 - roughness);
+		color += (albedo * diffuse + specular) * lights[i].color.rgb * lights[i].color.a * attenuation;
+	}
+
+	outColor = vec4(color, 1.0);
+}
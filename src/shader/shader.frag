@@ -1,7 +1,31 @@
-#version 450 
+#version 450
+
+// Sized to the bindless table when the pipeline is built.
+layout(constant_id = 100) const uint SAMPLED_IMAGE_COUNT = 1;
+layout(constant_id = 102) const uint SAMPLER_COUNT = 1;
+
+layout(set = 1, binding = 0) uniform texture2D textures[SAMPLED_IMAGE_COUNT];
+layout(set = 1, binding = 2) uniform sampler samplers[SAMPLER_COUNT];
+
+// Follows the object transform `shader.vert` reads.
+layout(push_constant) uniform Material {
+	layout(offset = 64) uint textureIndex;
+	uint samplerIndex;
+} material;
+
 layout(location = 0) in vec3 fragColor;
-layout (location = 0) out vec4 outColor;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragNormal;
+
+layout(location = 0) out vec4 outColor;
+
+// A fixed world space light from above, so models read as solid.
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.4, 0.3, 1.0));
+const float AMBIENT = 0.35;
 
 void main() {
-	outColor = vec4(fragColor, 1.0);
+	float diffuse = max(dot(normalize(fragNormal), LIGHT_DIRECTION), 0.0);
+	vec4 texel = texture(sampler2D(textures[material.textureIndex], samplers[material.samplerIndex]), fragTexCoord);
+	vec4 color = vec4(fragColor, 1.0) * texel;
+	outColor = vec4(color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse), color.a);
 }
@@ -6,12 +6,23 @@ layout(binding = 0) uniform UniformBufferObject {
 	mat4 proj;
 } ubo;
 
-layout(location = 0) in vec2 inPosition;
+layout(push_constant) uniform ObjectConstants {
+	mat4 model;
+} object;
+
+layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec3 inNormal;
 
 layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragNormal;
 
 void main() {
-	gl_Position = ubo.model * ubo.view * ubo.proj * vec4(inPosition, 0.0, 1.0);
+	mat4 model = ubo.model * object.model;
+	gl_Position = ubo.proj * ubo.view * model * vec4(inPosition, 1.0);
 	fragColor = inColor;
+	fragTexCoord = inTexCoord;
+	fragNormal = mat3(model) * inNormal;
 }
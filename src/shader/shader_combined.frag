@@ -0,0 +1,21 @@
+#version 450
+
+// The draw's texture, bound at set 1 (`core::texture::TEXTURE_SET`).
+layout(set = 1, binding = 0) uniform sampler2D texSampler;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragNormal;
+
+layout(location = 0) out vec4 outColor;
+
+// A fixed world space light from above, so models read as solid.
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.4, 0.3, 1.0));
+const float AMBIENT = 0.35;
+
+void main() {
+	float diffuse = max(dot(normalize(fragNormal), LIGHT_DIRECTION), 0.0);
+	vec4 texel = texture(texSampler, fragTexCoord);
+	vec4 color = vec4(fragColor, 1.0) * texel;
+	outColor = vec4(color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse), color.a);
+}
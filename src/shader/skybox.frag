@@ -0,0 +1,13 @@
+#version 450
+
+layout(set = 1, binding = 0) uniform samplerCube skybox;
+
+layout(location = 0) in vec3 fragDirection;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	// The world is z-up, cube maps are y-up.
+	vec3 direction = vec3(fragDirection.x, fragDirection.z, -fragDirection.y);
+	outColor = vec4(texture(skybox, direction).rgb, 1.0);
+}
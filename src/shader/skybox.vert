@@ -0,0 +1,20 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+	mat4 model;
+	mat4 view;
+	mat4 proj;
+} ubo;
+
+// One triangle covering the screen on the far plane, no vertex input.
+layout(location = 0) out vec3 fragDirection;
+
+void main() {
+	vec2 ndc = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+	gl_Position = vec4(ndc, 1.0, 1.0);
+
+	// Back through the projection and the view's rotation only, so the sky
+	// stays put as the camera moves.
+	vec4 viewPosition = inverse(ubo.proj) * gl_Position;
+	fragDirection = transpose(mat3(ubo.view)) * (viewPosition.xyz / viewPosition.w);
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(binding = 0) uniform sampler2D inputImage;
+
+layout(push_constant) uniform Params {
+	float exposure;
+} params;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+// Narkowicz's fit of the ACES filmic curve.
+vec3 aces(vec3 x) {
+	const float a = 2.51;
+	const float b = 0.03;
+	const float c = 2.43;
+	const float d = 0.59;
+	const float e = 0.14;
+	return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+void main() {
+	vec3 color = texture(inputImage, fragUv).rgb * params.exposure;
+	outColor = vec4(aces(color), 1.0);
+}
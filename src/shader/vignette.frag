@@ -0,0 +1,20 @@
+#version 450
+
+layout(binding = 0) uniform sampler2D inputImage;
+
+layout(push_constant) uniform Params {
+	float strength;
+	float radius;
+} params;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+	vec3 color = texture(inputImage, fragUv).rgb;
+	// 0 at the center, 1 in the corners.
+	float distance = length(fragUv - 0.5) * sqrt(2.0);
+	float vignette = 1.0 - params.strength * smoothstep(params.radius, 1.0, distance);
+	outColor = vec4(color * vignette, 1.0);
+}